@@ -0,0 +1,55 @@
+use std::time::{Duration, Instant};
+
+/// Tracks user activity so the vault can be locked after a period of idleness.
+pub struct AutoLock {
+    timeout: Option<Duration>,
+    last_activity: Instant,
+}
+
+impl AutoLock {
+    pub fn new() -> Self {
+        Self {
+            timeout: None,
+            last_activity: Instant::now(),
+        }
+    }
+
+    /// Sets the idle timeout in minutes; 0 disables auto-lock.
+    pub fn configure(&mut self, minutes: u32) {
+        self.timeout = match minutes {
+            0 => None,
+            m => Some(Duration::from_secs(u64::from(m) * 60)),
+        };
+        self.touch();
+    }
+
+    pub fn touch(&mut self) {
+        self.last_activity = Instant::now();
+    }
+
+    pub fn is_expired(&self, now: Instant) -> bool {
+        match self.timeout {
+            Some(timeout) => now.saturating_duration_since(self.last_activity) >= timeout,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_lock_expiry() {
+        let mut auto_lock = AutoLock::new();
+        let later = Instant::now() + Duration::from_secs(3600);
+        assert!(!auto_lock.is_expired(later));
+
+        auto_lock.configure(5);
+        assert!(!auto_lock.is_expired(Instant::now()));
+        assert!(auto_lock.is_expired(later));
+
+        auto_lock.configure(0);
+        assert!(!auto_lock.is_expired(later));
+    }
+}
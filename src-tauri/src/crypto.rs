@@ -1,22 +1,89 @@
 use aes_gcm::{
-    aead::{Aead, AeadCore, KeyInit, OsRng},
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
     Aes256Gcm, Key, Nonce,
 };
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use directories::ProjectDirs;
+use hmac::{Hmac, Mac};
+use keyring::Entry;
 use rand::Rng;
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use std::{
+    fmt,
     fs::{self, File},
-    io::{Read, Write},
-    path::PathBuf,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
 };
 
+const KEYCHAIN_SERVICE: &str = "com.secondbrian.diary";
+const KEYCHAIN_USER: &str = "encryption-key";
+
 #[derive(Debug)]
 pub struct Crypto {
     key: Secret<[u8; 32]>,
+    key_path: PathBuf,
+    backend: KeyStorageBackend,
+}
+
+/// Where the vault key is persisted between runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStorageBackend {
+    Keychain,
+    File,
+}
+
+#[derive(Debug)]
+pub enum CryptoError {
+    InvalidEnvelope(String),
+    Aead,
+    InvalidUtf8,
+    KeychainAccessDenied(String),
+    InvalidKey,
+    KeyMissing,
+    Decompression(String),
+    IncorrectPassword,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::InvalidEnvelope(e) => write!(f, "invalid encrypted envelope: {}", e),
+            CryptoError::Aead => write!(f, "decryption failed (wrong key or corrupted data)"),
+            CryptoError::InvalidUtf8 => write!(f, "decrypted data is not valid UTF-8"),
+            CryptoError::KeychainAccessDenied(e) => write!(f, "access to the system keychain was denied: {}", e),
+            CryptoError::InvalidKey => write!(f, "stored encryption key has an invalid length"),
+            CryptoError::KeyMissing => write!(f, "encryption key is missing"),
+            CryptoError::Decompression(e) => write!(f, "failed to decompress data: {}", e),
+            CryptoError::IncorrectPassword => write!(f, "incorrect password or modified file"),
+        }
+    }
 }
 
+impl std::error::Error for CryptoError {}
+
+/// Compact envelope versions, stored base64-encoded:
+/// v1 is `version || nonce (12) || ciphertext`,
+/// v2 is `version || flags || nonce (12) || ciphertext`.
+const ENVELOPE_V1: u8 = 1;
+const ENVELOPE_V2: u8 = 2;
+const NONCE_LEN: usize = 12;
+/// AES-GCM appends a 16-byte tag, so ciphertext is never shorter.
+const TAG_LEN: usize = 16;
+
+/// v2 flag: the plaintext was zstd-compressed before encryption.
+const FLAG_ZSTD: u8 = 0b0000_0001;
+/// Below this size the zstd frame header costs more than it saves.
+const COMPRESSION_THRESHOLD: usize = 1024;
+const ZSTD_LEVEL: i32 = 3;
+
+/// Domain separation for `keyed_hash`, which shares the vault key.
+const KEYED_HASH_CONTEXT: &[u8] = b"secondbrain content hash v1";
+
+/// Legacy envelope, serialized as JSON with every byte as a decimal number.
 #[derive(Serialize, Deserialize)]
 struct EncryptedData {
     nonce: Vec<u8>,
@@ -24,15 +91,145 @@ struct EncryptedData {
 }
 
 impl Crypto {
-    pub fn new() -> Self {
-        let key = match Self::load_key() {
+    /// Loads the vault key, preferring the platform keychain and falling back
+    /// to the key file when no keychain is available. A file-based key found
+    /// next to a working keychain is migrated into it and the file removed.
+    pub fn new() -> Result<Self, CryptoError> {
+        let key_path = Self::get_key_path();
+
+        let entry = match Self::keychain_entry() {
+            Ok(entry) => entry,
+            Err(e) => {
+                println!("⚠️ [CRYPTO] Keychain unavailable, using key file: {}", e);
+                return Ok(Self::with_key_path(key_path));
+            }
+        };
+
+        match entry.get_secret() {
+            Ok(secret) => {
+                let key: [u8; 32] = secret.try_into().map_err(|_| CryptoError::InvalidKey)?;
+                // Finish a migration that stored the key but crashed before removing the file.
+                if Self::load_key(&key_path) == Some(key) {
+                    let _ = secure_delete(&key_path);
+                }
+                Ok(Self {
+                    key: Secret::new(key),
+                    key_path,
+                    backend: KeyStorageBackend::Keychain,
+                })
+            }
+            Err(keyring::Error::NoEntry) => {
+                let existing = Self::load_key(&key_path);
+                let key = existing.unwrap_or_else(Self::generate_key);
+
+                if let Err(e) = Self::store_in_keychain(&entry, &key) {
+                    println!("⚠️ [CRYPTO] Could not store key in keychain, using key file: {}", e);
+                    if existing.is_none() {
+                        Self::save_key(&key_path, &key);
+                    }
+                    return Ok(Self {
+                        key: Secret::new(key),
+                        key_path,
+                        backend: KeyStorageBackend::File,
+                    });
+                }
+
+                if existing.is_some() {
+                    println!("🔑 [CRYPTO] Migrated encryption key from file into keychain");
+                    if let Err(e) = secure_delete(&key_path) {
+                        println!("⚠️ [CRYPTO] Failed to remove migrated key file: {}", e);
+                    }
+                }
+                Ok(Self {
+                    key: Secret::new(key),
+                    key_path,
+                    backend: KeyStorageBackend::Keychain,
+                })
+            }
+            Err(keyring::Error::NoStorageAccess(e)) => {
+                // The key may well live in the keychain; generating a new one
+                // here would orphan every entry, so only a file key is acceptable.
+                if key_path.exists() {
+                    Ok(Self::with_key_path(key_path))
+                } else {
+                    Err(CryptoError::KeychainAccessDenied(e.to_string()))
+                }
+            }
+            Err(e) => {
+                println!("⚠️ [CRYPTO] Keychain unavailable, using key file: {}", e);
+                Ok(Self::with_key_path(key_path))
+            }
+        }
+    }
+
+    /// Uses a plain key file at `key_path`, never touching the keychain.
+    pub fn with_key_path(key_path: PathBuf) -> Self {
+        let key = match Self::load_key(&key_path) {
             Some(k) => k,
-            None => Self::generate_and_save_key(),
+            None => Self::generate_and_save_key(&key_path),
         };
-        Self { key: Secret::new(key) }
+        Self {
+            key: Secret::new(key),
+            key_path,
+            backend: KeyStorageBackend::File,
+        }
+    }
+
+    /// Loads the existing key again from `backend`, e.g. when unlocking the
+    /// vault. Unlike `with_key_path`, a missing key file is an error rather
+    /// than a reason to generate a new key.
+    pub fn reopen(backend: KeyStorageBackend, key_path: PathBuf) -> Result<Self, CryptoError> {
+        match backend {
+            KeyStorageBackend::Keychain => Self::load_from_keychain(key_path),
+            KeyStorageBackend::File if key_path.exists() => Ok(Self::with_key_path(key_path)),
+            KeyStorageBackend::File => Err(CryptoError::KeyMissing),
+        }
     }
 
-    fn get_key_path() -> PathBuf {
+    /// Reads the key already stored in the keychain. Unlike `new`, this never
+    /// generates or stores a key: a replacement would orphan every entry.
+    fn load_from_keychain(key_path: PathBuf) -> Result<Self, CryptoError> {
+        let secret = Self::keychain_entry()
+            .and_then(|entry| entry.get_secret())
+            .map_err(|e| match e {
+                keyring::Error::NoEntry => CryptoError::KeyMissing,
+                e => CryptoError::KeychainAccessDenied(e.to_string()),
+            })?;
+        let key: [u8; 32] = secret.try_into().map_err(|_| CryptoError::InvalidKey)?;
+        Ok(Self {
+            key: Secret::new(key),
+            key_path,
+            backend: KeyStorageBackend::Keychain,
+        })
+    }
+
+    pub fn backend(&self) -> KeyStorageBackend {
+        self.backend
+    }
+
+    pub fn key_path(&self) -> &Path {
+        &self.key_path
+    }
+
+    fn keychain_entry() -> Result<Entry, keyring::Error> {
+        Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_USER)
+    }
+
+    /// Writes the key and reads it back, so a keychain that silently drops
+    /// secrets is never trusted with the only copy.
+    fn store_in_keychain(entry: &Entry, key: &[u8; 32]) -> Result<(), keyring::Error> {
+        entry.set_secret(key)?;
+        match entry.get_secret() {
+            Ok(stored) if stored.as_slice() == key => Ok(()),
+            Ok(_) => Err(keyring::Error::Invalid(
+                "secret".to_string(),
+                "keychain returned a different key than was stored".to_string(),
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_key_path() -> PathBuf {
         let proj_dirs = ProjectDirs::from("com", "secondbrian", "diary")
             .expect("Failed to get project directories");
         let data_dir = proj_dirs.data_dir();
@@ -40,8 +237,7 @@ impl Crypto {
         data_dir.join("encryption.key")
     }
 
-    fn load_key() -> Option<[u8; 32]> {
-        let key_path = Self::get_key_path();
+    fn load_key(key_path: &Path) -> Option<[u8; 32]> {
         if !key_path.exists() {
             return None;
         }
@@ -52,58 +248,483 @@ impl Crypto {
         Some(key)
     }
 
-    fn generate_and_save_key() -> [u8; 32] {
+    fn generate_key() -> [u8; 32] {
         let mut key = [0u8; 32];
         rand::thread_rng().fill(&mut key);
+        key
+    }
 
-        let key_path = Self::get_key_path();
+    fn generate_and_save_key(key_path: &Path) -> [u8; 32] {
+        let key = Self::generate_key();
+        Self::save_key(key_path, &key);
+        key
+    }
+
+    fn save_key(key_path: &Path, key: &[u8; 32]) {
         let mut file = File::create(key_path).expect("Failed to create key file");
-        file.write_all(&key).expect("Failed to write key to file");
+        file.write_all(key).expect("Failed to write key to file");
+    }
 
-        key
+    fn staged_key_path(&self) -> PathBuf {
+        self.key_path.with_extension("key.pending")
+    }
+
+    /// Creates a fresh in-memory key bound to the same key file. Nothing is
+    /// written to disk until the key is staged and committed.
+    pub fn generate_replacement(&self) -> Self {
+        Self {
+            key: Secret::new(Self::generate_key()),
+            key_path: self.key_path.clone(),
+            backend: self.backend,
+        }
+    }
+
+    /// Writes this key next to the live key file and fsyncs it, so a rotation
+    /// interrupted after the database commit can still be recovered.
+    pub fn stage_key(&self) -> io::Result<()> {
+        let mut file = File::create(self.staged_key_path())?;
+        file.write_all(self.key.expose_secret())?;
+        file.sync_all()
+    }
+
+    /// Atomically replaces the live key with the staged one, either in the
+    /// keychain or by renaming over the key file.
+    pub fn commit_staged_key(&self) -> io::Result<()> {
+        if self.backend == KeyStorageBackend::Keychain {
+            Self::keychain_entry()
+                .and_then(|entry| Self::store_in_keychain(&entry, self.key.expose_secret()))
+                .map_err(io::Error::other)?;
+            return secure_delete(&self.staged_key_path());
+        }
+
+        fs::rename(self.staged_key_path(), &self.key_path)?;
+        if let Some(dir) = self.key_path.parent() {
+            // Persist the rename itself; not supported on every platform.
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+        Ok(())
+    }
+
+    /// Destroys the key stored for `backend` and stores a fresh one in its
+    /// place, for wiping the vault. A key file is overwritten before removal.
+    pub fn reset_key(backend: KeyStorageBackend, key_path: PathBuf) -> io::Result<Self> {
+        let replacement = Self {
+            key: Secret::new(Self::generate_key()),
+            key_path,
+            backend,
+        };
+        let _ = secure_delete(&replacement.staged_key_path());
+
+        match backend {
+            KeyStorageBackend::Keychain => {
+                Self::keychain_entry()
+                    .and_then(|entry| Self::store_in_keychain(&entry, replacement.key.expose_secret()))
+                    .map_err(io::Error::other)?;
+            }
+            KeyStorageBackend::File => {
+                if replacement.key_path.exists() {
+                    secure_delete(&replacement.key_path)?;
+                }
+                let mut file = File::create(&replacement.key_path)?;
+                file.write_all(replacement.key.expose_secret())?;
+                file.sync_all()?;
+            }
+        }
+
+        Ok(replacement)
+    }
+
+    pub fn discard_staged_key(&self) {
+        let _ = secure_delete(&self.staged_key_path());
+    }
+
+    /// Returns the key left behind by an interrupted rotation, if any.
+    pub fn load_staged(&self) -> Option<Self> {
+        let staged_path = self.staged_key_path();
+        let key = Self::load_key(&staged_path)?;
+        Some(Self {
+            key: Secret::new(key),
+            key_path: self.key_path.clone(),
+            backend: self.backend,
+        })
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(self.key.expose_secret()))
     }
 
     pub fn encrypt(&self, data: &str) -> String {
-        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(self.key.expose_secret()));
+        self.encrypt_bytes(data.as_bytes())
+    }
+
+    pub fn encrypt_bytes(&self, data: &[u8]) -> String {
+        let mut flags = 0;
+        let compressed = if data.len() >= COMPRESSION_THRESHOLD {
+            zstd::bulk::compress(data, ZSTD_LEVEL)
+                .ok()
+                .filter(|compressed| compressed.len() < data.len())
+        } else {
+            None
+        };
+        let plaintext = match &compressed {
+            Some(compressed) => {
+                flags |= FLAG_ZSTD;
+                compressed.as_slice()
+            }
+            None => data,
+        };
+
         let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
-        
-        let ciphertext = cipher
-            .encrypt(&nonce, data.as_bytes())
+        let ciphertext = self
+            .cipher()
+            .encrypt(&nonce, plaintext)
             .expect("Encryption failed");
 
-        let encrypted_data = EncryptedData {
-            nonce: nonce.to_vec(),
-            ciphertext,
-        };
+        let mut envelope = Vec::with_capacity(2 + NONCE_LEN + ciphertext.len());
+        envelope.push(ENVELOPE_V2);
+        envelope.push(flags);
+        envelope.extend_from_slice(&nonce);
+        envelope.extend_from_slice(&ciphertext);
 
-        serde_json::to_string(&encrypted_data).expect("Failed to serialize encrypted data")
+        BASE64.encode(envelope)
     }
 
     pub fn decrypt(&self, encrypted_data_str: &str) -> String {
-        let encrypted_data: EncryptedData =
-            serde_json::from_str(encrypted_data_str).expect("Failed to deserialize encrypted data");
+        self.try_decrypt(encrypted_data_str).expect("Decryption failed")
+    }
 
-        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(self.key.expose_secret()));
-        let nonce = Nonce::from_slice(&encrypted_data.nonce);
+    pub fn try_decrypt(&self, encrypted_data_str: &str) -> Result<String, CryptoError> {
+        let plaintext = self.decrypt_bytes(encrypted_data_str)?;
+        String::from_utf8(plaintext).map_err(|_| CryptoError::InvalidUtf8)
+    }
+
+    pub fn decrypt_bytes(&self, encrypted_data_str: &str) -> Result<Vec<u8>, CryptoError> {
+        let (flags, nonce, ciphertext) = if Self::is_legacy_format(encrypted_data_str) {
+            let encrypted_data: EncryptedData = serde_json::from_str(encrypted_data_str)
+                .map_err(|e| CryptoError::InvalidEnvelope(e.to_string()))?;
+            (0, encrypted_data.nonce, encrypted_data.ciphertext)
+        } else {
+            let mut envelope = BASE64
+                .decode(encrypted_data_str)
+                .map_err(|e| CryptoError::InvalidEnvelope(e.to_string()))?;
+            let (flags, header_len) = match envelope.first() {
+                Some(&ENVELOPE_V1) => (0, 1),
+                Some(&ENVELOPE_V2) if envelope.len() > 1 => (envelope[1], 2),
+                Some(&ENVELOPE_V2) => {
+                    return Err(CryptoError::InvalidEnvelope("envelope is truncated".to_string()))
+                }
+                other => {
+                    return Err(CryptoError::InvalidEnvelope(format!(
+                        "unknown envelope version {:?}",
+                        other
+                    )))
+                }
+            };
+            if envelope.len() <= header_len + NONCE_LEN {
+                return Err(CryptoError::InvalidEnvelope("envelope is truncated".to_string()));
+            }
+            let ciphertext = envelope.split_off(header_len + NONCE_LEN);
+            (flags, envelope[header_len..].to_vec(), ciphertext)
+        };
+
+        if nonce.len() != NONCE_LEN {
+            return Err(CryptoError::InvalidEnvelope("invalid nonce length".to_string()));
+        }
+
+        let plaintext = self
+            .cipher()
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| CryptoError::Aead)?;
+
+        if flags & FLAG_ZSTD != 0 {
+            zstd::stream::decode_all(plaintext.as_slice())
+                .map_err(|e| CryptoError::Decompression(e.to_string()))
+        } else {
+            Ok(plaintext)
+        }
+    }
+
+    /// Hex HMAC-SHA256 of `parts` under the vault key, for comparing
+    /// content without storing a plain hash of it. Parts are length-prefixed
+    /// so ("ab", "c") and ("a", "bc") differ.
+    pub fn keyed_hash(&self, parts: &[&str]) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(self.key.expose_secret())
+            .expect("HMAC accepts any key length");
+        mac.update(KEYED_HASH_CONTEXT);
+        for part in parts {
+            mac.update(&(part.len() as u64).to_le_bytes());
+            mac.update(part.as_bytes());
+        }
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
 
-        let plaintext = cipher
-            .decrypt(nonce, encrypted_data.ciphertext.as_ref())
-            .expect("Decryption failed");
+    /// True for values written in the original JSON envelope format.
+    pub fn is_legacy_format(encrypted_data_str: &str) -> bool {
+        encrypted_data_str.trim_start().starts_with('{')
+    }
 
-        String::from_utf8(plaintext).expect("Invalid UTF-8")
+    /// True when `value` has the shape of an envelope written by `encrypt`,
+    /// whatever key it was written with. Says nothing about whether it
+    /// decrypts; use it to tell ciphertext from plaintext.
+    pub fn looks_encrypted(value: &str) -> bool {
+        if Self::is_legacy_format(value) {
+            return serde_json::from_str::<EncryptedData>(value)
+                .map(|data| data.nonce.len() == NONCE_LEN && data.ciphertext.len() >= TAG_LEN)
+                .unwrap_or(false);
+        }
+        let Ok(envelope) = BASE64.decode(value) else {
+            return false;
+        };
+        let header_len = match envelope.first() {
+            Some(&ENVELOPE_V1) => 1,
+            Some(&ENVELOPE_V2) => 2,
+            _ => return false,
+        };
+        envelope.len() >= header_len + NONCE_LEN + TAG_LEN
     }
 }
 
+/// Password-sealed files, independent of the vault key:
+/// `magic (4) || version || m_cost (u32 LE) || t_cost (u32 LE) ||
+/// p_cost (u32 LE) || salt (16) || nonce (12) || ciphertext`.
+/// The whole header is authenticated as associated data.
+const SEALED_MAGIC: &[u8; 4] = b"SBSE";
+const SEALED_V1: u8 = 1;
+const SALT_LEN: usize = 16;
+const SEALED_HEADER_LEN: usize = 4 + 1 + 12 + SALT_LEN;
+/// Refuse cost parameters that would make opening a file a denial of service.
+const MAX_M_COST_KIB: u32 = 1 << 20;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+fn password_key(password: &str, salt: &[u8], params: Params) -> Result<Aes256Gcm, CryptoError> {
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| CryptoError::InvalidEnvelope(e.to_string()))?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    key.fill(0);
+    Ok(cipher)
+}
+
+/// Encrypts `plaintext` under a key derived from `password` with Argon2id.
+pub fn seal_with_password(plaintext: &[u8], password: &str) -> Result<Vec<u8>, CryptoError> {
+    let params = Params::default();
+    let salt: [u8; SALT_LEN] = rand::thread_rng().gen();
+
+    let mut sealed = Vec::with_capacity(SEALED_HEADER_LEN + NONCE_LEN + plaintext.len() + 16);
+    sealed.extend_from_slice(SEALED_MAGIC);
+    sealed.push(SEALED_V1);
+    sealed.extend_from_slice(&params.m_cost().to_le_bytes());
+    sealed.extend_from_slice(&params.t_cost().to_le_bytes());
+    sealed.extend_from_slice(&params.p_cost().to_le_bytes());
+    sealed.extend_from_slice(&salt);
+
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = password_key(password, &salt, params)?
+        .encrypt(&nonce, Payload { msg: plaintext, aad: &sealed })
+        .expect("Encryption failed");
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+
+    Ok(sealed)
+}
+
+/// Decrypts a file written by `seal_with_password`. A wrong password and a
+/// modified file both fail authentication and are reported the same way.
+pub fn open_with_password(sealed: &[u8], password: &str) -> Result<Vec<u8>, CryptoError> {
+    if sealed.len() < SEALED_HEADER_LEN + NONCE_LEN || &sealed[..4] != SEALED_MAGIC {
+        return Err(CryptoError::InvalidEnvelope("not a password-protected export".to_string()));
+    }
+    if sealed[4] != SEALED_V1 {
+        return Err(CryptoError::InvalidEnvelope(format!("unknown export version {}", sealed[4])));
+    }
+    let read_u32 = |at: usize| u32::from_le_bytes(sealed[at..at + 4].try_into().expect("4 bytes"));
+    let (m_cost, t_cost, p_cost) = (read_u32(5), read_u32(9), read_u32(13));
+    if m_cost > MAX_M_COST_KIB || t_cost > MAX_T_COST || p_cost > MAX_P_COST {
+        return Err(CryptoError::InvalidEnvelope("key derivation parameters are too large".to_string()));
+    }
+    let params = Params::new(m_cost, t_cost, p_cost, Some(32))
+        .map_err(|e| CryptoError::InvalidEnvelope(e.to_string()))?;
+
+    let (header, rest) = sealed.split_at(SEALED_HEADER_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    password_key(password, &header[17..], params)?
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| CryptoError::IncorrectPassword)
+}
+
+/// Overwrites a key file with zeros before unlinking it.
+fn secure_delete(path: &Path) -> io::Result<()> {
+    let len = fs::metadata(path)?.len() as usize;
+    let mut file = fs::OpenOptions::new().write(true).open(path)?;
+    file.write_all(&vec![0u8; len])?;
+    file.sync_all()?;
+    drop(file);
+    fs::remove_file(path)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// A fresh key that never touches the user's real key location. The
+    /// key file is removed again, the key stays in memory.
+    fn temp_crypto() -> Crypto {
+        let key_path = std::env::temp_dir().join(format!("secondbrian-test-{}.key", rand::random::<u64>()));
+        let crypto = Crypto::with_key_path(key_path.clone());
+        fs::remove_file(key_path).unwrap();
+        crypto
+    }
+
     #[test]
     fn test_encrypt_decrypt() {
-        let crypto = Crypto::new();
+        let crypto = temp_crypto();
         let original = "This is a secret message";
         let encrypted = crypto.encrypt(original);
         let decrypted = crypto.decrypt(&encrypted);
         assert_eq!(original, decrypted);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_replacement_key_cannot_decrypt_old_data() {
+        let crypto = temp_crypto();
+        let encrypted = crypto.encrypt("rotate me");
+        let replacement = crypto.generate_replacement();
+        assert!(matches!(replacement.try_decrypt(&encrypted), Err(CryptoError::Aead)));
+    }
+
+    fn encrypt_legacy(crypto: &Crypto, data: &str) -> String {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = crypto.cipher().encrypt(&nonce, data.as_bytes()).unwrap();
+        serde_json::to_string(&EncryptedData {
+            nonce: nonce.to_vec(),
+            ciphertext,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_compact_format_round_trip() {
+        let crypto = temp_crypto();
+        let original = "Compact 🧠 storage";
+        let encrypted = crypto.encrypt(original);
+        assert!(!Crypto::is_legacy_format(&encrypted));
+        assert_eq!(crypto.try_decrypt(&encrypted).unwrap(), original);
+
+        // version + flags + nonce + ciphertext + 16-byte tag, base64 encoded
+        let raw_len = 2 + NONCE_LEN + original.len() + 16;
+        assert_eq!(encrypted.len(), raw_len.div_ceil(3) * 4);
+    }
+
+    #[test]
+    fn test_legacy_json_format_still_decrypts() {
+        let crypto = temp_crypto();
+        let legacy = encrypt_legacy(&crypto, "written by an older version");
+        assert!(Crypto::is_legacy_format(&legacy));
+        assert_eq!(crypto.try_decrypt(&legacy).unwrap(), "written by an older version");
+    }
+
+    #[test]
+    fn test_v1_envelope_still_decrypts() {
+        let crypto = temp_crypto();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = crypto.cipher().encrypt(&nonce, b"uncompressed v1".as_ref()).unwrap();
+        let mut envelope = vec![ENVELOPE_V1];
+        envelope.extend_from_slice(&nonce);
+        envelope.extend_from_slice(&ciphertext);
+        assert_eq!(crypto.try_decrypt(&BASE64.encode(envelope)).unwrap(), "uncompressed v1");
+    }
+
+    #[test]
+    fn test_large_plaintext_is_compressed() {
+        let crypto = temp_crypto();
+        let original = "pasted research notes ".repeat(2500);
+        let encrypted = crypto.encrypt(&original);
+        let envelope = BASE64.decode(&encrypted).unwrap();
+        assert_eq!(envelope[1] & FLAG_ZSTD, FLAG_ZSTD);
+        assert!(envelope.len() < original.len() / 10);
+        assert_eq!(crypto.try_decrypt(&encrypted).unwrap(), original);
+
+        let small = crypto.encrypt("short entry");
+        assert_eq!(BASE64.decode(&small).unwrap()[1] & FLAG_ZSTD, 0);
+    }
+
+    #[test]
+    fn test_unknown_envelope_version_is_rejected() {
+        let crypto = temp_crypto();
+        let mut envelope = BASE64.decode(crypto.encrypt("data")).unwrap();
+        envelope[0] = 99;
+        assert!(matches!(
+            crypto.try_decrypt(&BASE64.encode(envelope)),
+            Err(CryptoError::InvalidEnvelope(_))
+        ));
+    }
+
+    #[test]
+    fn test_looks_encrypted_checks_envelope_shape() {
+        let crypto = temp_crypto();
+        assert!(Crypto::looks_encrypted(&crypto.encrypt("")));
+        assert!(Crypto::looks_encrypted(&crypto.encrypt("a title")));
+        assert!(Crypto::looks_encrypted(&crypto.generate_replacement().encrypt("other key")));
+        assert!(Crypto::looks_encrypted(&encrypt_legacy(&crypto, "legacy")));
+        for plaintext in ["", "a title", "{not json", "Zm9vYmFy", "AQID"] {
+            assert!(!Crypto::looks_encrypted(plaintext), "{:?}", plaintext);
+        }
+    }
+
+    #[test]
+    fn test_keyed_hash_depends_on_key_and_boundaries() {
+        let crypto = temp_crypto();
+        let hash = crypto.keyed_hash(&["ab", "c"]);
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, crypto.keyed_hash(&["ab", "c"]));
+        assert_ne!(hash, crypto.keyed_hash(&["a", "bc"]));
+        assert_ne!(hash, crypto.generate_replacement().keyed_hash(&["ab", "c"]));
+    }
+
+    #[test]
+    fn test_reset_key_replaces_key_file() {
+        let dir = std::env::temp_dir().join(format!("secondbrian-reset-key-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let key_path = dir.join("encryption.key");
+        let crypto = Crypto::with_key_path(key_path.clone());
+        let encrypted = crypto.encrypt("before the reset");
+
+        let reset = Crypto::reset_key(KeyStorageBackend::File, key_path.clone()).unwrap();
+        assert!(matches!(reset.try_decrypt(&encrypted), Err(CryptoError::Aead)));
+        let reloaded = Crypto::with_key_path(key_path);
+        assert_eq!(reloaded.try_decrypt(&reset.encrypt("after")).unwrap(), "after");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_password_sealed_round_trip() {
+        let sealed = seal_with_password(b"shared entry", "correct horse").unwrap();
+        assert_eq!(&sealed[..4], SEALED_MAGIC);
+        assert_eq!(open_with_password(&sealed, "correct horse").unwrap(), b"shared entry");
+        assert!(matches!(
+            open_with_password(&sealed, "wrong"),
+            Err(CryptoError::IncorrectPassword)
+        ));
+
+        // Both the payload and the authenticated header are tamper-evident.
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open_with_password(&tampered, "correct horse").is_err());
+        let mut tampered = sealed.clone();
+        tampered[SEALED_HEADER_LEN - 1] ^= 1;
+        assert!(open_with_password(&tampered, "correct horse").is_err());
+        assert!(matches!(
+            open_with_password(b"plain text", "correct horse"),
+            Err(CryptoError::InvalidEnvelope(_))
+        ));
+    }
+}
@@ -0,0 +1,73 @@
+use std::io::{self, Write};
+
+/// Byte order mark Excel needs to read a CSV file as UTF-8.
+pub const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Column names of the entry metadata export, in order.
+pub const HEADER: [&str; 7] = [
+    "id",
+    "title",
+    "created_at",
+    "updated_at",
+    "tags",
+    "word_count",
+    "relationship_count",
+];
+
+/// Writes one RFC 4180 record, quoting fields that contain separators,
+/// quotes, line breaks or surrounding whitespace. Fields a spreadsheet
+/// would run as a formula get a leading `'` so they open as text.
+pub fn write_record<W: Write, S: AsRef<str>>(out: &mut W, fields: &[S]) -> io::Result<()> {
+    for (index, field) in fields.iter().enumerate() {
+        if index > 0 {
+            out.write_all(b",")?;
+        }
+        write_field(out, field.as_ref())?;
+    }
+    out.write_all(b"\r\n")
+}
+
+fn write_field<W: Write>(out: &mut W, field: &str) -> io::Result<()> {
+    let escaped;
+    let field = if field.starts_with(['=', '+', '-', '@']) {
+        escaped = format!("'{}", field);
+        escaped.as_str()
+    } else {
+        field
+    };
+    let needs_quotes = field.contains([',', '"', '\n', '\r'])
+        || field.starts_with(char::is_whitespace)
+        || field.ends_with(char::is_whitespace);
+    if !needs_quotes {
+        return out.write_all(field.as_bytes());
+    }
+    out.write_all(b"\"")?;
+    out.write_all(field.replace('"', "\"\"").as_bytes())?;
+    out.write_all(b"\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(fields: &[&str]) -> String {
+        let mut out = Vec::new();
+        write_record(&mut out, fields).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_fields_are_quoted_only_when_needed() {
+        assert_eq!(record(&["a", "b c", ""]), "a,b c,\r\n");
+        assert_eq!(record(&["Fish, chips"]), "\"Fish, chips\"\r\n");
+        assert_eq!(record(&["say \"hi\""]), "\"say \"\"hi\"\"\"\r\n");
+        assert_eq!(record(&["two\nlines", " padded"]), "\"two\nlines\",\" padded\"\r\n");
+    }
+
+    #[test]
+    fn test_formula_fields_are_escaped() {
+        assert_eq!(record(&["=1+1", "+44 20", "-x", "@SUM(A1)"]), "'=1+1,'+44 20,'-x,'@SUM(A1)\r\n");
+        assert_eq!(record(&["=HYPERLINK(\"x\", \"y\")"]), "\"'=HYPERLINK(\"\"x\"\", \"\"y\"\")\"\r\n");
+        assert_eq!(record(&["a=b", "2024-03-01"]), "a=b,2024-03-01\r\n");
+    }
+}
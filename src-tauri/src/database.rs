@@ -1,15 +1,173 @@
-use crate::crypto::Crypto;
-use chrono::{DateTime, Utc};
+use crate::crypto::{self, Crypto, CryptoError, KeyStorageBackend};
+use crate::csv_export;
+use crate::day_one;
+use crate::error::{DiaryError, DiaryResult};
+use crate::filter::{self, DiaryFilter, DiarySort, PropertyOp, PropertyPredicate};
+use crate::graph_export::{self, GraphFormat};
+use crate::journal::{self, JournalRecord};
+use crate::normalize;
+use crate::operations::{Batched, Progress};
+use crate::review::{self, Review, ReviewRelationship, REVIEW_TAG};
+use crate::search::{cut_snippet, find_matches, MatchRange};
+use crate::text_diff::{self, LineDiff};
+use crate::wikilinks::{extract_wikilinks, rename_wikilinks};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, NaiveDate, SecondsFormat, Utc};
 use directories::ProjectDirs;
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, Connection, Result as SqliteResult, ToSql};
+use rusqlite::{functions::FunctionFlags, params, Connection, OpenFlags, OptionalExtension, Result as SqliteResult, Transaction, TransactionBehavior};
 use serde::{Deserialize, Serialize};
-use std::{fs, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 use uuid::Uuid;
 
 type DbPool = Pool<SqliteConnectionManager>;
 
+/// A schema migration: plain SQL, or a data migration that needs the vault
+/// key. `run_migrations` stops at the first `Key` migration while the key is
+/// unavailable, so later ones never run on data it hasn't converted yet.
+enum Migration {
+    Sql(&'static str),
+    Key(fn(&Connection, &Crypto) -> SqliteResult<()>),
+}
+
+/// Schema migrations, applied in order on top of the tables created in
+/// `initialize_db`. `PRAGMA user_version` records how many have run.
+const MIGRATIONS: &[Migration] = &[
+    // 1: distinguish relationships created from [[wikilinks]] from manual ones
+    Migration::Sql("ALTER TABLE relationships ADD COLUMN source TEXT NOT NULL DEFAULT 'manual';"),
+    // 2: one relationship per (parent, child, type); keep the oldest duplicate
+    Migration::Sql("DELETE FROM relationships WHERE rowid NOT IN (
+         SELECT MIN(rowid) FROM relationships GROUP BY parent_id, child_id, relationship_type
+     );
+     CREATE UNIQUE INDEX IF NOT EXISTS idx_relationships_unique
+         ON relationships (parent_id, child_id, relationship_type);"),
+    // 3: track when a relationship was last edited
+    Migration::Sql("ALTER TABLE relationships ADD COLUMN updated_at TEXT;
+     UPDATE relationships SET updated_at = created_at;"),
+    // 4: free-form context and a strength for each relationship
+    Migration::Sql("ALTER TABLE relationships ADD COLUMN note TEXT;
+     ALTER TABLE relationships ADD COLUMN weight REAL NOT NULL DEFAULT 1.0;"),
+    // 5: plaintext word count, NULL until computed for pre-existing entries
+    Migration::Sql("ALTER TABLE diary_entries ADD COLUMN word_count INTEGER;"),
+    // 6: when an entry was last opened, for the recent list
+    Migration::Sql("ALTER TABLE diary_entries ADD COLUMN last_viewed_at TEXT;
+     CREATE INDEX IF NOT EXISTS idx_diary_entries_updated_at ON diary_entries (updated_at);
+     CREATE INDEX IF NOT EXISTS idx_diary_entries_last_viewed_at ON diary_entries (last_viewed_at);"),
+    // 7: one daily note per calendar date
+    Migration::Sql("ALTER TABLE diary_entries ADD COLUMN daily_date TEXT;
+     CREATE UNIQUE INDEX IF NOT EXISTS idx_diary_entries_daily_date ON diary_entries (daily_date);"),
+    // 8: pinned entries and their manual order
+    Migration::Sql("ALTER TABLE diary_entries ADD COLUMN pinned_at TEXT;
+     ALTER TABLE diary_entries ADD COLUMN sort_order INTEGER;"),
+    // 9: keyed hash of title, creation time and content for duplicate
+    // detection, NULL until computed for pre-existing entries
+    Migration::Sql("ALTER TABLE diary_entries ADD COLUMN content_hash TEXT;
+     CREATE INDEX IF NOT EXISTS idx_diary_entries_content_hash ON diary_entries (content_hash);"),
+    // 10: archived entries, kept but left out of exports by default
+    Migration::Sql("ALTER TABLE diary_entries ADD COLUMN archived_at TEXT;"),
+    // 11: case- and accent-folded tag names for matching, NULL until
+    // backfilled for pre-existing tags
+    Migration::Sql("ALTER TABLE tags ADD COLUMN name_key TEXT;
+     CREATE INDEX IF NOT EXISTS idx_tags_name_key ON tags (name_key);"),
+    // 12: how often an entry has been opened
+    Migration::Sql("ALTER TABLE diary_entries ADD COLUMN view_count INTEGER NOT NULL DEFAULT 0;"),
+    // 13: private entries whose content needs the passphrase
+    Migration::Sql("ALTER TABLE diary_entries ADD COLUMN locked INTEGER NOT NULL DEFAULT 0;"),
+    // 14: encrypt titles written before titles were encrypted
+    Migration::Key(DiaryDB::encrypt_plaintext_titles),
+    // 15: encrypt attachment file names and types
+    Migration::Key(DiaryDB::encrypt_attachment_names),
+];
+
+/// Columns added by `MIGRATIONS` and the value rows had before each was
+/// added. A read-only vault on an older schema reads them through temp
+/// views that fill these in, so readers can select every column.
+const MIGRATED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("relationships", "source", "'manual'"),
+    ("relationships", "updated_at", "created_at"),
+    ("relationships", "note", "NULL"),
+    ("relationships", "weight", "1.0"),
+    ("diary_entries", "word_count", "NULL"),
+    ("diary_entries", "last_viewed_at", "NULL"),
+    ("diary_entries", "daily_date", "NULL"),
+    ("diary_entries", "pinned_at", "NULL"),
+    ("diary_entries", "sort_order", "NULL"),
+    ("diary_entries", "content_hash", "NULL"),
+    ("diary_entries", "archived_at", "NULL"),
+    ("tags", "name_key", "fold(name)"),
+    ("diary_entries", "view_count", "0"),
+    ("diary_entries", "locked", "0"),
+];
+
+/// Tables `initialize_db` created after the first release, with their
+/// columns. A read-only vault from before them reads empty temp tables
+/// in their place. New tables belong here as well.
+const ADDED_TABLES: &[(&str, &str)] = &[
+    ("attachments", "id, diary_id, filename, mime_type, size, created_at, data"),
+    ("templates", "id, name, content, default_tags, created_at, updated_at"),
+    ("diary_properties", "diary_id, key, value"),
+    ("drafts", "diary_id, title, content, tags, updated_at"),
+    ("reminders", "id, diary_id, remind_at, message, completed"),
+    ("diary_versions", "id, diary_id, version, title, content, saved_at"),
+    ("saved_searches", "id, name, query, created_at"),
+    ("settings", "key, value"),
+    ("audit_log", "id, operation, detail, created_at"),
+    ("audit_log_entries", "audit_id, diary_id"),
+    ("diary_views", "diary_id, viewed_at"),
+];
+
+/// `settings` key of the passphrase check value.
+const PASSPHRASE_SETTING: &str = "passphrase_check";
+/// Plaintext sealed with the passphrase; opening it proves the passphrase.
+const PASSPHRASE_CHECK: &[u8] = b"secondbrain passphrase check";
+/// How long `verify_password` unlocks private entries for.
+const ELEVATION_DURATION: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Relationship type used for links parsed out of entry content.
+const WIKILINK_RELATIONSHIP_TYPE: &str = "links_to";
+const SOURCE_MANUAL: &str = "manual";
+const SOURCE_WIKILINK: &str = "wikilink";
+
+/// Tag marking the entry `quick_capture` appends to, and its title when
+/// it has to be created.
+const INBOX_TAG: &str = "inbox";
+const INBOX_TITLE: &str = "Inbox";
+
+/// Text `reset_vault` requires before it destroys anything.
+pub const RESET_VAULT_CONFIRMATION: &str = "DELETE EVERYTHING";
+
+/// Tag carried by daily notes, whose titles are their `YYYY-MM-DD` date.
+const DAILY_NOTE_TAG: &str = "daily";
+const DAILY_NOTE_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Settings key turning `verify_vault` on at startup.
+const VERIFY_ON_STARTUP_SETTING: &str = "verify_on_startup";
+
+/// What `verify_vault` decrypts: table, row id expression, encrypted
+/// text columns and encrypted byte columns. Kept in step with
+/// `rotate_encryption_key`.
+const ENCRYPTED_COLUMNS: [(&str, &str, &[&str], &[&str]); 8] = [
+    ("diary_entries", "id", &["title", "content"], &[]),
+    ("attachments", "id", &["filename", "mime_type"], &["data"]),
+    ("templates", "id", &["name", "content"], &[]),
+    ("drafts", "diary_id", &["title", "content"], &[]),
+    ("diary_properties", "CAST(rowid AS TEXT)", &["value"], &[]),
+    ("reminders", "id", &["message"], &[]),
+    ("diary_versions", "id", &["title", "content"], &[]),
+    ("saved_searches", "id", &["name", "query"], &[]),
+];
+
+const DEFAULT_RELATIONSHIP_WEIGHT: f64 = 1.0;
+const MAX_RELATIONSHIP_WEIGHT: f64 = 10.0;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DiaryEntry {
     pub id: String,
@@ -18,8 +176,285 @@ pub struct DiaryEntry {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub tags: Vec<String>,
+    pub word_count: i64,
+    pub reading_time_minutes: i64,
+    /// Custom properties keyed by name, values are arbitrary JSON.
+    pub properties: serde_json::Value,
+    /// Private entry; `content` is empty in listings until the session is
+    /// elevated with `verify_password`.
+    pub locked: bool,
+}
+
+/// Listing shape for an entry, without its content.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiarySummary {
+    pub id: String,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_viewed_at: Option<DateTime<Utc>>,
+    pub tags: Vec<String>,
+    /// `None` until the word count backfill has reached this entry.
+    pub word_count: Option<i64>,
+    pub reading_time_minutes: Option<i64>,
+}
+
+/// A `query_diaries` match with its content, for listings that return
+/// whole entries.
+struct ListedDiary {
+    summary: DiarySummary,
+    content: String,
+    locked: bool,
+}
+
+/// An entry with how often it was opened, overall or since a given time.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ViewedDiary {
+    #[serde(flatten)]
+    pub summary: DiarySummary,
+    pub view_count: i64,
+}
+
+/// Autosaved, not yet saved state of an entry. `diary_id` is `None` for
+/// an entry that has never been saved.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Draft {
+    pub diary_id: Option<String>,
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
+    pub updated_at: String,
+}
+
+/// An earlier state of an entry, kept when `save_diary` replaced it.
+/// Versions count up from 1 per entry.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiaryVersion {
+    pub version: i64,
+    pub title: String,
+    /// When this state was replaced by a newer save.
+    pub saved_at: DateTime<Utc>,
+}
+
+/// Content diff between two states of an entry, plus both titles.
+#[derive(Debug, Serialize)]
+pub struct VersionDiff {
+    pub from_title: String,
+    pub to_title: String,
+    #[serde(flatten)]
+    pub diff: LineDiff,
+}
+
+/// A reminder to come back to an entry at `remind_at`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reminder {
+    pub id: String,
+    pub diary_id: String,
+    pub remind_at: DateTime<Utc>,
+    pub message: String,
+    pub completed: bool,
+}
+
+/// A due reminder together with the title of its entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DueReminder {
+    #[serde(flatten)]
+    pub reminder: Reminder,
+    pub diary_title: String,
+}
+
+/// `drafts.diary_id` of the draft for a new, unsaved entry.
+const NEW_ENTRY_DRAFT_KEY: &str = "";
+
+/// Version selectors for the live entry and its autosaved draft.
+const CURRENT_VERSION: &str = "current";
+const DRAFT_VERSION: &str = "draft";
+
+/// Earlier states kept per entry; the oldest are dropped past this.
+const MAX_VERSIONS_PER_ENTRY: i64 = 50;
+
+/// Entries checked or saved per step by `DayOneImporter`, and rows per step
+/// of `CsvExporter` and `JournalExporter`.
+const IMPORT_BATCH_SIZE: usize = 100;
+const EXPORT_PROGRESS_BATCH: usize = 100;
+
+/// Entries saved per step by `JournalImporter`.
+const JOURNAL_IMPORT_BATCH: usize = 200;
+
+/// One entry for `save_diaries_batch`. Timestamps default to now.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewDiary {
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Outcome of a journal import. In a dry run nothing is written and the
+/// imported entries have no id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub imported: Vec<ImportedEntry>,
+    /// Entries already in the vault, or repeated in the file, left alone.
+    pub skipped: usize,
+    /// Entries already in the vault whose tags and properties were merged.
+    pub updated: usize,
+    pub errors: Vec<ImportError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedEntry {
+    /// Position of the entry in the source file.
+    pub index: usize,
+    pub id: Option<String>,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub tags: Vec<String>,
+    pub property_keys: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportError {
+    pub index: usize,
+    pub message: String,
+}
+
+/// `settings` key of the audit log retention, in days.
+const AUDIT_RETENTION_SETTING: &str = "audit_retention_days";
+/// `settings` key of the view history retention, in days.
+const VIEW_HISTORY_RETENTION_SETTING: &str = "view_history_retention_days";
+
+/// Kinds of change recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    Save,
+    Delete,
+    Archive,
+    /// An archived entry brought back.
+    Restore,
+    RelationshipAdd,
+    RelationshipRemove,
+    /// A relationship's type, ends, note or weight changed.
+    RelationshipUpdate,
+    TagAdd,
+    TagRemove,
+    PropertySet,
+    PropertyDelete,
+    AttachmentAdd,
+    AttachmentDelete,
+    Lock,
+    Unlock,
+    Import,
+    Export,
+    KeyRotation,
+}
+
+impl AuditOperation {
+    const ALL: [AuditOperation; 18] = [
+        AuditOperation::Save,
+        AuditOperation::Delete,
+        AuditOperation::Archive,
+        AuditOperation::Restore,
+        AuditOperation::RelationshipAdd,
+        AuditOperation::RelationshipRemove,
+        AuditOperation::RelationshipUpdate,
+        AuditOperation::TagAdd,
+        AuditOperation::TagRemove,
+        AuditOperation::PropertySet,
+        AuditOperation::PropertyDelete,
+        AuditOperation::AttachmentAdd,
+        AuditOperation::AttachmentDelete,
+        AuditOperation::Lock,
+        AuditOperation::Unlock,
+        AuditOperation::Import,
+        AuditOperation::Export,
+        AuditOperation::KeyRotation,
+    ];
+    
+    fn as_str(self) -> &'static str {
+        match self {
+            AuditOperation::Save => "save",
+            AuditOperation::Delete => "delete",
+            AuditOperation::Archive => "archive",
+            AuditOperation::Restore => "restore",
+            AuditOperation::RelationshipAdd => "relationship_add",
+            AuditOperation::RelationshipRemove => "relationship_remove",
+            AuditOperation::RelationshipUpdate => "relationship_update",
+            AuditOperation::TagAdd => "tag_add",
+            AuditOperation::TagRemove => "tag_remove",
+            AuditOperation::PropertySet => "property_set",
+            AuditOperation::PropertyDelete => "property_delete",
+            AuditOperation::AttachmentAdd => "attachment_add",
+            AuditOperation::AttachmentDelete => "attachment_delete",
+            AuditOperation::Lock => "lock",
+            AuditOperation::Unlock => "unlock",
+            AuditOperation::Import => "import",
+            AuditOperation::Export => "export",
+            AuditOperation::KeyRotation => "key_rotation",
+        }
+    }
+    
+    fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|operation| operation.as_str() == value)
+    }
+}
+
+/// One audit log record. Only ids, counts and types are kept, never
+/// titles or content, as the log is stored unencrypted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub id: i64,
+    pub operation: AuditOperation,
+    pub entry_ids: Vec<String>,
+    pub detail: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Outcome of a `JournalExporter`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalExport {
+    pub exported: usize,
+    /// Locked entries left out because the session wasn't elevated.
+    pub skipped_locked: usize,
+}
+
+/// Outcome of a `JournalImporter`. Only counts are kept, so importing a
+/// large journal doesn't collect every entry in memory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JournalImport {
+    pub imported: usize,
+    /// Entries already in the vault, or repeated in the file.
+    pub skipped: usize,
+    /// Manual relationships added between the entries.
+    pub relationships: usize,
+    pub errors: Vec<ImportError>,
 }
 
+/// Payload of a password-protected single-entry export.
+#[derive(Debug, Serialize, Deserialize)]
+struct SharedEntry {
+    title: String,
+    content: String,
+    tags: Vec<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+/// A full-text search hit with a snippet around the first match.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub entry: DiarySummary,
+    pub snippet: String,
+    /// Matched ranges within `snippet`, in bytes.
+    pub matches: Vec<MatchRange>,
+}
+
+const SNIPPET_CHARS: usize = 160;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Tag {
     pub id: String,
@@ -34,20 +469,398 @@ pub struct GraphNode {
     pub properties: serde_json::Value,
 }
 
+/// Relationship edges point from parent to child, as named by
+/// `add_relationship`, unless inverse labels were requested. Tag edges
+/// point from the diary to the tag, and tag co-occurrence edges between
+/// tag names in alphabetical order.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GraphEdge {
     pub id: String,
     pub source: String,
     pub target: String,
     pub label: String,
+    /// Relationship strength; tag edges always have the default weight and
+    /// co-occurrence edges the number of entries sharing both tags.
+    pub weight: f64,
+    pub edge_type: GraphEdgeType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphEdgeType {
+    Relationship,
+    Tag,
+    TagCooccurrence,
+}
+
+/// Two tags and the number of entries carrying both. `tag_a` sorts
+/// before `tag_b`, so each pair appears once.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagCooccurrence {
+    pub tag_a: String,
+    pub tag_b: String,
+    pub count: i64,
 }
 
+/// Version of the `GraphData` wire shape, bumped on breaking changes.
+/// 2: relationship edges point parent to child and carry `edge_type`.
+pub const GRAPH_SCHEMA_VERSION: u32 = 2;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GraphData {
+    pub version: u32,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RelationshipTypeCount {
+    pub relationship_type: String,
+    pub count: i64,
+}
+
+/// Journal-wide numbers for the statistics dashboard. Days and months are
+/// bucketed in local time.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Statistics {
+    pub total_entries: i64,
+    /// The last 12 months, oldest first, including months without entries.
+    pub entries_per_month: Vec<MonthlyCount>,
+    pub total_words: i64,
+    pub average_words: f64,
+    pub top_tags: Vec<TagUsage>,
+    pub longest_streak_days: i64,
+    /// Consecutive days with an entry, ending today (or yesterday if
+    /// nothing has been written yet today).
+    pub current_streak_days: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MonthlyCount {
+    /// `YYYY-MM`
+    pub month: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagUsage {
+    pub name: String,
+    pub count: i64,
+}
+
+/// Outcome of `run_maintenance`. Problems are reported, never repaired.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MaintenanceReport {
+    pub passed: bool,
+    /// Messages from `PRAGMA integrity_check`; empty when it reported "ok".
+    pub integrity_errors: Vec<String>,
+    pub foreign_key_violations: Vec<ForeignKeyViolation>,
+    pub bytes_reclaimed: u64,
+    pub duration_ms: u64,
+}
+
+/// Outcome of `verify_vault`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultReport {
+    pub passed: bool,
+    /// Encrypted records checked, across all tables.
+    pub checked: usize,
+    pub failures: Vec<DecryptFailure>,
+    pub duration_ms: u64,
+}
+
+/// A record that didn't decrypt with the current key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecryptFailure {
+    pub table: String,
+    pub id: String,
+    pub column: String,
+    pub kind: DecryptFailureKind,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecryptFailureKind {
+    /// Not a well-formed encrypted envelope, e.g. bad JSON or base64.
+    InvalidEnvelope,
+    /// The authentication tag didn't match: wrong key or altered data.
+    Aead,
+    InvalidUtf8,
+    Decompression,
+    Other,
+}
+
+impl From<&CryptoError> for DecryptFailureKind {
+    fn from(e: &CryptoError) -> Self {
+        match e {
+            CryptoError::InvalidEnvelope(_) => DecryptFailureKind::InvalidEnvelope,
+            CryptoError::Aead => DecryptFailureKind::Aead,
+            CryptoError::InvalidUtf8 => DecryptFailureKind::InvalidUtf8,
+            CryptoError::Decompression(_) => DecryptFailureKind::Decompression,
+            _ => DecryptFailureKind::Other,
+        }
+    }
+}
+
+/// A row whose foreign key points at a missing parent row.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    pub rowid: Option<i64>,
+    pub parent: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatabaseInfo {
+    pub path: String,
+    pub file_size_bytes: u64,
+    pub entry_count: i64,
+    pub tag_count: i64,
+    pub relationship_count: i64,
+    pub schema_version: i64,
+}
+
+/// Result of one `backfill_word_counts` batch.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackfillProgress {
+    pub processed: usize,
+    pub remaining: usize,
+}
+
+pub const WORD_COUNT_BACKFILL_BATCH: usize = 100;
+const WORDS_PER_MINUTE: i64 = 200;
+
+/// One calendar day in the activity heatmap.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DayActivity {
+    /// `YYYY-MM-DD`
+    pub date: String,
+    pub entry_count: i64,
+    pub total_word_count: i64,
+}
+
+/// Longest range `get_activity_heatmap` will fill day by day.
+const MAX_HEATMAP_DAYS: i64 = 3660;
+
+const STATISTICS_TOP_TAGS: i64 = 10;
+const STATISTICS_MONTHS: u32 = 12;
+
+/// Outcome for one id in a bulk operation.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkItemResult {
+    pub id: String,
+    pub status: BulkStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkStatus {
+    Succeeded,
+    NotFound,
+}
+
+/// An entry pointing at another one through a relationship.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backlink {
+    pub relationship_id: String,
+    pub diary_id: String,
+    pub title: String,
+    pub relationship_type: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A suggested entry with the signals that made it relevant.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RelatedEntry {
+    pub diary_id: String,
+    pub title: String,
+    pub score: i64,
+    pub reasons: Vec<RelatedReason>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RelatedReason {
+    SharedTag { tag: String },
+    DirectLink { relationship_type: String },
+    SharedNeighbor { diary_id: String },
+}
+
+// Weights for related-entry scoring.
+const SHARED_TAG_SCORE: i64 = 2;
+const DIRECT_LINK_SCORE: i64 = 3;
+const SHARED_NEIGHBOR_SCORE: i64 = 1;
+
+/// Reusable starting point for new entries. `{{date}}`, `{{time}}` and
+/// `{{title}}` in the content are expanded on use.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Template {
+    pub id: String,
+    pub name: String,
+    pub content: String,
+    pub default_tags: Vec<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Revision of `SearchQuery` this build writes. Bump it when a change
+/// alters what an existing stored query means, and upgrade older queries
+/// in `SearchQuery::from_stored`.
+pub const SEARCH_QUERY_VERSION: u32 = 1;
+
+/// How the tags of a `SearchQuery` combine.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagMatch {
+    /// Entries carrying every tag.
+    #[default]
+    All,
+    /// Entries carrying at least one of the tags.
+    Any,
+}
+
+/// Filters stored by a saved search. Missing fields take their defaults,
+/// so queries saved before a field existed keep loading.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchQuery {
+    pub version: u32,
+    /// Tags to filter on, ignoring case and accents.
+    pub tags: Vec<String>,
+    pub tag_match: TagMatch,
+    /// Whitespace-separated terms that must all appear in the title or
+    /// content, as in `search_diaries`.
+    pub text: String,
+    /// Inclusive bounds on `created_at`.
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+    /// Only entries created within this many days of running the search.
+    pub within_days: Option<u32>,
+    pub include_archived: bool,
+}
+
+impl Default for SearchQuery {
+    fn default() -> Self {
+        Self {
+            version: SEARCH_QUERY_VERSION,
+            tags: Vec::new(),
+            tag_match: TagMatch::All,
+            text: String::new(),
+            created_from: None,
+            created_to: None,
+            within_days: None,
+            include_archived: false,
+        }
+    }
+}
+
+impl SearchQuery {
+    /// Parses a stored query, refusing ones written by a newer version
+    /// whose filters this build would silently ignore.
+    fn from_stored(json: &str) -> DiaryResult<Self> {
+        let query: SearchQuery = serde_json::from_str(json)
+            .map_err(|e| DiaryError::Validation(format!("Invalid saved search: {}", e)))?;
+        if query.version > SEARCH_QUERY_VERSION {
+            return Err(DiaryError::Validation(format!(
+                "Saved search needs query version {}, this version supports {}",
+                query.version, SEARCH_QUERY_VERSION
+            )));
+        }
+        Ok(query)
+    }
+}
+
+/// A named `SearchQuery`, re-run on demand.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub id: String,
+    pub name: String,
+    pub query: SearchQuery,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: String,
+    pub diary_id: String,
+    pub filename: String,
+    pub mime_type: String,
+    pub size: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttachmentData {
+    #[serde(flatten)]
+    pub attachment: Attachment,
+    /// Decrypted file bytes, base64-encoded for the frontend.
+    pub data: String,
+}
+
+const MAX_ATTACHMENT_SIZE: u64 = 50 * 1024 * 1024;
+
+/// Optional filters for `get_graph_data`. Categories combine with AND; the
+/// default filter returns the whole graph.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GraphFilter {
+    /// When false, tag nodes and tag edges are dropped.
+    pub include_tags: bool,
+    /// Only diaries carrying at least one of these tags (ignoring case and accents).
+    pub tag_filter: Vec<String>,
+    /// Only relationship edges of these types.
+    pub relationship_types: Vec<String>,
+    /// Inclusive bounds on diary `created_at`.
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+    /// Emit relationship edges child to parent with inverse labels
+    /// (`parent_of` becomes `child_of`).
+    pub inverse_labels: bool,
+    /// Add tag-to-tag edges for tags sharing at least this many entries.
+    /// Ignored when tags are excluded.
+    pub tag_cooccurrence: Option<u32>,
+}
+
+impl Default for GraphFilter {
+    fn default() -> Self {
+        Self {
+            include_tags: true,
+            tag_filter: Vec::new(),
+            relationship_types: Vec::new(),
+            created_from: None,
+            created_to: None,
+            inverse_labels: false,
+            tag_cooccurrence: None,
+        }
+    }
+}
+
+/// A bounded neighborhood of the graph around one entry.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Subgraph {
+    #[serde(flatten)]
+    pub graph: GraphData,
+    /// Set when expansion stopped at `MAX_SUBGRAPH_NODES`.
+    pub truncated: bool,
+}
+
+const MAX_SUBGRAPH_NODES: usize = 500;
+
+/// A shortest path between two entries, nodes and edges in walking order.
+/// Empty with `connected: false` when no path exists within the hop limit.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GraphPath {
     pub nodes: Vec<GraphNode>,
     pub edges: Vec<GraphEdge>,
+    pub connected: bool,
 }
 
+const MAX_PATH_HOPS: usize = 10;
+
+/// Traversal handle for a graph node: `(is_tag, id)`.
+type GraphNodeRef = (bool, String);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Relationship {
     pub id: String,
@@ -55,56 +868,611 @@ pub struct Relationship {
     pub child_id: String,
     pub relationship_type: String,
     pub created_at: String,
+    /// "manual" or "wikilink" for links synced from entry content.
+    pub source: String,
+    pub updated_at: String,
+    pub note: Option<String>,
+    pub weight: f64,
+}
+
+/// A relationship as seen from one of its entries, with the id and title
+/// of the entry on the other side.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LinkedRelationship {
+    #[serde(flatten)]
+    pub relationship: Relationship,
+    pub other_id: String,
+    pub other_title: String,
+    /// Whether the queried entry is the parent of this relationship.
+    pub is_parent: bool,
+}
+
+/// An entry id and its decrypted title.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TitleMatch {
+    pub id: String,
+    pub title: String,
+}
+
+/// Outcome of `save_diary_from_editor`.
+#[derive(Debug, Clone)]
+pub struct SavedDiary {
+    pub id: String,
+    /// The entry's tags differ from before the save.
+    pub tags_changed: bool,
+    /// Relationships from `[[wikilinks]]` were added or removed.
+    pub links_changed: bool,
+}
+
+/// Result of syncing an entry's wikilink relationships.
+struct WikilinkSync {
+    /// Link targets that don't match any entry title.
+    unresolved: Vec<String>,
+    /// Whether any relationship was added or removed.
+    changed: bool,
 }
 
 pub struct DiaryDB {
     pool: DbPool,
-    crypto: Arc<Crypto>,
+    crypto: Option<Arc<Crypto>>,
+    locked_key: Option<(KeyStorageBackend, PathBuf)>,
+    /// Decrypted titles with their `normalize::fold` keys, most recently
+    /// updated first. Titles are encrypted at rest, so their folded form
+    /// only lives here. Built on first use and dropped whenever an entry is
+    /// written or the vault locks.
+    title_cache: Mutex<Option<Vec<(String, TitleMatch)>>>,
+    /// Set by `open_read_only`; every write is refused.
+    read_only: bool,
+    /// When the passphrase was last verified; locked entries can be read
+    /// for `ELEVATION_DURATION` after.
+    elevated_at: Mutex<Option<Instant>>,
 }
 
 impl DiaryDB {
     pub fn new() -> Self {
-        let db_path = Self::get_db_path();
+        // A denied keychain prompt must not take the app down; commands that
+        // need the key report it until `reload_key` succeeds.
+        let crypto = match Crypto::new() {
+            Ok(crypto) => Some(crypto),
+            Err(e) => {
+                println!("⚠️ [CRYPTO] Encryption key unavailable: {}", e);
+                None
+            }
+        };
+        Self::open_with(&Self::get_db_path(), crypto)
+    }
+    
+    pub fn open(db_path: &str, crypto: Crypto) -> Self {
+        Self::open_with(db_path, Some(crypto))
+    }
+    
+    fn open_with(db_path: &str, crypto: Option<Crypto>) -> Self {
         let manager = SqliteConnectionManager::file(db_path).with_init(|conn| {
             conn.execute_batch("PRAGMA foreign_keys = ON;")
         });
         let pool = Pool::new(manager).expect("Failed to create database pool");
         
-        let crypto = Arc::new(Crypto::new());
+        let crypto = crypto.map(Arc::new);
         
-        let db = Self {
+        let mut db = Self {
             pool,
             crypto,
+            locked_key: None,
+            title_cache: Mutex::new(None),
+            read_only: false,
+            elevated_at: Mutex::new(None),
         };
         
         db.initialize_db().expect("Failed to initialize database");
+        db.recover_interrupted_rotation()
+            .expect("Failed to recover from interrupted key rotation");
+        db.run_key_migrations().expect("Failed to migrate encrypted data");
+        if let Err(e) = db.prune_audit_log() {
+            println!("⚠️ [AUDIT] Could not prune the audit log: {}", e);
+        }
+        if let Err(e) = db.prune_view_history() {
+            println!("⚠️ [VIEW] Could not prune the view history: {}", e);
+        }
         db
     }
     
-    fn get_db_path() -> String {
-        let proj_dirs = ProjectDirs::from("com", "secondbrian", "diary")
-            .expect("Failed to get project directories");
-        let data_dir = proj_dirs.data_dir();
-        fs::create_dir_all(data_dir).expect("Failed to create data directory");
-        data_dir.join("diary.db").to_str().unwrap().to_string()
+    /// Opens a vault file, such as a backup copy, without ever writing to
+    /// it. Schema and key migrations are skipped and every mutating method
+    /// fails with `DiaryError::ReadOnly`.
+    pub fn open_read_only(db_path: &str) -> DiaryResult<Self> {
+        let crypto = match Crypto::new() {
+            Ok(crypto) => Some(crypto),
+            Err(e) => {
+                println!("⚠️ [CRYPTO] Encryption key unavailable: {}", e);
+                None
+            }
+        };
+        Self::open_read_only_with(db_path, crypto)
     }
     
-    pub fn initialize_db(&self) -> SqliteResult<()> {
-        let conn = self.pool.get().expect("Failed to get database connection");
+    fn open_read_only_with(db_path: &str, crypto: Option<Crypto>) -> DiaryResult<Self> {
+        // SQLite would otherwise create an empty database, even read-only.
+        if !Path::new(db_path).is_file() {
+            return Err(DiaryError::NotFound(format!("Vault file {}", db_path)));
+        }
+        let manager = SqliteConnectionManager::file(db_path)
+            .with_flags(OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+            .with_init(|conn| {
+                Self::register_fold(conn)?;
+                Self::fill_missing_schema(conn)?;
+                conn.execute_batch("PRAGMA query_only = ON;")
+            });
+        let pool = Pool::new(manager)
+            .map_err(|e| DiaryError::Validation(format!("Could not open {} read-only: {}", db_path, e)))?;
         
-        // Enable foreign key constraints
-        conn.execute("PRAGMA foreign_keys = ON", [])?;
+        let db = Self {
+            pool,
+            crypto: crypto.map(Arc::new),
+            locked_key: None,
+            title_cache: Mutex::new(None),
+            read_only: true,
+            elevated_at: Mutex::new(None),
+        };
         
-        // Create diary entries table
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS diary_entries (
-                id TEXT PRIMARY KEY,
-                title TEXT NOT NULL,
-                content TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
-            )",
-            [],
+        let conn = db.pool.get().expect("Failed to get database connection");
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if (version as usize) < MIGRATIONS.len() {
+            println!(
+                "⚠️ [READ_ONLY] Vault schema is at version {} of {}; migrations are skipped and newer features may fail",
+                version,
+                MIGRATIONS.len()
+            );
+        }
+        drop(conn);
+        
+        println!("🔒 [READ_ONLY] Opened {} read-only", db_path);
+        Ok(db)
+    }
+    
+    /// Makes `normalize::fold` callable from SQL as `fold(text)`, for
+    /// default values in `MIGRATED_COLUMNS`.
+    fn register_fold(conn: &Connection) -> SqliteResult<()> {
+        conn.create_scalar_function(
+            "fold",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |ctx| Ok(normalize::fold(&ctx.get::<String>(0)?)),
+        )
+    }
+    
+    /// Brings an older schema up to date for reading: each table missing
+    /// some of `MIGRATED_COLUMNS` is shadowed by a temp view adding them
+    /// with their defaults, and each of `ADDED_TABLES` that doesn't exist
+    /// gets an empty temp table. Temp objects live outside the vault file,
+    /// so this works on a read-only connection.
+    fn fill_missing_schema(conn: &Connection) -> SqliteResult<()> {
+        let table_exists = |table: &str| -> SqliteResult<bool> {
+            conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM main.sqlite_master WHERE type = 'table' AND name = ?1)",
+                params![table],
+                |row| row.get(0),
+            )
+        };
+        for (table, columns) in ADDED_TABLES {
+            if !table_exists(table)? {
+                conn.execute_batch(&format!("CREATE TEMP TABLE IF NOT EXISTS {} ({});", table, columns))?;
+            }
+        }
+
+        let mut tables: Vec<&str> = MIGRATED_COLUMNS.iter().map(|(table, _, _)| *table).collect();
+        tables.sort_unstable();
+        tables.dedup();
+        for table in tables {
+            let existing: Vec<String> = {
+                let mut stmt = conn.prepare(&format!("PRAGMA main.table_info({})", table))?;
+                let column_iter = stmt.query_map([], |row| row.get(1))?;
+                column_iter.collect::<SqliteResult<_>>()?
+            };
+            if existing.is_empty() {
+                continue;
+            }
+            let missing: Vec<String> = MIGRATED_COLUMNS
+                .iter()
+                .filter(|(column_table, column, _)| *column_table == table && !existing.iter().any(|name| name == column))
+                .map(|(_, column, default)| format!("{} AS {}", default, column))
+                .collect();
+            if !missing.is_empty() {
+                conn.execute_batch(&format!(
+                    "CREATE TEMP VIEW IF NOT EXISTS {table} AS SELECT *, {} FROM main.{table};",
+                    missing.join(", ")
+                ))?;
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    
+    fn ensure_writable(&self) -> DiaryResult<()> {
+        if self.read_only {
+            return Err(DiaryError::ReadOnly);
+        }
+        Ok(())
+    }
+    
+    fn crypto(&self) -> DiaryResult<&Arc<Crypto>> {
+        match &self.crypto {
+            Some(crypto) => Ok(crypto),
+            None if self.is_locked() => Err(DiaryError::Locked),
+            None => Err(DiaryError::KeyUnavailable),
+        }
+    }
+    
+    /// Drops the in-memory key; `Secret` zeroizes it once the last handle goes.
+    /// Needs a passphrase, since `unlock` asks for it.
+    pub fn lock(&mut self) -> DiaryResult<()> {
+        if !self.has_passphrase()? {
+            return Err(DiaryError::Validation("Set a passphrase before locking the vault".to_string()));
+        }
+        self.invalidate_titles();
+        *self.elevated_at.lock().unwrap() = None;
+        if let Some(crypto) = self.crypto.take() {
+            self.locked_key = Some((crypto.backend(), crypto.key_path().to_path_buf()));
+        }
+        Ok(())
+    }
+    
+    /// Reloads the key once `passphrase` matches the passphrase check.
+    pub fn unlock(&mut self, passphrase: &str) -> DiaryResult<()> {
+        let (backend, key_path) = match &self.locked_key {
+            Some(locked_key) => locked_key.clone(),
+            None => return Ok(()),
+        };
+        let check = self.passphrase_check()?.ok_or_else(|| {
+            DiaryError::Validation("No passphrase is set; set one first".to_string())
+        })?;
+        crypto::open_with_password(&check, passphrase)?;
+        self.crypto = Some(Arc::new(Crypto::reopen(backend, key_path)?));
+        self.locked_key = None;
+        Ok(())
+    }
+    
+    pub fn is_locked(&self) -> bool {
+        self.locked_key.is_some()
+    }
+    
+    /// Appends an audit record, on the caller's transaction so it commits
+    /// or rolls back with the change it describes.
+    fn audit(conn: &Connection, operation: AuditOperation, entry_ids: &[&str], detail: &str) -> SqliteResult<()> {
+        conn.execute(
+            "INSERT INTO audit_log (operation, detail, created_at) VALUES (?1, ?2, ?3)",
+            params![operation.as_str(), detail, Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true)],
+        )?;
+        let audit_id = conn.last_insert_rowid();
+        let mut insert = conn.prepare_cached("INSERT INTO audit_log_entries (audit_id, diary_id) VALUES (?1, ?2)")?;
+        for diary_id in entry_ids {
+            insert.execute(params![audit_id, diary_id])?;
+        }
+        Ok(())
+    }
+    
+    /// Audits an operation that doesn't write anything else, such as an
+    /// export. The operation already happened, so a failure to record it
+    /// is only logged; read-only vaults record nothing.
+    fn record_audit(&self, operation: AuditOperation, entry_ids: &[&str], detail: &str) {
+        if self.read_only {
+            return;
+        }
+        let conn = self.pool.get().expect("Failed to get database connection");
+        if let Err(e) = Self::audit(&conn, operation, entry_ids, detail) {
+            println!("⚠️ [AUDIT] Could not record {}: {}", operation.as_str(), e);
+        }
+    }
+    
+    /// Audit records, newest first, optionally only those touching `diary_id`.
+    pub fn get_audit_log(&self, limit: usize, offset: usize, diary_id: Option<&str>) -> DiaryResult<Vec<AuditRecord>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let mut stmt = conn.prepare(
+            "SELECT a.id, a.operation, a.detail, a.created_at
+             FROM audit_log a
+             WHERE ?1 IS NULL
+                OR EXISTS (SELECT 1 FROM audit_log_entries ae WHERE ae.audit_id = a.id AND ae.diary_id = ?1)
+             ORDER BY a.id DESC
+             LIMIT ?2 OFFSET ?3"
+        )?;
+        let rows = stmt.query_map(params![diary_id, limit as i64, offset as i64], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?))
+        })?;
+        let mut select_ids = conn.prepare("SELECT diary_id FROM audit_log_entries WHERE audit_id = ?1 ORDER BY rowid")?;
+        
+        let mut records = Vec::new();
+        for row in rows {
+            let (id, operation, detail, created_at) = row?;
+            let Some(operation) = AuditOperation::parse(&operation) else {
+                continue;
+            };
+            let entry_ids = select_ids.query_map(params![id], |row| row.get(0))?.collect::<SqliteResult<_>>()?;
+            records.push(AuditRecord {
+                id,
+                operation,
+                entry_ids,
+                detail,
+                created_at: DateTime::parse_from_rfc3339(&created_at)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now()),
+            });
+        }
+        
+        Ok(records)
+    }
+    
+    /// Days audit records are kept for; `None` keeps them forever.
+    pub fn get_audit_retention_days(&self) -> DiaryResult<Option<u32>> {
+        self.retention_days(AUDIT_RETENTION_SETTING)
+    }
+    
+    /// Sets how long audit records are kept. Older records are pruned each
+    /// time the vault is opened.
+    pub fn set_audit_retention_days(&self, days: Option<u32>) -> DiaryResult<()> {
+        self.set_retention_days(AUDIT_RETENTION_SETTING, days)
+    }
+    
+    fn retention_days(&self, setting: &str) -> DiaryResult<Option<u32>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let days: Option<String> = conn
+            .query_row("SELECT value FROM settings WHERE key = ?1", params![setting], |row| row.get(0))
+            .optional()?;
+        Ok(days.and_then(|days| days.parse().ok()))
+    }
+    
+    fn set_retention_days(&self, setting: &str, days: Option<u32>) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        if days == Some(0) {
+            return Err(DiaryError::Validation("Retention must be at least one day".to_string()));
+        }
+        let conn = self.pool.get().expect("Failed to get database connection");
+        match days {
+            Some(days) => conn.execute(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2)
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                params![setting, days.to_string()],
+            )?,
+            None => conn.execute("DELETE FROM settings WHERE key = ?1", params![setting])?,
+        };
+        Ok(())
+    }
+    
+    /// Drops audit records older than the retention setting and returns
+    /// how many went.
+    fn prune_audit_log(&self) -> DiaryResult<usize> {
+        let Some(days) = self.get_audit_retention_days()? else {
+            return Ok(0);
+        };
+        let cutoff = (Utc::now() - Duration::days(i64::from(days))).to_rfc3339_opts(SecondsFormat::Micros, true);
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM audit_log_entries WHERE audit_id IN (SELECT id FROM audit_log WHERE created_at < ?1)",
+            params![cutoff],
+        )?;
+        let pruned = tx.execute("DELETE FROM audit_log WHERE created_at < ?1", params![cutoff])?;
+        tx.commit()?;
+        if pruned > 0 {
+            println!("📝 [AUDIT] Pruned {} records older than {} days", pruned, days);
+        }
+        Ok(pruned)
+    }
+    
+    /// Sets the passphrase that guards locked entries. Replacing one needs
+    /// the `current` passphrase.
+    pub fn set_passphrase(&self, current: Option<&str>, new: &str) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        if new.is_empty() {
+            return Err(DiaryError::Validation("Passphrase must not be empty".to_string()));
+        }
+        if let Some(check) = self.passphrase_check()? {
+            crypto::open_with_password(&check, current.unwrap_or_default())?;
+        }
+        
+        let check = BASE64.encode(crypto::seal_with_password(PASSPHRASE_CHECK, new)?);
+        let conn = self.pool.get().expect("Failed to get database connection");
+        conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![PASSPHRASE_SETTING, check],
+        )?;
+        *self.elevated_at.lock().unwrap() = Some(Instant::now());
+        
+        Ok(())
+    }
+    
+    pub fn has_passphrase(&self) -> DiaryResult<bool> {
+        Ok(self.passphrase_check()?.is_some())
+    }
+    
+    fn passphrase_check(&self) -> DiaryResult<Option<Vec<u8>>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let check: Option<String> = conn
+            .query_row("SELECT value FROM settings WHERE key = ?1", params![PASSPHRASE_SETTING], |row| row.get(0))
+            .optional()?;
+        check
+            .map(|check| BASE64.decode(check).map_err(|e| DiaryError::Validation(format!("Corrupt passphrase check: {}", e))))
+            .transpose()
+    }
+    
+    /// Checks `password` against the passphrase and, if it matches, lets
+    /// this session read locked entries for `ELEVATION_DURATION`.
+    pub fn verify_password(&self, password: &str) -> DiaryResult<()> {
+        let check = self.passphrase_check()?.ok_or_else(|| {
+            DiaryError::Validation("No passphrase is set; set one first".to_string())
+        })?;
+        crypto::open_with_password(&check, password)?;
+        *self.elevated_at.lock().unwrap() = Some(Instant::now());
+        Ok(())
+    }
+    
+    pub fn is_elevated(&self) -> bool {
+        self.elevated_at
+            .lock()
+            .unwrap()
+            .is_some_and(|at| at.elapsed() < ELEVATION_DURATION)
+    }
+    
+    /// Fails with `EntryLocked` if `diary_id` is locked and the session
+    /// isn't elevated.
+    fn ensure_readable(&self, conn: &Connection, diary_id: &str) -> DiaryResult<()> {
+        let locked: bool = conn
+            .query_row("SELECT locked FROM diary_entries WHERE id = ?1", params![diary_id], |row| row.get(0))
+            .optional()?
+            .unwrap_or(false);
+        if locked && !self.is_elevated() {
+            return Err(DiaryError::EntryLocked);
+        }
+        Ok(())
+    }
+    
+    /// Marks an entry as private. Locking needs a passphrase to be set;
+    /// unlocking needs an elevated session.
+    pub fn set_diary_locked(&self, id: &str, locked: bool) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        if locked && !self.has_passphrase()? {
+            return Err(DiaryError::Validation("Set a passphrase before locking entries".to_string()));
+        }
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        if !locked {
+            self.ensure_readable(&conn, id)?;
+        }
+        
+        let tx = conn.transaction()?;
+        let updated = tx.execute(
+            "UPDATE diary_entries SET locked = ?1 WHERE id = ?2",
+            params![locked, id],
+        )?;
+        if updated == 0 {
+            return Err(DiaryError::NotFound(format!("Diary entry {}", id)));
+        }
+        let operation = if locked { AuditOperation::Lock } else { AuditOperation::Unlock };
+        Self::audit(&tx, operation, &[id], "")?;
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    /// Retries loading the vault key, e.g. after the user denied a keychain prompt.
+    /// A locked vault is refused; only `unlock` with the passphrase opens it.
+    pub fn reload_key(&mut self) -> DiaryResult<KeyStorageBackend> {
+        if self.is_locked() {
+            return Err(DiaryError::Locked);
+        }
+        let crypto = Crypto::new()?;
+        let backend = crypto.backend();
+        self.crypto = Some(Arc::new(crypto));
+        if !self.read_only {
+            self.recover_interrupted_rotation()?;
+            self.run_key_migrations()?;
+        }
+        Ok(backend)
+    }
+    
+    /// Runs the migrations `initialize_db` left pending because they need
+    /// the vault key. Called again whenever the key becomes available.
+    fn run_key_migrations(&self) -> DiaryResult<()> {
+        let crypto = match &self.crypto {
+            Some(crypto) => crypto.clone(),
+            None => return Ok(()),
+        };
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        Self::run_migrations(&mut conn, Some(&crypto))?;
+        Ok(())
+    }
+    
+    /// Encrypts titles written before titles were encrypted. Plaintext is
+    /// told apart by envelope shape, not by failing to decrypt, so a damaged
+    /// or foreign-key title is left as it is for `verify_vault` to report.
+    fn encrypt_plaintext_titles(conn: &Connection, crypto: &Crypto) -> SqliteResult<()> {
+        let titles = {
+            let mut stmt = conn.prepare("SELECT id, title FROM diary_entries")?;
+            let title_iter = stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?;
+            title_iter.collect::<SqliteResult<Vec<_>>>()?
+        };
+        
+        let mut migrated = 0;
+        let mut undecryptable = 0;
+        for (id, title) in &titles {
+            if !Crypto::looks_encrypted(title) {
+                conn.execute(
+                    "UPDATE diary_entries SET title = ?1 WHERE id = ?2",
+                    params![crypto.encrypt(title), id],
+                )?;
+                migrated += 1;
+            } else if crypto.try_decrypt(title).is_err() {
+                undecryptable += 1;
+            }
+        }
+        
+        if migrated > 0 {
+            println!("🔑 [MIGRATION] Encrypted {} plaintext titles", migrated);
+        }
+        if undecryptable > 0 {
+            println!("⚠️ [MIGRATION] {} encrypted titles don't decrypt with the current key; left unchanged", undecryptable);
+        }
+        Ok(())
+    }
+    
+    fn encrypt_attachment_names(conn: &Connection, crypto: &Crypto) -> SqliteResult<()> {
+        let attachments = {
+            let mut stmt = conn.prepare("SELECT id, filename, mime_type FROM attachments")?;
+            let attachment_iter = stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+            })?;
+            attachment_iter.collect::<SqliteResult<Vec<_>>>()?
+        };
+        
+        let mut migrated = 0;
+        for (id, filename, mime_type) in &attachments {
+            if !Crypto::looks_encrypted(filename) {
+                conn.execute(
+                    "UPDATE attachments SET filename = ?1, mime_type = ?2 WHERE id = ?3",
+                    params![crypto.encrypt(filename), crypto.encrypt(mime_type), id],
+                )?;
+                migrated += 1;
+            }
+        }
+        
+        if migrated > 0 {
+            println!("🔑 [MIGRATION] Encrypted the names of {} attachments", migrated);
+        }
+        Ok(())
+    }
+    
+    pub fn key_storage_backend(&self) -> DiaryResult<KeyStorageBackend> {
+        Ok(self.crypto()?.backend())
+    }
+    
+    fn get_db_path() -> String {
+        let proj_dirs = ProjectDirs::from("com", "secondbrian", "diary")
+            .expect("Failed to get project directories");
+        let data_dir = proj_dirs.data_dir();
+        fs::create_dir_all(data_dir).expect("Failed to create data directory");
+        data_dir.join("diary.db").to_str().unwrap().to_string()
+    }
+    
+    pub fn initialize_db(&self) -> SqliteResult<()> {
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        
+        // Enable foreign key constraints
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+        
+        // Create diary entries table
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS diary_entries (
+                id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )",
+            [],
         )?;
         
         // Create tags table
@@ -142,25 +1510,261 @@ impl DiaryDB {
             [],
         )?;
         
+        // Create attachments table; file bytes are stored encrypted
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS attachments (
+                id TEXT PRIMARY KEY,
+                diary_id TEXT NOT NULL,
+                filename TEXT NOT NULL,
+                mime_type TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                data TEXT NOT NULL,
+                FOREIGN KEY (diary_id) REFERENCES diary_entries (id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        
+        // Create templates table; name and content are encrypted like entries
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS templates (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                content TEXT NOT NULL,
+                default_tags TEXT NOT NULL DEFAULT '[]',
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+        
+        // Create custom properties table; values are encrypted JSON
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS diary_properties (
+                diary_id TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (diary_id, key),
+                FOREIGN KEY (diary_id) REFERENCES diary_entries (id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        
+        // Create drafts table; the empty diary id holds the draft of an
+        // entry that has never been saved
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS drafts (
+                diary_id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                tags TEXT NOT NULL DEFAULT '[]',
+                updated_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+        
+        // Create reminders table; remind_at is stored as UTC RFC 3339 with
+        // a fixed format so it compares correctly as text
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS reminders (
+                id TEXT PRIMARY KEY,
+                diary_id TEXT NOT NULL,
+                remind_at TEXT NOT NULL,
+                message TEXT NOT NULL,
+                completed INTEGER NOT NULL DEFAULT 0,
+                FOREIGN KEY (diary_id) REFERENCES diary_entries (id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_reminders_due ON reminders (completed, remind_at)",
+            [],
+        )?;
+        
+        // Create diary versions table; title and content are encrypted
+        // copies of what a save replaced
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS diary_versions (
+                id TEXT PRIMARY KEY,
+                diary_id TEXT NOT NULL,
+                version INTEGER NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                saved_at TEXT NOT NULL,
+                UNIQUE (diary_id, version),
+                FOREIGN KEY (diary_id) REFERENCES diary_entries (id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        
+        // Create saved searches table; name and query are encrypted, the
+        // query as a JSON `SearchQuery`
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS saved_searches (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                query TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+        
+        // Create settings table for vault-wide values such as the
+        // passphrase check
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+        
+        // Create audit log tables; records are only ever appended, and
+        // pruned by age. Entry ids are kept apart so the log can be
+        // filtered by entry, and outlive the entries they refer to.
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                operation TEXT NOT NULL,
+                detail TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS audit_log_entries (
+                audit_id INTEGER NOT NULL,
+                diary_id TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_audit_log_entries_diary ON audit_log_entries (diary_id, audit_id);
+            CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log (created_at);
+            CREATE TRIGGER IF NOT EXISTS audit_log_append_only BEFORE UPDATE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'audit log is append-only');
+            END;",
+        )?;
+        
+        // Create diary views table; one row per time an entry was opened,
+        // so view counts can be limited to a period
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS diary_views (
+                diary_id TEXT NOT NULL,
+                viewed_at TEXT NOT NULL,
+                FOREIGN KEY (diary_id) REFERENCES diary_entries (id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_diary_views_viewed_at ON diary_views (viewed_at, diary_id)",
+            [],
+        )?;
+        
+        Self::run_migrations(&mut conn, None)?;
+        Self::backfill_tag_keys(&conn)
+    }
+    
+    /// Applies pending migrations in order. Without `crypto` it stops at
+    /// the first one that needs the key; `run_key_migrations` resumes it.
+    fn run_migrations(conn: &mut Connection, crypto: Option<&Crypto>) -> SqliteResult<()> {
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let tx = match (migration, crypto) {
+                (Migration::Sql(sql), _) => {
+                    let tx = conn.transaction()?;
+                    tx.execute_batch(sql)?;
+                    tx
+                }
+                (Migration::Key(migrate), Some(crypto)) => {
+                    let tx = conn.transaction()?;
+                    migrate(&tx, crypto)?;
+                    tx
+                }
+                (Migration::Key(_), None) => break,
+            };
+            tx.pragma_update(None, "user_version", (index + 1) as i64)?;
+            tx.commit()?;
+            println!("📝 [MIGRATION] Applied schema migration {}", index + 1);
+        }
+        
         Ok(())
     }
     
-    pub fn save_diary(&self, id: Option<&str>, title: &str, content: &str, tags: &[String]) -> SqliteResult<String> {
-        let conn = self.pool.get().expect("Failed to get database connection");
-        let encrypted_content = self.crypto.encrypt(content);
+    pub fn save_diary(&self, id: Option<&str>, title: &str, content: &str, tags: &[String]) -> DiaryResult<String> {
+        Ok(self.save_diary_with(id, title, content, tags, false)?.id)
+    }
+    
+    /// `save_diary` for the editor: also clears the draft the editor
+    /// autosaved for this entry, and reports whether the tags or the
+    /// wikilink relationships changed so listeners are only told when they
+    /// did. Other callers leave drafts alone, since the new-entry draft
+    /// belongs to whatever the editor has open.
+    pub fn save_diary_from_editor(
+        &self,
+        id: Option<&str>,
+        title: &str,
+        content: &str,
+        tags: &[String],
+    ) -> DiaryResult<SavedDiary> {
+        self.save_diary_with(id, title, content, tags, true)
+    }
+    
+    fn save_diary_with(
+        &self,
+        id: Option<&str>,
+        title: &str,
+        content: &str,
+        tags: &[String],
+        clear_draft: bool,
+    ) -> DiaryResult<SavedDiary> {
+        self.ensure_writable()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        let crypto = self.crypto()?;
+        let encrypted_title = crypto.encrypt(title);
+        let encrypted_content = crypto.encrypt(content);
+        let word_count = count_words(content);
         let now = Utc::now();
         let now_str = now.to_rfc3339();
         
+        let mut previous_tags = BTreeSet::new();
         let diary_id = match id {
             Some(existing_id) => {
-                // Update existing diary
-                conn.execute(
-                    "UPDATE diary_entries SET title = ?1, content = ?2, updated_at = ?3 WHERE id = ?4",
-                    params![title, encrypted_content, now_str, existing_id],
+                self.ensure_readable(&tx, existing_id)?;
+                // Update existing diary, keeping what it replaces as a version
+                let previous: Option<(String, String, String)> = tx
+                    .query_row(
+                        "SELECT title, content, created_at FROM diary_entries WHERE id = ?1",
+                        params![existing_id],
+                        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                    )
+                    .optional()?;
+                if let Some((previous_title, previous_content, _)) = &previous {
+                    // A record that no longer decrypts counts as changed, so
+                    // saving over it keeps the damaged copy and repairs it.
+                    let unchanged = decrypt_migrated(crypto, previous_title).is_ok_and(|previous| previous == title)
+                        && crypto.try_decrypt(previous_content).is_ok_and(|previous| previous == content);
+                    if !unchanged {
+                        Self::record_version(&tx, existing_id, previous_title, previous_content, &now_str)?;
+                    }
+                }
+                let created_at = previous
+                    .map(|(_, _, created_at)| created_at)
+                    .and_then(|created_at| DateTime::parse_from_rfc3339(&created_at).ok())
+                    .map_or(now, |created_at| created_at.with_timezone(&Utc));
+                tx.execute(
+                    "UPDATE diary_entries SET title = ?1, content = ?2, updated_at = ?3, word_count = ?4, content_hash = ?5
+                     WHERE id = ?6",
+                    params![
+                        encrypted_title,
+                        encrypted_content,
+                        now_str,
+                        word_count,
+                        content_hash(crypto, title, created_at, content),
+                        existing_id
+                    ],
                 )?;
                 
                 // Delete existing tag relationships
-                conn.execute(
+                previous_tags = Self::tag_ids_of(&tx, existing_id)?;
+                tx.execute(
                     "DELETE FROM diary_tags WHERE diary_id = ?1",
                     params![existing_id],
                 )?;
@@ -170,10 +1774,18 @@ impl DiaryDB {
             None => {
                 // Create new diary
                 let new_id = Uuid::new_v4().to_string();
-                conn.execute(
-                    "INSERT INTO diary_entries (id, title, content, created_at, updated_at) 
-                     VALUES (?1, ?2, ?3, ?4, ?5)",
-                    params![new_id, title, encrypted_content, now_str, now_str],
+                tx.execute(
+                    "INSERT INTO diary_entries (id, title, content, created_at, updated_at, word_count, content_hash) 
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        new_id,
+                        encrypted_title,
+                        encrypted_content,
+                        now_str,
+                        now_str,
+                        word_count,
+                        content_hash(crypto, title, now, content)
+                    ],
                 )?;
                 new_id
             }
@@ -181,451 +1793,7786 @@ impl DiaryDB {
         
         // Process tags
         for tag_name in tags {
-            let tag_id = self.get_or_create_tag(&conn, tag_name)?;
+            let tag_id = self.get_or_create_tag(&tx, tag_name)?;
             
             // Create relationship
-            conn.execute(
+            tx.execute(
                 "INSERT OR IGNORE INTO diary_tags (diary_id, tag_id) VALUES (?1, ?2)",
                 params![diary_id, tag_id],
             )?;
         }
         
-        Ok(diary_id)
-    }
-    
-    fn get_or_create_tag(&self, conn: &Connection, tag_name: &str) -> SqliteResult<String> {
-        // Try to find existing tag
-        let mut stmt = conn.prepare("SELECT id FROM tags WHERE name = ?1")?;
-        let mut rows = stmt.query(params![tag_name])?;
+        let tags_changed = Self::tag_ids_of(&tx, &diary_id)? != previous_tags;
+        let links_changed = Self::sync_wikilinks_with(&tx, crypto, &diary_id, content)?.changed;
         
-        if let Some(row) = rows.next()? {
-            return Ok(row.get(0)?);
+        if clear_draft {
+            tx.execute(
+                "DELETE FROM drafts WHERE diary_id = ?1",
+                params![id.unwrap_or(NEW_ENTRY_DRAFT_KEY)],
+            )?;
         }
+        Self::audit(&tx, AuditOperation::Save, &[&diary_id], if id.is_some() { "updated" } else { "created" })?;
+        tx.commit()?;
+        self.invalidate_titles();
         
-        // Create new tag if not found
-        let tag_id = Uuid::new_v4().to_string();
-        conn.execute(
-            "INSERT INTO tags (id, name) VALUES (?1, ?2)",
-            params![tag_id, tag_name],
-        )?;
-        
-        Ok(tag_id)
+        Ok(SavedDiary { id: diary_id, tags_changed, links_changed })
     }
     
-    pub fn get_diary(&self, id: &str) -> SqliteResult<DiaryEntry> {
-        let conn = self.pool.get().expect("Failed to get database connection");
+    fn tag_ids_of(conn: &Connection, diary_id: &str) -> SqliteResult<BTreeSet<String>> {
+        let mut stmt = conn.prepare("SELECT tag_id FROM diary_tags WHERE diary_id = ?1")?;
+        let tag_iter = stmt.query_map(params![diary_id], |row| row.get(0))?;
+        tag_iter.collect()
+    }
+    
+    /// Renames an entry. With `update_links`, the `[[Old Title]]` links of
+    /// entries linking to it are rewritten to the new title, each keeping
+    /// its previous content as a version. Returns the rewritten entries.
+    ///
+    /// The title is trimmed, and must not be empty or hold `]]`, `|` or a
+    /// line break, which would break the rewritten links.
+    pub fn rename_diary(&self, id: &str, new_title: &str, update_links: bool) -> DiaryResult<Vec<TitleMatch>> {
+        self.ensure_writable()?;
+        let new_title = new_title.trim();
+        if new_title.is_empty() {
+            return Err(DiaryError::Validation("Title must not be empty".to_string()));
+        }
+        if new_title.contains("]]") || new_title.contains(['|', '\n', '\r']) {
+            return Err(DiaryError::Validation(
+                "Title must not contain ]], | or line breaks".to_string(),
+            ));
+        }
+        let crypto = self.crypto()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        let now = Utc::now();
+        let now_str = now.to_rfc3339();
+        self.ensure_readable(&tx, id)?;
         
-        let mut stmt = conn.prepare(
-            "SELECT id, title, content, created_at, updated_at FROM diary_entries WHERE id = ?1"
-        )?;
+        let (encrypted_title, encrypted_content, created_at): (String, String, String) = tx
+            .query_row(
+                "SELECT title, content, created_at FROM diary_entries WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?
+            .ok_or_else(|| DiaryError::NotFound(format!("Diary entry {}", id)))?;
+        let old_title = decrypt_migrated(crypto, &encrypted_title)?;
+        if old_title == new_title {
+            return Ok(Vec::new());
+        }
+        let created_at = DateTime::parse_from_rfc3339(&created_at)
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or(now);
         
-        let mut rows = stmt.query(params![id])?;
+        Self::record_version(&tx, id, &encrypted_title, &encrypted_content, &now_str)?;
+        tx.execute(
+            "UPDATE diary_entries SET title = ?1, updated_at = ?2, content_hash = ?3 WHERE id = ?4",
+            params![
+                crypto.encrypt(new_title),
+                now_str,
+                content_hash(crypto, new_title, created_at, &crypto.try_decrypt(&encrypted_content)?),
+                id
+            ],
+        )?;
         
-        if let Some(row) = rows.next()? {
-            let id: String = row.get(0)?;
-            let title: String = row.get(1)?;
-            let encrypted_content: String = row.get(2)?;
-            let created_at: String = row.get(3)?;
-            let updated_at: String = row.get(4)?;
-            
-            let content = self.crypto.decrypt(&encrypted_content);
-            let created_at = DateTime::parse_from_rfc3339(&created_at)
-                .map(|dt| dt.with_timezone(&Utc))
-                .unwrap_or_else(|_| Utc::now());
-            let updated_at = DateTime::parse_from_rfc3339(&updated_at)
-                .map(|dt| dt.with_timezone(&Utc))
-                .unwrap_or_else(|_| Utc::now());
+        let mut rewritten = Vec::new();
+        if update_links {
+            let linking: Vec<(String, String, String, String)> = {
+                let mut stmt = tx.prepare(
+                    "SELECT DISTINCT d.id, d.title, d.content, d.created_at
+                     FROM relationships r
+                     JOIN diary_entries d ON d.id = r.parent_id
+                     WHERE r.child_id = ?1 AND r.source = ?2 AND r.parent_id != ?1"
+                )?;
+                let rows = stmt.query_map(params![id, SOURCE_WIKILINK], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                })?;
+                rows.collect::<SqliteResult<_>>()?
+            };
             
-            // Get tags for this diary entry
-            let tags = self.get_tags_for_diary(&id)?;
+            for (linking_id, encrypted_title, encrypted_content, created_at) in linking {
+                self.ensure_readable(&tx, &linking_id)?;
+                let content = crypto.try_decrypt(&encrypted_content)?;
+                let Some(content) = rename_wikilinks(&content, &old_title, new_title) else {
+                    continue;
+                };
+                let title = decrypt_migrated(crypto, &encrypted_title)?;
+                let created_at = DateTime::parse_from_rfc3339(&created_at)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or(now);
+                Self::record_version(&tx, &linking_id, &encrypted_title, &encrypted_content, &now_str)?;
+                tx.execute(
+                    "UPDATE diary_entries SET content = ?1, updated_at = ?2, word_count = ?3, content_hash = ?4
+                     WHERE id = ?5",
+                    params![
+                        crypto.encrypt(&content),
+                        now_str,
+                        count_words(&content),
+                        content_hash(crypto, &title, created_at, &content),
+                        linking_id
+                    ],
+                )?;
+                rewritten.push(TitleMatch { id: linking_id, title });
+            }
             
-            Ok(DiaryEntry {
-                id,
-                title,
-                content,
-                created_at,
-                updated_at,
-                tags,
-            })
-        } else {
-            Err(rusqlite::Error::QueryReturnedNoRows)
+            // Links may now resolve to other entries sharing either title.
+            let titles = Self::title_index(&tx, crypto)?;
+            for entry in &rewritten {
+                let content: String = tx.query_row(
+                    "SELECT content FROM diary_entries WHERE id = ?1",
+                    params![entry.id],
+                    |row| row.get(0),
+                )?;
+                Self::sync_wikilinks_indexed(&tx, &titles, &entry.id, &crypto.try_decrypt(&content)?)?;
+            }
         }
+        
+        let mut touched: Vec<&str> = vec![id];
+        touched.extend(rewritten.iter().map(|entry| entry.id.as_str()));
+        Self::audit(&tx, AuditOperation::Save, &touched, &format!("renamed, {} links updated", rewritten.len()))?;
+        tx.commit()?;
+        self.invalidate_titles();
+        println!("📝 [RENAME_DIARY] Renamed {} and rewrote links in {} entries", id, rewritten.len());
+        
+        Ok(rewritten)
     }
     
-    fn get_tags_for_diary(&self, diary_id: &str) -> SqliteResult<Vec<String>> {
+    /// Stores the autosaved state of an entry without touching the entry
+    /// itself, its tags or its `updated_at`.
+    pub fn save_draft(&self, diary_id: Option<&str>, title: &str, content: &str, tags: &[String]) -> DiaryResult<Draft> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
         let conn = self.pool.get().expect("Failed to get database connection");
+        if let Some(diary_id) = diary_id {
+            self.ensure_readable(&conn, diary_id)?;
+        }
         
-        let mut stmt = conn.prepare(
-            "SELECT t.name FROM tags t
-             JOIN diary_tags dt ON t.id = dt.tag_id
-             WHERE dt.diary_id = ?1"
+        conn.execute(
+            "INSERT INTO drafts (diary_id, title, content, tags, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(diary_id) DO UPDATE SET
+                 title = excluded.title,
+                 content = excluded.content,
+                 tags = excluded.tags,
+                 updated_at = excluded.updated_at",
+            params![
+                diary_id.unwrap_or(NEW_ENTRY_DRAFT_KEY),
+                crypto.encrypt(title),
+                crypto.encrypt(content),
+                serde_json::to_string(tags).expect("tags serialize"),
+                Utc::now().to_rfc3339(),
+            ],
         )?;
         
-        let tag_iter = stmt.query_map(params![diary_id], |row| {
-            let name: String = row.get(0)?;
-            Ok(name)
-        })?;
+        Self::get_draft_with(&conn, crypto, diary_id)?
+            .ok_or_else(|| DiaryError::NotFound("Draft".to_string()))
+    }
+    
+    pub fn get_draft(&self, diary_id: Option<&str>) -> DiaryResult<Option<Draft>> {
+        let crypto = self.crypto()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        if let Some(diary_id) = diary_id {
+            self.ensure_readable(&conn, diary_id)?;
+        }
         
-        let mut tags = Vec::new();
-        for tag_result in tag_iter {
-            tags.push(tag_result?);
+        Self::get_draft_with(&conn, crypto, diary_id)
+    }
+    
+    pub fn discard_draft(&self, diary_id: Option<&str>) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        if let Some(diary_id) = diary_id {
+            self.ensure_readable(&conn, diary_id)?;
         }
         
-        Ok(tags)
+        conn.execute(
+            "DELETE FROM drafts WHERE diary_id = ?1",
+            params![diary_id.unwrap_or(NEW_ENTRY_DRAFT_KEY)],
+        )?;
+        
+        Ok(())
     }
     
-    pub fn list_diaries(&self) -> SqliteResult<Vec<DiaryEntry>> {
+    /// Every draft left behind, newest first, for crash recovery. Drafts of
+    /// locked entries are left out until the session is elevated.
+    pub fn list_unsaved_drafts(&self) -> DiaryResult<Vec<Draft>> {
+        let crypto = self.crypto()?;
         let conn = self.pool.get().expect("Failed to get database connection");
         
         let mut stmt = conn.prepare(
-            "SELECT id, title, content, created_at, updated_at FROM diary_entries ORDER BY created_at DESC"
+            "SELECT d.diary_id, d.title, d.content, d.tags, d.updated_at
+             FROM drafts d
+             LEFT JOIN diary_entries e ON e.id = d.diary_id
+             WHERE ?1 OR COALESCE(e.locked, 0) = 0
+             ORDER BY d.updated_at DESC"
         )?;
+        let draft_iter = stmt.query_map(params![self.is_elevated()], Self::draft_from_row)?;
         
-        let diary_iter = stmt.query_map([], |row| {
-            let id: String = row.get(0)?;
-            let title: String = row.get(1)?;
-            let encrypted_content: String = row.get(2)?;
-            let created_at: String = row.get(3)?;
-            let updated_at: String = row.get(4)?;
-            
-            let content = self.crypto.decrypt(&encrypted_content);
-            let created_at = DateTime::parse_from_rfc3339(&created_at)
-                .map(|dt| dt.with_timezone(&Utc))
-                .unwrap_or_else(|_| Utc::now());
-            let updated_at = DateTime::parse_from_rfc3339(&updated_at)
-                .map(|dt| dt.with_timezone(&Utc))
-                .unwrap_or_else(|_| Utc::now());
-            
-            Ok((id, title, content, created_at, updated_at))
-        })?;
-        
-        let mut diaries = Vec::new();
-        for diary_result in diary_iter {
-            let (id, title, content, created_at, updated_at) = diary_result?;
-            let tags = self.get_tags_for_diary(&id)?;
-            
-            diaries.push(DiaryEntry {
-                id,
-                title,
-                content,
-                created_at,
-                updated_at,
-                tags,
-            });
+        let mut drafts = Vec::new();
+        for draft_result in draft_iter {
+            drafts.push(Self::decrypt_draft(crypto, draft_result?)?);
         }
         
-        Ok(diaries)
+        Ok(drafts)
+    }
+    
+    fn get_draft_with(conn: &Connection, crypto: &Crypto, diary_id: Option<&str>) -> DiaryResult<Option<Draft>> {
+        conn.query_row(
+            "SELECT diary_id, title, content, tags, updated_at FROM drafts WHERE diary_id = ?1",
+            params![diary_id.unwrap_or(NEW_ENTRY_DRAFT_KEY)],
+            Self::draft_from_row,
+        )
+        .optional()?
+        .map(|draft| Self::decrypt_draft(crypto, draft))
+        .transpose()
+    }
+    
+    /// Reads a draft with its title and content still encrypted, for
+    /// `decrypt_draft` to open outside the row closure.
+    fn draft_from_row(row: &rusqlite::Row) -> SqliteResult<Draft> {
+        let diary_id: String = row.get(0)?;
+        let tags: String = row.get(3)?;
+        
+        Ok(Draft {
+            diary_id: Some(diary_id).filter(|id| id != NEW_ENTRY_DRAFT_KEY),
+            title: row.get(1)?,
+            content: row.get(2)?,
+            tags: serde_json::from_str(&tags).unwrap_or_default(),
+            updated_at: row.get(4)?,
+        })
     }
     
-    pub fn search_diaries_by_tag(&self, tag_name: &str) -> SqliteResult<Vec<DiaryEntry>> {
+    fn decrypt_draft(crypto: &Crypto, mut draft: Draft) -> DiaryResult<Draft> {
+        draft.title = decrypt_migrated(crypto, &draft.title)?;
+        draft.content = crypto.try_decrypt(&draft.content)?;
+        Ok(draft)
+    }
+    
+    /// Stores an entry's replaced title and content, still encrypted, as its
+    /// next version and drops versions past `MAX_VERSIONS_PER_ENTRY`.
+    fn record_version(
+        conn: &Connection,
+        diary_id: &str,
+        encrypted_title: &str,
+        encrypted_content: &str,
+        saved_at: &str,
+    ) -> SqliteResult<()> {
+        conn.execute(
+            "INSERT INTO diary_versions (id, diary_id, version, title, content, saved_at)
+             VALUES (?1, ?2, (SELECT COALESCE(MAX(version), 0) + 1 FROM diary_versions WHERE diary_id = ?2), ?3, ?4, ?5)",
+            params![Uuid::new_v4().to_string(), diary_id, encrypted_title, encrypted_content, saved_at],
+        )?;
+        conn.execute(
+            "DELETE FROM diary_versions
+             WHERE diary_id = ?1
+               AND version <= (SELECT MAX(version) FROM diary_versions WHERE diary_id = ?1) - ?2",
+            params![diary_id, MAX_VERSIONS_PER_ENTRY],
+        )?;
+        Ok(())
+    }
+    
+    /// Earlier versions of an entry, newest first.
+    pub fn list_diary_versions(&self, diary_id: &str) -> DiaryResult<Vec<DiaryVersion>> {
         let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
         
         let mut stmt = conn.prepare(
-            "SELECT e.id, e.title, e.content, e.created_at, e.updated_at
-             FROM diary_entries e
-             JOIN diary_tags dt ON e.id = dt.diary_id
-             JOIN tags t ON dt.tag_id = t.id
-             WHERE t.name = ?1
-             ORDER BY e.created_at DESC"
+            "SELECT version, title, saved_at FROM diary_versions WHERE diary_id = ?1 ORDER BY version DESC"
         )?;
-        
-        let diary_iter = stmt.query_map(params![tag_name], |row| {
-            let id: String = row.get(0)?;
-            let title: String = row.get(1)?;
-            let encrypted_content: String = row.get(2)?;
-            let created_at: String = row.get(3)?;
-            let updated_at: String = row.get(4)?;
-            
-            let content = self.crypto.decrypt(&encrypted_content);
-            let created_at = DateTime::parse_from_rfc3339(&created_at)
-                .map(|dt| dt.with_timezone(&Utc))
-                .unwrap_or_else(|_| Utc::now());
-            let updated_at = DateTime::parse_from_rfc3339(&updated_at)
-                .map(|dt| dt.with_timezone(&Utc))
-                .unwrap_or_else(|_| Utc::now());
-            
-            Ok((id, title, content, created_at, updated_at))
+        let version_iter = stmt.query_map(params![diary_id], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
         })?;
         
-        let mut diaries = Vec::new();
-        for diary_result in diary_iter {
-            let (id, title, content, created_at, updated_at) = diary_result?;
-            let tags = self.get_tags_for_diary(&id)?;
-            
-            diaries.push(DiaryEntry {
-                id,
-                title,
-                content,
-                created_at,
-                updated_at,
-                tags,
+        let mut versions = Vec::new();
+        for version in version_iter {
+            let (version, encrypted_title, saved_at) = version?;
+            versions.push(DiaryVersion {
+                version,
+                title: decrypt_migrated(crypto, &encrypted_title)?,
+                saved_at: DateTime::parse_from_rfc3339(&saved_at)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now()),
             });
         }
         
-        Ok(diaries)
+        Ok(versions)
     }
     
-    pub fn get_graph_data(&self) -> SqliteResult<GraphData> {
+    /// Line diff of an entry's content between two states. Each side is a
+    /// version number, `"current"` for the saved entry or `"draft"` for its
+    /// autosaved draft.
+    pub fn diff_diary_versions(&self, diary_id: &str, from_version: &str, to_version: &str) -> DiaryResult<VersionDiff> {
         let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        self.ensure_readable(&conn, diary_id)?;
         
-        // Get all diary entries as nodes
-        let mut diary_stmt = conn.prepare(
-            "SELECT id, title, created_at FROM diary_entries"
-        )?;
+        let (from_title, from_content) = Self::version_snapshot(&conn, crypto, diary_id, from_version)?;
+        let (to_title, to_content) = Self::version_snapshot(&conn, crypto, diary_id, to_version)?;
         
-        let diary_iter = diary_stmt.query_map([], |row| {
-            let id: String = row.get(0)?;
-            let title: String = row.get(1)?;
-            let created_at: String = row.get(2)?;
-            
-            Ok((id, title, created_at))
-        })?;
+        Ok(VersionDiff {
+            from_title,
+            to_title,
+            diff: text_diff::diff_lines(&from_content, &to_content),
+        })
+    }
+    
+    /// Decrypted title and content of one state of an entry.
+    fn version_snapshot(conn: &Connection, crypto: &Crypto, diary_id: &str, version: &str) -> DiaryResult<(String, String)> {
+        let version = version.trim();
+        let encrypted: Option<(String, String)> = match version {
+            CURRENT_VERSION => conn
+                .query_row(
+                    "SELECT title, content FROM diary_entries WHERE id = ?1",
+                    params![diary_id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?,
+            DRAFT_VERSION => conn
+                .query_row(
+                    "SELECT title, content FROM drafts WHERE diary_id = ?1",
+                    params![diary_id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?,
+            number => {
+                let number: i64 = number.parse().map_err(|_| {
+                    DiaryError::Validation(format!(
+                        "Unknown version '{}', expected a version number, 'current' or 'draft'",
+                        number
+                    ))
+                })?;
+                conn.query_row(
+                    "SELECT title, content FROM diary_versions WHERE diary_id = ?1 AND version = ?2",
+                    params![diary_id, number],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?
+            }
+        };
         
-        let mut nodes = Vec::new();
-        for diary_result in diary_iter {
-            let (id, title, created_at) = diary_result?;
-            
-            let properties = serde_json::json!({
-                "title": title,
-                "created_at": created_at,
-            });
+        let (encrypted_title, encrypted_content) = encrypted
+            .ok_or_else(|| DiaryError::NotFound(format!("Version '{}' of diary entry {}", version, diary_id)))?;
+        Ok((decrypt_migrated(crypto, &encrypted_title)?, crypto.try_decrypt(&encrypted_content)?))
+    }
+    
+    /// Saves many new entries in one transaction and returns their ids in
+    /// input order. Any failing row rolls back the whole batch.
+    pub fn save_diaries_batch(&self, entries: &[NewDiary]) -> DiaryResult<Vec<String>> {
+        self.ensure_writable()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        let ids = self.insert_diaries(&tx, entries)?;
+        
+        let saved: Vec<&str> = ids.iter().map(String::as_str).collect();
+        Self::audit(&tx, AuditOperation::Save, &saved, &format!("batch of {}", ids.len()))?;
+        tx.commit()?;
+        self.invalidate_titles();
+        println!("📝 [BATCH] Saved {} diary entries", ids.len());
+        
+        Ok(ids)
+    }
+    
+    /// Inserts `entries` with their tags and wikilinks inside the caller's
+    /// transaction, for `save_diaries_batch` and the importers that write
+    /// more per entry. The caller invalidates the title cache on commit.
+    fn insert_diaries(&self, tx: &Connection, entries: &[NewDiary]) -> DiaryResult<Vec<String>> {
+        let crypto = self.crypto()?;
+        let at_index = |index: usize, e: DiaryError| DiaryError::Validation(format!("Entry {}: {}", index, e));
+        
+        let mut ids = Vec::with_capacity(entries.len());
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO diary_entries (id, title, content, created_at, updated_at, word_count, content_hash)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)"
+            )?;
+            let mut insert_tag = tx.prepare("INSERT OR IGNORE INTO diary_tags (diary_id, tag_id) VALUES (?1, ?2)")?;
+            let mut tag_ids: HashMap<String, String> = HashMap::new();
+            let now = Utc::now();
             
-            nodes.push(GraphNode {
-                id: id.clone(),
-                label: title,
-                node_type: "diary".to_string(),
-                properties,
-            });
+            for (index, entry) in entries.iter().enumerate() {
+                if entry.title.trim().is_empty() {
+                    return Err(at_index(index, DiaryError::Validation("title must not be empty".to_string())));
+                }
+                let created_at = entry.created_at.unwrap_or(now);
+                let updated_at = entry.updated_at.unwrap_or(created_at);
+                if updated_at < created_at {
+                    return Err(at_index(index, DiaryError::Validation("updated_at is before created_at".to_string())));
+                }
+                
+                let id = Uuid::new_v4().to_string();
+                insert
+                    .execute(params![
+                        id,
+                        crypto.encrypt(&entry.title),
+                        crypto.encrypt(&entry.content),
+                        created_at.to_rfc3339(),
+                        updated_at.to_rfc3339(),
+                        count_words(&entry.content),
+                        content_hash(crypto, &entry.title, created_at, &entry.content),
+                    ])
+                    .map_err(|e| at_index(index, e.into()))?;
+                
+                for tag_name in &entry.tags {
+                    let tag_id = match tag_ids.get(tag_name) {
+                        Some(tag_id) => tag_id.clone(),
+                        None => {
+                            let tag_id = self.get_or_create_tag(tx, tag_name).map_err(|e| at_index(index, e.into()))?;
+                            tag_ids.insert(tag_name.clone(), tag_id.clone());
+                            tag_id
+                        }
+                    };
+                    insert_tag.execute(params![id, tag_id]).map_err(|e| at_index(index, e.into()))?;
+                }
+                ids.push(id);
+            }
         }
         
-        // Get all tags as nodes
-        let mut tag_stmt = conn.prepare("SELECT id, name FROM tags")?;
+        // Links are resolved after all inserts so entries in the batch can
+        // link to each other.
+        if entries.iter().any(|entry| !extract_wikilinks(&entry.content).is_empty()) {
+            let titles = Self::title_index(tx, crypto)?;
+            for (index, (id, entry)) in ids.iter().zip(entries).enumerate() {
+                Self::sync_wikilinks_indexed(tx, &titles, id, &entry.content).map_err(|e| at_index(index, e))?;
+            }
+        }
         
-        let tag_iter = tag_stmt.query_map([], |row| {
-            let id: String = row.get(0)?;
-            let name: String = row.get(1)?;
-            
-            Ok((id, name))
-        })?;
+        Ok(ids)
+    }
+    
+    #[cfg(test)]
+    pub fn import_day_one(
+        &self,
+        path: &Path,
+        dry_run: bool,
+        update_duplicates: bool,
+        progress: &mut Progress,
+    ) -> DiaryResult<ImportReport> {
+        crate::operations::run(self, DayOneImporter::new(path, dry_run, update_duplicates)?, progress)
+    }
+    
+    /// Re-syncs the wikilinks of the given entries against a single title
+    /// index.
+    fn resync_wikilinks(&self, ids: &[String]) -> DiaryResult<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let crypto = self.crypto()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        let titles = Self::title_index(&tx, crypto)?;
+        {
+            let mut select = tx.prepare("SELECT content FROM diary_entries WHERE id = ?1")?;
+            for id in ids {
+                let encrypted_content: String = select.query_row(params![id], |row| row.get(0))?;
+                Self::sync_wikilinks_indexed(&tx, &titles, id, &crypto.try_decrypt(&encrypted_content)?)?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+    
+    /// Adds the tags and properties of an imported entry to the existing
+    /// entry it duplicates, in one transaction.
+    fn merge_imported_metadata(&self, diary_id: &str, entry: &day_one::DayOneEntry) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        self.ensure_readable(&tx, diary_id)?;
         
-        for tag_result in tag_iter {
-            let (id, name) = tag_result?;
-            
-            let properties = serde_json::json!({
-                "name": name,
-            });
-            
-            nodes.push(GraphNode {
-                id: id.clone(),
-                label: name,
-                node_type: "tag".to_string(),
-                properties,
-            });
+        for tag in entry.diary.tags.iter().filter(|tag| !tag.trim().is_empty()) {
+            let tag_id = self.get_or_create_tag(&tx, tag)?;
+            tx.execute("INSERT OR IGNORE INTO diary_tags (diary_id, tag_id) VALUES (?1, ?2)", params![diary_id, tag_id])?;
+        }
+        for (key, value) in &entry.properties {
+            Self::write_property(&tx, crypto, diary_id, key, value)?;
+        }
+        Self::audit(&tx, AuditOperation::Import, &[diary_id], "Day One: merged tags and properties")?;
+        tx.commit()?;
+        Ok(())
+    }
+    
+    /// Saves new Day One entries with their properties in one transaction
+    /// and returns their ids in input order.
+    fn save_day_one_batch(&self, entries: &[day_one::DayOneEntry]) -> DiaryResult<Vec<String>> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        let diaries: Vec<NewDiary> = entries.iter().map(|entry| entry.diary.clone()).collect();
+        let ids = self.insert_diaries(&tx, &diaries)?;
+        for (id, entry) in ids.iter().zip(entries) {
+            for (key, value) in &entry.properties {
+                Self::write_property(&tx, crypto, id, key, value)?;
+            }
         }
         
-        // Get all relationships as edges
-        let mut edges = Vec::new();
+        let saved: Vec<&str> = ids.iter().map(String::as_str).collect();
+        Self::audit(&tx, AuditOperation::Import, &saved, &format!("Day One: batch of {}", ids.len()))?;
+        tx.commit()?;
+        self.invalidate_titles();
         
-        // Tag relationships
-        let mut tag_edge_stmt = conn.prepare(
-            "SELECT dt.diary_id, dt.tag_id, t.name
-             FROM diary_tags dt
-             JOIN tags t ON dt.tag_id = t.id"
+        Ok(ids)
+    }
+    
+    /// Id and content hash of every entry, oldest first. Hashes the
+    /// backfill hasn't stored yet are computed in memory, so this never
+    /// writes and works for dry runs and read-only vaults.
+    fn entry_content_hashes(&self) -> DiaryResult<Vec<(String, String)>> {
+        let crypto = self.crypto()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let mut stmt = conn.prepare(
+            "SELECT id, content_hash,
+                    CASE WHEN content_hash IS NULL THEN title END,
+                    CASE WHEN content_hash IS NULL THEN content END,
+                    created_at
+             FROM diary_entries ORDER BY created_at, id"
         )?;
-        
-        let tag_edge_iter = tag_edge_stmt.query_map([], |row| {
-            let diary_id: String = row.get(0)?;
-            let tag_id: String = row.get(1)?;
-            let tag_name: String = row.get(2)?;
-            
-            Ok((diary_id, tag_id, tag_name))
+        let hash_iter = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, String>(4)?,
+            ))
         })?;
         
-        for edge_result in tag_edge_iter {
-            let (diary_id, tag_id, tag_name) = edge_result?;
-            
-            edges.push(GraphEdge {
-                id: format!("tag-{}-{}", diary_id, tag_id),
-                source: diary_id,
-                target: tag_id,
-                label: format!("tagged_as_{}", tag_name),
-            });
+        let mut hashes = Vec::new();
+        for hash_result in hash_iter {
+            let (id, hash, encrypted_title, encrypted_content, created_at) = hash_result?;
+            let hash = match hash {
+                Some(hash) => hash,
+                None => {
+                    let title = decrypt_migrated(crypto, &encrypted_title.unwrap_or_default())?;
+                    let content = crypto.try_decrypt(&encrypted_content.unwrap_or_default())?;
+                    let created_at = DateTime::parse_from_rfc3339(&created_at)
+                        .map(|dt| dt.with_timezone(&Utc))
+                        .unwrap_or_else(|_| Utc::now());
+                    content_hash(crypto, &title, created_at, &content)
+                }
+            };
+            hashes.push((id, hash));
         }
         
-        // Diary entry relationships
-        let mut rel_edge_stmt = conn.prepare(
-            "SELECT id, parent_id, child_id, relationship_type
-             FROM relationships"
-        )?;
-        
-        let rel_edge_iter = rel_edge_stmt.query_map([], |row| {
-            let id: String = row.get(0)?;
-            let parent_id: String = row.get(1)?;
-            let child_id: String = row.get(2)?;
-            let relationship_type: String = row.get(3)?;
-            
-            Ok((id, parent_id, child_id, relationship_type))
-        })?;
+        Ok(hashes)
+    }
+    
+    /// Content hash of every entry, mapped to the oldest entry with it.
+    fn content_hashes(&self) -> DiaryResult<HashMap<String, String>> {
+        let mut hashes = HashMap::new();
+        for (id, hash) in self.entry_content_hashes()? {
+            hashes.entry(hash).or_insert(id);
+        }
+        Ok(hashes)
+    }
+    
+    /// Groups of entries with identical title, creation time and content,
+    /// oldest first within each group.
+    pub fn find_duplicate_diaries(&self) -> DiaryResult<Vec<Vec<DiarySummary>>> {
+        if !self.read_only {
+            while self.backfill_word_counts(WORD_COUNT_BACKFILL_BATCH)?.remaining > 0 {}
+        }
         
-        for edge_result in rel_edge_iter {
-            let (id, parent_id, child_id, relationship_type) = edge_result?;
-            
-            edges.push(GraphEdge {
-                id,
-                source: child_id,     // Child is the source of the edge
-                target: parent_id,    // Parent is the target
-                label: relationship_type,
-            });
+        let mut by_hash: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (id, hash) in self.entry_content_hashes()? {
+            by_hash.entry(hash).or_default().push(id);
+        }
+        
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, title, created_at, updated_at, last_viewed_at, word_count
+             FROM diary_entries WHERE id = ?1"
+        )?;
+        
+        let mut groups = Vec::new();
+        for ids in by_hash.into_values().filter(|ids| ids.len() > 1) {
+            let mut group = Vec::with_capacity(ids.len());
+            for id in ids {
+                let mut summary = stmt.query_row(params![id], |row| Self::summary_from_row(crypto, row))?;
+                summary.tags = self.get_tags_for_diary(&summary.id)?;
+                group.push(summary);
+            }
+            groups.push(group);
         }
         
-        Ok(GraphData { nodes, edges })
+        Ok(groups)
     }
-
-    pub fn delete_diary(&self, id: &str) -> SqliteResult<()> {
-        println!("📝 [DELETE_DIARY] Starting deletion for diary ID: {}", id);
+    
+    /// Writes one entry to `path`, encrypted with a key derived from
+    /// `password` rather than the vault key, so it can be shared.
+    pub fn export_entry_encrypted(&self, id: &str, path: &Path, password: &str) -> DiaryResult<()> {
+        if password.is_empty() {
+            return Err(DiaryError::Validation("Password must not be empty".to_string()));
+        }
+        let diary = self.read_diary(id, false)?;
+        let shared = SharedEntry {
+            title: diary.title,
+            content: diary.content,
+            tags: diary.tags,
+            created_at: diary.created_at,
+            updated_at: diary.updated_at,
+        };
+        
+        let json = serde_json::to_vec(&shared).expect("entry serializes");
+        fs::write(path, crypto::seal_with_password(&json, password)?)?;
+        self.record_audit(AuditOperation::Export, &[id], "password-protected entry");
+        Ok(())
+    }
+    
+    /// Decrypts an `export_entry_encrypted` file and saves it as a new entry
+    /// under the vault key, keeping its timestamps. Returns the new entry,
+    /// or the existing one when the vault already holds the same entry.
+    pub fn import_encrypted_entry(&self, path: &Path, password: &str) -> DiaryResult<TitleMatch> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
+        let json = crypto::open_with_password(&fs::read(path)?, password)?;
+        let shared: SharedEntry = serde_json::from_slice(&json)
+            .map_err(|e| DiaryError::Validation(format!("Invalid shared entry: {}", e)))?;
+        
+        let hash = content_hash(crypto, &shared.title, shared.created_at, &shared.content);
+        if let Some(id) = self.content_hashes()?.remove(&hash) {
+            println!("📝 [IMPORT] Shared entry is already in the vault as {}", id);
+            return Ok(TitleMatch { id, title: shared.title });
+        }
         
-        // Get a connection from the pool
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        let ids = self.insert_diaries(&tx, &[NewDiary {
+            title: shared.title.clone(),
+            content: shared.content,
+            tags: shared.tags,
+            created_at: Some(shared.created_at),
+            updated_at: Some(shared.updated_at),
+        }])?;
+        let id = ids.into_iter().next().expect("one entry saved");
+        Self::audit(&tx, AuditOperation::Import, &[&id], "password-protected entry")?;
+        tx.commit()?;
+        self.invalidate_titles();
+        Ok(TitleMatch { id, title: shared.title })
+    }
+    
+    /// Re-syncs the `links_to` relationships of an entry with the
+    /// `[[wikilinks]]` in its content and returns the link targets that
+    /// don't match any entry title, so the UI can offer to create them.
+    pub fn sync_wikilinks(&self, diary_id: &str) -> DiaryResult<Vec<String>> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
         let conn = self.pool.get().expect("Failed to get database connection");
+        self.ensure_readable(&conn, diary_id)?;
         
-        // Check foreign keys status
-        let foreign_keys_enabled: i32 = conn.query_row(
-            "PRAGMA foreign_keys",
-            [],
-            |row| row.get(0)
-        )?;
-        println!("📝 [DELETE_DIARY] Foreign keys enabled: {}", foreign_keys_enabled);
+        let encrypted_content: String = conn
+            .query_row(
+                "SELECT content FROM diary_entries WHERE id = ?1",
+                params![diary_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| DiaryError::NotFound(format!("Diary entry {}", diary_id)))?;
         
-        // Check for existing relationships
-        let rel_count: i32 = conn.query_row(
-            "SELECT COUNT(*) FROM relationships WHERE parent_id = ?1 OR child_id = ?1",
-            params![id],
-            |row| row.get(0)
-        )?;
-        println!("📝 [DELETE_DIARY] Found {} relationships for this diary", rel_count);
+        Ok(Self::sync_wikilinks_with(&conn, crypto, diary_id, &crypto.try_decrypt(&encrypted_content)?)?.unresolved)
+    }
+    
+    fn sync_wikilinks_with(
+        conn: &Connection,
+        crypto: &Crypto,
+        diary_id: &str,
+        content: &str,
+    ) -> DiaryResult<WikilinkSync> {
+        let links = extract_wikilinks(content);
+        let titles = if links.is_empty() {
+            HashMap::new()
+        } else {
+            Self::title_index(conn, crypto)?
+        };
         
-        // Check for existing tags
-        let tags_count: i32 = conn.query_row(
-            "SELECT COUNT(*) FROM diary_tags WHERE diary_id = ?1",
-            params![id],
-            |row| row.get(0)
-        )?;
-        println!("📝 [DELETE_DIARY] Found {} tag connections for this diary", tags_count);
+        Self::sync_wikilinks_indexed(conn, &titles, diary_id, content)
+    }
+    
+    /// `sync_wikilinks_with` against a prebuilt `title_index`, so batch
+    /// callers decrypt every title once instead of once per entry.
+    fn sync_wikilinks_indexed(
+        conn: &Connection,
+        titles: &HashMap<String, Vec<String>>,
+        diary_id: &str,
+        content: &str,
+    ) -> DiaryResult<WikilinkSync> {
+        let links = extract_wikilinks(content);
         
-        // First, manually delete any relationships
-        println!("📝 [DELETE_DIARY] Step 1: Manually deleting relationships");
-        let deleted_rels = conn.execute(
-            "DELETE FROM relationships WHERE parent_id = ?1 OR child_id = ?1",
-            params![id]
-        )?;
-        println!("📝 [DELETE_DIARY] Deleted {} relationships", deleted_rels);
+        let mut unresolved = Vec::new();
+        let mut targets: Vec<&str> = Vec::new();
+        for link in &links {
+            match titles.get(&normalize::fold(link)) {
+                Some(ids) => targets.extend(
+                    ids.iter().map(String::as_str).filter(|id| *id != diary_id),
+                ),
+                None => unresolved.push(link.clone()),
+            }
+        }
         
-        // Second, manually delete tag connections
-        println!("📝 [DELETE_DIARY] Step 2: Manually deleting tag connections");
-        let deleted_tags = conn.execute(
-            "DELETE FROM diary_tags WHERE diary_id = ?1",
-            params![id]
-        )?;
-        println!("📝 [DELETE_DIARY] Deleted {} tag connections", deleted_tags);
+        // Drop links whose [[target]] was removed from the text; manual
+        // relationships are never touched here.
+        let existing: Vec<(String, String)> = {
+            let mut stmt = conn.prepare(
+                "SELECT id, child_id FROM relationships WHERE parent_id = ?1 AND source = ?2"
+            )?;
+            let existing_iter = stmt.query_map(params![diary_id, SOURCE_WIKILINK], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+            existing_iter.collect::<SqliteResult<_>>()?
+        };
+        let mut removed = vec![diary_id];
+        for (id, child_id) in &existing {
+            if !targets.contains(&child_id.as_str()) {
+                conn.execute("DELETE FROM relationships WHERE id = ?1", params![id])?;
+                removed.push(child_id);
+            }
+        }
         
-        // Finally, delete the diary entry
-        println!("📝 [DELETE_DIARY] Step 3: Deleting the diary entry");
-        let deleted_diary = conn.execute(
-            "DELETE FROM diary_entries WHERE id = ?1",
-            params![id]
-        )?;
-        println!("📝 [DELETE_DIARY] Deleted {} diary entries", deleted_diary);
+        let now = Utc::now().to_rfc3339();
+        let mut added = vec![diary_id];
+        for target in targets {
+            if existing.iter().any(|(_, child_id)| child_id == target) {
+                continue;
+            }
+            // A manual relationship of the same type already covers this link.
+            let inserted = conn.execute(
+                "INSERT OR IGNORE INTO relationships (id, parent_id, child_id, relationship_type, created_at, source, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?5)",
+                params![
+                    Uuid::new_v4().to_string(),
+                    diary_id,
+                    target,
+                    WIKILINK_RELATIONSHIP_TYPE,
+                    now,
+                    SOURCE_WIKILINK
+                ],
+            )?;
+            if inserted > 0 {
+                added.push(target);
+            }
+        }
         
-        if deleted_diary == 0 {
-            println!("⚠️ [DELETE_DIARY] Warning: No diary entries were deleted!");
-            return Err(rusqlite::Error::QueryReturnedNoRows);
+        if removed.len() > 1 {
+            Self::audit(conn, AuditOperation::RelationshipRemove, &removed, SOURCE_WIKILINK)?;
         }
+        if added.len() > 1 {
+            Self::audit(conn, AuditOperation::RelationshipAdd, &added, SOURCE_WIKILINK)?;
+        }
+        let changed = removed.len() > 1 || added.len() > 1;
+        Ok(WikilinkSync { unresolved, changed })
+    }
+    
+    /// Ids of every entry whose title equals `title`, ignoring case and
+    /// accents.
+    pub fn find_diary_by_title(&self, title: &str) -> DiaryResult<Vec<String>> {
+        let wanted = normalize::fold(title);
+        self.with_titles(|titles| {
+            titles
+                .iter()
+                .filter(|(key, _)| *key == wanted)
+                .map(|(_, entry)| entry.id.clone())
+                .collect()
+        })
+    }
+    
+    /// Entries whose title starts with `prefix`, ignoring case and accents,
+    /// most recently updated first.
+    pub fn search_titles(&self, prefix: &str, limit: usize) -> DiaryResult<Vec<TitleMatch>> {
+        let prefix = normalize::fold(prefix);
+        self.with_titles(|titles| {
+            titles
+                .iter()
+                .filter(|(key, _)| key.starts_with(&prefix))
+                .take(limit)
+                .map(|(_, entry)| entry.clone())
+                .collect()
+        })
+    }
+    
+    fn with_titles<R>(&self, f: impl FnOnce(&[(String, TitleMatch)]) -> R) -> DiaryResult<R> {
+        let crypto = self.crypto()?;
+        let mut cache = self.title_cache.lock().unwrap();
         
-        // Verify all relationships were deleted
-        let remaining_rels: i32 = conn.query_row(
-            "SELECT COUNT(*) FROM relationships WHERE parent_id = ?1 OR child_id = ?1",
-            params![id],
-            |row| row.get(0)
-        )?;
-        println!("📝 [DELETE_DIARY] Remaining relationships: {}", remaining_rels);
+        if cache.is_none() {
+            let conn = self.pool.get().expect("Failed to get database connection");
+            let mut stmt = conn.prepare("SELECT id, title FROM diary_entries ORDER BY updated_at DESC")?;
+            let title_iter = stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?;
+            
+            let mut titles = Vec::new();
+            for title_result in title_iter {
+                let (id, encrypted_title) = title_result?;
+                let title = decrypt_migrated(crypto, &encrypted_title)?;
+                titles.push((normalize::fold(&title), TitleMatch { id, title }));
+            }
+            *cache = Some(titles);
+        }
         
-        if remaining_rels > 0 {
-            println!("⚠️ [DELETE_DIARY] Warning: Some relationships remained after deletion!");
+        Ok(f(cache.as_deref().unwrap_or_default()))
+    }
+    
+    fn invalidate_titles(&self) {
+        *self.title_cache.lock().unwrap() = None;
+    }
+    
+    /// Maps folded titles (see `normalize::fold`) to the ids of the entries
+    /// carrying them.
+    fn title_index(conn: &Connection, crypto: &Crypto) -> DiaryResult<HashMap<String, Vec<String>>> {
+        let mut stmt = conn.prepare("SELECT id, title FROM diary_entries ORDER BY created_at DESC")?;
+        let title_iter = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        
+        let mut titles: HashMap<String, Vec<String>> = HashMap::new();
+        for title_result in title_iter {
+            let (id, encrypted_title) = title_result?;
+            let title = decrypt_migrated(crypto, &encrypted_title)?;
+            titles.entry(normalize::fold(&title)).or_default().push(id);
         }
         
-        // Verify all tag connections were deleted
-        let remaining_tags: i32 = conn.query_row(
-            "SELECT COUNT(*) FROM diary_tags WHERE diary_id = ?1",
-            params![id],
-            |row| row.get(0)
+        Ok(titles)
+    }
+    
+    /// Returns the tag matching `tag_name` ignoring case and accents, the
+    /// oldest one if several do, or creates it with its name in NFC.
+    fn get_or_create_tag(&self, conn: &Connection, tag_name: &str) -> SqliteResult<String> {
+        let name_key = normalize::fold(tag_name);
+        
+        // Try to find existing tag
+        let mut stmt = conn.prepare("SELECT id FROM tags WHERE name_key = ?1 ORDER BY rowid LIMIT 1")?;
+        let mut rows = stmt.query(params![name_key])?;
+        
+        if let Some(row) = rows.next()? {
+            return Ok(row.get(0)?);
+        }
+        
+        // Create new tag if not found
+        let tag_id = Uuid::new_v4().to_string();
+        conn.execute(
+            "INSERT INTO tags (id, name, name_key) VALUES (?1, ?2, ?3)",
+            params![tag_id, normalize::nfc(tag_name), name_key],
         )?;
-        println!("📝 [DELETE_DIARY] Remaining tag connections: {}", remaining_tags);
         
-        if remaining_tags > 0 {
-            println!("⚠️ [DELETE_DIARY] Warning: Some tag connections remained after deletion!");
+        Ok(tag_id)
+    }
+    
+    /// Fills `name_key` for tags created before it existed.
+    fn backfill_tag_keys(conn: &Connection) -> SqliteResult<()> {
+        let mut stmt = conn.prepare("SELECT id, name FROM tags WHERE name_key IS NULL")?;
+        let tags: Vec<(String, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<SqliteResult<_>>()?;
+        
+        for (id, name) in &tags {
+            conn.execute(
+                "UPDATE tags SET name_key = ?1 WHERE id = ?2",
+                params![normalize::fold(name), id],
+            )?;
+        }
+        if !tags.is_empty() {
+            println!("📝 [MIGRATION] Backfilled match keys for {} tags", tags.len());
         }
         
-        println!("📝 [DELETE_DIARY] Deletion process completed successfully");
         Ok(())
     }
-
-    pub fn add_relationship(&self, id: &str, parent_id: &str, child_id: &str, relationship_type: &str) -> SqliteResult<String> {
+    
+    /// Tags whose name starts with `prefix`, ignoring case and accents,
+    /// most used first.
+    pub fn suggest_tags(&self, prefix: &str, limit: usize) -> DiaryResult<Vec<TagUsage>> {
         let conn = self.pool.get().expect("Failed to get database connection");
-        let now = Utc::now().to_rfc3339();
+        let prefix = normalize::fold(prefix);
         
-        conn.execute(
-            "INSERT INTO relationships (id, parent_id, child_id, relationship_type, created_at) 
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![id, parent_id, child_id, relationship_type, now],
+        // Prefix filtering happens here rather than with LIKE, which would
+        // treat % and _ in the prefix as wildcards.
+        let mut stmt = conn.prepare(
+            "SELECT t.name, t.name_key, COUNT(dt.diary_id) AS uses
+             FROM tags t
+             LEFT JOIN diary_tags dt ON dt.tag_id = t.id
+             GROUP BY t.id
+             ORDER BY uses DESC, t.name"
         )?;
+        let tag_iter = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?))
+        })?;
+        
+        let mut suggestions = Vec::new();
+        for tag_result in tag_iter {
+            let (name, name_key, count) = tag_result?;
+            if name_key.starts_with(&prefix) {
+                suggestions.push(TagUsage { name, count });
+                if suggestions.len() == limit {
+                    break;
+                }
+            }
+        }
         
-        Ok(id.to_string())
+        Ok(suggestions)
     }
     
-    pub fn delete_relationship(&self, id: &str) -> SqliteResult<()> {
-        let conn = self.pool.get().expect("Failed to get database connection");
+    /// Returns the daily note for `date` (`YYYY-MM-DD`, default today) and
+    /// whether it was just created. Notes for past dates are backdated to
+    /// local midnight of that day.
+    pub fn get_or_create_daily_note(&self, date: Option<&str>) -> DiaryResult<(DiaryEntry, bool)> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
+        let today = Local::now().date_naive();
+        let date = match date {
+            Some(date) => parse_daily_date(date)?,
+            None => today,
+        };
+        let date_str = date.format(DAILY_NOTE_DATE_FORMAT).to_string();
         
-        conn.execute(
-            "DELETE FROM relationships WHERE id = ?1",
-            params![id],
-        )?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        // IMMEDIATE so two windows racing here serialize on the write lock.
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
         
-        Ok(())
+        let mut existing: Option<String> = tx
+            .query_row(
+                "SELECT id FROM diary_entries WHERE daily_date = ?1",
+                params![date_str],
+                |row| row.get(0),
+            )
+            .optional()?;
+        
+        if existing.is_none() {
+            // Daily notes written before daily_date existed are matched by
+            // tag and title, then adopted.
+            let mut stmt = tx.prepare(
+                "SELECT e.id, e.title FROM diary_entries e
+                 JOIN diary_tags dt ON dt.diary_id = e.id
+                 JOIN tags t ON t.id = dt.tag_id
+                 WHERE t.name_key = ?1 AND e.daily_date IS NULL"
+            )?;
+            let candidates: Vec<(String, String)> = stmt
+                .query_map(params![normalize::fold(DAILY_NOTE_TAG)], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<SqliteResult<_>>()?;
+            drop(stmt);
+            if let Some((id, _)) = candidates.into_iter().find(|(_, title)| crypto.decrypt(title) == date_str) {
+                tx.execute(
+                    "UPDATE diary_entries SET daily_date = ?1 WHERE id = ?2",
+                    params![date_str, id],
+                )?;
+                existing = Some(id);
+            }
+        }
+        
+        let created = existing.is_none();
+        let diary_id = match existing {
+            Some(id) => id,
+            None => {
+                let created_at = if date == today {
+                    Utc::now()
+                } else {
+                    date.and_hms_opt(0, 0, 0)
+                        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+                        .map(|dt| dt.with_timezone(&Utc))
+                        .unwrap_or_else(Utc::now)
+                };
+                let new_id = Uuid::new_v4().to_string();
+                tx.execute(
+                    "INSERT INTO diary_entries (id, title, content, created_at, updated_at, word_count, daily_date, content_hash)
+                     VALUES (?1, ?2, ?3, ?4, ?4, 0, ?5, ?6)",
+                    params![
+                        new_id,
+                        crypto.encrypt(&date_str),
+                        crypto.encrypt(""),
+                        created_at.to_rfc3339(),
+                        date_str,
+                        content_hash(crypto, &date_str, created_at, "")
+                    ],
+                )?;
+                let tag_id = self.get_or_create_tag(&tx, DAILY_NOTE_TAG)?;
+                tx.execute(
+                    "INSERT OR IGNORE INTO diary_tags (diary_id, tag_id) VALUES (?1, ?2)",
+                    params![new_id, tag_id],
+                )?;
+                println!("📝 [DAILY] Created daily note for {}", date_str);
+                Self::audit(&tx, AuditOperation::Save, &[&new_id], "daily note created")?;
+                new_id
+            }
+        };
+        
+        tx.commit()?;
+        drop(conn);
+        self.invalidate_titles();
+        
+        Ok((self.get_diary(&diary_id)?, created))
+    }
+    
+    /// Appends `text` as a timestamped bullet to the inbox entry, creating
+    /// it if needed. The read-modify-write happens in an IMMEDIATE
+    /// transaction so concurrent captures never drop each other's text.
+    pub fn quick_capture(&self, text: &str) -> DiaryResult<TitleMatch> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(DiaryError::Validation("Nothing to capture".to_string()));
+        }
+        let bullet = format!(
+            "- [{}] {}",
+            Local::now().format("%Y-%m-%d %H:%M"),
+            text.lines().collect::<Vec<_>>().join("\n  ")
+        );
+        
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        
+        let inbox: Option<(String, String, String, String)> = tx
+            .query_row(
+                "SELECT e.id, e.title, e.content, e.created_at FROM diary_entries e
+                 JOIN diary_tags dt ON dt.diary_id = e.id
+                 JOIN tags t ON t.id = dt.tag_id
+                 WHERE t.name_key = ?1
+                 ORDER BY e.created_at, e.id
+                 LIMIT 1",
+                params![normalize::fold(INBOX_TAG)],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+        let now = Utc::now();
+        
+        let (inbox_id, title, content) = match inbox {
+            Some((id, encrypted_title, encrypted_content, created_at)) => {
+                let title = decrypt_migrated(crypto, &encrypted_title)?;
+                let existing = crypto.try_decrypt(&encrypted_content)?;
+                let content = match existing.trim_end() {
+                    "" => bullet,
+                    existing => format!("{}\n{}", existing, bullet),
+                };
+                let created_at = DateTime::parse_from_rfc3339(&created_at)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or(now);
+                tx.execute(
+                    "UPDATE diary_entries SET content = ?1, updated_at = ?2, word_count = ?3, content_hash = ?4
+                     WHERE id = ?5",
+                    params![
+                        crypto.encrypt(&content),
+                        now.to_rfc3339(),
+                        count_words(&content),
+                        content_hash(crypto, &title, created_at, &content),
+                        id
+                    ],
+                )?;
+                (id, title, content)
+            }
+            None => {
+                let id = Uuid::new_v4().to_string();
+                tx.execute(
+                    "INSERT INTO diary_entries (id, title, content, created_at, updated_at, word_count, content_hash)
+                     VALUES (?1, ?2, ?3, ?4, ?4, ?5, ?6)",
+                    params![
+                        id,
+                        crypto.encrypt(INBOX_TITLE),
+                        crypto.encrypt(&bullet),
+                        now.to_rfc3339(),
+                        count_words(&bullet),
+                        content_hash(crypto, INBOX_TITLE, now, &bullet)
+                    ],
+                )?;
+                let tag_id = self.get_or_create_tag(&tx, INBOX_TAG)?;
+                tx.execute(
+                    "INSERT OR IGNORE INTO diary_tags (diary_id, tag_id) VALUES (?1, ?2)",
+                    params![id, tag_id],
+                )?;
+                println!("📝 [CAPTURE] Created inbox entry");
+                (id, INBOX_TITLE.to_string(), bullet)
+            }
+        };
+        Self::sync_wikilinks_with(&tx, crypto, &inbox_id, &content)?;
+        Self::audit(&tx, AuditOperation::Save, &[&inbox_id], "quick capture")?;
+        
+        tx.commit()?;
+        self.invalidate_titles();
+        
+        Ok(TitleMatch { id: inbox_id, title })
+    }
+    
+    /// Daily notes dated between `from` and `to` inclusive, oldest first.
+    pub fn list_daily_notes(&self, from: &str, to: &str) -> DiaryResult<Vec<DiarySummary>> {
+        self.query_diaries(&DiaryFilter {
+            daily_from: Some(parse_daily_date(from)?),
+            daily_to: Some(parse_daily_date(to)?),
+            archived: None,
+            sort: DiarySort::DailyDate,
+            ..DiaryFilter::default()
+        })
+    }
+    
+    pub fn get_diary(&self, id: &str) -> DiaryResult<DiaryEntry> {
+        self.read_diary(id, true)
     }
     
-    pub fn get_relationships(&self, diary_id: &str) -> SqliteResult<Vec<Relationship>> {
+    /// `get_diary` for internal reads such as exports, which with
+    /// `record_view` false don't count as the user opening the entry.
+    fn read_diary(&self, id: &str, record_view: bool) -> DiaryResult<DiaryEntry> {
         let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
         
         let mut stmt = conn.prepare(
-            "SELECT id, parent_id, child_id, relationship_type, created_at 
-             FROM relationships 
-             WHERE parent_id = ?1 OR child_id = ?1"
+            "SELECT id, title, content, created_at, updated_at, word_count, locked FROM diary_entries WHERE id = ?1"
         )?;
         
-        let relationship_iter = stmt.query_map(params![diary_id, diary_id], |row| {
+        let mut rows = stmt.query(params![id])?;
+        
+        if let Some(row) = rows.next()? {
+            let locked: bool = row.get(6)?;
+            if locked && !self.is_elevated() {
+                return Err(DiaryError::EntryLocked);
+            }
+            
+            // Viewing is not an edit, so updated_at stays; a read-only
+            // vault doesn't record views at all.
+            if record_view && !self.read_only {
+                Self::record_view(&conn, id);
+            }
+            
             let id: String = row.get(0)?;
-            let parent_id: String = row.get(1)?;
-            let child_id: String = row.get(2)?;
-            let relationship_type: String = row.get(3)?;
-            let created_at_str: String = row.get(4)?;
+            let encrypted_title: String = row.get(1)?;
+            let encrypted_content: String = row.get(2)?;
+            let created_at: String = row.get(3)?;
+            let updated_at: String = row.get(4)?;
+            let word_count: Option<i64> = row.get(5)?;
             
-            let created_at = DateTime::parse_from_rfc3339(&created_at_str)
+            let title = decrypt_migrated(crypto, &encrypted_title).expect("Decryption failed");
+            let content = crypto.decrypt(&encrypted_content);
+            let word_count = word_count.unwrap_or_else(|| count_words(&content));
+            let created_at = DateTime::parse_from_rfc3339(&created_at)
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now());
+            let updated_at = DateTime::parse_from_rfc3339(&updated_at)
                 .map(|dt| dt.with_timezone(&Utc))
                 .unwrap_or_else(|_| Utc::now());
             
-            Ok(Relationship {
+            // Get tags for this diary entry
+            let tags = self.get_tags_for_diary(&id)?;
+            let properties = self.get_properties_for_diary(&id)?;
+            
+            Ok(DiaryEntry {
                 id,
-                parent_id,
-                child_id,
-                relationship_type,
-                created_at: created_at.to_rfc3339(),
+                title,
+                content,
+                created_at,
+                updated_at,
+                tags,
+                word_count,
+                reading_time_minutes: reading_time_minutes(word_count),
+                properties,
+                locked,
             })
+        } else {
+            Err(rusqlite::Error::QueryReturnedNoRows.into())
+        }
+    }
+    
+    /// Moves last_viewed_at and counts the view, in one transaction so the
+    /// count and the view history agree. Failing to record a view, e.g.
+    /// because another connection holds the write lock, is logged rather
+    /// than failing the read.
+    fn record_view(conn: &Connection, id: &str) {
+        let now = Utc::now().to_rfc3339();
+        // The caller is still reading the entry through `conn`, so this
+        // can't take the `&mut` that `Connection::transaction` needs.
+        let result = Transaction::new_unchecked(conn, TransactionBehavior::Deferred).and_then(|tx| {
+            tx.execute(
+                "UPDATE diary_entries SET last_viewed_at = ?1, view_count = view_count + 1 WHERE id = ?2",
+                params![now, id],
+            )?;
+            tx.execute("INSERT INTO diary_views (diary_id, viewed_at) VALUES (?1, ?2)", params![id, now])?;
+            tx.commit()
+        });
+        if let Err(e) = result {
+            println!("⚠️ [VIEW] Could not record view of {}: {}", id, e);
+        }
+    }
+    
+    /// Custom properties of an entry as a JSON object.
+    fn get_properties_for_diary(&self, diary_id: &str) -> DiaryResult<serde_json::Value> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        
+        let mut stmt = conn.prepare("SELECT key, value FROM diary_properties WHERE diary_id = ?1 ORDER BY key")?;
+        let property_iter = stmt.query_map(params![diary_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
         })?;
         
-        let mut relationships = Vec::new();
-        for relationship_result in relationship_iter {
-            relationships.push(relationship_result?);
+        let mut properties = serde_json::Map::new();
+        for property_result in property_iter {
+            let (key, encrypted_value) = property_result?;
+            properties.insert(key, decode_property(crypto, &encrypted_value)?);
         }
         
-        Ok(relationships)
+        Ok(serde_json::Value::Object(properties))
+    }
+    
+    /// Sets (or replaces) one custom property on an entry.
+    pub fn set_diary_property(&self, diary_id: &str, key: &str, value: &serde_json::Value) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        
+        if key.trim().is_empty() {
+            return Err(DiaryError::Validation("Property key must not be empty".to_string()));
+        }
+        let diary_exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM diary_entries WHERE id = ?1)",
+            params![diary_id],
+            |row| row.get(0),
+        )?;
+        if !diary_exists {
+            return Err(DiaryError::NotFound(format!("Diary entry {}", diary_id)));
+        }
+        self.ensure_readable(&conn, diary_id)?;
+        
+        let tx = conn.transaction()?;
+        Self::write_property(&tx, crypto, diary_id, key, value)?;
+        Self::audit(&tx, AuditOperation::PropertySet, &[diary_id], key)?;
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    fn write_property(
+        conn: &Connection,
+        crypto: &Crypto,
+        diary_id: &str,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> SqliteResult<()> {
+        conn.execute(
+            "INSERT INTO diary_properties (diary_id, key, value) VALUES (?1, ?2, ?3)
+             ON CONFLICT(diary_id, key) DO UPDATE SET value = excluded.value",
+            params![diary_id, key, crypto.encrypt(&value.to_string())],
+        )?;
+        Ok(())
     }
-} 
\ No newline at end of file
+    
+    pub fn delete_diary_property(&self, diary_id: &str, key: &str) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        self.ensure_readable(&conn, diary_id)?;
+        
+        let tx = conn.transaction()?;
+        let deleted = tx.execute(
+            "DELETE FROM diary_properties WHERE diary_id = ?1 AND key = ?2",
+            params![diary_id, key],
+        )?;
+        if deleted == 0 {
+            return Err(DiaryError::NotFound(format!("Property {} on diary entry {}", key, diary_id)));
+        }
+        Self::audit(&tx, AuditOperation::PropertyDelete, &[diary_id], key)?;
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    /// Entries whose property `key` equals `value` exactly.
+    pub fn query_diaries_by_property(&self, key: &str, value: &serde_json::Value) -> DiaryResult<Vec<DiaryEntry>> {
+        let listed = self.query_diaries_with_content(&DiaryFilter {
+            properties: vec![PropertyPredicate { key: key.to_string(), op: PropertyOp::Eq, value: value.clone() }],
+            archived: None,
+            sort: DiarySort::CreatedDesc,
+            ..DiaryFilter::default()
+        })?;
+        self.full_entries(listed)
+    }
+    
+    /// Completes listed entries with their properties.
+    fn full_entries(&self, listed: Vec<ListedDiary>) -> DiaryResult<Vec<DiaryEntry>> {
+        let mut diaries = Vec::with_capacity(listed.len());
+        for ListedDiary { summary, content, locked } in listed {
+            let word_count = summary.word_count.unwrap_or_else(|| count_words(&content));
+            let properties = self.get_properties_for_diary(&summary.id)?;
+            
+            diaries.push(DiaryEntry {
+                id: summary.id,
+                title: summary.title,
+                content,
+                created_at: summary.created_at,
+                updated_at: summary.updated_at,
+                tags: summary.tags,
+                word_count,
+                reading_time_minutes: reading_time_minutes(word_count),
+                properties,
+                locked,
+            });
+        }
+        
+        Ok(diaries)
+    }
+    
+    fn get_tags_for_diary(&self, diary_id: &str) -> SqliteResult<Vec<String>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let mut stmt = conn.prepare(
+            "SELECT t.name FROM tags t
+             JOIN diary_tags dt ON t.id = dt.tag_id
+             WHERE dt.diary_id = ?1"
+        )?;
+        
+        let tag_iter = stmt.query_map(params![diary_id], |row| {
+            let name: String = row.get(0)?;
+            Ok(name)
+        })?;
+        
+        let mut tags = Vec::new();
+        for tag_result in tag_iter {
+            tags.push(tag_result?);
+        }
+        
+        Ok(tags)
+    }
+    
+    pub fn list_diaries(&self) -> DiaryResult<Vec<DiaryEntry>> {
+        let listed = self.query_diaries_with_content(&DiaryFilter {
+            archived: None,
+            sort: DiarySort::CreatedDesc,
+            ..DiaryFilter::default()
+        })?;
+        self.full_entries(listed)
+    }
+    
+    /// Full-text search over titles and content. Every whitespace-separated
+    /// term must appear (case-insensitively) in the title or content; hits
+    /// are ordered by most recently updated.
+    pub fn search_diaries(&self, query: &str, limit: usize) -> DiaryResult<Vec<SearchResult>> {
+        let terms: Vec<&str> = query.split_whitespace().collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let listed = self.query_diaries_with_content(&DiaryFilter {
+            text: query.to_string(),
+            archived: None,
+            limit: Some(limit),
+            ..DiaryFilter::default()
+        })?;
+        
+        let mut results = Vec::with_capacity(listed.len());
+        // Locked entries are only found by their title.
+        for ListedDiary { summary: entry, content, .. } in listed {
+            let content_matches = match_all_terms(&entry.title, &content, &terms).unwrap_or_default();
+            let (snippet, matches) = cut_snippet(&content, &content_matches, SNIPPET_CHARS);
+            results.push(SearchResult { entry, snippet, matches });
+        }
+        
+        Ok(results)
+    }
+    
+    /// Entries matching every filter of `query`, most recently updated
+    /// first. Tags that no longer exist match nothing rather than failing.
+    pub fn search_entries(&self, query: &SearchQuery) -> DiaryResult<Vec<DiarySummary>> {
+        let created_from = match query.within_days {
+            Some(days) => {
+                let cutoff = Utc::now() - Duration::days(i64::from(days));
+                Some(query.created_from.map_or(cutoff, |from| from.max(cutoff)))
+            }
+            None => query.created_from,
+        };
+        
+        self.query_diaries(&DiaryFilter {
+            tags: query.tags.clone(),
+            tag_match: query.tag_match,
+            text: query.text.clone(),
+            created_from,
+            created_to: query.created_to,
+            archived: if query.include_archived { None } else { Some(false) },
+            ..DiaryFilter::default()
+        })
+    }
+    
+    /// Entries passing every filter of `filter`, sorted and paged as it
+    /// asks. The single-purpose listings and searches go through here too.
+    pub fn query_diaries(&self, filter: &DiaryFilter) -> DiaryResult<Vec<DiarySummary>> {
+        Ok(self.filter_diaries(filter, false)?.into_iter().map(|listed| listed.summary).collect())
+    }
+    
+    /// `query_diaries` with each entry's content, decrypted once for both
+    /// the text filter and the caller.
+    fn query_diaries_with_content(&self, filter: &DiaryFilter) -> DiaryResult<Vec<ListedDiary>> {
+        self.filter_diaries(filter, true)
+    }
+    
+    /// Runs `filter`. Content is only decrypted with `with_content` or to
+    /// match text, and stays empty for locked entries outside an elevated
+    /// session.
+    fn filter_diaries(&self, filter: &DiaryFilter, with_content: bool) -> DiaryResult<Vec<ListedDiary>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        
+        let (sql, sql_params) = filter::build_sql(filter);
+        let terms: Vec<&str> = filter.text.split_whitespace().collect();
+        let elevated = self.is_elevated();
+        let limit = filter.limit.unwrap_or(usize::MAX);
+        // Titles are encrypted, so a title sort pages after sorting here.
+        let page_while_reading = filter.sort != DiarySort::Title;
+        
+        // Dates are compared parsed, as stored timestamps don't all share
+        // one format, and content and property values are encrypted, so
+        // those filter in Rust.
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(&sql_params))?;
+        let mut skipped = 0;
+        let mut listed = Vec::new();
+        'rows: while let Some(row) = rows.next()? {
+            if page_while_reading && listed.len() >= limit {
+                break;
+            }
+            let summary = Self::summary_from_row(crypto, row)?;
+            if filter.created_from.is_some_and(|from| summary.created_at < from)
+                || filter.created_to.is_some_and(|to| summary.created_at > to)
+            {
+                continue;
+            }
+            let locked: bool = row.get(7)?;
+            let content = if (with_content || !terms.is_empty()) && (elevated || !locked) {
+                crypto.decrypt(&row.get::<_, String>(6)?)
+            } else {
+                String::new()
+            };
+            if !terms.is_empty() && match_all_terms(&summary.title, &content, &terms).is_none() {
+                continue;
+            }
+            for (index, predicate) in filter.properties.iter().enumerate() {
+                let encrypted_value: String = row.get(filter::PROPERTY_COLUMN + index)?;
+                if !predicate.matches(&decode_property(crypto, &encrypted_value)?) {
+                    continue 'rows;
+                }
+            }
+            if page_while_reading && skipped < filter.offset {
+                skipped += 1;
+                continue;
+            }
+            listed.push(ListedDiary { summary, content, locked });
+        }
+        if !page_while_reading {
+            listed.sort_by_cached_key(|listed| normalize::fold(&listed.summary.title));
+            listed = listed.into_iter().skip(filter.offset).take(limit).collect();
+        }
+        
+        for ListedDiary { summary, .. } in &mut listed {
+            summary.tags = self.get_tags_for_diary(&summary.id)?;
+        }
+        Ok(listed)
+    }
+    
+    pub fn save_search(&self, name: &str, query: &SearchQuery) -> DiaryResult<SavedSearch> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        if name.trim().is_empty() {
+            return Err(DiaryError::Validation("Saved search name must not be empty".to_string()));
+        }
+        if query.version > SEARCH_QUERY_VERSION {
+            return Err(DiaryError::Validation(format!("Unsupported search query version {}", query.version)));
+        }
+        let saved = SavedSearch {
+            id: Uuid::new_v4().to_string(),
+            name: name.trim().to_string(),
+            query: SearchQuery { version: SEARCH_QUERY_VERSION, ..query.clone() },
+            created_at: Utc::now().to_rfc3339(),
+        };
+        let query_json = serde_json::to_string(&saved.query).expect("query serializes");
+        conn.execute(
+            "INSERT INTO saved_searches (id, name, query, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![saved.id, crypto.encrypt(&saved.name), crypto.encrypt(&query_json), saved.created_at],
+        )?;
+        
+        Ok(saved)
+    }
+    
+    /// All saved searches, sorted by name. Searches that can't be read,
+    /// such as ones saved by a newer version, are logged and left out so
+    /// they don't hide the rest.
+    pub fn list_saved_searches(&self) -> DiaryResult<Vec<SavedSearch>> {
+        let crypto = self.crypto()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let mut stmt = conn.prepare("SELECT id, name, query, created_at FROM saved_searches")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?))
+        })?;
+        
+        let mut searches = Vec::new();
+        for row in rows {
+            let (id, encrypted_name, encrypted_query, created_at) = row?;
+            let decrypted = crypto.try_decrypt(&encrypted_name).map_err(DiaryError::from).and_then(|name| {
+                let json = crypto.try_decrypt(&encrypted_query)?;
+                Ok((name, SearchQuery::from_stored(&json)?))
+            });
+            let (name, query) = match decrypted {
+                Ok(decrypted) => decrypted,
+                Err(e) => {
+                    println!("⚠️ [SAVED_SEARCH] Skipping saved search {}: {}", id, e);
+                    continue;
+                }
+            };
+            searches.push(SavedSearch {
+                id,
+                name,
+                query,
+                created_at,
+            });
+        }
+        searches.sort_by_key(|search| search.name.to_lowercase());
+        
+        Ok(searches)
+    }
+    
+    pub fn delete_saved_search(&self, id: &str) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let deleted = conn.execute("DELETE FROM saved_searches WHERE id = ?1", params![id])?;
+        if deleted == 0 {
+            return Err(DiaryError::NotFound(format!("Saved search {}", id)));
+        }
+        
+        Ok(())
+    }
+    
+    /// Runs a saved search through `search_entries`.
+    pub fn run_saved_search(&self, id: &str) -> DiaryResult<Vec<DiarySummary>> {
+        let query = {
+            let crypto = self.crypto()?;
+            let conn = self.pool.get().expect("Failed to get database connection");
+            let encrypted_query: String = conn
+                .query_row("SELECT query FROM saved_searches WHERE id = ?1", params![id], |row| row.get(0))
+                .optional()?
+                .ok_or_else(|| DiaryError::NotFound(format!("Saved search {}", id)))?;
+            SearchQuery::from_stored(&crypto.try_decrypt(&encrypted_query)?)?
+        };
+        
+        self.search_entries(&query)
+    }
+    
+    /// Pins an entry at the end of the manual pinned order. Pinning an
+    /// already pinned entry leaves it where it is.
+    pub fn pin_diary(&self, id: &str) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let updated = conn.execute(
+            "UPDATE diary_entries
+             SET pinned_at = ?1,
+                 sort_order = (SELECT COALESCE(MAX(sort_order), -1) + 1 FROM diary_entries WHERE pinned_at IS NOT NULL)
+             WHERE id = ?2 AND pinned_at IS NULL",
+            params![Utc::now().to_rfc3339(), id],
+        )?;
+        if updated == 0 {
+            let exists: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM diary_entries WHERE id = ?1)",
+                params![id],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                return Err(DiaryError::NotFound(format!("Diary entry {}", id)));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    pub fn unpin_diary(&self, id: &str) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let updated = conn.execute(
+            "UPDATE diary_entries SET pinned_at = NULL, sort_order = NULL WHERE id = ?1",
+            params![id],
+        )?;
+        if updated == 0 {
+            return Err(DiaryError::NotFound(format!("Diary entry {}", id)));
+        }
+        
+        Ok(())
+    }
+    
+    /// Marks an entry as archived. Archiving an archived entry keeps its
+    /// original archive time.
+    pub fn archive_diary(&self, id: &str) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        
+        let updated = tx.execute(
+            "UPDATE diary_entries SET archived_at = COALESCE(archived_at, ?1) WHERE id = ?2",
+            params![Utc::now().to_rfc3339(), id],
+        )?;
+        if updated == 0 {
+            return Err(DiaryError::NotFound(format!("Diary entry {}", id)));
+        }
+        Self::audit(&tx, AuditOperation::Archive, &[id], "")?;
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    pub fn unarchive_diary(&self, id: &str) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        
+        let updated = tx.execute(
+            "UPDATE diary_entries SET archived_at = NULL WHERE id = ?1",
+            params![id],
+        )?;
+        if updated == 0 {
+            return Err(DiaryError::NotFound(format!("Diary entry {}", id)));
+        }
+        Self::audit(&tx, AuditOperation::Restore, &[id], "")?;
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    /// Pinned entries in their manual order; pins without an order follow,
+    /// most recently updated first.
+    pub fn list_pinned_diaries(&self) -> DiaryResult<Vec<DiarySummary>> {
+        self.query_diaries(&DiaryFilter {
+            pinned: Some(true),
+            archived: None,
+            sort: DiarySort::Pinned,
+            ..DiaryFilter::default()
+        })
+    }
+    
+    /// Rewrites the pinned order so `ids_in_order` come first, in that
+    /// order. Pinned entries left out keep their relative order after them.
+    pub fn reorder_pinned_diaries(&self, ids_in_order: &[String]) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        
+        let pinned: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT id FROM diary_entries
+                 WHERE pinned_at IS NOT NULL
+                 ORDER BY sort_order IS NULL, sort_order, updated_at DESC"
+            )?;
+            let id_iter = stmt.query_map([], |row| row.get(0))?;
+            id_iter.collect::<SqliteResult<_>>()?
+        };
+        
+        let mut seen = HashSet::new();
+        for id in ids_in_order {
+            if !pinned.contains(id) {
+                return Err(DiaryError::Validation(format!("Diary entry {} is not pinned", id)));
+            }
+            if !seen.insert(id.as_str()) {
+                return Err(DiaryError::Validation(format!("Diary entry {} is listed twice", id)));
+            }
+        }
+        
+        {
+            let mut update = tx.prepare("UPDATE diary_entries SET sort_order = ?1 WHERE id = ?2")?;
+            let rest = pinned.iter().filter(|id| !seen.contains(id.as_str()));
+            for (position, id) in ids_in_order.iter().chain(rest).enumerate() {
+                update.execute(params![position as i64, id])?;
+            }
+        }
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    /// Most recently edited (`by = "updated"`) or opened (`by = "viewed"`)
+    /// entries. Content is never decrypted.
+    pub fn list_recent_diaries(&self, limit: usize, by: &str) -> DiaryResult<Vec<DiarySummary>> {
+        match by {
+            "updated" => {
+                return self.query_diaries(&DiaryFilter {
+                    archived: None,
+                    limit: Some(limit),
+                    ..DiaryFilter::default()
+                })
+            }
+            "viewed" => {}
+            other => {
+                return Err(DiaryError::Validation(format!(
+                    "Unknown recent ordering '{}', expected 'updated' or 'viewed'",
+                    other
+                )))
+            }
+        }
+        
+        // Views are app history rather than something to filter entries by,
+        // so `DiaryFilter` has no view sort and this one keeps its own query.
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, title, created_at, updated_at, last_viewed_at, word_count
+             FROM diary_entries WHERE last_viewed_at IS NOT NULL
+             ORDER BY last_viewed_at DESC LIMIT ?1"
+        )?;
+        let summary_iter = stmt.query_map(params![limit as i64], |row| Self::summary_from_row(crypto, row))?;
+        
+        let mut summaries = Vec::new();
+        for summary_result in summary_iter {
+            let mut summary = summary_result?;
+            summary.tags = self.get_tags_for_diary(&summary.id)?;
+            summaries.push(summary);
+        }
+        
+        Ok(summaries)
+    }
+    
+    /// Writes a review of `from` to `to` (YYYY-MM-DD, inclusive, in local
+    /// time) as an entry tagged `review`: entries created and modified, tags
+    /// first used, relationships added and what is left in the inbox.
+    /// Generating the same period again updates its review entry.
+    pub fn generate_review(&self, from: &str, to: &str) -> DiaryResult<TitleMatch> {
+        self.ensure_writable()?;
+        let (from_date, to_date) = (parse_daily_date(from)?, parse_daily_date(to)?);
+        if from_date > to_date {
+            return Err(DiaryError::Validation("A review can't end before it starts".to_string()));
+        }
+        let local_midnight = |date: NaiveDate| {
+            date.and_hms_opt(0, 0, 0)
+                .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or_else(Utc::now)
+        };
+        let start = local_midnight(from_date);
+        let end = to_date.succ_opt().map_or(DateTime::<Utc>::MAX_UTC, local_midnight);
+        let in_period = |at: DateTime<Utc>| start <= at && at < end;
+        
+        let entries = self.search_entries(&SearchQuery { include_archived: true, ..SearchQuery::default() })?;
+        let has_tag = |entry: &DiarySummary, tag: &str| {
+            let key = normalize::fold(tag);
+            entry.tags.iter().any(|tag| normalize::fold(tag) == key)
+        };
+        let titles: HashMap<&str, &str> = entries.iter().map(|entry| (entry.id.as_str(), entry.title.as_str())).collect();
+        let reviews: HashSet<&str> =
+            entries.iter().filter(|entry| has_tag(entry, REVIEW_TAG)).map(|entry| entry.id.as_str()).collect();
+        
+        let mut created = Vec::new();
+        let mut modified = Vec::new();
+        let mut first_used: HashMap<String, (DateTime<Utc>, &str)> = HashMap::new();
+        for entry in entries.iter().filter(|entry| !reviews.contains(entry.id.as_str())) {
+            if in_period(entry.created_at) {
+                created.push(entry);
+            } else if entry.created_at < start && in_period(entry.updated_at) {
+                modified.push(entry);
+            }
+            for tag in &entry.tags {
+                let used = first_used.entry(normalize::fold(tag)).or_insert((entry.created_at, tag));
+                if entry.created_at < used.0 {
+                    *used = (entry.created_at, tag);
+                }
+            }
+        }
+        created.sort_by_key(|entry| entry.created_at);
+        modified.sort_by_key(|entry| entry.updated_at);
+        let mut new_tags: Vec<String> = first_used
+            .into_values()
+            .filter(|(first, _)| in_period(*first))
+            .map(|(_, tag)| tag.to_string())
+            .collect();
+        new_tags.sort();
+        
+        let mut relationships = Vec::new();
+        {
+            let conn = self.pool.get().expect("Failed to get database connection");
+            let mut stmt = conn.prepare(
+                "SELECT parent_id, child_id, relationship_type, created_at FROM relationships ORDER BY created_at"
+            )?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let (parent_id, child_id): (String, String) = (row.get(0)?, row.get(1)?);
+                let created_at: String = row.get(3)?;
+                let Ok(created_at) = DateTime::parse_from_rfc3339(&created_at) else {
+                    continue;
+                };
+                // Links out of earlier reviews aren't news.
+                if !in_period(created_at.with_timezone(&Utc))
+                    || reviews.contains(parent_id.as_str())
+                    || reviews.contains(child_id.as_str())
+                {
+                    continue;
+                }
+                if let (Some(parent), Some(child)) = (titles.get(parent_id.as_str()), titles.get(child_id.as_str())) {
+                    relationships.push(ReviewRelationship {
+                        parent: parent.to_string(),
+                        child: child.to_string(),
+                        relationship_type: row.get(2)?,
+                    });
+                }
+            }
+        }
+        
+        let mut inbox: Vec<String> = Vec::new();
+        for entry in entries.iter().filter(|entry| has_tag(entry, INBOX_TAG)) {
+            let content = match self.read_diary(&entry.id, false) {
+                Ok(diary) => diary.content,
+                Err(DiaryError::EntryLocked) => continue,
+                Err(e) => return Err(e),
+            };
+            for line in content.lines() {
+                match (line.strip_prefix("- "), inbox.last_mut()) {
+                    (Some(item), _) => inbox.push(item.trim().to_string()),
+                    (None, Some(last)) if line.starts_with("  ") => {
+                        last.push(' ');
+                        last.push_str(line.trim());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        
+        let review = Review {
+            from: from_date,
+            to: to_date,
+            created: created.iter().map(|entry| entry.title.clone()).collect(),
+            modified: modified.iter().map(|entry| entry.title.clone()).collect(),
+            new_tags,
+            relationships,
+            inbox,
+        };
+        let title = review::title(from_date, to_date);
+        let existing = self.find_diary_by_title(&title)?.into_iter().find(|id| reviews.contains(id.as_str()));
+        let id = self.save_diary(existing.as_deref(), &title, &review::render(&review), &[REVIEW_TAG.to_string()])?;
+        println!("📝 [REVIEW] Wrote review {} for {} to {}", id, from, to);
+        
+        Ok(TitleMatch { id, title })
+    }
+    
+    /// Entries written on today's month and day in earlier years, newest
+    /// first. Days follow the system time zone.
+    pub fn get_on_this_day(&self) -> DiaryResult<Vec<DiarySummary>> {
+        self.on_this_day(Local::now().date_naive())
+    }
+    
+    fn on_this_day(&self, today: NaiveDate) -> DiaryResult<Vec<DiarySummary>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        
+        let mut stmt = conn.prepare(
+            "SELECT id, title, created_at, updated_at, last_viewed_at, word_count
+             FROM diary_entries ORDER BY created_at DESC"
+        )?;
+        let mut rows = stmt.query([])?;
+        
+        // Titles are only decrypted for the entries that match.
+        let mut summaries = Vec::new();
+        while let Some(row) = rows.next()? {
+            let created_at: String = row.get(2)?;
+            let Ok(created_at) = DateTime::parse_from_rfc3339(&created_at) else {
+                continue;
+            };
+            if !is_anniversary(created_at.with_timezone(&Local).date_naive(), today) {
+                continue;
+            }
+            let mut summary = Self::summary_from_row(crypto, row)?;
+            summary.tags = self.get_tags_for_diary(&summary.id)?;
+            summaries.push(summary);
+        }
+        
+        Ok(summaries)
+    }
+    
+    /// Unarchived entries neither updated nor viewed in the last `days`
+    /// days, the longest untouched first.
+    pub fn list_stale_diaries(&self, days: u32, limit: usize) -> DiaryResult<Vec<DiarySummary>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        let cutoff = (Utc::now() - Duration::days(i64::from(days))).to_rfc3339();
+        
+        // Staleness compares the later of the last edit and the last view,
+        // which no `DiaryFilter` bound or sort expresses, so this doesn't go
+        // through `query_diaries`.
+        let mut stmt = conn.prepare(
+            "SELECT id, title, created_at, updated_at, last_viewed_at, word_count
+             FROM diary_entries
+             WHERE archived_at IS NULL
+               AND updated_at < ?1
+               AND (last_viewed_at IS NULL OR last_viewed_at < ?1)
+             ORDER BY MAX(updated_at, COALESCE(last_viewed_at, updated_at)) ASC
+             LIMIT ?2"
+        )?;
+        let summary_iter = stmt.query_map(params![cutoff, limit as i64], |row| Self::summary_from_row(crypto, row))?;
+        
+        let mut summaries = Vec::new();
+        for summary_result in summary_iter {
+            let mut summary = summary_result?;
+            summary.tags = self.get_tags_for_diary(&summary.id)?;
+            summaries.push(summary);
+        }
+        
+        Ok(summaries)
+    }
+    
+    /// Entries opened most often, most viewed first. With `since` (an
+    /// RFC 3339 timestamp) only views from then on are counted. Views by
+    /// exports and other internal reads aren't counted.
+    pub fn list_most_viewed_diaries(&self, limit: usize, since: Option<&str>) -> DiaryResult<Vec<ViewedDiary>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        
+        let rows: Vec<(DiarySummary, i64)> = match since {
+            None => {
+                let mut stmt = conn.prepare(
+                    "SELECT id, title, created_at, updated_at, last_viewed_at, word_count, view_count
+                     FROM diary_entries
+                     WHERE view_count > 0
+                     ORDER BY view_count DESC, last_viewed_at DESC
+                     LIMIT ?1"
+                )?;
+                let row_iter = stmt.query_map(params![limit as i64], |row| {
+                    Ok((Self::summary_from_row(crypto, row)?, row.get(6)?))
+                })?;
+                row_iter.collect::<SqliteResult<_>>()?
+            }
+            Some(since) => {
+                let since = DateTime::parse_from_rfc3339(since)
+                    .map_err(|e| DiaryError::Validation(format!("Invalid timestamp {}: {}", since, e)))?
+                    .with_timezone(&Utc)
+                    .to_rfc3339();
+                let mut stmt = conn.prepare(
+                    "SELECT d.id, d.title, d.created_at, d.updated_at, d.last_viewed_at, d.word_count, v.views
+                     FROM (SELECT diary_id, COUNT(*) AS views FROM diary_views
+                           WHERE viewed_at >= ?1 GROUP BY diary_id) v
+                     JOIN diary_entries d ON d.id = v.diary_id
+                     ORDER BY v.views DESC, d.last_viewed_at DESC
+                     LIMIT ?2"
+                )?;
+                let row_iter = stmt.query_map(params![since, limit as i64], |row| {
+                    Ok((Self::summary_from_row(crypto, row)?, row.get(6)?))
+                })?;
+                row_iter.collect::<SqliteResult<_>>()?
+            }
+        };
+        
+        let mut viewed = Vec::with_capacity(rows.len());
+        for (mut summary, view_count) in rows {
+            summary.tags = self.get_tags_for_diary(&summary.id)?;
+            viewed.push(ViewedDiary { summary, view_count });
+        }
+        
+        Ok(viewed)
+    }
+    
+    /// Days individual views are kept for; `None` keeps them forever.
+    /// Total view counts are unaffected, but `list_most_viewed_diaries`
+    /// can only count views since a time within this period.
+    pub fn get_view_history_retention_days(&self) -> DiaryResult<Option<u32>> {
+        self.retention_days(VIEW_HISTORY_RETENTION_SETTING)
+    }
+    
+    /// Sets how long individual views are kept. Older views are pruned
+    /// each time the vault is opened.
+    pub fn set_view_history_retention_days(&self, days: Option<u32>) -> DiaryResult<()> {
+        self.set_retention_days(VIEW_HISTORY_RETENTION_SETTING, days)
+    }
+    
+    /// Drops views older than the retention setting and returns how many
+    /// went.
+    fn prune_view_history(&self) -> DiaryResult<usize> {
+        let Some(days) = self.get_view_history_retention_days()? else {
+            return Ok(0);
+        };
+        let cutoff = (Utc::now() - Duration::days(i64::from(days))).to_rfc3339();
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let pruned = conn.execute("DELETE FROM diary_views WHERE viewed_at < ?1", params![cutoff])?;
+        if pruned > 0 {
+            println!("📝 [VIEW] Pruned {} views older than {} days", pruned, days);
+        }
+        Ok(pruned)
+    }
+    
+    /// Maps `id, title, created_at, updated_at, last_viewed_at, word_count`
+    /// to a summary; tags are left for the caller to fill in.
+    fn summary_from_row(crypto: &Crypto, row: &rusqlite::Row) -> SqliteResult<DiarySummary> {
+        let parse = |value: String| {
+            DateTime::parse_from_rfc3339(&value)
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now())
+        };
+        let encrypted_title: String = row.get(1)?;
+        let word_count: Option<i64> = row.get(5)?;
+        
+        Ok(DiarySummary {
+            id: row.get(0)?,
+            title: decrypt_migrated(crypto, &encrypted_title).expect("Decryption failed"),
+            created_at: parse(row.get(2)?),
+            updated_at: parse(row.get(3)?),
+            last_viewed_at: row.get::<_, Option<String>>(4)?.map(parse),
+            tags: Vec::new(),
+            word_count,
+            reading_time_minutes: word_count.map(reading_time_minutes),
+        })
+    }
+    
+    /// Entries carrying `tag_name`, ignoring case and accents.
+    pub fn search_diaries_by_tag(&self, tag_name: &str) -> DiaryResult<Vec<DiaryEntry>> {
+        let listed = self.query_diaries_with_content(&DiaryFilter {
+            tags: vec![tag_name.to_string()],
+            archived: None,
+            sort: DiarySort::CreatedDesc,
+            ..DiaryFilter::default()
+        })?;
+        self.full_entries(listed)
+    }
+    
+    /// Computes `word_count` and `content_hash` for up to `batch_size`
+    /// entries saved before those columns existed. Callers loop until
+    /// `remaining` reaches zero.
+    pub fn backfill_word_counts(&self, batch_size: usize) -> DiaryResult<BackfillProgress> {
+        self.ensure_writable()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        
+        let mut stmt = conn.prepare(
+            "SELECT id, title, content, created_at FROM diary_entries
+             WHERE word_count IS NULL OR content_hash IS NULL
+             LIMIT ?1"
+        )?;
+        let batch: Vec<(String, String, String, String)> = stmt
+            .query_map(params![batch_size as i64], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })?
+            .collect::<SqliteResult<_>>()?;
+        
+        for (id, encrypted_title, encrypted_content, created_at) in &batch {
+            let title = crypto.try_decrypt(encrypted_title)?;
+            let content = crypto.try_decrypt(encrypted_content)?;
+            let created_at = DateTime::parse_from_rfc3339(created_at)
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now());
+            conn.execute(
+                "UPDATE diary_entries SET word_count = ?1, content_hash = ?2 WHERE id = ?3",
+                params![count_words(&content), content_hash(crypto, &title, created_at, &content), id],
+            )?;
+        }
+        
+        let remaining: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM diary_entries WHERE word_count IS NULL OR content_hash IS NULL",
+            [],
+            |row| row.get(0),
+        )?;
+        
+        Ok(BackfillProgress { processed: batch.len(), remaining: remaining as usize })
+    }
+    
+    pub fn get_statistics(&self) -> DiaryResult<Statistics> {
+        // Count anything the background backfill hasn't reached yet. A
+        // read-only vault can't store the counts, so those entries are left
+        // out of the word totals.
+        if !self.read_only {
+            while self.backfill_word_counts(WORD_COUNT_BACKFILL_BATCH)?.remaining > 0 {}
+        }
+        
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let (total_entries, total_words): (i64, i64) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(word_count), 0) FROM diary_entries",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        
+        let mut tag_stmt = conn.prepare(
+            "SELECT t.name, COUNT(*) AS uses
+             FROM diary_tags dt
+             JOIN tags t ON dt.tag_id = t.id
+             GROUP BY t.id
+             ORDER BY uses DESC, t.name
+             LIMIT ?1"
+        )?;
+        let top_tags = tag_stmt
+            .query_map(params![STATISTICS_TOP_TAGS], |row| {
+                Ok(TagUsage { name: row.get(0)?, count: row.get(1)? })
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        
+        let mut date_stmt = conn.prepare("SELECT created_at FROM diary_entries")?;
+        let mut days = BTreeSet::new();
+        let mut per_month: HashMap<(i32, u32), i64> = HashMap::new();
+        for created_at in date_stmt.query_map([], |row| row.get::<_, String>(0))? {
+            let Ok(created_at) = DateTime::parse_from_rfc3339(&created_at?) else {
+                continue;
+            };
+            let day = created_at.with_timezone(&Local).date_naive();
+            days.insert(day);
+            *per_month.entry((day.year(), day.month())).or_default() += 1;
+        }
+        
+        let today = Local::now().date_naive();
+        let mut entries_per_month = Vec::new();
+        let (mut year, mut month) = (today.year(), today.month());
+        for _ in 0..STATISTICS_MONTHS {
+            entries_per_month.push(MonthlyCount {
+                month: format!("{:04}-{:02}", year, month),
+                count: per_month.get(&(year, month)).copied().unwrap_or(0),
+            });
+            if month == 1 {
+                year -= 1;
+                month = 12;
+            } else {
+                month -= 1;
+            }
+        }
+        entries_per_month.reverse();
+        
+        let (longest_streak_days, current_streak_days) = writing_streaks(&days, today);
+        
+        Ok(Statistics {
+            total_entries,
+            entries_per_month,
+            total_words,
+            average_words: if total_entries > 0 { total_words as f64 / total_entries as f64 } else { 0.0 },
+            top_tags,
+            longest_streak_days,
+            current_streak_days,
+        })
+    }
+    
+    /// Entry and word counts per day from `from` to `to` inclusive, with
+    /// empty days included. Days follow `utc_offset_minutes` when given and
+    /// the system time zone otherwise.
+    pub fn get_activity_heatmap(&self, from: &str, to: &str, utc_offset_minutes: Option<i32>) -> DiaryResult<Vec<DayActivity>> {
+        let from = parse_daily_date(from)?;
+        let to = parse_daily_date(to)?;
+        if to < from {
+            return Err(DiaryError::Validation("Heatmap range ends before it starts".to_string()));
+        }
+        if (to - from).num_days() >= MAX_HEATMAP_DAYS {
+            return Err(DiaryError::Validation(format!(
+                "Heatmap range is limited to {} days",
+                MAX_HEATMAP_DAYS
+            )));
+        }
+        let offset = match utc_offset_minutes {
+            Some(minutes) => Some(
+                FixedOffset::east_opt(minutes * 60)
+                    .ok_or_else(|| DiaryError::Validation(format!("Invalid UTC offset {} minutes", minutes)))?,
+            ),
+            None => None,
+        };
+        
+        // A read-only vault can't store missing word counts; those entries
+        // count as 0 words.
+        if !self.read_only {
+            while self.backfill_word_counts(WORD_COUNT_BACKFILL_BATCH)?.remaining > 0 {}
+        }
+        
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let mut stmt = conn.prepare("SELECT created_at, COALESCE(word_count, 0) FROM diary_entries")?;
+        let mut per_day: HashMap<NaiveDate, (i64, i64)> = HashMap::new();
+        for row in stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))? {
+            let (created_at, word_count) = row?;
+            let Ok(created_at) = DateTime::parse_from_rfc3339(&created_at) else {
+                continue;
+            };
+            let day = match offset {
+                Some(offset) => created_at.with_timezone(&offset).date_naive(),
+                None => created_at.with_timezone(&Local).date_naive(),
+            };
+            if day < from || day > to {
+                continue;
+            }
+            let totals = per_day.entry(day).or_default();
+            totals.0 += 1;
+            totals.1 += word_count;
+        }
+        
+        Ok(from
+            .iter_days()
+            .take_while(|day| *day <= to)
+            .map(|day| {
+                let (entry_count, total_word_count) = per_day.get(&day).copied().unwrap_or((0, 0));
+                DayActivity {
+                    date: day.format(DAILY_NOTE_DATE_FORMAT).to_string(),
+                    entry_count,
+                    total_word_count,
+                }
+            })
+            .collect())
+    }
+    
+    /// Entries with no tags and no relationships in either direction.
+    pub fn list_orphan_diaries(&self) -> DiaryResult<Vec<DiaryEntry>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        
+        let mut stmt = conn.prepare(
+            "SELECT e.id, e.title, e.content, e.created_at, e.updated_at, e.word_count, e.locked
+             FROM diary_entries e
+             WHERE NOT EXISTS (SELECT 1 FROM diary_tags dt WHERE dt.diary_id = e.id)
+               AND NOT EXISTS (SELECT 1 FROM relationships r WHERE r.parent_id = e.id OR r.child_id = e.id)
+             ORDER BY e.created_at DESC"
+        )?;
+        let elevated = self.is_elevated();
+        
+        let diary_iter = stmt.query_map([], |row| {
+            let id: String = row.get(0)?;
+            let encrypted_title: String = row.get(1)?;
+            let encrypted_content: String = row.get(2)?;
+            let created_at: String = row.get(3)?;
+            let updated_at: String = row.get(4)?;
+            let word_count: Option<i64> = row.get(5)?;
+            let locked: bool = row.get(6)?;
+            
+            let title = decrypt_migrated(crypto, &encrypted_title).expect("Decryption failed");
+            let content = if locked && !elevated { String::new() } else { crypto.decrypt(&encrypted_content) };
+            let word_count = word_count.unwrap_or_else(|| count_words(&content));
+            let created_at = DateTime::parse_from_rfc3339(&created_at)
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now());
+            let updated_at = DateTime::parse_from_rfc3339(&updated_at)
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now());
+            
+            Ok(DiaryEntry {
+                id,
+                title,
+                content,
+                created_at,
+                updated_at,
+                tags: Vec::new(),
+                word_count,
+                reading_time_minutes: reading_time_minutes(word_count),
+                properties: serde_json::Value::Object(Default::default()),
+                locked,
+            })
+        })?;
+        
+        let mut diaries = Vec::new();
+        for diary_result in diary_iter {
+            let mut diary = diary_result?;
+            diary.properties = self.get_properties_for_diary(&diary.id)?;
+            diaries.push(diary);
+        }
+        
+        Ok(diaries)
+    }
+    
+    pub fn get_graph_data(&self, filter: &GraphFilter) -> DiaryResult<GraphData> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        
+        // Diaries passing the tag and date filters, if any were given.
+        let mut allowed_diaries: Option<HashSet<String>> = None;
+        if !filter.tag_filter.is_empty() || filter.created_from.is_some() || filter.created_to.is_some() {
+            let wanted_tags: HashSet<String> = filter.tag_filter.iter().map(|t| normalize::fold(t)).collect();
+            let mut tagged: HashSet<String> = HashSet::new();
+            if !wanted_tags.is_empty() {
+                let mut stmt = conn.prepare(
+                    "SELECT dt.diary_id, t.name_key FROM diary_tags dt JOIN tags t ON dt.tag_id = t.id"
+                )?;
+                let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+                for row in rows {
+                    let (diary_id, name_key) = row?;
+                    if wanted_tags.contains(&name_key) {
+                        tagged.insert(diary_id);
+                    }
+                }
+            }
+            
+            let mut allowed = HashSet::new();
+            let mut stmt = conn.prepare("SELECT id, created_at FROM diary_entries")?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+            for row in rows {
+                let (id, created_at) = row?;
+                if !wanted_tags.is_empty() && !tagged.contains(&id) {
+                    continue;
+                }
+                let created_at = DateTime::parse_from_rfc3339(&created_at)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now());
+                if filter.created_from.is_some_and(|from| created_at < from)
+                    || filter.created_to.is_some_and(|to| created_at > to)
+                {
+                    continue;
+                }
+                allowed.insert(id);
+            }
+            allowed_diaries = Some(allowed);
+        }
+        
+        // With a diary filter active, only keep tags still attached to a visible diary.
+        let mut allowed_tags: Option<HashSet<String>> = None;
+        if let Some(allowed) = &allowed_diaries {
+            let mut stmt = conn.prepare("SELECT diary_id, tag_id FROM diary_tags")?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+            let mut tags = HashSet::new();
+            for row in rows {
+                let (diary_id, tag_id) = row?;
+                if allowed.contains(&diary_id) {
+                    tags.insert(tag_id);
+                }
+            }
+            allowed_tags = Some(tags);
+        }
+        
+        let relationship_types: HashSet<&str> = filter.relationship_types.iter().map(String::as_str).collect();
+        
+        let include_tag = |id: &str| filter.include_tags && allowed_tags.as_ref().is_none_or(|allowed| allowed.contains(id));
+        let mut graph = Self::build_graph_data(
+            &conn,
+            crypto,
+            &|id| allowed_diaries.as_ref().is_none_or(|allowed| allowed.contains(id)),
+            &include_tag,
+            &|relationship_type| relationship_types.is_empty() || relationship_types.contains(relationship_type),
+            filter.inverse_labels,
+        )?;
+        
+        // Counts cover the whole vault, like node degrees, even when the
+        // diary filter hides some of the entries behind them.
+        if let Some(min_count) = filter.tag_cooccurrence {
+            for (tag_a_id, tag_b_id, pair) in Self::tag_cooccurrence(&conn, min_count)? {
+                if !include_tag(&tag_a_id) || !include_tag(&tag_b_id) {
+                    continue;
+                }
+                graph.edges.push(GraphEdge {
+                    id: format!("cooccur-{}-{}", tag_a_id, tag_b_id),
+                    source: tag_a_id,
+                    target: tag_b_id,
+                    label: "co_occurs_with".to_string(),
+                    weight: pair.count as f64,
+                    edge_type: GraphEdgeType::TagCooccurrence,
+                });
+            }
+        }
+        
+        Ok(graph)
+    }
+    
+    /// Pairs of tags used together on at least `min_count` entries, most
+    /// frequent first.
+    pub fn get_tag_cooccurrence(&self, min_count: u32) -> DiaryResult<Vec<TagCooccurrence>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let pairs = Self::tag_cooccurrence(&conn, min_count)?;
+        Ok(pairs.into_iter().map(|(_, _, pair)| pair).collect())
+    }
+    
+    /// Co-occurring tag pairs with the ids of both tags. Joining on
+    /// `ta.name < tb.name` keeps (a, b) and drops its mirror (b, a), as well
+    /// as a tag paired with itself.
+    fn tag_cooccurrence(conn: &Connection, min_count: u32) -> SqliteResult<Vec<(String, String, TagCooccurrence)>> {
+        let mut stmt = conn.prepare(
+            "SELECT ta.id, tb.id, ta.name, tb.name, COUNT(*) AS together
+             FROM diary_tags a
+             JOIN diary_tags b ON b.diary_id = a.diary_id
+             JOIN tags ta ON ta.id = a.tag_id
+             JOIN tags tb ON tb.id = b.tag_id
+             WHERE ta.name < tb.name
+             GROUP BY ta.id, tb.id
+             HAVING together >= ?1
+             ORDER BY together DESC, ta.name, tb.name"
+        )?;
+        
+        let pairs = stmt.query_map(params![min_count.max(1)], |row| {
+            let pair = TagCooccurrence {
+                tag_a: row.get(2)?,
+                tag_b: row.get(3)?,
+                count: row.get(4)?,
+            };
+            Ok((row.get(0)?, row.get(1)?, pair))
+        })?;
+        
+        pairs.collect()
+    }
+    
+    /// Writes the graph selected by `filter` to `path` as GraphML or DOT.
+    pub fn export_graph(&self, path: &Path, format: &str, filter: &GraphFilter) -> DiaryResult<()> {
+        let format = GraphFormat::parse(format)?;
+        let graph = self.get_graph_data(filter)?;
+        fs::write(path, graph_export::render(&graph, format))?;
+        self.record_audit(AuditOperation::Export, &[], &format!("graph: {} nodes", graph.nodes.len()));
+        Ok(())
+    }
+    
+    #[cfg(test)]
+    pub fn export_csv(
+        &self,
+        path: &Path,
+        include_archived: bool,
+        excel_bom: bool,
+        progress: &mut Progress,
+    ) -> DiaryResult<usize> {
+        crate::operations::run(self, CsvExporter::new(path, include_archived, excel_bom)?, progress)
+    }
+    
+    #[cfg(test)]
+    pub fn export_journal(&self, path: &Path, progress: &mut Progress) -> DiaryResult<JournalExport> {
+        crate::operations::run(self, JournalExporter::new(path)?, progress)
+    }
+    
+    #[cfg(test)]
+    pub fn import_journal(&self, path: &Path, progress: &mut Progress) -> DiaryResult<JournalImport> {
+        crate::operations::run(self, JournalImporter::new(path)?, progress)
+    }
+    
+    /// Saves and empties `batch` in one transaction, with each entry's
+    /// properties and state, noting the ids of entries with wikilinks in
+    /// `linked`. Returns the new ids in batch order.
+    fn save_journal_batch(&self, batch: &mut Vec<JournalRecord>, linked: &mut Vec<String>) -> DiaryResult<Vec<String>> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
+        let diaries: Vec<NewDiary> = batch
+            .iter()
+            .map(|record| NewDiary {
+                title: record.title.clone(),
+                content: record.content.clone(),
+                tags: record.tags.clone(),
+                created_at: Some(record.created_at),
+                updated_at: Some(record.updated_at),
+            })
+            .collect();
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        let ids = self.insert_diaries(&tx, &diaries)?;
+        for (id, record) in ids.iter().zip(batch.iter()) {
+            for (key, value) in &record.properties {
+                Self::write_property(&tx, crypto, id, key, value)?;
+            }
+            tx.execute(
+                "UPDATE diary_entries SET locked = ?1, archived_at = ?2, pinned_at = ?3, sort_order = ?4 WHERE id = ?5",
+                params![
+                    record.locked,
+                    record.archived_at.map(|at| at.to_rfc3339()),
+                    record.pinned_at.map(|at| at.to_rfc3339()),
+                    record.sort_order,
+                    id,
+                ],
+            )?;
+            // A daily note whose date the vault already has comes in as a
+            // plain entry.
+            if let Some(date) = record.daily_date {
+                tx.execute(
+                    "UPDATE diary_entries SET daily_date = ?1
+                     WHERE id = ?2 AND NOT EXISTS (SELECT 1 FROM diary_entries WHERE daily_date = ?1)",
+                    params![date.format(DAILY_NOTE_DATE_FORMAT).to_string(), id],
+                )?;
+            }
+            if !extract_wikilinks(&record.content).is_empty() {
+                linked.push(id.clone());
+            }
+        }
+        
+        let saved: Vec<&str> = ids.iter().map(String::as_str).collect();
+        Self::audit(&tx, AuditOperation::Import, &saved, &format!("journal: batch of {}", ids.len()))?;
+        tx.commit()?;
+        self.invalidate_titles();
+        batch.clear();
+        
+        Ok(ids)
+    }
+    
+    /// Adds the manual relationships of an imported journal, once every
+    /// entry is in. `ids` maps journal ids to vault ids; relationships with
+    /// an end that wasn't imported, or that the vault already has, are
+    /// left out. Returns how many were added.
+    ///
+    /// An entry repeated in the file maps to the journal id of its first
+    /// copy, so ids are looked up through the map a second time.
+    fn import_journal_relationships(
+        &self,
+        ids: &HashMap<String, String>,
+        relationships: &[(String, journal::JournalRelationship)],
+    ) -> DiaryResult<usize> {
+        self.ensure_writable()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        let mut ends: Vec<&str> = Vec::new();
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR IGNORE INTO relationships (id, parent_id, child_id, relationship_type, created_at, source, updated_at, note, weight)
+                 SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?5, ?7, ?8
+                 WHERE ?2 <> ?3
+                   AND EXISTS (SELECT 1 FROM diary_entries WHERE id = ?2)
+                   AND EXISTS (SELECT 1 FROM diary_entries WHERE id = ?3)"
+            )?;
+            let resolve = |id: &String| ids.get(id).map(|target| ids.get(target).unwrap_or(target));
+            for (parent_id, relationship) in relationships {
+                let (Some(parent_id), Some(child_id)) = (resolve(parent_id), resolve(&relationship.child_id)) else {
+                    continue;
+                };
+                if Self::validate_weight(relationship.weight).is_err() {
+                    continue;
+                }
+                let added = insert.execute(params![
+                    Uuid::new_v4().to_string(),
+                    parent_id,
+                    child_id,
+                    relationship.relationship_type,
+                    relationship.created_at.to_rfc3339(),
+                    SOURCE_MANUAL,
+                    relationship.note,
+                    relationship.weight,
+                ])?;
+                if added > 0 {
+                    ends.extend([parent_id.as_str(), child_id.as_str()]);
+                }
+            }
+        }
+        let added = ends.len() / 2;
+        if added > 0 {
+            ends.sort_unstable();
+            ends.dedup();
+            Self::audit(&tx, AuditOperation::RelationshipAdd, &ends, &format!("journal: {} relationships", added))?;
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+    
+    /// Returns the neighborhood of `center_id` up to `depth` hops away,
+    /// following relationships in both directions and diary-tag links.
+    /// Depth 1 is the entry, its tags and its directly related entries.
+    pub fn get_subgraph(&self, center_id: &str, depth: u32) -> DiaryResult<Subgraph> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM diary_entries WHERE id = ?1)",
+            params![center_id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(DiaryError::NotFound(format!("Diary entry {}", center_id)));
+        }
+        
+        let mut diary_ids: HashSet<String> = HashSet::from([center_id.to_string()]);
+        let mut tag_ids: HashSet<String> = HashSet::new();
+        let mut frontier: Vec<GraphNodeRef> = vec![(false, center_id.to_string())];
+        let mut truncated = false;
+        
+        'expand: for _ in 0..depth {
+            let mut next = Vec::new();
+            for node in &frontier {
+                let neighbors = Self::graph_neighbors(&conn, node)?;
+                
+                for ((neighbor_is_tag, neighbor_id), _) in neighbors {
+                    let seen = if neighbor_is_tag { &tag_ids } else { &diary_ids };
+                    if seen.contains(&neighbor_id) {
+                        continue;
+                    }
+                    if diary_ids.len() + tag_ids.len() >= MAX_SUBGRAPH_NODES {
+                        truncated = true;
+                        break 'expand;
+                    }
+                    let visited = if neighbor_is_tag { &mut tag_ids } else { &mut diary_ids };
+                    visited.insert(neighbor_id.clone());
+                    next.push((neighbor_is_tag, neighbor_id));
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        
+        let graph = Self::build_graph_data(
+            &conn,
+            crypto,
+            &|id| diary_ids.contains(id),
+            &|id| tag_ids.contains(id),
+            &|_| true,
+            false,
+        )?;
+        
+        Ok(Subgraph { graph, truncated })
+    }
+    
+    /// Finds a shortest path between two entries, treating relationships
+    /// and shared tags as undirected edges. The search gives up after
+    /// `MAX_PATH_HOPS` hops and reports the entries as not connected.
+    pub fn find_path(&self, from_id: &str, to_id: &str) -> DiaryResult<GraphPath> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        
+        for id in [from_id, to_id] {
+            let exists: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM diary_entries WHERE id = ?1)",
+                params![id],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                return Err(DiaryError::NotFound(format!("Diary entry {}", id)));
+            }
+        }
+        
+        let start: GraphNodeRef = (false, from_id.to_string());
+        let goal: GraphNodeRef = (false, to_id.to_string());
+        // Maps each reached node to the node and edge it was reached through.
+        let mut came_from: HashMap<GraphNodeRef, Option<(GraphNodeRef, String)>> = HashMap::new();
+        came_from.insert(start.clone(), None);
+        let mut frontier = vec![start];
+        
+        'search: for _ in 0..MAX_PATH_HOPS {
+            if came_from.contains_key(&goal) {
+                break;
+            }
+            let mut next = Vec::new();
+            for node in &frontier {
+                for (neighbor, edge_id) in Self::graph_neighbors(&conn, node)? {
+                    if came_from.contains_key(&neighbor) {
+                        continue;
+                    }
+                    came_from.insert(neighbor.clone(), Some((node.clone(), edge_id)));
+                    if neighbor == goal {
+                        break 'search;
+                    }
+                    next.push(neighbor);
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        
+        if !came_from.contains_key(&goal) {
+            return Ok(GraphPath { nodes: Vec::new(), edges: Vec::new(), connected: false });
+        }
+        
+        let mut path_nodes = vec![goal.clone()];
+        let mut path_edges = Vec::new();
+        let mut current = goal;
+        while let Some(Some((previous, edge_id))) = came_from.get(&current) {
+            path_edges.push(edge_id.clone());
+            path_nodes.push(previous.clone());
+            current = previous.clone();
+        }
+        path_nodes.reverse();
+        path_edges.reverse();
+        
+        let diary_ids: HashSet<&str> = path_nodes.iter().filter(|(is_tag, _)| !is_tag).map(|(_, id)| id.as_str()).collect();
+        let tag_ids: HashSet<&str> = path_nodes.iter().filter(|(is_tag, _)| *is_tag).map(|(_, id)| id.as_str()).collect();
+        let graph = Self::build_graph_data(
+            &conn,
+            crypto,
+            &|id| diary_ids.contains(id),
+            &|id| tag_ids.contains(id),
+            &|_| true,
+            false,
+        )?;
+        
+        let mut nodes_by_id: HashMap<String, GraphNode> = graph.nodes.into_iter().map(|n| (n.id.clone(), n)).collect();
+        let mut edges_by_id: HashMap<String, GraphEdge> = graph.edges.into_iter().map(|e| (e.id.clone(), e)).collect();
+        Ok(GraphPath {
+            nodes: path_nodes.iter().filter_map(|(_, id)| nodes_by_id.remove(id)).collect(),
+            edges: path_edges.iter().filter_map(|id| edges_by_id.remove(id)).collect(),
+            connected: true,
+        })
+    }
+    
+    /// Lists the nodes adjacent to `node` in the combined relationship and
+    /// tag graph, ignoring edge direction, with the id of the connecting edge.
+    fn graph_neighbors(conn: &Connection, node: &GraphNodeRef) -> DiaryResult<Vec<(GraphNodeRef, String)>> {
+        let (is_tag, id) = node;
+        let mut neighbors = Vec::new();
+        
+        if *is_tag {
+            let mut stmt = conn.prepare_cached(
+                "SELECT diary_id FROM diary_tags WHERE tag_id = ?1 ORDER BY diary_id"
+            )?;
+            for diary_id in stmt.query_map(params![id], |row| row.get::<_, String>(0))? {
+                let diary_id = diary_id?;
+                let edge_id = format!("tag-{}-{}", diary_id, id);
+                neighbors.push(((false, diary_id), edge_id));
+            }
+        } else {
+            let mut stmt = conn.prepare_cached(
+                "SELECT tag_id FROM diary_tags WHERE diary_id = ?1 ORDER BY tag_id"
+            )?;
+            for tag_id in stmt.query_map(params![id], |row| row.get::<_, String>(0))? {
+                let tag_id = tag_id?;
+                let edge_id = format!("tag-{}-{}", id, tag_id);
+                neighbors.push(((true, tag_id), edge_id));
+            }
+            
+            let mut stmt = conn.prepare_cached(
+                "SELECT child_id, id FROM relationships WHERE parent_id = ?1
+                 UNION ALL
+                 SELECT parent_id, id FROM relationships WHERE child_id = ?1
+                 ORDER BY 1, 2"
+            )?;
+            for row in stmt.query_map(params![id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+                let (diary_id, relationship_id) = row?;
+                neighbors.push(((false, diary_id), relationship_id));
+            }
+        }
+        
+        Ok(neighbors)
+    }
+    
+    /// Builds graph nodes for the diaries and tags accepted by the include
+    /// predicates, plus every edge whose endpoints are both included.
+    /// `include_relationship` is given the relationship type. With
+    /// `inverse_labels`, relationship edges point child to parent and carry
+    /// the inverse label instead.
+    fn build_graph_data(
+        conn: &Connection,
+        crypto: &Crypto,
+        include_diary: &dyn Fn(&str) -> bool,
+        include_tag: &dyn Fn(&str) -> bool,
+        include_relationship: &dyn Fn(&str) -> bool,
+        inverse_labels: bool,
+    ) -> DiaryResult<GraphData> {
+        // Get all diary entries as nodes. Degrees count edges across the
+        // whole graph, regardless of which nodes are included.
+        let mut diary_stmt = conn.prepare(
+            "SELECT e.id, e.title, e.created_at,
+                    (SELECT COUNT(*) FROM attachments a WHERE a.diary_id = e.id),
+                    COALESCE(td.degree, 0), COALESCE(rd.degree, 0)
+             FROM diary_entries e
+             LEFT JOIN (
+                 SELECT diary_id, COUNT(*) AS degree FROM diary_tags GROUP BY diary_id
+             ) td ON td.diary_id = e.id
+             LEFT JOIN (
+                 SELECT endpoint, COUNT(*) AS degree FROM (
+                     SELECT parent_id AS endpoint FROM relationships
+                     UNION ALL
+                     SELECT child_id FROM relationships
+                 ) GROUP BY endpoint
+             ) rd ON rd.endpoint = e.id"
+        )?;
+        
+        let diary_iter = diary_stmt.query_map([], |row| {
+            let id: String = row.get(0)?;
+            let encrypted_title: String = row.get(1)?;
+            let created_at: String = row.get(2)?;
+            let attachment_count: i64 = row.get(3)?;
+            let tag_degree: i64 = row.get(4)?;
+            let relationship_degree: i64 = row.get(5)?;
+            
+            Ok((id, encrypted_title, created_at, attachment_count, tag_degree, relationship_degree))
+        })?;
+        
+        let mut custom_properties: HashMap<String, serde_json::Map<String, serde_json::Value>> = HashMap::new();
+        let mut property_stmt = conn.prepare("SELECT diary_id, key, value FROM diary_properties ORDER BY key")?;
+        let property_iter = property_stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })?;
+        for property_result in property_iter {
+            let (diary_id, key, encrypted_value) = property_result?;
+            if include_diary(&diary_id) {
+                let value = decode_property(crypto, &encrypted_value)?;
+                custom_properties.entry(diary_id).or_default().insert(key, value);
+            }
+        }
+        
+        let mut nodes = Vec::new();
+        for diary_result in diary_iter {
+            let (id, encrypted_title, created_at, attachment_count, tag_degree, relationship_degree) = diary_result?;
+            if !include_diary(&id) {
+                continue;
+            }
+            let title = decrypt_migrated(crypto, &encrypted_title).expect("Decryption failed");
+            let custom = custom_properties.remove(&id).unwrap_or_default();
+            
+            let properties = serde_json::json!({
+                "title": title,
+                "created_at": created_at,
+                "attachment_count": attachment_count,
+                "degree": tag_degree + relationship_degree,
+                "tag_degree": tag_degree,
+                "relationship_degree": relationship_degree,
+                "is_orphan": tag_degree + relationship_degree == 0,
+                "properties": custom,
+            });
+            
+            nodes.push(GraphNode {
+                id: id.clone(),
+                label: title,
+                node_type: "diary".to_string(),
+                properties,
+            });
+        }
+        
+        // Get all tags as nodes
+        let mut tag_stmt = conn.prepare(
+            "SELECT t.id, t.name, COALESCE(d.degree, 0)
+             FROM tags t
+             LEFT JOIN (
+                 SELECT tag_id, COUNT(*) AS degree FROM diary_tags GROUP BY tag_id
+             ) d ON d.tag_id = t.id"
+        )?;
+        
+        let tag_iter = tag_stmt.query_map([], |row| {
+            let id: String = row.get(0)?;
+            let name: String = row.get(1)?;
+            let degree: i64 = row.get(2)?;
+            
+            Ok((id, name, degree))
+        })?;
+        
+        for tag_result in tag_iter {
+            let (id, name, degree) = tag_result?;
+            if !include_tag(&id) {
+                continue;
+            }
+            
+            let properties = serde_json::json!({
+                "name": name,
+                "degree": degree,
+            });
+            
+            nodes.push(GraphNode {
+                id: id.clone(),
+                label: name,
+                node_type: "tag".to_string(),
+                properties,
+            });
+        }
+        
+        // Get all relationships as edges
+        let mut edges = Vec::new();
+        
+        // Tag relationships
+        let mut tag_edge_stmt = conn.prepare(
+            "SELECT dt.diary_id, dt.tag_id, t.name
+             FROM diary_tags dt
+             JOIN tags t ON dt.tag_id = t.id"
+        )?;
+        
+        let tag_edge_iter = tag_edge_stmt.query_map([], |row| {
+            let diary_id: String = row.get(0)?;
+            let tag_id: String = row.get(1)?;
+            let tag_name: String = row.get(2)?;
+            
+            Ok((diary_id, tag_id, tag_name))
+        })?;
+        
+        for edge_result in tag_edge_iter {
+            let (diary_id, tag_id, tag_name) = edge_result?;
+            if !include_diary(&diary_id) || !include_tag(&tag_id) {
+                continue;
+            }
+            
+            edges.push(GraphEdge {
+                id: format!("tag-{}-{}", diary_id, tag_id),
+                source: diary_id,
+                target: tag_id,
+                label: format!("tagged_as_{}", tag_name),
+                weight: DEFAULT_RELATIONSHIP_WEIGHT,
+                edge_type: GraphEdgeType::Tag,
+            });
+        }
+        
+        // Diary entry relationships
+        let mut rel_edge_stmt = conn.prepare(
+            "SELECT id, parent_id, child_id, relationship_type, weight
+             FROM relationships"
+        )?;
+        
+        let rel_edge_iter = rel_edge_stmt.query_map([], |row| {
+            let id: String = row.get(0)?;
+            let parent_id: String = row.get(1)?;
+            let child_id: String = row.get(2)?;
+            let relationship_type: String = row.get(3)?;
+            let weight: f64 = row.get(4)?;
+            
+            Ok((id, parent_id, child_id, relationship_type, weight))
+        })?;
+        
+        for edge_result in rel_edge_iter {
+            let (id, parent_id, child_id, relationship_type, weight) = edge_result?;
+            if !include_diary(&parent_id) || !include_diary(&child_id) || !include_relationship(&relationship_type) {
+                continue;
+            }
+            
+            let (source, target, label) = if inverse_labels {
+                (child_id, parent_id, inverse_relationship_label(&relationship_type))
+            } else {
+                (parent_id, child_id, relationship_type)
+            };
+            edges.push(GraphEdge {
+                id,
+                source,
+                target,
+                label,
+                weight,
+                edge_type: GraphEdgeType::Relationship,
+            });
+        }
+        
+        Ok(GraphData { version: GRAPH_SCHEMA_VERSION, nodes, edges })
+    }
+
+    /// Adds `tag` to every existing entry in `ids` in one transaction.
+    pub fn bulk_add_tag(&self, ids: &[String], tag: &str) -> DiaryResult<Vec<BulkItemResult>> {
+        self.ensure_writable()?;
+        if tag.trim().is_empty() {
+            return Err(DiaryError::Validation("Tag must not be empty".to_string()));
+        }
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        
+        let tag_id = self.get_or_create_tag(&tx, tag)?;
+        let mut changed: Vec<&str> = Vec::new();
+        let results = {
+            let mut exists = tx.prepare("SELECT EXISTS(SELECT 1 FROM diary_entries WHERE id = ?1)")?;
+            let mut insert = tx.prepare("INSERT OR IGNORE INTO diary_tags (diary_id, tag_id) VALUES (?1, ?2)")?;
+            
+            let mut results = Vec::with_capacity(ids.len());
+            for id in ids {
+                let status = if exists.query_row(params![id], |row| row.get(0))? {
+                    if insert.execute(params![id, tag_id])? > 0 {
+                        changed.push(id);
+                    }
+                    BulkStatus::Succeeded
+                } else {
+                    BulkStatus::NotFound
+                };
+                results.push(BulkItemResult { id: id.clone(), status });
+            }
+            results
+        };
+        if !changed.is_empty() {
+            Self::audit(&tx, AuditOperation::TagAdd, &changed, tag)?;
+        }
+        tx.commit()?;
+        
+        Ok(results)
+    }
+    
+    /// Removes `tag` from every existing entry in `ids` in one transaction.
+    /// Entries that didn't carry the tag still count as succeeded. Tags are
+    /// matched ignoring case and accents.
+    pub fn bulk_remove_tag(&self, ids: &[String], tag: &str) -> DiaryResult<Vec<BulkItemResult>> {
+        self.ensure_writable()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        
+        let name_key = normalize::fold(tag);
+        let mut changed: Vec<&str> = Vec::new();
+        let results = {
+            let mut exists = tx.prepare("SELECT EXISTS(SELECT 1 FROM diary_entries WHERE id = ?1)")?;
+            let mut delete = tx.prepare(
+                "DELETE FROM diary_tags WHERE diary_id = ?1 AND tag_id IN (SELECT id FROM tags WHERE name_key = ?2)"
+            )?;
+            
+            let mut results = Vec::with_capacity(ids.len());
+            for id in ids {
+                let status = if exists.query_row(params![id], |row| row.get(0))? {
+                    if delete.execute(params![id, name_key])? > 0 {
+                        changed.push(id);
+                    }
+                    BulkStatus::Succeeded
+                } else {
+                    BulkStatus::NotFound
+                };
+                results.push(BulkItemResult { id: id.clone(), status });
+            }
+            results
+        };
+        if !changed.is_empty() {
+            Self::audit(&tx, AuditOperation::TagRemove, &changed, tag)?;
+        }
+        tx.commit()?;
+        
+        Ok(results)
+    }
+    
+    /// Deletes every existing entry in `ids`, along with its relationships,
+    /// tag links and attachments, in one transaction.
+    pub fn bulk_delete_diaries(&self, ids: &[String]) -> DiaryResult<Vec<BulkItemResult>> {
+        self.ensure_writable()?;
+        println!("📝 [BULK_DELETE] Deleting {} diary entries", ids.len());
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        
+        let results = {
+            let mut delete_relationships = tx.prepare("DELETE FROM relationships WHERE parent_id = ?1 OR child_id = ?1")?;
+            let mut delete_tags = tx.prepare("DELETE FROM diary_tags WHERE diary_id = ?1")?;
+            let mut delete_attachments = tx.prepare("DELETE FROM attachments WHERE diary_id = ?1")?;
+            let mut delete_draft = tx.prepare("DELETE FROM drafts WHERE diary_id = ?1")?;
+            let mut delete_properties = tx.prepare("DELETE FROM diary_properties WHERE diary_id = ?1")?;
+            let mut delete_reminders = tx.prepare("DELETE FROM reminders WHERE diary_id = ?1")?;
+            let mut delete_versions = tx.prepare("DELETE FROM diary_versions WHERE diary_id = ?1")?;
+            let mut delete_views = tx.prepare("DELETE FROM diary_views WHERE diary_id = ?1")?;
+            let mut delete_diary = tx.prepare("DELETE FROM diary_entries WHERE id = ?1")?;
+            
+            let mut results = Vec::with_capacity(ids.len());
+            for id in ids {
+                delete_relationships.execute(params![id])?;
+                delete_tags.execute(params![id])?;
+                delete_attachments.execute(params![id])?;
+                delete_draft.execute(params![id])?;
+                delete_properties.execute(params![id])?;
+                delete_reminders.execute(params![id])?;
+                delete_versions.execute(params![id])?;
+                delete_views.execute(params![id])?;
+                let status = if delete_diary.execute(params![id])? > 0 {
+                    BulkStatus::Succeeded
+                } else {
+                    BulkStatus::NotFound
+                };
+                results.push(BulkItemResult { id: id.clone(), status });
+            }
+            results
+        };
+        let deleted: Vec<&str> = results
+            .iter()
+            .filter(|result| matches!(result.status, BulkStatus::Succeeded))
+            .map(|result| result.id.as_str())
+            .collect();
+        if !deleted.is_empty() {
+            Self::audit(&tx, AuditOperation::Delete, &deleted, &format!("bulk delete of {}", deleted.len()))?;
+        }
+        tx.commit()?;
+        self.invalidate_titles();
+        
+        Ok(results)
+    }
+    
+    pub fn delete_diary(&self, id: &str) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        println!("📝 [DELETE_DIARY] Starting deletion for diary ID: {}", id);
+        
+        // Get a connection from the pool; everything below is one transaction
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        
+        // Check foreign keys status
+        let foreign_keys_enabled: i32 = tx.query_row(
+            "PRAGMA foreign_keys",
+            [],
+            |row| row.get(0)
+        )?;
+        println!("📝 [DELETE_DIARY] Foreign keys enabled: {}", foreign_keys_enabled);
+        
+        // Check for existing relationships
+        let rel_count: i32 = tx.query_row(
+            "SELECT COUNT(*) FROM relationships WHERE parent_id = ?1 OR child_id = ?1",
+            params![id],
+            |row| row.get(0)
+        )?;
+        println!("📝 [DELETE_DIARY] Found {} relationships for this diary", rel_count);
+        
+        // Check for existing tags
+        let tags_count: i32 = tx.query_row(
+            "SELECT COUNT(*) FROM diary_tags WHERE diary_id = ?1",
+            params![id],
+            |row| row.get(0)
+        )?;
+        println!("📝 [DELETE_DIARY] Found {} tag connections for this diary", tags_count);
+        
+        // First, manually delete any relationships
+        println!("📝 [DELETE_DIARY] Step 1: Manually deleting relationships");
+        let deleted_rels = tx.execute(
+            "DELETE FROM relationships WHERE parent_id = ?1 OR child_id = ?1",
+            params![id]
+        )?;
+        println!("📝 [DELETE_DIARY] Deleted {} relationships", deleted_rels);
+        
+        // Second, manually delete tag connections
+        println!("📝 [DELETE_DIARY] Step 2: Manually deleting tag connections");
+        let deleted_tags = tx.execute(
+            "DELETE FROM diary_tags WHERE diary_id = ?1",
+            params![id]
+        )?;
+        println!("📝 [DELETE_DIARY] Deleted {} tag connections", deleted_tags);
+        
+        // Third, delete attachments
+        println!("📝 [DELETE_DIARY] Step 3: Deleting attachments");
+        let deleted_attachments = tx.execute(
+            "DELETE FROM attachments WHERE diary_id = ?1",
+            params![id]
+        )?;
+        println!("📝 [DELETE_DIARY] Deleted {} attachments", deleted_attachments);
+        
+        tx.execute("DELETE FROM drafts WHERE diary_id = ?1", params![id])?;
+        tx.execute("DELETE FROM diary_properties WHERE diary_id = ?1", params![id])?;
+        tx.execute("DELETE FROM reminders WHERE diary_id = ?1", params![id])?;
+        tx.execute("DELETE FROM diary_versions WHERE diary_id = ?1", params![id])?;
+        tx.execute("DELETE FROM diary_views WHERE diary_id = ?1", params![id])?;
+        self.invalidate_titles();
+        
+        // Finally, delete the diary entry
+        println!("📝 [DELETE_DIARY] Step 4: Deleting the diary entry");
+        let deleted_diary = tx.execute(
+            "DELETE FROM diary_entries WHERE id = ?1",
+            params![id]
+        )?;
+        println!("📝 [DELETE_DIARY] Deleted {} diary entries", deleted_diary);
+        
+        if deleted_diary == 0 {
+            println!("⚠️ [DELETE_DIARY] Warning: No diary entries were deleted!");
+            return Err(rusqlite::Error::QueryReturnedNoRows.into());
+        }
+        Self::audit(&tx, AuditOperation::Delete, &[id], "deleted")?;
+        
+        // Verify all relationships were deleted
+        let remaining_rels: i32 = tx.query_row(
+            "SELECT COUNT(*) FROM relationships WHERE parent_id = ?1 OR child_id = ?1",
+            params![id],
+            |row| row.get(0)
+        )?;
+        println!("📝 [DELETE_DIARY] Remaining relationships: {}", remaining_rels);
+        
+        if remaining_rels > 0 {
+            println!("⚠️ [DELETE_DIARY] Warning: Some relationships remained after deletion!");
+        }
+        
+        // Verify all tag connections were deleted
+        let remaining_tags: i32 = tx.query_row(
+            "SELECT COUNT(*) FROM diary_tags WHERE diary_id = ?1",
+            params![id],
+            |row| row.get(0)
+        )?;
+        println!("📝 [DELETE_DIARY] Remaining tag connections: {}", remaining_tags);
+        
+        if remaining_tags > 0 {
+            println!("⚠️ [DELETE_DIARY] Warning: Some tag connections remained after deletion!");
+        }
+        
+        tx.commit()?;
+        println!("📝 [DELETE_DIARY] Deletion process completed successfully");
+        Ok(())
+    }
+
+    pub fn add_relationship(
+        &self,
+        parent_id: &str,
+        child_id: &str,
+        relationship_type: &str,
+        note: Option<&str>,
+        weight: Option<f64>,
+    ) -> DiaryResult<String> {
+        self.ensure_writable()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        let weight = weight.unwrap_or(DEFAULT_RELATIONSHIP_WEIGHT);
+        let note = note.filter(|note| !note.trim().is_empty());
+        
+        Self::validate_relationship(&tx, parent_id, child_id, relationship_type)?;
+        Self::validate_weight(weight)?;
+        
+        tx.execute(
+            "INSERT INTO relationships (id, parent_id, child_id, relationship_type, created_at, source, updated_at, note, weight) 
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?5, ?7, ?8)",
+            params![id, parent_id, child_id, relationship_type, now, SOURCE_MANUAL, note, weight],
+        )?;
+        Self::audit(&tx, AuditOperation::RelationshipAdd, &[parent_id, child_id], relationship_type)?;
+        tx.commit()?;
+        
+        Ok(id)
+    }
+    
+    /// Checks that a relationship links two distinct, existing entries and
+    /// doesn't duplicate an existing (parent, child, type) triple.
+    fn validate_relationship(
+        conn: &Connection,
+        parent_id: &str,
+        child_id: &str,
+        relationship_type: &str,
+    ) -> DiaryResult<()> {
+        if parent_id.is_empty() || child_id.is_empty() {
+            return Err(DiaryError::Validation(
+                "Parent ID and child ID are required".to_string(),
+            ));
+        }
+        if relationship_type.trim().is_empty() {
+            return Err(DiaryError::Validation(
+                "Relationship type is required".to_string(),
+            ));
+        }
+        if parent_id == child_id {
+            return Err(DiaryError::Validation(
+                "An entry cannot be related to itself".to_string(),
+            ));
+        }
+        
+        for diary_id in [parent_id, child_id] {
+            let exists: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM diary_entries WHERE id = ?1)",
+                params![diary_id],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                return Err(DiaryError::NotFound(format!("Diary entry {}", diary_id)));
+            }
+        }
+        
+        let duplicate: bool = conn.query_row(
+            "SELECT EXISTS(
+                 SELECT 1 FROM relationships
+                 WHERE parent_id = ?1 AND child_id = ?2 AND relationship_type = ?3
+             )",
+            params![parent_id, child_id, relationship_type],
+            |row| row.get(0),
+        )?;
+        if duplicate {
+            return Err(DiaryError::Validation(format!(
+                "A '{}' relationship between these entries already exists",
+                relationship_type
+            )));
+        }
+        
+        Ok(())
+    }
+    
+    /// Distinct relationship types with how often each is used, most used first.
+    pub fn get_relationship_types(&self) -> SqliteResult<Vec<RelationshipTypeCount>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let mut stmt = conn.prepare(
+            "SELECT relationship_type, COUNT(*) AS count
+             FROM relationships
+             GROUP BY relationship_type
+             ORDER BY count DESC, relationship_type"
+        )?;
+        
+        let type_iter = stmt.query_map([], |row| {
+            Ok(RelationshipTypeCount {
+                relationship_type: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?;
+        
+        type_iter.collect()
+    }
+    
+    /// Renames every relationship of type `old` to `new`. Where a pair of
+    /// entries already has a `new` relationship the `old` one is dropped, so
+    /// the two types merge. Returns the number of relationships renamed.
+    pub fn rename_relationship_type(&self, old: &str, new: &str) -> DiaryResult<usize> {
+        self.ensure_writable()?;
+        let new = new.trim();
+        if new.is_empty() {
+            return Err(DiaryError::Validation(
+                "Relationship type is required".to_string(),
+            ));
+        }
+        if old == new {
+            return Ok(0);
+        }
+        
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        let affected: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT parent_id FROM relationships WHERE relationship_type = ?1
+                 UNION SELECT child_id FROM relationships WHERE relationship_type = ?1"
+            )?;
+            let affected_iter = stmt.query_map(params![old], |row| row.get(0))?;
+            affected_iter.collect::<SqliteResult<_>>()?
+        };
+        
+        tx.execute(
+            "DELETE FROM relationships
+             WHERE relationship_type = ?1
+               AND EXISTS (
+                   SELECT 1 FROM relationships existing
+                   WHERE existing.parent_id = relationships.parent_id
+                     AND existing.child_id = relationships.child_id
+                     AND existing.relationship_type = ?2
+               )",
+            params![old, new],
+        )?;
+        let renamed = tx.execute(
+            "UPDATE relationships SET relationship_type = ?1, updated_at = ?2
+             WHERE relationship_type = ?3",
+            params![new, Utc::now().to_rfc3339(), old],
+        )?;
+        if !affected.is_empty() {
+            let affected: Vec<&str> = affected.iter().map(String::as_str).collect();
+            Self::audit(&tx, AuditOperation::RelationshipUpdate, &affected, new)?;
+        }
+        
+        tx.commit()?;
+        Ok(renamed)
+    }
+    
+    fn validate_weight(weight: f64) -> DiaryResult<()> {
+        if !(0.0..=MAX_RELATIONSHIP_WEIGHT).contains(&weight) {
+            return Err(DiaryError::Validation(format!(
+                "Relationship weight must be between 0 and {}",
+                MAX_RELATIONSHIP_WEIGHT
+            )));
+        }
+        Ok(())
+    }
+    
+    pub fn delete_relationship(&self, id: &str) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        
+        let ends: Option<(String, String, String)> = tx
+            .query_row(
+                "SELECT parent_id, child_id, relationship_type FROM relationships WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        tx.execute(
+            "DELETE FROM relationships WHERE id = ?1",
+            params![id],
+        )?;
+        if let Some((parent_id, child_id, relationship_type)) = ends {
+            Self::audit(&tx, AuditOperation::RelationshipRemove, &[&parent_id, &child_id], &relationship_type)?;
+        }
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    /// Relationships of an entry together with the entry on the other side.
+    /// Relationships whose other entry no longer exists are skipped.
+    pub fn get_relationships(&self, diary_id: &str) -> DiaryResult<Vec<LinkedRelationship>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        
+        let mut stmt = conn.prepare(
+            "SELECT r.id, r.parent_id, r.child_id, r.relationship_type, r.created_at, r.source, r.updated_at, r.note, r.weight,
+                    e.id, e.title
+             FROM relationships r
+             JOIN diary_entries e
+               ON e.id = CASE WHEN r.parent_id = ?1 THEN r.child_id ELSE r.parent_id END
+             WHERE r.parent_id = ?1 OR r.child_id = ?1"
+        )?;
+        
+        let relationship_iter = stmt.query_map(params![diary_id], |row| {
+            let relationship = Self::relationship_from_row(row)?;
+            let other_id: String = row.get(9)?;
+            let encrypted_title: String = row.get(10)?;
+            
+            Ok(LinkedRelationship {
+                is_parent: relationship.parent_id == diary_id,
+                relationship,
+                other_id,
+                other_title: decrypt_migrated(crypto, &encrypted_title).expect("Decryption failed"),
+            })
+        })?;
+        
+        let mut relationships = Vec::new();
+        for relationship_result in relationship_iter {
+            relationships.push(relationship_result?);
+        }
+        
+        Ok(relationships)
+    }
+    
+    fn get_relationship(conn: &Connection, id: &str) -> DiaryResult<Relationship> {
+        conn.query_row(
+            "SELECT id, parent_id, child_id, relationship_type, created_at, source, updated_at, note, weight
+             FROM relationships
+             WHERE id = ?1",
+            params![id],
+            Self::relationship_from_row,
+        )
+        .optional()?
+        .ok_or_else(|| DiaryError::NotFound(format!("Relationship {}", id)))
+    }
+    
+    fn relationship_from_row(row: &rusqlite::Row) -> SqliteResult<Relationship> {
+        let id: String = row.get(0)?;
+        let parent_id: String = row.get(1)?;
+        let child_id: String = row.get(2)?;
+        let relationship_type: String = row.get(3)?;
+        let created_at_str: String = row.get(4)?;
+        let source: String = row.get(5)?;
+        let updated_at_str: Option<String> = row.get(6)?;
+        let note: Option<String> = row.get(7)?;
+        let weight: f64 = row.get(8)?;
+        
+        let created_at = DateTime::parse_from_rfc3339(&created_at_str)
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now());
+        let updated_at = updated_at_str
+            .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or(created_at);
+        
+        Ok(Relationship {
+            id,
+            parent_id,
+            child_id,
+            relationship_type,
+            created_at: created_at.to_rfc3339(),
+            source,
+            updated_at: updated_at.to_rfc3339(),
+            note,
+            weight,
+        })
+    }
+    
+    /// Changes the type, endpoints, note and/or weight of a relationship,
+    /// keeping any field passed as `None`; an empty note clears it. Editing a
+    /// wikilink relationship turns it into a manual one so the next content
+    /// sync doesn't discard the edit.
+    pub fn update_relationship(
+        &self,
+        id: &str,
+        relationship_type: Option<&str>,
+        parent_id: Option<&str>,
+        child_id: Option<&str>,
+        note: Option<&str>,
+        weight: Option<f64>,
+    ) -> DiaryResult<Relationship> {
+        self.ensure_writable()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        let current = Self::get_relationship(&tx, id)?;
+        
+        let parent_id = parent_id.unwrap_or(&current.parent_id);
+        let child_id = child_id.unwrap_or(&current.child_id);
+        let relationship_type = relationship_type.unwrap_or(&current.relationship_type);
+        let note = match note {
+            Some(note) if note.trim().is_empty() => None,
+            Some(note) => Some(note),
+            None => current.note.as_deref(),
+        };
+        let weight = weight.unwrap_or(current.weight);
+        
+        let endpoints_changed = parent_id != current.parent_id
+            || child_id != current.child_id
+            || relationship_type != current.relationship_type;
+        if endpoints_changed {
+            Self::validate_relationship(&tx, parent_id, child_id, relationship_type)?;
+        }
+        Self::validate_weight(weight)?;
+        if !endpoints_changed && note == current.note.as_deref() && weight == current.weight {
+            return Ok(current);
+        }
+        
+        tx.execute(
+            "UPDATE relationships
+             SET parent_id = ?1, child_id = ?2, relationship_type = ?3, source = ?4, updated_at = ?5,
+                 note = ?6, weight = ?7
+             WHERE id = ?8",
+            params![
+                parent_id,
+                child_id,
+                relationship_type,
+                SOURCE_MANUAL,
+                Utc::now().to_rfc3339(),
+                note,
+                weight,
+                id
+            ],
+        )?;
+        let mut ends = vec![current.parent_id.as_str(), current.child_id.as_str()];
+        for end in [parent_id, child_id] {
+            if !ends.contains(&end) {
+                ends.push(end);
+            }
+        }
+        Self::audit(&tx, AuditOperation::RelationshipUpdate, &ends, relationship_type)?;
+        let updated = Self::get_relationship(&tx, id)?;
+        tx.commit()?;
+        
+        Ok(updated)
+    }
+    
+    /// Returns the entries that point at `diary_id`, i.e. the parents of
+    /// relationships where it is the child, newest first.
+    pub fn get_backlinks(&self, diary_id: &str) -> DiaryResult<Vec<Backlink>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        
+        let mut stmt = conn.prepare(
+            "SELECT r.id, e.id, e.title, r.relationship_type, e.created_at
+             FROM relationships r
+             JOIN diary_entries e ON e.id = r.parent_id
+             WHERE r.child_id = ?1
+             ORDER BY e.created_at DESC"
+        )?;
+        
+        let backlink_iter = stmt.query_map(params![diary_id], |row| {
+            let relationship_id: String = row.get(0)?;
+            let diary_id: String = row.get(1)?;
+            let encrypted_title: String = row.get(2)?;
+            let relationship_type: String = row.get(3)?;
+            let created_at: String = row.get(4)?;
+            
+            let created_at = DateTime::parse_from_rfc3339(&created_at)
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now());
+            
+            Ok(Backlink {
+                relationship_id,
+                diary_id,
+                title: decrypt_migrated(crypto, &encrypted_title).expect("Decryption failed"),
+                relationship_type,
+                created_at,
+            })
+        })?;
+        
+        let mut backlinks = Vec::new();
+        for backlink_result in backlink_iter {
+            backlinks.push(backlink_result?);
+        }
+        
+        Ok(backlinks)
+    }
+    
+    /// Suggests entries related to `diary_id` by shared tags, direct
+    /// relationships and shared neighbors. Ties break by most recently
+    /// updated. Directly linked entries are dropped when `exclude_linked`.
+    pub fn get_related_entries(&self, diary_id: &str, limit: usize, exclude_linked: bool) -> DiaryResult<Vec<RelatedEntry>> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let crypto = self.crypto()?;
+        
+        let diary_exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM diary_entries WHERE id = ?1)",
+            params![diary_id],
+            |row| row.get(0),
+        )?;
+        if !diary_exists {
+            return Err(DiaryError::NotFound(format!("Diary entry {}", diary_id)));
+        }
+        
+        let mut candidates: HashMap<String, (i64, Vec<RelatedReason>)> = HashMap::new();
+        
+        let mut shared_tag_stmt = conn.prepare(
+            "SELECT other.diary_id, t.name
+             FROM diary_tags own
+             JOIN diary_tags other ON other.tag_id = own.tag_id AND other.diary_id != own.diary_id
+             JOIN tags t ON t.id = own.tag_id
+             WHERE own.diary_id = ?1
+             ORDER BY t.name"
+        )?;
+        for row in shared_tag_stmt.query_map(params![diary_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+            let (other_id, tag) = row?;
+            let entry = candidates.entry(other_id).or_default();
+            entry.0 += SHARED_TAG_SCORE;
+            entry.1.push(RelatedReason::SharedTag { tag });
+        }
+        
+        let mut neighbor_stmt = conn.prepare(
+            "SELECT child_id, relationship_type FROM relationships WHERE parent_id = ?1
+             UNION ALL
+             SELECT parent_id, relationship_type FROM relationships WHERE child_id = ?1
+             ORDER BY 1, 2"
+        )?;
+        let neighbors_of = |stmt: &mut rusqlite::Statement, id: &str| -> SqliteResult<Vec<(String, String)>> {
+            stmt.query_map(params![id], |row| Ok((row.get(0)?, row.get(1)?)))?.collect()
+        };
+        
+        let direct = neighbors_of(&mut neighbor_stmt, diary_id)?;
+        let linked: HashSet<String> = direct.iter().map(|(id, _)| id.clone()).collect();
+        for (neighbor_id, relationship_type) in &direct {
+            let entry = candidates.entry(neighbor_id.clone()).or_default();
+            entry.0 += DIRECT_LINK_SCORE;
+            entry.1.push(RelatedReason::DirectLink { relationship_type: relationship_type.clone() });
+        }
+        
+        for neighbor_id in linked.iter().collect::<BTreeSet<_>>() {
+            let mut seen = HashSet::new();
+            for (second_id, _) in neighbors_of(&mut neighbor_stmt, neighbor_id)? {
+                if second_id == diary_id || !seen.insert(second_id.clone()) {
+                    continue;
+                }
+                let entry = candidates.entry(second_id).or_default();
+                entry.0 += SHARED_NEIGHBOR_SCORE;
+                entry.1.push(RelatedReason::SharedNeighbor { diary_id: neighbor_id.clone() });
+            }
+        }
+        
+        candidates.remove(diary_id);
+        if exclude_linked {
+            candidates.retain(|id, _| !linked.contains(id));
+        }
+        
+        let mut entry_stmt = conn.prepare("SELECT title, updated_at FROM diary_entries WHERE id = ?1")?;
+        let mut ranked = Vec::with_capacity(candidates.len());
+        for (id, (score, reasons)) in candidates {
+            let (encrypted_title, updated_at): (String, String) =
+                entry_stmt.query_row(params![id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            ranked.push((score, updated_at, id, encrypted_title, reasons));
+        }
+        // RFC 3339 timestamps in UTC sort chronologically as strings.
+        ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.cmp(&a.1)).then_with(|| a.2.cmp(&b.2)));
+        ranked.truncate(limit);
+        
+        Ok(ranked
+            .into_iter()
+            .map(|(score, _, diary_id, encrypted_title, reasons)| RelatedEntry {
+                diary_id,
+                title: decrypt_migrated(crypto, &encrypted_title).expect("Decryption failed"),
+                score,
+                reasons,
+            })
+            .collect())
+    }
+    
+    /// Schedules a reminder on an entry. `remind_at` must be RFC 3339.
+    pub fn set_reminder(&self, diary_id: &str, remind_at: &str, message: &str) -> DiaryResult<Reminder> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let remind_at = DateTime::parse_from_rfc3339(remind_at)
+            .map_err(|_| DiaryError::Validation(format!("Invalid reminder time '{}'; expected RFC 3339", remind_at)))?
+            .with_timezone(&Utc);
+        let diary_exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM diary_entries WHERE id = ?1)",
+            params![diary_id],
+            |row| row.get(0),
+        )?;
+        if !diary_exists {
+            return Err(DiaryError::NotFound(format!("Diary entry {}", diary_id)));
+        }
+        
+        let reminder = Reminder {
+            id: Uuid::new_v4().to_string(),
+            diary_id: diary_id.to_string(),
+            remind_at,
+            message: message.to_string(),
+            completed: false,
+        };
+        conn.execute(
+            "INSERT INTO reminders (id, diary_id, remind_at, message, completed) VALUES (?1, ?2, ?3, ?4, 0)",
+            params![
+                reminder.id,
+                reminder.diary_id,
+                reminder_timestamp(reminder.remind_at),
+                crypto.encrypt(message),
+            ],
+        )?;
+        
+        Ok(reminder)
+    }
+    
+    /// Open reminders whose time has come, oldest first.
+    pub fn list_due_reminders(&self) -> DiaryResult<Vec<DueReminder>> {
+        let crypto = self.crypto()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let mut stmt = conn.prepare(
+            "SELECT r.id, r.diary_id, r.remind_at, r.message, r.completed, d.title
+             FROM reminders r
+             JOIN diary_entries d ON d.id = r.diary_id
+             WHERE r.completed = 0 AND r.remind_at <= ?1
+             ORDER BY r.remind_at"
+        )?;
+        let reminder_iter = stmt.query_map(params![reminder_timestamp(Utc::now())], |row| {
+            Ok((Self::reminder_from_row(row)?, row.get::<_, String>(5)?))
+        })?;
+        
+        // The poller calls this in the background, so a damaged row is
+        // skipped rather than failing every other reminder.
+        let mut reminders = Vec::new();
+        for reminder_result in reminder_iter {
+            let (mut reminder, encrypted_title) = reminder_result?;
+            let decrypted = crypto
+                .try_decrypt(&reminder.message)
+                .and_then(|message| decrypt_migrated(crypto, &encrypted_title).map(|title| (message, title)));
+            match decrypted {
+                Ok((message, diary_title)) => {
+                    reminder.message = message;
+                    reminders.push(DueReminder { reminder, diary_title });
+                }
+                Err(e) => println!("⚠️ [REMINDER] Skipping reminder {}: {}", reminder.id, e),
+            }
+        }
+        
+        Ok(reminders)
+    }
+    
+    pub fn complete_reminder(&self, id: &str) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let updated = conn.execute("UPDATE reminders SET completed = 1 WHERE id = ?1", params![id])?;
+        if updated == 0 {
+            return Err(DiaryError::NotFound(format!("Reminder {}", id)));
+        }
+        
+        Ok(())
+    }
+    
+    /// All reminders of an entry, completed or not, by time.
+    pub fn list_reminders_for_diary(&self, diary_id: &str) -> DiaryResult<Vec<Reminder>> {
+        let crypto = self.crypto()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let mut stmt = conn.prepare(
+            "SELECT id, diary_id, remind_at, message, completed FROM reminders
+             WHERE diary_id = ?1
+             ORDER BY remind_at"
+        )?;
+        let reminder_iter = stmt.query_map(params![diary_id], Self::reminder_from_row)?;
+        
+        let mut reminders = Vec::new();
+        for reminder_result in reminder_iter {
+            let mut reminder = reminder_result?;
+            reminder.message = crypto.try_decrypt(&reminder.message)?;
+            reminders.push(reminder);
+        }
+        
+        Ok(reminders)
+    }
+    
+    /// Reads a reminder with its `message` still encrypted; callers decrypt
+    /// it outside the row closure so a damaged message is an error, not a panic.
+    fn reminder_from_row(row: &rusqlite::Row) -> SqliteResult<Reminder> {
+        let remind_at: String = row.get(2)?;
+        
+        Ok(Reminder {
+            id: row.get(0)?,
+            diary_id: row.get(1)?,
+            remind_at: DateTime::parse_from_rfc3339(&remind_at)
+                .map(|date| date.with_timezone(&Utc))
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e)))?,
+            message: row.get(3)?,
+            completed: row.get(4)?,
+        })
+    }
+    
+    pub fn save_template(&self, id: Option<&str>, name: &str, content: &str, default_tags: &[String]) -> DiaryResult<Template> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        if name.trim().is_empty() {
+            return Err(DiaryError::Validation("Template name must not be empty".to_string()));
+        }
+        let tags_json = serde_json::to_string(default_tags).expect("tags serialize");
+        let now = Utc::now().to_rfc3339();
+        
+        let template_id = match id {
+            Some(existing_id) => {
+                let updated = conn.execute(
+                    "UPDATE templates SET name = ?1, content = ?2, default_tags = ?3, updated_at = ?4 WHERE id = ?5",
+                    params![crypto.encrypt(name), crypto.encrypt(content), tags_json, now, existing_id],
+                )?;
+                if updated == 0 {
+                    return Err(DiaryError::NotFound(format!("Template {}", existing_id)));
+                }
+                existing_id.to_string()
+            }
+            None => {
+                let new_id = Uuid::new_v4().to_string();
+                conn.execute(
+                    "INSERT INTO templates (id, name, content, default_tags, created_at, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+                    params![new_id, crypto.encrypt(name), crypto.encrypt(content), tags_json, now],
+                )?;
+                new_id
+            }
+        };
+        
+        Self::get_template(&conn, crypto, &template_id)
+    }
+    
+    /// All templates, sorted by name.
+    pub fn list_templates(&self) -> DiaryResult<Vec<Template>> {
+        let crypto = self.crypto()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let mut stmt = conn.prepare(
+            "SELECT id, name, content, default_tags, created_at, updated_at FROM templates"
+        )?;
+        let template_iter = stmt.query_map([], |row| Self::template_from_row(crypto, row))?;
+        
+        let mut templates = Vec::new();
+        for template_result in template_iter {
+            templates.push(template_result?);
+        }
+        templates.sort_by_key(|template| template.name.to_lowercase());
+        
+        Ok(templates)
+    }
+    
+    pub fn delete_template(&self, id: &str) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let deleted = conn.execute("DELETE FROM templates WHERE id = ?1", params![id])?;
+        if deleted == 0 {
+            return Err(DiaryError::NotFound(format!("Template {}", id)));
+        }
+        
+        Ok(())
+    }
+    
+    /// Creates a new entry from a template, expanding its placeholders and
+    /// applying its default tags.
+    pub fn create_diary_from_template(&self, template_id: &str, title: &str) -> DiaryResult<DiaryEntry> {
+        self.ensure_writable()?;
+        let template = {
+            let crypto = self.crypto()?;
+            let conn = self.pool.get().expect("Failed to get database connection");
+            Self::get_template(&conn, crypto, template_id)?
+        };
+        
+        let now = Local::now();
+        let content = template
+            .content
+            .replace("{{date}}", &now.format("%Y-%m-%d").to_string())
+            .replace("{{time}}", &now.format("%H:%M").to_string())
+            .replace("{{title}}", title);
+        
+        let diary_id = self.save_diary(None, title, &content, &template.default_tags)?;
+        self.read_diary(&diary_id, false)
+    }
+    
+    fn get_template(conn: &Connection, crypto: &Crypto, id: &str) -> DiaryResult<Template> {
+        conn.query_row(
+            "SELECT id, name, content, default_tags, created_at, updated_at FROM templates WHERE id = ?1",
+            params![id],
+            |row| Self::template_from_row(crypto, row),
+        )
+        .optional()?
+        .ok_or_else(|| DiaryError::NotFound(format!("Template {}", id)))
+    }
+    
+    fn template_from_row(crypto: &Crypto, row: &rusqlite::Row) -> SqliteResult<Template> {
+        let encrypted_name: String = row.get(1)?;
+        let encrypted_content: String = row.get(2)?;
+        let default_tags: String = row.get(3)?;
+        
+        Ok(Template {
+            id: row.get(0)?,
+            name: crypto.decrypt(&encrypted_name),
+            content: crypto.decrypt(&encrypted_content),
+            default_tags: serde_json::from_str(&default_tags).unwrap_or_default(),
+            created_at: row.get(4)?,
+            updated_at: row.get(5)?,
+        })
+    }
+    
+    pub fn add_attachment(&self, diary_id: &str, path: &Path) -> DiaryResult<Attachment> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        
+        let diary_exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM diary_entries WHERE id = ?1)",
+            params![diary_id],
+            |row| row.get(0),
+        )?;
+        if !diary_exists {
+            return Err(DiaryError::NotFound(format!("Diary entry {}", diary_id)));
+        }
+        self.ensure_readable(&conn, diary_id)?;
+        
+        let size = fs::metadata(path)?.len();
+        if size > MAX_ATTACHMENT_SIZE {
+            return Err(DiaryError::Validation(format!(
+                "Attachments are limited to {} MB",
+                MAX_ATTACHMENT_SIZE / (1024 * 1024)
+            )));
+        }
+        let data = fs::read(path)?;
+        
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "attachment".to_string());
+        let attachment = Attachment {
+            id: Uuid::new_v4().to_string(),
+            diary_id: diary_id.to_string(),
+            mime_type: mime_type_for(&filename).to_string(),
+            filename,
+            size: data.len() as i64,
+            created_at: Utc::now().to_rfc3339(),
+        };
+        
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO attachments (id, diary_id, filename, mime_type, size, created_at, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                attachment.id,
+                attachment.diary_id,
+                crypto.encrypt(&attachment.filename),
+                crypto.encrypt(&attachment.mime_type),
+                attachment.size,
+                attachment.created_at,
+                crypto.encrypt_bytes(&data)
+            ],
+        )?;
+        Self::audit(&tx, AuditOperation::AttachmentAdd, &[diary_id], &attachment.id)?;
+        tx.commit()?;
+        
+        Ok(attachment)
+    }
+    
+    pub fn get_attachment(&self, id: &str) -> DiaryResult<AttachmentData> {
+        let crypto = self.crypto()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let (attachment, encrypted_data) = conn
+            .query_row(
+                "SELECT id, diary_id, filename, mime_type, size, created_at, data
+                 FROM attachments WHERE id = ?1",
+                params![id],
+                |row| Ok((Self::attachment_from_row(crypto, row)?, row.get::<_, String>(6)?)),
+            )
+            .optional()?
+            .ok_or_else(|| DiaryError::NotFound(format!("Attachment {}", id)))?;
+        self.ensure_readable(&conn, &attachment.diary_id)?;
+        
+        let data = crypto.decrypt_bytes(&encrypted_data)?;
+        Ok(AttachmentData {
+            attachment,
+            data: BASE64.encode(data),
+        })
+    }
+    
+    pub fn list_attachments(&self, diary_id: &str) -> DiaryResult<Vec<Attachment>> {
+        let crypto = self.crypto()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let mut stmt = conn.prepare(
+            "SELECT id, diary_id, filename, mime_type, size, created_at
+             FROM attachments
+             WHERE diary_id = ?1
+             ORDER BY created_at"
+        )?;
+        
+        let attachment_iter = stmt.query_map(params![diary_id], |row| Self::attachment_from_row(crypto, row))?;
+        Ok(attachment_iter.collect::<SqliteResult<_>>()?)
+    }
+    
+    pub fn delete_attachment(&self, id: &str) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        
+        let diary_id: String = conn
+            .query_row("SELECT diary_id FROM attachments WHERE id = ?1", params![id], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| DiaryError::NotFound(format!("Attachment {}", id)))?;
+        self.ensure_readable(&conn, &diary_id)?;
+        
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM attachments WHERE id = ?1", params![id])?;
+        Self::audit(&tx, AuditOperation::AttachmentDelete, &[&diary_id], id)?;
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    fn attachment_from_row(crypto: &Crypto, row: &rusqlite::Row) -> SqliteResult<Attachment> {
+        let encrypted_filename: String = row.get(2)?;
+        let encrypted_mime_type: String = row.get(3)?;
+        Ok(Attachment {
+            id: row.get(0)?,
+            diary_id: row.get(1)?,
+            filename: decrypt_migrated(crypto, &encrypted_filename).expect("Decryption failed"),
+            mime_type: decrypt_migrated(crypto, &encrypted_mime_type).expect("Decryption failed"),
+            size: row.get(4)?,
+            created_at: row.get(5)?,
+        })
+    }
+    
+    /// Re-encrypts every diary entry under a freshly generated key.
+    ///
+    /// All rows are rewritten inside a single transaction and the new key is
+    /// staged on disk before the commit, then swapped in with a rename. A crash
+    /// before the commit leaves the old key and old ciphertext untouched; a
+    /// crash between commit and rename is repaired on the next startup by
+    /// `recover_interrupted_rotation`.
+    pub fn rotate_encryption_key<F>(&mut self, mut on_progress: F) -> DiaryResult<usize>
+    where
+        F: FnMut(usize, usize),
+    {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?.clone();
+        let new_crypto = crypto.generate_replacement();
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        
+        let select_ids = |sql: &str| -> SqliteResult<Vec<String>> {
+            let mut stmt = tx.prepare(sql)?;
+            let id_iter = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            id_iter.collect()
+        };
+        let ids = select_ids("SELECT id FROM diary_entries")?;
+        let attachment_ids = select_ids("SELECT id FROM attachments")?;
+        let template_ids = select_ids("SELECT id FROM templates")?;
+        let draft_ids = select_ids("SELECT diary_id FROM drafts")?;
+        let property_ids = select_ids("SELECT CAST(rowid AS TEXT) FROM diary_properties")?;
+        let reminder_ids = select_ids("SELECT id FROM reminders")?;
+        let version_ids = select_ids("SELECT id FROM diary_versions")?;
+        let saved_search_ids = select_ids("SELECT id FROM saved_searches")?;
+        let total = ids.len()
+            + attachment_ids.len()
+            + template_ids.len()
+            + draft_ids.len()
+            + property_ids.len()
+            + reminder_ids.len()
+            + version_ids.len()
+            + saved_search_ids.len();
+        on_progress(0, total);
+        
+        {
+            let mut select = tx.prepare("SELECT title, content, created_at FROM diary_entries WHERE id = ?1")?;
+            let mut update = tx.prepare(
+                "UPDATE diary_entries SET title = ?1, content = ?2, content_hash = ?3 WHERE id = ?4"
+            )?;
+            
+            for (index, id) in ids.iter().enumerate() {
+                let (encrypted_title, encrypted_content, created_at): (String, String, String) =
+                    select.query_row(params![id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+                let title = decrypt_migrated(&crypto, &encrypted_title)?;
+                let content = crypto.try_decrypt(&encrypted_content)?;
+                let created_at = DateTime::parse_from_rfc3339(&created_at)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now());
+                update.execute(params![
+                    new_crypto.encrypt(&title),
+                    new_crypto.encrypt(&content),
+                    content_hash(&new_crypto, &title, created_at, &content),
+                    id
+                ])?;
+                on_progress(index + 1, total);
+            }
+            
+            let mut select = tx.prepare("SELECT filename, mime_type, data FROM attachments WHERE id = ?1")?;
+            let mut update = tx.prepare(
+                "UPDATE attachments SET filename = ?1, mime_type = ?2, data = ?3 WHERE id = ?4"
+            )?;
+            
+            for (index, id) in attachment_ids.iter().enumerate() {
+                let (encrypted_filename, encrypted_mime_type, encrypted_data): (String, String, String) =
+                    select.query_row(params![id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+                let filename = decrypt_migrated(&crypto, &encrypted_filename)?;
+                let mime_type = decrypt_migrated(&crypto, &encrypted_mime_type)?;
+                let data = crypto.decrypt_bytes(&encrypted_data)?;
+                update.execute(params![
+                    new_crypto.encrypt(&filename),
+                    new_crypto.encrypt(&mime_type),
+                    new_crypto.encrypt_bytes(&data),
+                    id
+                ])?;
+                on_progress(ids.len() + index + 1, total);
+            }
+            
+            let mut select = tx.prepare("SELECT name, content FROM templates WHERE id = ?1")?;
+            let mut update = tx.prepare("UPDATE templates SET name = ?1, content = ?2 WHERE id = ?3")?;
+            
+            for (index, id) in template_ids.iter().enumerate() {
+                let (encrypted_name, encrypted_content): (String, String) =
+                    select.query_row(params![id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                let name = crypto.try_decrypt(&encrypted_name)?;
+                let content = crypto.try_decrypt(&encrypted_content)?;
+                update.execute(params![
+                    new_crypto.encrypt(&name),
+                    new_crypto.encrypt(&content),
+                    id
+                ])?;
+                on_progress(ids.len() + attachment_ids.len() + index + 1, total);
+            }
+            
+            let mut select = tx.prepare("SELECT title, content FROM drafts WHERE diary_id = ?1")?;
+            let mut update = tx.prepare("UPDATE drafts SET title = ?1, content = ?2 WHERE diary_id = ?3")?;
+            let done = ids.len() + attachment_ids.len() + template_ids.len();
+            
+            for (index, id) in draft_ids.iter().enumerate() {
+                let (encrypted_title, encrypted_content): (String, String) =
+                    select.query_row(params![id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                let title = decrypt_migrated(&crypto, &encrypted_title)?;
+                let content = crypto.try_decrypt(&encrypted_content)?;
+                update.execute(params![
+                    new_crypto.encrypt(&title),
+                    new_crypto.encrypt(&content),
+                    id
+                ])?;
+                on_progress(done + index + 1, total);
+            }
+            
+            let mut select = tx.prepare("SELECT value FROM diary_properties WHERE rowid = CAST(?1 AS INTEGER)")?;
+            let mut update = tx.prepare("UPDATE diary_properties SET value = ?1 WHERE rowid = CAST(?2 AS INTEGER)")?;
+            let done = done + draft_ids.len();
+            
+            for (index, id) in property_ids.iter().enumerate() {
+                let encrypted_value: String = select.query_row(params![id], |row| row.get(0))?;
+                let value = crypto.try_decrypt(&encrypted_value)?;
+                update.execute(params![new_crypto.encrypt(&value), id])?;
+                on_progress(done + index + 1, total);
+            }
+            
+            let mut select = tx.prepare("SELECT message FROM reminders WHERE id = ?1")?;
+            let mut update = tx.prepare("UPDATE reminders SET message = ?1 WHERE id = ?2")?;
+            let done = done + property_ids.len();
+            
+            for (index, id) in reminder_ids.iter().enumerate() {
+                let encrypted_message: String = select.query_row(params![id], |row| row.get(0))?;
+                let message = crypto.try_decrypt(&encrypted_message)?;
+                update.execute(params![new_crypto.encrypt(&message), id])?;
+                on_progress(done + index + 1, total);
+            }
+            
+            let mut select = tx.prepare("SELECT title, content FROM diary_versions WHERE id = ?1")?;
+            let mut update = tx.prepare("UPDATE diary_versions SET title = ?1, content = ?2 WHERE id = ?3")?;
+            let done = done + reminder_ids.len();
+            
+            for (index, id) in version_ids.iter().enumerate() {
+                let (encrypted_title, encrypted_content): (String, String) =
+                    select.query_row(params![id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                let title = decrypt_migrated(&crypto, &encrypted_title)?;
+                let content = crypto.try_decrypt(&encrypted_content)?;
+                update.execute(params![
+                    new_crypto.encrypt(&title),
+                    new_crypto.encrypt(&content),
+                    id
+                ])?;
+                on_progress(done + index + 1, total);
+            }
+            
+            let mut select = tx.prepare("SELECT name, query FROM saved_searches WHERE id = ?1")?;
+            let mut update = tx.prepare("UPDATE saved_searches SET name = ?1, query = ?2 WHERE id = ?3")?;
+            let done = done + version_ids.len();
+            
+            for (index, id) in saved_search_ids.iter().enumerate() {
+                let (encrypted_name, encrypted_query): (String, String) =
+                    select.query_row(params![id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                let name = crypto.try_decrypt(&encrypted_name)?;
+                let query = crypto.try_decrypt(&encrypted_query)?;
+                update.execute(params![new_crypto.encrypt(&name), new_crypto.encrypt(&query), id])?;
+                on_progress(done + index + 1, total);
+            }
+        }
+        
+        Self::audit(&tx, AuditOperation::KeyRotation, &[], &format!("{} records re-encrypted", total))?;
+        if let Err(e) = new_crypto.stage_key() {
+            new_crypto.discard_staged_key();
+            return Err(e.into());
+        }
+        if let Err(e) = tx.commit() {
+            new_crypto.discard_staged_key();
+            return Err(e.into());
+        }
+        
+        // Every row is under the new key from here on, so switch to it
+        // before persisting it. If that fails the staged key is left for
+        // `recover_interrupted_rotation`.
+        let new_crypto = Arc::new(new_crypto);
+        self.crypto = Some(new_crypto.clone());
+        new_crypto.commit_staged_key()?;
+        Ok(total)
+    }
+    
+    /// Checks the database for corruption and dangling references, then
+    /// vacuums and refreshes the query planner statistics. A file failing
+    /// the integrity check is left as it is, since rewriting it could lose
+    /// what a recovery tool would still find.
+    pub fn run_maintenance(&self) -> DiaryResult<MaintenanceReport> {
+        self.ensure_writable()?;
+        let started = Instant::now();
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let integrity_errors: Vec<String> = conn
+            .prepare("PRAGMA integrity_check")?
+            .query_map([], |row| row.get(0))?
+            .collect::<SqliteResult<Vec<String>>>()?
+            .into_iter()
+            .filter(|message| message != "ok")
+            .collect();
+        let foreign_key_violations = conn
+            .prepare("PRAGMA foreign_key_check")?
+            .query_map([], |row| {
+                Ok(ForeignKeyViolation {
+                    table: row.get(0)?,
+                    rowid: row.get(1)?,
+                    parent: row.get(2)?,
+                })
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        
+        let database_size = |conn: &Connection| -> SqliteResult<u64> {
+            conn.query_row(
+                "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|size| size as u64)
+        };
+        let size_before = database_size(&conn)?;
+        if integrity_errors.is_empty() {
+            conn.execute_batch("VACUUM; ANALYZE;")?;
+        } else {
+            println!("⚠️ [MAINTENANCE] Integrity check failed, skipping VACUUM and ANALYZE");
+        }
+        let size_after = database_size(&conn)?;
+        
+        let report = MaintenanceReport {
+            passed: integrity_errors.is_empty() && foreign_key_violations.is_empty(),
+            integrity_errors,
+            foreign_key_violations,
+            bytes_reclaimed: size_before.saturating_sub(size_after),
+            duration_ms: started.elapsed().as_millis() as u64,
+        };
+        println!(
+            "🧹 [MAINTENANCE] {} in {} ms, {} bytes reclaimed",
+            if report.passed { "Passed" } else { "Problems found" },
+            report.duration_ms,
+            report.bytes_reclaimed
+        );
+        Ok(report)
+    }
+    
+    /// Tries to decrypt every encrypted record with the current key, such as
+    /// after restoring from a backup, and reports the ones that fail. Reads
+    /// only, and carries on past failures.
+    pub fn verify_vault(&self) -> DiaryResult<VaultReport> {
+        let started = Instant::now();
+        let crypto = self.crypto()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let mut checked = 0;
+        let mut failures = Vec::new();
+        for (table, id, text_columns, byte_columns) in ENCRYPTED_COLUMNS {
+            let columns = [text_columns, byte_columns].concat();
+            let mut stmt = conn.prepare(&format!("SELECT {}, {} FROM {}", id, columns.join(", "), table))?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let id: String = row.get(0)?;
+                for (index, column) in columns.iter().enumerate() {
+                    let Some(encrypted) = row.get::<_, Option<String>>(index + 1)? else {
+                        continue;
+                    };
+                    let result = if index >= text_columns.len() {
+                        crypto.decrypt_bytes(&encrypted).map(|_| ())
+                    } else {
+                        crypto.try_decrypt(&encrypted).map(|_| ())
+                    };
+                    if let Err(e) = result {
+                        failures.push(DecryptFailure {
+                            table: table.to_string(),
+                            id: id.clone(),
+                            column: column.to_string(),
+                            kind: DecryptFailureKind::from(&e),
+                            reason: e.to_string(),
+                        });
+                    }
+                }
+                checked += 1;
+            }
+        }
+        
+        let report = VaultReport {
+            passed: failures.is_empty(),
+            checked,
+            failures,
+            duration_ms: started.elapsed().as_millis() as u64,
+        };
+        println!(
+            "🔐 [VERIFY] Checked {} records in {} ms, {} failed to decrypt",
+            report.checked,
+            report.duration_ms,
+            report.failures.len()
+        );
+        Ok(report)
+    }
+    
+    pub fn get_verify_on_startup(&self) -> DiaryResult<bool> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let value: Option<String> = conn
+            .query_row("SELECT value FROM settings WHERE key = ?1", params![VERIFY_ON_STARTUP_SETTING], |row| row.get(0))
+            .optional()?;
+        Ok(value.as_deref() == Some("1"))
+    }
+    
+    /// Sets whether `verify_vault` runs each time the app starts.
+    pub fn set_verify_on_startup(&self, enabled: bool) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        let conn = self.pool.get().expect("Failed to get database connection");
+        conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![VERIFY_ON_STARTUP_SETTING, if enabled { "1" } else { "0" }],
+        )?;
+        Ok(())
+    }
+    
+    pub fn get_database_info(&self) -> DiaryResult<DatabaseInfo> {
+        let conn = self.pool.get().expect("Failed to get database connection");
+        
+        let path = conn.path().unwrap_or_default().to_string();
+        let file_size_bytes = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+        let count = |table: &str| -> SqliteResult<i64> {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+        };
+        
+        Ok(DatabaseInfo {
+            path,
+            file_size_bytes,
+            entry_count: count("diary_entries")?,
+            tag_count: count("tags")?,
+            relationship_count: count("relationships")?,
+            schema_version: conn.query_row("PRAGMA user_version", [], |row| row.get(0))?,
+        })
+    }
+    
+    /// Irreversibly deletes every entry and everything attached to it,
+    /// destroys the vault key and starts over with a fresh key and an empty
+    /// schema. Needs the vault unlocked and, when a passphrase is set, an
+    /// elevated session.
+    pub fn reset_vault(&mut self, confirmation: &str) -> DiaryResult<()> {
+        self.ensure_writable()?;
+        if confirmation != RESET_VAULT_CONFIRMATION {
+            return Err(DiaryError::Validation(format!(
+                "Type \"{}\" to confirm resetting the vault",
+                RESET_VAULT_CONFIRMATION
+            )));
+        }
+        let crypto = self.crypto()?;
+        if self.has_passphrase()? && !self.is_elevated() {
+            return Err(DiaryError::Validation("Enter your passphrase before resetting the vault".to_string()));
+        }
+        let (backend, key_path) = (crypto.backend(), crypto.key_path().to_path_buf());
+        
+        {
+            let mut conn = self.pool.get().expect("Failed to get database connection");
+            // Dropping parents first would trip foreign keys mid-way, and
+            // secure_delete zeroes the freed pages instead of leaving them.
+            conn.execute_batch("PRAGMA foreign_keys = OFF; PRAGMA secure_delete = ON;")?;
+            let dropped = Self::drop_all_tables(&mut conn);
+            conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA secure_delete = OFF;")?;
+            println!("🗑️ [RESET] Dropped {} tables", dropped?);
+        }
+        
+        // Drop the old key from memory before the new one exists.
+        self.crypto = None;
+        self.locked_key = None;
+        self.invalidate_titles();
+        self.crypto = Some(Arc::new(Crypto::reset_key(backend, key_path)?));
+        
+        self.initialize_db()?;
+        println!("🗑️ [RESET] Vault reset with a fresh key");
+        Ok(())
+    }
+    
+    /// Drops every table in one transaction, so a failure leaves the vault
+    /// as it was, then vacuums. Returns the number of tables dropped.
+    fn drop_all_tables(conn: &mut Connection) -> SqliteResult<usize> {
+        let tx = conn.transaction()?;
+        let tables: Vec<String> = tx
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")?
+            .query_map([], |row| row.get(0))?
+            .collect::<SqliteResult<_>>()?;
+        for table in &tables {
+            tx.execute_batch(&format!("DROP TABLE \"{}\";", table.replace('"', "\"\"")))?;
+        }
+        tx.pragma_update(None, "user_version", 0)?;
+        tx.commit()?;
+        conn.execute_batch("VACUUM;")?;
+        Ok(tables.len())
+    }
+    
+    /// Rewrites entries still stored in the legacy JSON envelope into the
+    /// compact format. Returns the number of entries rewritten.
+    pub fn compact_storage(&self) -> DiaryResult<usize> {
+        self.ensure_writable()?;
+        let crypto = self.crypto()?;
+        let mut conn = self.pool.get().expect("Failed to get database connection");
+        let tx = conn.transaction()?;
+        
+        let legacy_rows = {
+            let mut stmt = tx.prepare("SELECT id, title, content FROM diary_entries")?;
+            let row_iter = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?;
+            let mut legacy_rows = Vec::new();
+            for row_result in row_iter {
+                let (id, title, content) = row_result?;
+                if Crypto::is_legacy_format(&title) || Crypto::is_legacy_format(&content) {
+                    legacy_rows.push((id, title, content));
+                }
+            }
+            legacy_rows
+        };
+        
+        {
+            let mut update = tx.prepare(
+                "UPDATE diary_entries SET title = ?1, content = ?2 WHERE id = ?3"
+            )?;
+            for (id, title, content) in &legacy_rows {
+                update.execute(params![
+                    crypto.encrypt(&crypto.try_decrypt(title)?),
+                    crypto.encrypt(&crypto.try_decrypt(content)?),
+                    id
+                ])?;
+            }
+        }
+        tx.commit()?;
+        
+        Ok(legacy_rows.len())
+    }
+    
+    /// Finishes or rolls back a key rotation that was interrupted after the
+    /// new key was staged. Whichever key can decrypt the stored records wins;
+    /// one record of every table in `ENCRYPTED_COLUMNS` is tried, so a vault
+    /// without entries still keeps the key its other records need.
+    fn recover_interrupted_rotation(&mut self) -> DiaryResult<()> {
+        let crypto = match &self.crypto {
+            Some(crypto) => crypto.clone(),
+            None => return Ok(()),
+        };
+        let staged = match crypto.load_staged() {
+            Some(staged) => staged,
+            None => return Ok(()),
+        };
+        
+        let conn = self.pool.get().expect("Failed to get database connection");
+        let mut staged_opens = false;
+        for (table, _, text_columns, byte_columns) in ENCRYPTED_COLUMNS {
+            // Older schemas kept some text columns in plaintext; the last
+            // one, or the byte column, was always encrypted.
+            let (column, bytes) = match byte_columns.first() {
+                Some(column) => (*column, true),
+                None => (*text_columns.last().expect("every table has an encrypted column"), false),
+            };
+            let sample: Option<String> = conn
+                .query_row(&format!("SELECT {} FROM {} LIMIT 1", column, table), [], |row| row.get(0))
+                .optional()?;
+            let Some(sample) = sample else {
+                continue;
+            };
+            let opens = |key: &Crypto| {
+                if bytes {
+                    key.decrypt_bytes(&sample).is_ok()
+                } else {
+                    key.try_decrypt(&sample).is_ok()
+                }
+            };
+            if opens(crypto.as_ref()) {
+                break;
+            }
+            if opens(&staged) {
+                staged_opens = true;
+                break;
+            }
+        }
+        drop(conn);
+        
+        if staged_opens {
+            staged.commit_staged_key()?;
+            self.crypto = Some(Arc::new(staged));
+        } else {
+            staged.discard_staged_key();
+        }
+        
+        Ok(())
+    }
+}
+
+/// Imports a Day One JSON export, keeping the original timestamps and
+/// tags and storing location and weather as custom properties. Entries
+/// that can't be read are listed in the report instead of aborting the
+/// import; with `dry_run` nothing is written.
+///
+/// Entries already in the vault (same title, creation time and content)
+/// are skipped, so a failed import can simply be re-run. With
+/// `update_duplicates` their tags and properties are merged instead.
+///
+/// Each step checks or saves `IMPORT_BATCH_SIZE` entries, checking for
+/// cancellation in between. Batches saved before a cancel stay in the
+/// vault and are skipped as duplicates on the next run.
+pub struct DayOneImporter {
+    dry_run: bool,
+    update_duplicates: bool,
+    pending: std::iter::Enumerate<std::vec::IntoIter<Result<day_one::DayOneEntry, String>>>,
+    total: usize,
+    /// Content hashes already in the vault, loaded by the first step.
+    existing: Option<HashMap<String, String>>,
+    seen: HashSet<String>,
+    /// New entries and their positions in the file.
+    indexes: Vec<usize>,
+    entries: Vec<day_one::DayOneEntry>,
+    ids: Vec<Option<String>>,
+    skipped: usize,
+    updated: usize,
+    errors: Vec<ImportError>,
+}
+
+impl DayOneImporter {
+    pub fn new(path: &Path, dry_run: bool, update_duplicates: bool) -> DiaryResult<Self> {
+        let json = fs::read_to_string(path)?;
+        let parsed = day_one::parse_export(&json).map_err(DiaryError::Validation)?;
+        Ok(Self {
+            dry_run,
+            update_duplicates,
+            total: parsed.len(),
+            pending: parsed.into_iter().enumerate(),
+            existing: None,
+            seen: HashSet::new(),
+            indexes: Vec::new(),
+            entries: Vec::new(),
+            ids: Vec::new(),
+            skipped: 0,
+            updated: 0,
+            errors: Vec::new(),
+        })
+    }
+    
+    /// Checks the next batch of entries against the vault, merging into
+    /// duplicates when asked to. Returns false once every entry is checked.
+    fn check_batch(&mut self, db: &DiaryDB, progress: &mut Progress) -> DiaryResult<bool> {
+        let existing = match &mut self.existing {
+            Some(existing) => existing,
+            None => {
+                if !self.dry_run {
+                    db.ensure_writable()?;
+                }
+                self.existing.insert(db.content_hashes()?)
+            }
+        };
+        let crypto = db.crypto()?;
+        let checked = self.total - self.pending.len();
+        progress.report("checking", checked, self.total);
+        progress.check_cancelled()?;
+        
+        for (index, entry) in self.pending.by_ref().take(IMPORT_BATCH_SIZE) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(message) => {
+                    self.errors.push(ImportError { index, message });
+                    continue;
+                }
+            };
+            let diary = &entry.diary;
+            let hash = content_hash(crypto, &diary.title, diary.created_at.unwrap_or_else(Utc::now), &diary.content);
+            if !self.seen.insert(hash.clone()) {
+                self.skipped += 1;
+                continue;
+            }
+            let Some(existing_id) = existing.get(&hash) else {
+                self.indexes.push(index);
+                self.entries.push(entry);
+                continue;
+            };
+            if !self.update_duplicates {
+                self.skipped += 1;
+                continue;
+            }
+            self.updated += 1;
+            if !self.dry_run {
+                if let Err(e) = db.merge_imported_metadata(existing_id, &entry) {
+                    self.errors.push(ImportError { index, message: e.to_string() });
+                }
+            }
+        }
+        
+        Ok(self.pending.len() > 0)
+    }
+    
+    /// Saves the next batch of new entries and their properties. Returns
+    /// false once every new entry is saved.
+    fn save_batch(&mut self, db: &DiaryDB, progress: &mut Progress) -> DiaryResult<bool> {
+        let start = self.ids.len();
+        if self.dry_run || start == self.entries.len() {
+            return Ok(false);
+        }
+        progress.report("importing", start, self.entries.len());
+        progress.check_cancelled()?;
+        
+        let chunk = &self.entries[start..(start + IMPORT_BATCH_SIZE).min(self.entries.len())];
+        let ids = db.save_day_one_batch(chunk)?;
+        self.ids.extend(ids.into_iter().map(Some));
+        
+        Ok(true)
+    }
+    
+    fn finish(&mut self, db: &DiaryDB, progress: &mut Progress) -> DiaryResult<ImportReport> {
+        progress.report("checking", self.total, self.total);
+        if self.dry_run {
+            self.ids = vec![None; self.entries.len()];
+        } else {
+            progress.report("importing", self.ids.len(), self.entries.len());
+            // Each batch only resolves links to entries saved so far, so
+            // links into later batches are resolved once everything is in.
+            if self.entries.len() > IMPORT_BATCH_SIZE {
+                let linked: Vec<String> = self
+                    .ids
+                    .iter()
+                    .zip(&self.entries)
+                    .filter(|(_, entry)| !extract_wikilinks(&entry.diary.content).is_empty())
+                    .map(|(id, _)| id.clone().expect("saved entry"))
+                    .collect();
+                db.resync_wikilinks(&linked)?;
+            }
+        }
+        
+        let entries = std::mem::take(&mut self.entries);
+        let mut imported = Vec::with_capacity(entries.len());
+        for ((index, entry), id) in self.indexes.drain(..).zip(entries).zip(self.ids.drain(..)) {
+            imported.push(ImportedEntry {
+                index,
+                id,
+                title: entry.diary.title,
+                created_at: entry.diary.created_at.unwrap_or_else(Utc::now),
+                tags: entry.diary.tags,
+                property_keys: entry.properties.into_iter().map(|(key, _)| key).collect(),
+            });
+        }
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|error| error.index);
+        
+        println!(
+            "📝 [IMPORT] Day One: {} entries {}, {} skipped, {} updated, {} errors",
+            imported.len(),
+            if self.dry_run { "checked" } else { "imported" },
+            self.skipped,
+            self.updated,
+            errors.len()
+        );
+        Ok(ImportReport { dry_run: self.dry_run, imported, skipped: self.skipped, updated: self.updated, errors })
+    }
+}
+
+impl Batched for DayOneImporter {
+    type Output = ImportReport;
+    
+    fn step(&mut self, db: &DiaryDB, progress: &mut Progress) -> DiaryResult<Option<ImportReport>> {
+        if self.check_batch(db, progress)? || self.save_batch(db, progress)? {
+            return Ok(None);
+        }
+        self.finish(db, progress).map(Some)
+    }
+}
+
+/// Writes one CSV row of metadata per entry to `path`, oldest first and
+/// without content, and returns the number of rows written. Each step
+/// writes `EXPORT_PROGRESS_BATCH` rows. The file is removed if the export
+/// is dropped unfinished, so a failure or cancel doesn't leave half a file
+/// behind.
+pub struct CsvExporter {
+    path: PathBuf,
+    out: Option<BufWriter<fs::File>>,
+    include_archived: bool,
+    total: Option<usize>,
+    written: usize,
+    /// `created_at` and id of the last row written.
+    after: (String, String),
+}
+
+impl CsvExporter {
+    pub fn new(path: &Path, include_archived: bool, excel_bom: bool) -> DiaryResult<Self> {
+        let mut exporter = Self {
+            path: path.to_path_buf(),
+            out: Some(BufWriter::new(fs::File::create(path)?)),
+            include_archived,
+            total: None,
+            written: 0,
+            after: (String::new(), String::new()),
+        };
+        let out = exporter.out.as_mut().expect("open until finished");
+        if excel_bom {
+            out.write_all(csv_export::UTF8_BOM)?;
+        }
+        csv_export::write_record(out, &csv_export::HEADER)?;
+        Ok(exporter)
+    }
+}
+
+impl Batched for CsvExporter {
+    type Output = usize;
+    
+    fn step(&mut self, db: &DiaryDB, progress: &mut Progress) -> DiaryResult<Option<usize>> {
+        let conn = db.pool.get().expect("Failed to get database connection");
+        let crypto = db.crypto()?;
+        let total = match self.total {
+            Some(total) => total,
+            None => {
+                let total: i64 = conn.query_row(
+                    "SELECT COUNT(*) FROM diary_entries WHERE ?1 OR archived_at IS NULL",
+                    params![self.include_archived],
+                    |row| row.get(0),
+                )?;
+                *self.total.insert(total as usize)
+            }
+        };
+        progress.report("exporting", self.written, total);
+        progress.check_cancelled()?;
+        
+        // Content is only read for entries the word count backfill hasn't
+        // reached yet. Tag names are joined on the unit separator, which
+        // can't appear in a tag, and re-joined sorted below.
+        let mut stmt = conn.prepare(
+            "SELECT d.id, d.title, d.created_at, d.updated_at, d.word_count,
+                    CASE WHEN d.word_count IS NULL THEN d.content END,
+                    (SELECT GROUP_CONCAT(t.name, char(31)) FROM diary_tags dt
+                     JOIN tags t ON t.id = dt.tag_id
+                     WHERE dt.diary_id = d.id),
+                    (SELECT COUNT(*) FROM relationships r
+                     WHERE r.parent_id = d.id OR r.child_id = d.id)
+             FROM diary_entries d
+             WHERE (?1 OR d.archived_at IS NULL) AND (d.created_at, d.id) > (?2, ?3)
+             ORDER BY d.created_at, d.id
+             LIMIT ?4"
+        )?;
+        let mut rows = stmt.query(params![self.include_archived, self.after.0, self.after.1, EXPORT_PROGRESS_BATCH as i64])?;
+        
+        let out = self.out.as_mut().expect("open until finished");
+        let mut batch = 0;
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(0)?;
+            let encrypted_title: String = row.get(1)?;
+            let created_at: String = row.get(2)?;
+            let updated_at: String = row.get(3)?;
+            let word_count: Option<i64> = row.get(4)?;
+            let encrypted_content: Option<String> = row.get(5)?;
+            let tags: Option<String> = row.get(6)?;
+            let relationship_count: i64 = row.get(7)?;
+            
+            let word_count = word_count
+                .unwrap_or_else(|| count_words(&crypto.decrypt(encrypted_content.as_deref().unwrap_or_default())));
+            let mut tags: Vec<&str> = tags.as_deref().map(|tags| tags.split('\u{1f}').collect()).unwrap_or_default();
+            tags.sort_unstable();
+            
+            csv_export::write_record(
+                out,
+                &[
+                    id.clone(),
+                    decrypt_migrated(crypto, &encrypted_title).expect("Decryption failed"),
+                    created_at.clone(),
+                    updated_at,
+                    tags.join(";"),
+                    word_count.to_string(),
+                    relationship_count.to_string(),
+                ],
+            )?;
+            self.after = (created_at, id);
+            batch += 1;
+        }
+        self.written += batch;
+        if batch == EXPORT_PROGRESS_BATCH {
+            return Ok(None);
+        }
+        
+        out.flush()?;
+        self.out = None;
+        progress.report("exporting", self.written, total);
+        db.record_audit(AuditOperation::Export, &[], &format!("csv: {} entries", self.written));
+        
+        Ok(Some(self.written))
+    }
+}
+
+impl Drop for CsvExporter {
+    fn drop(&mut self) {
+        if self.out.take().is_some() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Writes every entry to `path` as a JSON Lines journal,
+/// `EXPORT_PROGRESS_BATCH` entries per step. Locked entries are only
+/// included in an elevated session. Like `CsvExporter`, an unfinished
+/// export removes its file when dropped.
+pub struct JournalExporter {
+    path: PathBuf,
+    out: Option<BufWriter<fs::File>>,
+    total: Option<usize>,
+    seen: usize,
+    report: JournalExport,
+    /// `created_at` and id of the last entry seen.
+    after: (String, String),
+}
+
+impl JournalExporter {
+    pub fn new(path: &Path) -> DiaryResult<Self> {
+        let mut exporter = Self {
+            path: path.to_path_buf(),
+            out: Some(BufWriter::new(fs::File::create(path)?)),
+            total: None,
+            seen: 0,
+            report: JournalExport { exported: 0, skipped_locked: 0 },
+            after: (String::new(), String::new()),
+        };
+        journal::write_header(exporter.out.as_mut().expect("open until finished"))?;
+        Ok(exporter)
+    }
+}
+
+impl Batched for JournalExporter {
+    type Output = JournalExport;
+    
+    fn step(&mut self, db: &DiaryDB, progress: &mut Progress) -> DiaryResult<Option<JournalExport>> {
+        let conn = db.pool.get().expect("Failed to get database connection");
+        let crypto = db.crypto()?;
+        let total = match self.total {
+            Some(total) => total,
+            None => {
+                let total: i64 = conn.query_row("SELECT COUNT(*) FROM diary_entries", [], |row| row.get(0))?;
+                *self.total.insert(total as usize)
+            }
+        };
+        progress.report("exporting", self.seen, total);
+        progress.check_cancelled()?;
+        let elevated = db.is_elevated();
+        
+        let mut stmt = conn.prepare(
+            "SELECT id, title, content, created_at, updated_at, locked, archived_at, pinned_at, sort_order, daily_date
+             FROM diary_entries
+             WHERE (created_at, id) > (?1, ?2)
+             ORDER BY created_at, id
+             LIMIT ?3"
+        )?;
+        let mut rows = stmt.query(params![self.after.0, self.after.1, EXPORT_PROGRESS_BATCH as i64])?;
+        let mut select_relationships = conn.prepare(
+            "SELECT child_id, relationship_type, note, weight, created_at FROM relationships
+             WHERE parent_id = ?1 AND source = ?2
+             ORDER BY created_at, id"
+        )?;
+        
+        let out = self.out.as_mut().expect("open until finished");
+        let mut batch = 0;
+        while let Some(row) = rows.next()? {
+            batch += 1;
+            let id: String = row.get(0)?;
+            let created_at: String = row.get(3)?;
+            self.after = (created_at.clone(), id.clone());
+            let locked: bool = row.get(5)?;
+            if locked && !elevated {
+                self.report.skipped_locked += 1;
+                continue;
+            }
+            
+            let parse = |value: String| {
+                DateTime::parse_from_rfc3339(&value)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now())
+            };
+            let properties = match db.get_properties_for_diary(&id)? {
+                serde_json::Value::Object(properties) => properties,
+                _ => serde_json::Map::new(),
+            };
+            let relationships = select_relationships
+                .query_map(params![id, SOURCE_MANUAL], |row| {
+                    Ok(journal::JournalRelationship {
+                        child_id: row.get(0)?,
+                        relationship_type: row.get(1)?,
+                        note: row.get(2)?,
+                        weight: row.get(3)?,
+                        created_at: parse(row.get(4)?),
+                    })
+                })?
+                .collect::<SqliteResult<Vec<_>>>()?;
+            let daily_date: Option<String> = row.get(9)?;
+            let record = JournalRecord {
+                title: decrypt_migrated(crypto, &row.get::<_, String>(1)?)?,
+                content: crypto.try_decrypt(&row.get::<_, String>(2)?)?,
+                tags: db.get_tags_for_diary(&id)?,
+                created_at: parse(created_at),
+                updated_at: parse(row.get(4)?),
+                properties,
+                locked,
+                archived_at: row.get::<_, Option<String>>(6)?.map(parse),
+                pinned_at: row.get::<_, Option<String>>(7)?.map(parse),
+                sort_order: row.get(8)?,
+                daily_date: daily_date.as_deref().map(parse_daily_date).transpose()?,
+                relationships,
+                id,
+            };
+            journal::write_record(out, &record)?;
+            self.report.exported += 1;
+        }
+        self.seen += batch;
+        if batch == EXPORT_PROGRESS_BATCH {
+            return Ok(None);
+        }
+        
+        out.flush()?;
+        self.out = None;
+        progress.report("exporting", self.seen, total);
+        
+        db.record_audit(AuditOperation::Export, &[], &format!("journal: {} entries", self.report.exported));
+        println!("📝 [EXPORT] Journal: {} entries, {} locked skipped", self.report.exported, self.report.skipped_locked);
+        Ok(Some(self.report.clone()))
+    }
+}
+
+impl Drop for JournalExporter {
+    fn drop(&mut self) {
+        if self.out.take().is_some() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Imports a journal written by `JournalExporter`, reading it a line at a
+/// time and saving `JOURNAL_IMPORT_BATCH` entries per step. As with Day
+/// One imports, entries already in the vault are skipped, so a cancelled
+/// import can be re-run to pick up where it stopped.
+pub struct JournalImporter {
+    lines: std::iter::Enumerate<std::io::Lines<BufReader<fs::File>>>,
+    total: usize,
+    /// Content hashes already in the vault, loaded by the first step.
+    existing: Option<HashMap<String, String>>,
+    report: JournalImport,
+    /// Imported entries with wikilinks, re-synced once all are in.
+    linked: Vec<String>,
+    /// Journal id of every entry read to its vault id, or for a repeated
+    /// entry to the journal id of its first copy.
+    ids: HashMap<String, String>,
+    /// Manual relationships by the journal id of their parent, added once
+    /// all entries are in.
+    relationships: Vec<(String, journal::JournalRelationship)>,
+}
+
+impl JournalImporter {
+    pub fn new(path: &Path) -> DiaryResult<Self> {
+        // Counting lines first gives progress a total without holding the file.
+        let total = BufReader::new(fs::File::open(path)?).split(b'\n').count().saturating_sub(1);
+        let mut lines = BufReader::new(fs::File::open(path)?).lines();
+        let header = lines
+            .next()
+            .transpose()?
+            .ok_or_else(|| DiaryError::Validation("Journal export is empty".to_string()))?;
+        journal::check_header(&header).map_err(DiaryError::Validation)?;
+        
+        Ok(Self {
+            lines: lines.enumerate(),
+            total,
+            existing: None,
+            report: JournalImport::default(),
+            linked: Vec::new(),
+            ids: HashMap::new(),
+            relationships: Vec::new(),
+        })
+    }
+}
+
+impl Batched for JournalImporter {
+    type Output = JournalImport;
+    
+    fn step(&mut self, db: &DiaryDB, progress: &mut Progress) -> DiaryResult<Option<JournalImport>> {
+        db.ensure_writable()?;
+        let existing = match &mut self.existing {
+            Some(existing) => existing,
+            None => {
+                progress.report("importing", 0, self.total);
+                self.existing.insert(db.content_hashes()?)
+            }
+        };
+        let crypto = db.crypto()?;
+        
+        let mut batch = Vec::with_capacity(JOURNAL_IMPORT_BATCH);
+        let mut read = 0;
+        for (index, line) in self.lines.by_ref() {
+            read = index + 1;
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut record = match journal::parse_record(&line) {
+                Ok(record) => record,
+                Err(message) => {
+                    self.report.errors.push(ImportError { index, message });
+                    continue;
+                }
+            };
+            // Relationships of skipped entries are kept too, so re-running a
+            // cancelled import still adds those of its earlier batches.
+            let relationships = std::mem::take(&mut record.relationships);
+            self.relationships.extend(relationships.into_iter().map(|relationship| (record.id.clone(), relationship)));
+            let hash = content_hash(crypto, &record.title, record.created_at, &record.content);
+            if let Some(seen) = existing.get(&hash) {
+                self.ids.insert(record.id.clone(), seen.clone());
+                self.report.skipped += 1;
+                continue;
+            }
+            existing.insert(hash, record.id.clone());
+            batch.push(record);
+            if batch.len() == JOURNAL_IMPORT_BATCH {
+                break;
+            }
+        }
+        let finished = batch.len() < JOURNAL_IMPORT_BATCH;
+        if !batch.is_empty() {
+            progress.check_cancelled()?;
+            let journal_ids: Vec<String> = batch.iter().map(|record| record.id.clone()).collect();
+            let saved = db.save_journal_batch(&mut batch, &mut self.linked)?;
+            self.report.imported += saved.len();
+            self.ids.extend(journal_ids.into_iter().zip(saved));
+            progress.report("importing", read, self.total);
+        }
+        if !finished {
+            return Ok(None);
+        }
+        
+        progress.report("importing", self.total, self.total);
+        // Links into later batches couldn't resolve when their batch was saved.
+        db.resync_wikilinks(&self.linked)?;
+        self.report.relationships = db.import_journal_relationships(&self.ids, &self.relationships)?;
+        
+        println!(
+            "📝 [IMPORT] Journal: {} entries imported, {} skipped, {} errors",
+            self.report.imported,
+            self.report.skipped,
+            self.report.errors.len()
+        );
+        Ok(Some(std::mem::take(&mut self.report)))
+    }
+}
+
+/// Label for reading a relationship from child to parent.
+fn inverse_relationship_label(relationship_type: &str) -> String {
+    match relationship_type {
+        "parent_of" => "child_of".to_string(),
+        "child_of" => "parent_of".to_string(),
+        "depends_on" => "dependency_of".to_string(),
+        "dependency_of" => "depends_on".to_string(),
+        "links_to" => "linked_from".to_string(),
+        "linked_from" => "links_to".to_string(),
+        "related_to" => "related_to".to_string(),
+        other => format!("inverse_of_{}", other),
+    }
+}
+
+/// Decrypts a value from a column that was stored in plaintext until a
+/// migration encrypted it: entry titles (14) and attachment names and
+/// types (15). A read-only vault never runs migrations, so a value that
+/// isn't an envelope is returned as it is.
+fn decrypt_migrated(crypto: &Crypto, title: &str) -> Result<String, CryptoError> {
+    if Crypto::looks_encrypted(title) {
+        crypto.try_decrypt(title)
+    } else {
+        Ok(title.to_string())
+    }
+}
+
+fn decode_property(crypto: &Crypto, encrypted_value: &str) -> DiaryResult<serde_json::Value> {
+    let json = crypto.try_decrypt(encrypted_value)?;
+    serde_json::from_str(&json)
+        .map_err(|e| DiaryError::Validation(format!("Corrupt property value: {}", e)))
+}
+
+fn parse_daily_date(date: &str) -> DiaryResult<NaiveDate> {
+    NaiveDate::parse_from_str(date, DAILY_NOTE_DATE_FORMAT)
+        .map_err(|_| DiaryError::Validation(format!("Invalid date '{}', expected YYYY-MM-DD", date)))
+}
+
+/// Byte ranges of `terms` in `content`, sorted and without overlaps, or
+/// `None` if some term is in neither the title nor the content.
+fn match_all_terms(title: &str, content: &str, terms: &[&str]) -> Option<Vec<(usize, usize)>> {
+    let mut content_matches = Vec::new();
+    for term in terms {
+        let found = find_matches(content, term);
+        if found.is_empty() && find_matches(title, term).is_empty() {
+            return None;
+        }
+        content_matches.extend(found);
+    }
+    content_matches.sort_unstable();
+    content_matches.dedup_by(|later, earlier| later.0 < earlier.1);
+    Some(content_matches)
+}
+
+fn count_words(text: &str) -> i64 {
+    text.split_whitespace().count() as i64
+}
+
+/// Minutes to read `word_count` words, rounded up.
+fn reading_time_minutes(word_count: i64) -> i64 {
+    (word_count + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE
+}
+
+/// Whether `day` falls on the same month and day as `today` in an earlier
+/// year. February 29 entries come up on February 28 in common years.
+fn is_anniversary(day: NaiveDate, today: NaiveDate) -> bool {
+    if day.year() >= today.year() {
+        return false;
+    }
+    if (day.month(), day.day()) == (today.month(), today.day()) {
+        return true;
+    }
+    let leap_year = NaiveDate::from_ymd_opt(today.year(), 2, 29).is_some();
+    (day.month(), day.day()) == (2, 29) && (today.month(), today.day()) == (2, 28) && !leap_year
+}
+
+/// Returns the longest run of consecutive days and the run ending at
+/// `today`, which may also end yesterday so an unwritten today doesn't
+/// reset it.
+fn writing_streaks(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> (i64, i64) {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for day in days {
+        run = match previous {
+            Some(prev) if *day - prev == Duration::days(1) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(*day);
+    }
+    
+    let mut cursor = if days.contains(&today) { today } else { today - Duration::days(1) };
+    let mut current = 0;
+    while days.contains(&cursor) {
+        current += 1;
+        cursor -= Duration::days(1);
+    }
+    
+    (longest, current)
+}
+
+/// Identity of an entry for duplicate detection: its title, creation time
+/// to the second and content, keyed with the vault key so the stored hash
+/// reveals nothing about the plaintext.
+fn content_hash(crypto: &Crypto, title: &str, created_at: DateTime<Utc>, content: &str) -> String {
+    crypto.keyed_hash(&[title, &created_at.to_rfc3339_opts(SecondsFormat::Secs, true), content])
+}
+
+/// Formats a reminder time so that stored times sort correctly as text.
+fn reminder_timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn mime_type_for(filename: &str) -> &'static str {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "heic" => "image/heic",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operations::Operations;
+    use crate::text_diff::DiffLineKind;
+    use chrono::TimeZone;
+    
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("secondbrian-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    fn open_temp_db(dir: &std::path::Path) -> DiaryDB {
+        let crypto = Crypto::with_key_path(dir.join("encryption.key"));
+        DiaryDB::open(dir.join("diary.db").to_str().unwrap(), crypto)
+    }
+    
+    /// The tables of a vault from before any migration.
+    const BASELINE_SCHEMA: &str =
+        "CREATE TABLE diary_entries (id TEXT PRIMARY KEY, title TEXT NOT NULL, content TEXT NOT NULL,
+                                     created_at TEXT NOT NULL, updated_at TEXT NOT NULL);
+         CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+         CREATE TABLE diary_tags (diary_id TEXT NOT NULL, tag_id TEXT NOT NULL, PRIMARY KEY (diary_id, tag_id));
+         CREATE TABLE relationships (id TEXT PRIMARY KEY, parent_id TEXT NOT NULL, child_id TEXT NOT NULL,
+                                     relationship_type TEXT NOT NULL, created_at TEXT NOT NULL);";
+    
+    #[test]
+    fn test_rotate_encryption_key() {
+        let dir = temp_dir();
+        let mut db = open_temp_db(&dir);
+        let first = db.save_diary(None, "First", "first content", &["a".to_string()]).unwrap();
+        let second = db.save_diary(None, "Second", "second content", &[]).unwrap();
+        let template = db.save_template(None, "Template", "template body", &[]).unwrap();
+        db.save_draft(Some(&first), "First", "draft content", &[]).unwrap();
+        db.set_diary_property(&first, "mood", &serde_json::json!({"level": 3})).unwrap();
+        db.set_reminder(&second, "2020-01-01T00:00:00Z", "reread").unwrap();
+        db.save_diary(Some(&second), "Second", "second content", &[]).unwrap();
+        db.save_diary(Some(&second), "Second", "edited", &[]).unwrap();
+        db.save_diary(Some(&second), "Second", "second content", &[]).unwrap();
+        let search = db.save_search("Firsts", &SearchQuery { text: "first".to_string(), ..Default::default() }).unwrap();
+        let old_key = fs::read(dir.join("encryption.key")).unwrap();
+        
+        let mut progress = Vec::new();
+        let rotated = db.rotate_encryption_key(|done, total| progress.push((done, total))).unwrap();
+        
+        assert_eq!(rotated, 9);
+        assert_eq!(progress.last(), Some(&(9, 9)));
+        assert_eq!(db.list_templates().unwrap()[0].id, template.id);
+        assert_eq!(db.list_templates().unwrap()[0].content, "template body");
+        assert_eq!(db.get_draft(Some(&first)).unwrap().unwrap().content, "draft content");
+        assert_eq!(db.get_diary(&first).unwrap().properties["mood"]["level"], 3);
+        assert_eq!(db.list_reminders_for_diary(&second).unwrap()[0].message, "reread");
+        assert_eq!(db.diff_diary_versions(&second, "1", "2").unwrap().diff.hunks.len(), 1);
+        assert_eq!(db.run_saved_search(&search.id).unwrap()[0].id, first);
+        assert_ne!(fs::read(dir.join("encryption.key")).unwrap(), old_key);
+        assert!(!dir.join("encryption.key.pending").exists());
+        assert_eq!(db.get_diary(&first).unwrap().content, "first content");
+        assert_eq!(db.get_diary(&second).unwrap().content, "second content");
+        
+        // A fresh process must pick up the rotated key from disk.
+        drop(db);
+        let reopened = open_temp_db(&dir);
+        assert_eq!(reopened.get_diary(&first).unwrap().content, "first content");
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_recover_rotation_interrupted_after_commit() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let id = db.save_diary(None, "Entry", "survives the crash", &[]).unwrap();
+        
+        // Simulate a crash between the database commit and the key file rename.
+        let new_crypto = db.crypto().unwrap().generate_replacement();
+        new_crypto.stage_key().unwrap();
+        let conn = db.pool.get().unwrap();
+        conn.execute(
+            "UPDATE diary_entries SET title = ?1, content = ?2 WHERE id = ?3",
+            params![new_crypto.encrypt("Entry"), new_crypto.encrypt("survives the crash"), id],
+        ).unwrap();
+        drop(conn);
+        drop(db);
+        
+        let reopened = open_temp_db(&dir);
+        assert_eq!(reopened.get_diary(&id).unwrap().content, "survives the crash");
+        assert!(!dir.join("encryption.key.pending").exists());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_recover_rotation_interrupted_without_entries() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let template = db.save_template(None, "Template", "template body", &[]).unwrap();
+        
+        // Only a template was re-encrypted before the crash.
+        let new_crypto = db.crypto().unwrap().generate_replacement();
+        new_crypto.stage_key().unwrap();
+        let conn = db.pool.get().unwrap();
+        conn.execute(
+            "UPDATE templates SET name = ?1, content = ?2 WHERE id = ?3",
+            params![new_crypto.encrypt("Template"), new_crypto.encrypt("template body"), template.id],
+        ).unwrap();
+        drop(conn);
+        drop(db);
+        
+        let reopened = open_temp_db(&dir);
+        assert_eq!(reopened.list_templates().unwrap()[0].content, "template body");
+        assert!(!dir.join("encryption.key.pending").exists());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_lock_and_unlock() {
+        let dir = temp_dir();
+        let mut db = open_temp_db(&dir);
+        let id = db.save_diary(None, "Entry", "locked away", &[]).unwrap();
+        
+        // Locking needs a passphrase to unlock with.
+        assert!(matches!(db.lock(), Err(DiaryError::Validation(_))));
+        assert!(!db.is_locked());
+        db.set_passphrase(None, "open sesame").unwrap();
+        
+        db.lock().unwrap();
+        assert!(db.is_locked());
+        assert!(matches!(db.get_diary(&id), Err(DiaryError::Locked)));
+        assert!(matches!(db.reload_key(), Err(DiaryError::Locked)));
+        assert!(db.is_locked());
+        assert!(matches!(db.save_diary(None, "New", "content", &[]), Err(DiaryError::Locked)));
+        
+        for wrong in ["", "open says me"] {
+            assert!(matches!(
+                db.unlock(wrong),
+                Err(DiaryError::Crypto(CryptoError::IncorrectPassword))
+            ));
+            assert!(db.is_locked());
+        }
+        db.unlock("open sesame").unwrap();
+        assert!(!db.is_locked());
+        assert_eq!(db.get_diary(&id).unwrap().content, "locked away");
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_titles_are_encrypted_and_migrated() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let id = db.save_diary(None, "Therapy notes", "content", &[]).unwrap();
+        
+        let conn = db.pool.get().unwrap();
+        let stored: String = conn
+            .query_row("SELECT title FROM diary_entries WHERE id = ?1", params![id], |row| row.get(0))
+            .unwrap();
+        assert!(!stored.contains("Therapy"));
+        
+        // Simulate a vault written by an older version: a plaintext title,
+        // and a title encrypted under some other key.
+        let foreign_id = db.save_diary(None, "Foreign", "content", &[]).unwrap();
+        let foreign = db.crypto().unwrap().generate_replacement().encrypt("Foreign");
+        conn.execute(
+            "UPDATE diary_entries SET title = 'Old plaintext' WHERE id = ?1",
+            params![id],
+        ).unwrap();
+        conn.execute(
+            "UPDATE diary_entries SET title = ?1 WHERE id = ?2",
+            params![foreign, foreign_id],
+        ).unwrap();
+        conn.pragma_update(None, "user_version", 13).unwrap();
+        drop(conn);
+        drop(db);
+        
+        let reopened = open_temp_db(&dir);
+        assert_eq!(reopened.get_diary(&id).unwrap().title, "Old plaintext");
+        let conn = reopened.pool.get().unwrap();
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len() as i64);
+        // Ciphertext that doesn't decrypt is damage to report, not plaintext.
+        let stored: String = conn
+            .query_row("SELECT title FROM diary_entries WHERE id = ?1", params![foreign_id], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, foreign);
+        drop(conn);
+        
+        // The migration runs once; opening again changes nothing.
+        reopened.run_key_migrations().unwrap();
+        drop(reopened);
+        let reopened = open_temp_db(&dir);
+        assert_eq!(reopened.get_diary(&id).unwrap().title, "Old plaintext");
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_compact_storage_rewrites_legacy_rows() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let id = db.save_diary(None, "Legacy", "legacy content", &[]).unwrap();
+        
+        // Re-encode the row the way older versions stored it.
+        let conn = db.pool.get().unwrap();
+        let legacy = |value: &str| {
+            let compact = db.crypto().unwrap().encrypt(value);
+            let envelope = BASE64.decode(compact).unwrap();
+            serde_json::json!({ "nonce": &envelope[2..14], "ciphertext": &envelope[14..] }).to_string()
+        };
+        conn.execute(
+            "UPDATE diary_entries SET title = ?1, content = ?2 WHERE id = ?3",
+            params![legacy("Legacy"), legacy("legacy content"), id],
+        ).unwrap();
+        drop(conn);
+        
+        assert_eq!(db.compact_storage().unwrap(), 1);
+        assert_eq!(db.compact_storage().unwrap(), 0);
+        
+        let conn = db.pool.get().unwrap();
+        let content: String = conn
+            .query_row("SELECT content FROM diary_entries WHERE id = ?1", params![id], |row| row.get(0))
+            .unwrap();
+        assert!(!Crypto::is_legacy_format(&content));
+        drop(conn);
+        
+        let entry = db.get_diary(&id).unwrap();
+        assert_eq!(entry.title, "Legacy");
+        assert_eq!(entry.content, "legacy content");
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_attachments_are_encrypted_and_removed_with_diary() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let id = db.save_diary(None, "With photo", "content", &[]).unwrap();
+        let file_path = dir.join("photo.PNG");
+        fs::write(&file_path, b"not really a png").unwrap();
+        
+        let attachment = db.add_attachment(&id, &file_path).unwrap();
+        assert_eq!(attachment.mime_type, "image/png");
+        assert_eq!(attachment.size, 16);
+        
+        let fetched = db.get_attachment(&attachment.id).unwrap();
+        assert_eq!(BASE64.decode(fetched.data).unwrap(), b"not really a png");
+        assert_eq!(db.list_attachments(&id).unwrap()[0].filename, "photo.PNG");
+        let stored_name = |db: &DiaryDB| -> String {
+            let conn = db.pool.get().unwrap();
+            conn.query_row("SELECT filename FROM attachments WHERE id = ?1", params![attachment.id], |row| row.get(0))
+                .unwrap()
+        };
+        assert!(!stored_name(&db).contains("photo"));
+        
+        // Names stored in plaintext by an older version are encrypted on open.
+        {
+            let conn = db.pool.get().unwrap();
+            conn.execute(
+                "UPDATE attachments SET filename = 'photo.PNG', mime_type = 'image/png' WHERE id = ?1",
+                params![attachment.id],
+            ).unwrap();
+            conn.pragma_update(None, "user_version", 14).unwrap();
+        }
+        drop(db);
+        let db = open_temp_db(&dir);
+        assert!(!stored_name(&db).contains("photo"));
+        let listed = db.list_attachments(&id).unwrap();
+        assert_eq!((listed[0].filename.as_str(), listed[0].mime_type.as_str()), ("photo.PNG", "image/png"));
+        
+        let graph = db.get_graph_data(&GraphFilter::default()).unwrap();
+        assert_eq!(graph.nodes[0].properties["attachment_count"], 1);
+        
+        db.delete_diary(&id).unwrap();
+        assert!(matches!(db.get_attachment(&attachment.id), Err(DiaryError::NotFound(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_wikilinks_sync_relationships() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let atlas = db.save_diary(None, "Project Atlas", "The atlas project", &[]).unwrap();
+        let other = db.save_diary(None, "Other", "unrelated", &[]).unwrap();
+        let note = db
+            .save_diary(None, "Note", "Working on [[project atlas]] and [[Missing Page]]", &[])
+            .unwrap();
+        let manual = db.add_relationship(&note, &other, "depends_on", None, None).unwrap();
+        
+        let relationships = db.get_relationships(&note).unwrap();
+        let link = relationships.iter().find(|r| r.relationship.source == SOURCE_WIKILINK).unwrap();
+        assert_eq!(link.relationship.parent_id, note);
+        assert_eq!(link.relationship.child_id, atlas);
+        assert_eq!(link.relationship.relationship_type, WIKILINK_RELATIONSHIP_TYPE);
+        
+        let backlinks = db.get_backlinks(&atlas).unwrap();
+        assert_eq!(backlinks.len(), 1);
+        assert_eq!(backlinks[0].diary_id, note);
+        assert_eq!(backlinks[0].title, "Note");
+        assert_eq!(db.sync_wikilinks(&note).unwrap(), vec!["Missing Page"]);
+        
+        // Removing the link from the text removes the relationship, but the
+        // manual one survives.
+        let saved = db.save_diary_from_editor(Some(&note), "Note", "No more links", &[]).unwrap();
+        assert!(saved.links_changed && !saved.tags_changed);
+        let relationships = db.get_relationships(&note).unwrap();
+        assert_eq!(relationships.len(), 1);
+        assert_eq!(relationships[0].relationship.id, manual);
+        
+        // Saves report tag and link changes only when there were any.
+        let tags = vec!["work".to_string()];
+        let saved = db.save_diary_from_editor(Some(&note), "Note", "Still no links", &tags).unwrap();
+        assert!(saved.tags_changed && !saved.links_changed);
+        let saved = db.save_diary_from_editor(Some(&note), "Note", "Back to [[Other]]", &tags).unwrap();
+        assert!(!saved.tags_changed && saved.links_changed);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_add_relationship_validation() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let a = db.save_diary(None, "A", "a", &[]).unwrap();
+        let b = db.save_diary(None, "B", "b", &[]).unwrap();
+        
+        db.add_relationship(&a, &b, "depends_on", None, None).unwrap();
+        assert!(matches!(
+            db.add_relationship(&a, &b, "depends_on", None, None),
+            Err(DiaryError::Validation(_))
+        ));
+        assert!(matches!(
+            db.add_relationship(&a, &a, "depends_on", None, None),
+            Err(DiaryError::Validation(_))
+        ));
+        assert!(matches!(
+            db.add_relationship(&a, "missing", "depends_on", None, None),
+            Err(DiaryError::NotFound(_))
+        ));
+        assert!(matches!(
+            db.add_relationship("", &b, "depends_on", None, None),
+            Err(DiaryError::Validation(_))
+        ));
+        db.add_relationship(&a, &b, "inspired_by", None, None).unwrap();
+        assert_eq!(db.get_relationships(&a).unwrap().len(), 2);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_migration_dedupes_relationships() {
+        let dir = temp_dir();
+        
+        // Recreate the pre-migration state: no unique index and duplicate rows.
+        let conn = Connection::open(dir.join("diary.db")).unwrap();
+        conn.execute_batch(BASELINE_SCHEMA).unwrap();
+        for id in ["a", "b"] {
+            conn.execute(
+                "INSERT INTO diary_entries VALUES (?1, ?1, ?1, '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00')",
+                params![id],
+            ).unwrap();
+        }
+        for id in ["r1", "r2", "r3"] {
+            conn.execute(
+                "INSERT INTO relationships (id, parent_id, child_id, relationship_type, created_at)
+                 VALUES (?1, 'a', 'b', 'depends_on', '2024-01-01T00:00:00+00:00')",
+                params![id],
+            ).unwrap();
+        }
+        drop(conn);
+        
+        let reopened = open_temp_db(&dir);
+        let relationships = reopened.get_relationships("a").unwrap();
+        assert_eq!(relationships.len(), 1);
+        assert_eq!(relationships[0].relationship.id, "r1");
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_dedupe_migration_restores_unique_index() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let a = db.save_diary(None, "A", "a", &[]).unwrap();
+        let b = db.save_diary(None, "B", "b", &[]).unwrap();
+        let insert = |conn: &Connection, id: &str| {
+            conn.execute(
+                "INSERT INTO relationships (id, parent_id, child_id, relationship_type, created_at)
+                 VALUES (?1, ?2, ?3, 'depends_on', '2024-01-01T00:00:00+00:00')",
+                params![id, a, b],
+            )
+        };
+        
+        let conn = db.pool.get().unwrap();
+        conn.execute_batch("DROP INDEX idx_relationships_unique;").unwrap();
+        for id in ["r1", "r2", "r3"] {
+            insert(&conn, id).unwrap();
+        }
+        let Migration::Sql(dedupe) = MIGRATIONS[1] else { unreachable!() };
+        conn.execute_batch(dedupe).unwrap();
+        assert!(insert(&conn, "r4").is_err());
+        drop(conn);
+        
+        let relationships = db.get_relationships(&a).unwrap();
+        assert_eq!(relationships.len(), 1);
+        assert_eq!(relationships[0].relationship.id, "r1");
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_add_relationship_appears_in_relationships_and_graph() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let parent = db.save_diary(None, "Parent", "parent", &[]).unwrap();
+        let child = db.save_diary(None, "Child", "child", &[]).unwrap();
+        
+        let id = db.add_relationship(&parent, &child, "depends_on", None, None).unwrap();
+        assert!(Uuid::parse_str(&id).is_ok());
+        
+        for diary_id in [&parent, &child] {
+            let relationships = db.get_relationships(diary_id).unwrap();
+            assert!(relationships.iter().any(|r| r.relationship.id == id));
+        }
+        let graph = db.get_graph_data(&GraphFilter::default()).unwrap();
+        assert!(graph.edges.iter().any(|edge| edge.id == id));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_update_relationship() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let a = db.save_diary(None, "A", "a", &[]).unwrap();
+        let b = db.save_diary(None, "B", "b", &[]).unwrap();
+        let c = db.save_diary(None, "C", "c", &[]).unwrap();
+        let id = db.add_relationship(&a, &b, "depends_on", None, None).unwrap();
+        db.add_relationship(&a, &c, "inspired_by", None, None).unwrap();
+        
+        let updated = db.update_relationship(&id, Some("inspired_by"), None, None, None, None).unwrap();
+        assert_eq!(updated.relationship_type, "inspired_by");
+        assert_eq!(updated.parent_id, a);
+        assert_eq!(updated.child_id, b);
+        
+        // Moving the child onto C would duplicate the existing A -> C link.
+        assert!(matches!(
+            db.update_relationship(&id, None, None, Some(&c), None, None),
+            Err(DiaryError::Validation(_))
+        ));
+        assert!(matches!(
+            db.update_relationship(&id, None, Some(&b), None, None, None),
+            Err(DiaryError::Validation(_))
+        ));
+        assert!(matches!(
+            db.update_relationship("missing", Some("x"), None, None, None, None),
+            Err(DiaryError::NotFound(_))
+        ));
+        
+        let moved = db.update_relationship(&id, None, Some(&c), None, None, None).unwrap();
+        assert_eq!(moved.parent_id, c);
+        assert_eq!(moved.created_at, updated.created_at);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_relationship_note_and_weight() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let a = db.save_diary(None, "A", "a", &[]).unwrap();
+        let b = db.save_diary(None, "B", "b", &[]).unwrap();
+        
+        assert!(matches!(
+            db.add_relationship(&a, &b, "depends_on", None, Some(11.0)),
+            Err(DiaryError::Validation(_))
+        ));
+        let id = db
+            .add_relationship(&a, &b, "depends_on", Some("same project"), Some(4.5))
+            .unwrap();
+        
+        let relationship = &db.get_relationships(&a).unwrap()[0].relationship;
+        assert_eq!(relationship.note.as_deref(), Some("same project"));
+        assert_eq!(relationship.weight, 4.5);
+        let graph = db.get_graph_data(&GraphFilter::default()).unwrap();
+        assert_eq!(graph.edges.iter().find(|e| e.id == id).unwrap().weight, 4.5);
+        
+        let updated = db.update_relationship(&id, None, None, None, Some(""), Some(2.0)).unwrap();
+        assert_eq!(updated.note, None);
+        assert_eq!(updated.weight, 2.0);
+        assert!(matches!(
+            db.update_relationship(&id, None, None, None, None, Some(-1.0)),
+            Err(DiaryError::Validation(_))
+        ));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_relationship_types_and_rename() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let a = db.save_diary(None, "A", "a", &[]).unwrap();
+        let b = db.save_diary(None, "B", "b", &[]).unwrap();
+        let c = db.save_diary(None, "C", "c", &[]).unwrap();
+        db.add_relationship(&a, &b, "depends_on", None, None).unwrap();
+        db.add_relationship(&a, &b, "depends-on", None, None).unwrap();
+        db.add_relationship(&a, &c, "depends-on", None, None).unwrap();
+        db.add_relationship(&b, &c, "dependsOn", None, None).unwrap();
+        
+        let types = db.get_relationship_types().unwrap();
+        assert_eq!(types[0].relationship_type, "depends-on");
+        assert_eq!(types[0].count, 2);
+        assert_eq!(types.len(), 3);
+        
+        // A -> B already has depends_on, so the depends-on duplicate merges away.
+        assert_eq!(db.rename_relationship_type("depends-on", "depends_on").unwrap(), 1);
+        assert_eq!(db.rename_relationship_type("dependsOn", "depends_on").unwrap(), 1);
+        
+        let types = db.get_relationship_types().unwrap();
+        assert_eq!(types.len(), 1);
+        assert_eq!(types[0].relationship_type, "depends_on");
+        assert_eq!(types[0].count, 3);
+        assert!(matches!(
+            db.rename_relationship_type("depends_on", " "),
+            Err(DiaryError::Validation(_))
+        ));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_subgraph_expands_by_depth() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let center = db.save_diary(None, "Center", "c", &["rust".to_string()]).unwrap();
+        let linked = db.save_diary(None, "Linked", "l", &[]).unwrap();
+        let same_tag = db.save_diary(None, "Same tag", "s", &["rust".to_string()]).unwrap();
+        let far = db.save_diary(None, "Far", "f", &[]).unwrap();
+        db.save_diary(None, "Unrelated", "u", &[]).unwrap();
+        db.add_relationship(&linked, &center, "depends_on", None, None).unwrap();
+        db.add_relationship(&linked, &far, "depends_on", None, None).unwrap();
+        // Diamond: far is also reachable through same_tag.
+        db.add_relationship(&same_tag, &far, "depends_on", None, None).unwrap();
+        
+        let ids = |subgraph: &Subgraph| {
+            let mut ids: Vec<String> = subgraph.graph.nodes.iter().map(|n| n.label.clone()).collect();
+            ids.sort();
+            ids
+        };
+        
+        let depth_one = db.get_subgraph(&center, 1).unwrap();
+        assert_eq!(ids(&depth_one), vec!["Center", "Linked", "rust"]);
+        assert_eq!(depth_one.graph.edges.len(), 2);
+        assert!(!depth_one.truncated);
+        
+        let depth_two = db.get_subgraph(&center, 2).unwrap();
+        assert_eq!(ids(&depth_two), vec!["Center", "Far", "Linked", "Same tag", "rust"]);
+        let depth_three = db.get_subgraph(&center, 3).unwrap();
+        assert_eq!(depth_three.graph.nodes.len(), 5);
+        assert!(!ids(&depth_three).contains(&"Unrelated".to_string()));
+        
+        assert!(matches!(db.get_subgraph("missing", 1), Err(DiaryError::NotFound(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_graph_filters_compose() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let work = db.save_diary(None, "Work", "w", &["Job".to_string()]).unwrap();
+        let other_work = db.save_diary(None, "More work", "m", &["job".to_string(), "misc".to_string()]).unwrap();
+        let home = db.save_diary(None, "Home", "h", &["home".to_string()]).unwrap();
+        db.add_relationship(&work, &other_work, "depends_on", None, None).unwrap();
+        db.add_relationship(&work, &home, "depends_on", None, None).unwrap();
+        db.add_relationship(&other_work, &work, "inspired_by", None, None).unwrap();
+        
+        let by_tag = db.get_graph_data(&GraphFilter {
+            tag_filter: vec!["JOB".to_string()],
+            ..GraphFilter::default()
+        }).unwrap();
+        let labels: HashSet<&str> = by_tag.nodes.iter().map(|n| n.label.as_str()).collect();
+        assert!(labels.contains("Work") && labels.contains("More work") && labels.contains("misc"));
+        assert!(!labels.contains("Home") && !labels.contains("home"));
+        let node_ids: HashSet<&str> = by_tag.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert!(by_tag.edges.iter().all(|e| node_ids.contains(e.source.as_str()) && node_ids.contains(e.target.as_str())));
+        
+        let narrowed = db.get_graph_data(&GraphFilter {
+            include_tags: false,
+            tag_filter: vec!["job".to_string()],
+            relationship_types: vec!["inspired_by".to_string()],
+            ..GraphFilter::default()
+        }).unwrap();
+        assert_eq!(narrowed.nodes.len(), 2);
+        assert!(narrowed.nodes.iter().all(|n| n.node_type == "diary"));
+        assert_eq!(narrowed.edges.len(), 1);
+        assert_eq!(narrowed.edges[0].label, "inspired_by");
+        
+        let future = db.get_graph_data(&GraphFilter {
+            created_from: Some(Utc::now() + chrono::Duration::days(1)),
+            ..GraphFilter::default()
+        }).unwrap();
+        assert!(future.nodes.is_empty() && future.edges.is_empty());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_related_entries_scoring() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let center = db.save_diary(None, "Center", "c", &["rust".to_string(), "tauri".to_string()]).unwrap();
+        let both_tags = db.save_diary(None, "Both tags", "b", &["rust".to_string(), "tauri".to_string()]).unwrap();
+        let linked = db.save_diary(None, "Linked", "l", &[]).unwrap();
+        let second = db.save_diary(None, "Second degree", "s", &[]).unwrap();
+        let older_tag = db.save_diary(None, "Older tag", "o", &["rust".to_string()]).unwrap();
+        let newer_tag = db.save_diary(None, "Newer tag", "n", &["rust".to_string()]).unwrap();
+        db.add_relationship(&center, &linked, "depends_on", None, None).unwrap();
+        db.add_relationship(&second, &linked, "depends_on", None, None).unwrap();
+        
+        let related = db.get_related_entries(&center, 10, false).unwrap();
+        let order: Vec<&str> = related.iter().map(|r| r.title.as_str()).collect();
+        assert_eq!(order, vec!["Both tags", "Linked", "Newer tag", "Older tag", "Second degree"]);
+        assert_eq!(related[0].score, 4);
+        assert_eq!(related[4].reasons, vec![RelatedReason::SharedNeighbor { diary_id: linked.clone() }]);
+        assert!(related.iter().all(|r| r.diary_id != center));
+        
+        let unlinked = db.get_related_entries(&center, 2, true).unwrap();
+        assert_eq!(unlinked.len(), 2);
+        assert_eq!(unlinked[0].diary_id, both_tags);
+        assert_eq!(unlinked[1].diary_id, newer_tag);
+        assert_ne!(unlinked[1].diary_id, older_tag);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_find_path_through_tags_and_links() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let start = db.save_diary(None, "Start", "s", &["rust".to_string()]).unwrap();
+        let middle = db.save_diary(None, "Middle", "m", &["rust".to_string()]).unwrap();
+        let end = db.save_diary(None, "End", "e", &[]).unwrap();
+        let island = db.save_diary(None, "Island", "i", &[]).unwrap();
+        db.add_relationship(&end, &middle, "depends_on", None, None).unwrap();
+        
+        let path = db.find_path(&start, &end).unwrap();
+        assert!(path.connected);
+        let labels: Vec<&str> = path.nodes.iter().map(|n| n.label.as_str()).collect();
+        assert_eq!(labels, vec!["Start", "rust", "Middle", "End"]);
+        assert_eq!(path.nodes[1].node_type, "tag");
+        assert_eq!(path.edges.len(), 3);
+        assert_eq!(path.edges[2].label, "depends_on");
+        
+        let same = db.find_path(&start, &start).unwrap();
+        assert!(same.connected);
+        assert_eq!(same.nodes.len(), 1);
+        assert!(same.edges.is_empty());
+        
+        let none = db.find_path(&start, &island).unwrap();
+        assert!(!none.connected);
+        assert!(none.nodes.is_empty() && none.edges.is_empty());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_graph_degrees_and_orphans() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let hub = db.save_diary(None, "Hub", "h", &["rust".to_string()]).unwrap();
+        let spoke = db.save_diary(None, "Spoke", "s", &[]).unwrap();
+        let orphan = db.save_diary(None, "Orphan", "o", &[]).unwrap();
+        db.add_relationship(&spoke, &hub, "depends_on", None, None).unwrap();
+        
+        let graph = db.get_graph_data(&GraphFilter::default()).unwrap();
+        let props = |id: &str| graph.nodes.iter().find(|n| n.id == id).unwrap().properties.clone();
+        assert_eq!(props(&hub)["degree"], 2);
+        assert_eq!(props(&hub)["tag_degree"], 1);
+        assert_eq!(props(&hub)["relationship_degree"], 1);
+        assert_eq!(props(&spoke)["is_orphan"], false);
+        assert_eq!(props(&orphan)["is_orphan"], true);
+        let tag = graph.nodes.iter().find(|n| n.node_type == "tag").unwrap();
+        assert_eq!(tag.properties["degree"], 1);
+        
+        let orphans = db.list_orphan_diaries().unwrap();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].id, orphan);
+        assert_eq!(orphans[0].title, "Orphan");
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_statistics() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        db.save_diary(None, "One", "three words here", &["rust".to_string()]).unwrap();
+        let legacy = db.save_diary(None, "Two", "just one", &["rust".to_string(), "life".to_string()]).unwrap();
+        {
+            let conn = db.pool.get().unwrap();
+            conn.execute("UPDATE diary_entries SET word_count = NULL WHERE id = ?1", params![legacy]).unwrap();
+        }
+        
+        let stats = db.get_statistics().unwrap();
+        assert_eq!(stats.total_entries, 2);
+        assert_eq!(stats.total_words, 5);
+        assert_eq!(stats.average_words, 2.5);
+        assert_eq!(stats.top_tags[0].name, "rust");
+        assert_eq!(stats.top_tags[0].count, 2);
+        assert_eq!(stats.entries_per_month.len(), 12);
+        assert_eq!(stats.entries_per_month.last().unwrap().count, 2);
+        assert!(stats.entries_per_month[..11].iter().all(|m| m.count == 0));
+        assert_eq!(stats.current_streak_days, 1);
+        
+        let day = |d: u32| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+        let days: BTreeSet<NaiveDate> = [1, 2, 3, 7, 9, 10].into_iter().map(day).collect();
+        assert_eq!(writing_streaks(&days, day(10)), (3, 2));
+        assert_eq!(writing_streaks(&days, day(11)), (3, 2));
+        assert_eq!(writing_streaks(&days, day(12)), (3, 0));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_word_count_backfill() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let long_text = vec!["word"; 450].join(" ");
+        let first = db.save_diary(None, "Long", &long_text, &[]).unwrap();
+        let second = db.save_diary(None, "Short", "a few words", &[]).unwrap();
+        {
+            let conn = db.pool.get().unwrap();
+            conn.execute("UPDATE diary_entries SET word_count = NULL", []).unwrap();
+        }
+        
+        // Reads fall back to counting the decrypted content.
+        let entry = db.get_diary(&first).unwrap();
+        assert_eq!(entry.word_count, 450);
+        assert_eq!(entry.reading_time_minutes, 3);
+        
+        let progress = db.backfill_word_counts(1).unwrap();
+        assert_eq!((progress.processed, progress.remaining), (1, 1));
+        let progress = db.backfill_word_counts(1).unwrap();
+        assert_eq!((progress.processed, progress.remaining), (1, 0));
+        
+        let conn = db.pool.get().unwrap();
+        let stored: i64 = conn
+            .query_row("SELECT word_count FROM diary_entries WHERE id = ?1", params![second], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, 3);
+        drop(conn);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_recent_diaries() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let first = db.save_diary(None, "First", "one", &["a".to_string()]).unwrap();
+        let second = db.save_diary(None, "Second", "two words", &[]).unwrap();
+        
+        let updated = db.list_recent_diaries(10, "updated").unwrap();
+        assert_eq!(updated[0].id, second);
+        assert_eq!(updated[1].tags, vec!["a".to_string()]);
+        assert_eq!(updated[0].word_count, Some(2));
+        assert!(db.list_recent_diaries(10, "viewed").unwrap().is_empty());
+        
+        let before = db.get_diary(&first).unwrap().updated_at;
+        let viewed = db.list_recent_diaries(10, "viewed").unwrap();
+        assert_eq!(viewed.len(), 1);
+        assert_eq!(viewed[0].id, first);
+        assert!(viewed[0].last_viewed_at.is_some());
+        assert_eq!(viewed[0].updated_at, before);
+        assert_eq!(db.list_recent_diaries(1, "updated").unwrap()[0].id, second);
+        
+        assert!(matches!(db.list_recent_diaries(10, "created"), Err(DiaryError::Validation(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_daily_notes() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        
+        let (today, created) = db.get_or_create_daily_note(None).unwrap();
+        assert!(created);
+        assert_eq!(today.title, Local::now().date_naive().format("%Y-%m-%d").to_string());
+        assert_eq!(today.tags, vec!["daily".to_string()]);
+        let (again, created) = db.get_or_create_daily_note(None).unwrap();
+        assert!(!created);
+        assert_eq!(again.id, today.id);
+        
+        let (past, _) = db.get_or_create_daily_note(Some("2024-02-29")).unwrap();
+        assert_eq!(past.created_at.with_timezone(&Local).date_naive(), NaiveDate::from_ymd_opt(2024, 2, 29).unwrap());
+        
+        // A note written by hand before daily_date existed is adopted.
+        let manual = db.save_diary(None, "2024-03-01", "by hand", &["daily".to_string()]).unwrap();
+        let (adopted, created) = db.get_or_create_daily_note(Some("2024-03-01")).unwrap();
+        assert!(!created);
+        assert_eq!(adopted.id, manual);
+        
+        let listed = db.list_daily_notes("2024-01-01", "2024-12-31").unwrap();
+        let titles: Vec<&str> = listed.iter().map(|n| n.title.as_str()).collect();
+        assert_eq!(titles, vec!["2024-02-29", "2024-03-01"]);
+        
+        assert!(matches!(db.get_or_create_daily_note(Some("yesterday")), Err(DiaryError::Validation(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_activity_heatmap_uses_offset() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let late = db.save_diary(None, "Late", "one two three", &[]).unwrap();
+        db.save_diary(None, "Early", "four", &[]).unwrap();
+        {
+            let conn = db.pool.get().unwrap();
+            conn.execute(
+                "UPDATE diary_entries SET created_at = '2024-05-01T23:30:00+00:00' WHERE id = ?1",
+                params![late],
+            ).unwrap();
+            conn.execute(
+                "UPDATE diary_entries SET created_at = '2024-05-01T08:00:00+00:00' WHERE id != ?1",
+                params![late],
+            ).unwrap();
+        }
+        
+        let utc = db.get_activity_heatmap("2024-04-30", "2024-05-03", Some(0)).unwrap();
+        let counts: Vec<(i64, i64)> = utc.iter().map(|d| (d.entry_count, d.total_word_count)).collect();
+        assert_eq!(utc[0].date, "2024-04-30");
+        assert_eq!(counts, vec![(0, 0), (2, 4), (0, 0), (0, 0)]);
+        
+        // Two hours east of UTC the late entry falls on the next day.
+        let shifted = db.get_activity_heatmap("2024-04-30", "2024-05-03", Some(120)).unwrap();
+        let counts: Vec<(i64, i64)> = shifted.iter().map(|d| (d.entry_count, d.total_word_count)).collect();
+        assert_eq!(counts, vec![(0, 0), (1, 1), (1, 3), (0, 0)]);
+        
+        assert!(matches!(db.get_activity_heatmap("2024-05-03", "2024-05-01", None), Err(DiaryError::Validation(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_templates() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let template = db.save_template(
+            None,
+            "Meeting",
+            "# {{title}}\nHeld on {{date}} at {{time}}",
+            &["meeting".to_string()],
+        ).unwrap();
+        db.save_template(None, "Book summary", "Book: {{title}}", &[]).unwrap();
+        
+        let names: Vec<String> = db.list_templates().unwrap().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["Book summary".to_string(), "Meeting".to_string()]);
+        {
+            let conn = db.pool.get().unwrap();
+            let stored: String = conn
+                .query_row("SELECT content FROM templates WHERE id = ?1", params![template.id], |row| row.get(0))
+                .unwrap();
+            assert!(!stored.contains("{{title}}"));
+        }
+        
+        let entry = db.create_diary_from_template(&template.id, "Standup").unwrap();
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        assert!(entry.content.starts_with("# Standup\nHeld on "));
+        assert!(entry.content.contains(&today));
+        assert_eq!(entry.tags, vec!["meeting".to_string()]);
+        
+        let renamed = db.save_template(Some(&template.id), "Meeting notes", "x", &[]).unwrap();
+        assert_eq!(renamed.name, "Meeting notes");
+        assert!(matches!(db.save_template(Some("missing"), "a", "b", &[]), Err(DiaryError::NotFound(_))));
+        
+        db.delete_template(&template.id).unwrap();
+        assert!(matches!(db.create_diary_from_template(&template.id, "x"), Err(DiaryError::NotFound(_))));
+        assert!(matches!(db.delete_template(&template.id), Err(DiaryError::NotFound(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_bulk_operations() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let first = db.save_diary(None, "First", "a", &["keep".to_string()]).unwrap();
+        let second = db.save_diary(None, "Second", "b", &[]).unwrap();
+        db.add_relationship(&first, &second, "depends_on", None, None).unwrap();
+        let ids = vec![first.clone(), "missing".to_string(), second.clone()];
+        
+        let added = db.bulk_add_tag(&ids, "cleanup").unwrap();
+        let statuses: Vec<BulkStatus> = added.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, vec![BulkStatus::Succeeded, BulkStatus::NotFound, BulkStatus::Succeeded]);
+        db.bulk_add_tag(&ids, "cleanup").unwrap();
+        assert_eq!(db.search_diaries_by_tag("cleanup").unwrap().len(), 2);
+        {
+            let conn = db.pool.get().unwrap();
+            let tag_rows: i64 = conn
+                .query_row("SELECT COUNT(*) FROM tags WHERE name = 'cleanup'", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(tag_rows, 1);
+        }
+        
+        db.bulk_remove_tag(&ids, "cleanup").unwrap();
+        assert!(db.search_diaries_by_tag("cleanup").unwrap().is_empty());
+        assert_eq!(db.get_diary(&first).unwrap().tags, vec!["keep".to_string()]);
+        
+        let deleted = db.bulk_delete_diaries(&ids).unwrap();
+        assert_eq!(deleted[1].status, BulkStatus::NotFound);
+        assert!(db.list_diaries().unwrap().is_empty());
+        assert!(db.get_relationships(&first).unwrap().is_empty());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_save_diaries_batch() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let created_at = DateTime::parse_from_rfc3339("2023-01-02T03:04:05+00:00").unwrap().with_timezone(&Utc);
+        let new_diary = |title: &str, content: &str, tags: &[&str]| NewDiary {
+            title: title.to_string(),
+            content: content.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            created_at: None,
+            updated_at: None,
+        };
+        let mut first = new_diary("First", "see [[Second]]", &["import", "shared"]);
+        first.created_at = Some(created_at);
+        let batch = vec![first, new_diary("Second", "plain", &["shared"])];
+        
+        let ids = db.save_diaries_batch(&batch).unwrap();
+        assert_eq!(ids.len(), 2);
+        let entry = db.get_diary(&ids[0]).unwrap();
+        assert_eq!(entry.title, "First");
+        assert_eq!(entry.created_at, created_at);
+        assert_eq!(entry.updated_at, created_at);
+        assert_eq!(db.search_diaries_by_tag("shared").unwrap().len(), 2);
+        assert_eq!(db.get_backlinks(&ids[1]).unwrap()[0].diary_id, ids[0]);
+        
+        let bad = vec![new_diary("Fine", "x", &[]), new_diary(" ", "x", &[])];
+        match db.save_diaries_batch(&bad) {
+            Err(DiaryError::Validation(message)) => assert!(message.starts_with("Entry 1:")),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(db.list_diaries().unwrap().len(), 2);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_drafts() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let id = db.save_diary(None, "Saved", "v1", &["t".to_string()]).unwrap();
+        let saved_at = db.get_diary(&id).unwrap().updated_at;
+        
+        db.save_draft(Some(&id), "Saved", "v2 in progress", &["t".to_string(), "new".to_string()]).unwrap();
+        db.save_draft(None, "Brand new", "unsaved", &[]).unwrap();
+        let draft = db.get_draft(Some(&id)).unwrap().unwrap();
+        assert_eq!(draft.content, "v2 in progress");
+        assert_eq!(draft.tags.len(), 2);
+        let entry = db.get_diary(&id).unwrap();
+        assert_eq!(entry.updated_at, saved_at);
+        assert_eq!(entry.tags, vec!["t".to_string()]);
+        
+        let drafts = db.list_unsaved_drafts().unwrap();
+        assert_eq!(drafts.len(), 2);
+        assert_eq!(drafts[0].diary_id, None);
+        
+        // Entries created elsewhere, like quick capture or templates, leave
+        // the editor's new-entry draft alone.
+        db.quick_capture("call the bank").unwrap();
+        db.save_diary(None, "From a template", "body", &[]).unwrap();
+        assert_eq!(db.get_draft(None).unwrap().unwrap().content, "unsaved");
+        
+        db.save_diary_from_editor(Some(&id), "Saved", "v2", &[]).unwrap();
+        assert!(db.get_draft(Some(&id)).unwrap().is_none());
+        db.save_diary_from_editor(None, "Brand new", "saved now", &[]).unwrap();
+        assert!(db.get_draft(None).unwrap().is_none());
+        
+        db.save_draft(Some(&id), "x", "y", &[]).unwrap();
+        let conn = db.pool.get().unwrap();
+        conn.execute("UPDATE drafts SET content = 'garbage' WHERE diary_id = ?1", params![id]).unwrap();
+        drop(conn);
+        assert!(matches!(db.get_draft(Some(&id)), Err(DiaryError::Crypto(_))));
+        assert!(matches!(db.list_unsaved_drafts(), Err(DiaryError::Crypto(_))));
+        db.discard_draft(Some(&id)).unwrap();
+        assert!(db.list_unsaved_drafts().unwrap().is_empty());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_title_lookup() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let older = db.save_diary(None, "Rust notes", "a", &[]).unwrap();
+        let newer = db.save_diary(None, "rust NOTES", "b", &[]).unwrap();
+        db.save_diary(None, "Rusty bikes", "c", &[]).unwrap();
+        db.save_diary(None, "Other", "d", &[]).unwrap();
+        
+        let mut exact = db.find_diary_by_title("RUST notes").unwrap();
+        exact.sort();
+        let mut expected = vec![older.clone(), newer.clone()];
+        expected.sort();
+        assert_eq!(exact, expected);
+        assert!(db.find_diary_by_title("Rust").unwrap().is_empty());
+        
+        let matches = db.search_titles("rus", 2).unwrap();
+        let titles: Vec<&str> = matches.iter().map(|m| m.title.as_str()).collect();
+        assert_eq!(titles, vec!["Rusty bikes", "rust NOTES"]);
+        
+        // Writes invalidate the cached titles.
+        db.save_diary(Some(&older), "Renamed", "a", &[]).unwrap();
+        assert_eq!(db.find_diary_by_title("rust notes").unwrap(), vec![newer]);
+        assert_eq!(db.search_titles("ren", 10).unwrap()[0].id, older);
+        db.delete_diary(&older).unwrap();
+        assert!(db.search_titles("ren", 10).unwrap().is_empty());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_search_snippets() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let filler = "日本語のテキスト😀 ".repeat(30);
+        let content = format!("{}Meeting with Alice about the Garden project. Alice said hi.", filler);
+        let hit = db.save_diary(None, "Notes", &content, &[]).unwrap();
+        db.save_diary(None, "Garden plan", "Nothing about the other person", &[]).unwrap();
+        
+        let results = db.search_diaries("alice garden", 10).unwrap();
+        assert_eq!(results.len(), 1);
+        let result = &results[0];
+        assert_eq!(result.entry.id, hit);
+        assert!(result.snippet.starts_with("…"));
+        assert!(result.snippet.chars().count() <= 162);
+        let highlighted: Vec<&str> = result
+            .matches
+            .iter()
+            .map(|m| &result.snippet[m.start..m.start + m.len])
+            .collect();
+        assert_eq!(highlighted, vec!["Alice", "Garden", "Alice"]);
+        
+        // Title-only hits have a snippet from the start of the content.
+        let title_hit = db.search_diaries("plan", 10).unwrap();
+        assert_eq!(title_hit[0].snippet, "Nothing about the other person");
+        assert!(title_hit[0].matches.is_empty());
+        assert!(db.search_diaries("   ", 10).unwrap().is_empty());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_custom_properties() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let happy = db.save_diary(None, "Happy", "a", &[]).unwrap();
+        let tired = db.save_diary(None, "Tired", "b", &[]).unwrap();
+        let place = serde_json::json!({"city": "Oslo", "lat": 59.9});
+        db.set_diary_property(&happy, "mood", &serde_json::json!("great")).unwrap();
+        db.set_diary_property(&happy, "energy", &serde_json::json!(8)).unwrap();
+        db.set_diary_property(&happy, "location", &place).unwrap();
+        db.set_diary_property(&tired, "mood", &serde_json::json!("meh")).unwrap();
+        db.set_diary_property(&tired, "mood", &serde_json::json!("great")).unwrap();
+        db.set_diary_property(&tired, "outside", &serde_json::json!(false)).unwrap();
+        
+        let entry = db.get_diary(&happy).unwrap();
+        assert_eq!(entry.properties["energy"], 8);
+        assert_eq!(entry.properties["location"], place);
+        assert_eq!(db.query_diaries_by_property("mood", &serde_json::json!("great")).unwrap().len(), 2);
+        assert!(db.query_diaries_by_property("energy", &serde_json::json!("8")).unwrap().is_empty());
+        
+        let graph = db.get_graph_data(&GraphFilter::default()).unwrap();
+        let node = graph.nodes.iter().find(|n| n.id == tired).unwrap();
+        assert_eq!(node.properties["properties"]["outside"], false);
+        
+        db.delete_diary_property(&happy, "energy").unwrap();
+        assert!(db.get_diary(&happy).unwrap().properties.get("energy").is_none());
+        assert!(matches!(db.delete_diary_property(&happy, "energy"), Err(DiaryError::NotFound(_))));
+        assert!(matches!(
+            db.set_diary_property("missing", "mood", &serde_json::json!(1)),
+            Err(DiaryError::NotFound(_))
+        ));
+        
+        db.delete_diary(&happy).unwrap();
+        let conn = db.pool.get().unwrap();
+        let remaining: i64 = conn
+            .query_row("SELECT COUNT(*) FROM diary_properties WHERE diary_id = ?1", params![happy], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, 0);
+        drop(conn);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_pinned_ordering() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let a = db.save_diary(None, "A", "a", &[]).unwrap();
+        let b = db.save_diary(None, "B", "b", &[]).unwrap();
+        let c = db.save_diary(None, "C", "c", &[]).unwrap();
+        db.save_diary(None, "Unpinned", "d", &[]).unwrap();
+        
+        db.pin_diary(&a).unwrap();
+        db.pin_diary(&b).unwrap();
+        db.pin_diary(&a).unwrap();
+        db.pin_diary(&c).unwrap();
+        let order = |db: &DiaryDB| -> Vec<String> {
+            db.list_pinned_diaries().unwrap().into_iter().map(|s| s.title).collect()
+        };
+        assert_eq!(order(&db), vec!["A", "B", "C"]);
+        
+        db.reorder_pinned_diaries(&[c.clone(), a.clone()]).unwrap();
+        assert_eq!(order(&db), vec!["C", "A", "B"]);
+        
+        // Pins without an order go last, most recently updated first.
+        {
+            let conn = db.pool.get().unwrap();
+            conn.execute("UPDATE diary_entries SET sort_order = NULL WHERE id = ?1", params![c]).unwrap();
+        }
+        assert_eq!(order(&db), vec!["A", "B", "C"]);
+        
+        db.unpin_diary(&a).unwrap();
+        db.pin_diary(&a).unwrap();
+        assert_eq!(order(&db).first().map(String::as_str), Some("B"));
+        assert!(matches!(db.reorder_pinned_diaries(&[b.clone(), b.clone()]), Err(DiaryError::Validation(_))));
+        assert!(matches!(db.pin_diary("missing"), Err(DiaryError::NotFound(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_graph_edge_direction_and_wire_shape() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let parent = db.save_diary(None, "Parent", "p", &["rust".to_string()]).unwrap();
+        let child = db.save_diary(None, "Child", "c", &[]).unwrap();
+        let relationship_id = db.add_relationship(&parent, &child, "parent_of", None, None).unwrap();
+        
+        let graph = db.get_graph_data(&GraphFilter::default()).unwrap();
+        assert_eq!(graph.version, GRAPH_SCHEMA_VERSION);
+        let edge = graph.edges.iter().find(|e| e.id == relationship_id).unwrap();
+        assert_eq!((edge.source.as_str(), edge.target.as_str()), (parent.as_str(), child.as_str()));
+        assert_eq!(edge.edge_type, GraphEdgeType::Relationship);
+        let tag_edge = graph.edges.iter().find(|e| e.edge_type == GraphEdgeType::Tag).unwrap();
+        assert_eq!(tag_edge.source, parent);
+        
+        let json = serde_json::to_value(edge).unwrap();
+        assert_eq!(json["edge_type"], "relationship");
+        assert_eq!(json["source"], parent.as_str());
+        assert_eq!(serde_json::to_value(&graph).unwrap()["version"], 2);
+        
+        let inverse = db.get_graph_data(&GraphFilter { inverse_labels: true, ..GraphFilter::default() }).unwrap();
+        let edge = inverse.edges.iter().find(|e| e.id == relationship_id).unwrap();
+        assert_eq!((edge.source.as_str(), edge.target.as_str()), (child.as_str(), parent.as_str()));
+        assert_eq!(edge.label, "child_of");
+        assert_eq!(inverse_relationship_label("cites"), "inverse_of_cites");
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_relationships_include_other_entry() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let parent = db.save_diary(None, "Parent", "p", &[]).unwrap();
+        let child = db.save_diary(None, "Child", "c", &[]).unwrap();
+        db.add_relationship(&parent, &child, "depends_on", None, None).unwrap();
+        
+        let from_parent = db.get_relationships(&parent).unwrap();
+        assert_eq!(from_parent[0].other_id, child);
+        assert_eq!(from_parent[0].other_title, "Child");
+        assert!(from_parent[0].is_parent);
+        let from_child = db.get_relationships(&child).unwrap();
+        assert_eq!(from_child[0].other_title, "Parent");
+        assert!(!from_child[0].is_parent);
+        let json = serde_json::to_value(&from_child[0]).unwrap();
+        assert_eq!(json["parent_id"], parent.as_str());
+        
+        // Old databases may hold relationships to deleted entries.
+        {
+            let conn = db.pool.get().unwrap();
+            conn.execute_batch("PRAGMA foreign_keys = OFF;").unwrap();
+            conn.execute(
+                "INSERT INTO relationships (id, parent_id, child_id, relationship_type, created_at)
+                 VALUES ('dangling', ?1, 'gone', 'depends_on', ?2)",
+                params![parent, Utc::now().to_rfc3339()],
+            ).unwrap();
+            conn.execute_batch("PRAGMA foreign_keys = ON;").unwrap();
+        }
+        assert_eq!(db.get_relationships(&parent).unwrap().len(), 1);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_export_graph_honors_filter() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        db.save_diary(None, "Kept & <safe>", "k", &["work".to_string()]).unwrap();
+        db.save_diary(None, "Skipped", "s", &["home".to_string()]).unwrap();
+        
+        let filter = GraphFilter { tag_filter: vec!["work".to_string()], ..GraphFilter::default() };
+        let graphml = dir.join("graph.graphml");
+        db.export_graph(&graphml, "graphml", &filter).unwrap();
+        let xml = fs::read_to_string(&graphml).unwrap();
+        assert!(xml.contains("Kept &amp; &lt;safe&gt;"));
+        assert!(!xml.contains("Skipped"));
+        
+        let dot = dir.join("graph.dot");
+        db.export_graph(&dot, "dot", &GraphFilter::default()).unwrap();
+        assert!(fs::read_to_string(&dot).unwrap().contains("\"Skipped\""));
+        assert!(matches!(
+            db.export_graph(&dot, "svg", &GraphFilter::default()),
+            Err(DiaryError::Validation(_))
+        ));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_import_day_one() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let export = dir.join("Journal.json");
+        fs::write(&export, r#"{"entries": [
+            {"text": "Harbour walk\nFog all morning.", "creationDate": "2015-02-01T09:00:00Z",
+             "tags": ["walks"], "location": {"placeName": "Harbour"}, "weather": {"conditionsDescription": "Fog"}},
+            {"text": "missing date"},
+            {"text": "Second", "creationDate": "2015-02-02T09:00:00Z"}
+        ]}"#).unwrap();
+        
+        let preview = db.import_day_one(&export, true, false, &mut Progress::silent()).unwrap();
+        assert!(preview.dry_run);
+        assert_eq!(preview.imported.len(), 2);
+        assert!(preview.imported.iter().all(|entry| entry.id.is_none()));
+        assert_eq!(preview.errors.len(), 1);
+        assert_eq!(preview.errors[0].index, 1);
+        assert!(db.list_diaries().unwrap().is_empty());
+        
+        let report = db.import_day_one(&export, false, false, &mut Progress::silent()).unwrap();
+        assert_eq!(report.imported.len(), 2);
+        assert_eq!(report.imported[1].index, 2);
+        let id = report.imported[0].id.clone().unwrap();
+        let diary = db.get_diary(&id).unwrap();
+        assert_eq!(diary.title, "Harbour walk");
+        assert_eq!(diary.content, "Fog all morning.");
+        assert_eq!(diary.tags, vec!["walks".to_string()]);
+        assert_eq!(diary.created_at.to_rfc3339(), "2015-02-01T09:00:00+00:00");
+        assert_eq!(diary.properties["location"]["placeName"], "Harbour");
+        assert_eq!(diary.properties["weather"]["conditionsDescription"], "Fog");
+        
+        fs::write(&export, "{}").unwrap();
+        assert!(matches!(db.import_day_one(&export, false, false, &mut Progress::silent()), Err(DiaryError::Validation(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_reminders() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let id = db.save_diary(None, "Follow-up", "call back", &[]).unwrap();
+        let other = db.save_diary(None, "Other", "", &[]).unwrap();
+        
+        let past = db.set_reminder(&id, "2020-01-01T09:00:00+02:00", "in two weeks").unwrap();
+        assert_eq!(past.remind_at.to_rfc3339(), "2020-01-01T07:00:00+00:00");
+        let future = (Utc::now() + Duration::days(1)).to_rfc3339();
+        db.set_reminder(&id, &future, "later").unwrap();
+        db.set_reminder(&other, "2021-06-01T00:00:00Z", "other").unwrap();
+        assert!(matches!(db.set_reminder(&id, "tomorrow", "x"), Err(DiaryError::Validation(_))));
+        assert!(matches!(db.set_reminder("missing", &future, "x"), Err(DiaryError::NotFound(_))));
+        
+        let due = db.list_due_reminders().unwrap();
+        assert_eq!(due.len(), 2);
+        assert_eq!(due[0].reminder.message, "in two weeks");
+        assert_eq!(due[0].diary_title, "Follow-up");
+        
+        // A damaged message is skipped by the poller's listing, not fatal.
+        let conn = db.pool.get().unwrap();
+        conn.execute("UPDATE reminders SET message = 'garbage' WHERE diary_id = ?1", params![other]).unwrap();
+        drop(conn);
+        let due = db.list_due_reminders().unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].reminder.id, past.id);
+        assert!(matches!(db.list_reminders_for_diary(&other), Err(DiaryError::Crypto(_))));
+        
+        db.complete_reminder(&past.id).unwrap();
+        assert!(db.list_due_reminders().unwrap().is_empty());
+        assert!(matches!(db.complete_reminder("missing"), Err(DiaryError::NotFound(_))));
+        let reminders = db.list_reminders_for_diary(&id).unwrap();
+        assert_eq!(reminders.len(), 2);
+        assert!(reminders[0].completed);
+        assert_eq!(reminders[1].message, "later");
+        
+        db.delete_diary(&id).unwrap();
+        assert!(db.list_reminders_for_diary(&id).unwrap().is_empty());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_encrypted_entry_export_round_trip() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let id = db.save_diary(None, "Shared", "for a friend", &["letters".to_string()]).unwrap();
+        let original = db.get_diary(&id).unwrap();
+        let file = dir.join("shared.sbentry");
+        
+        db.export_entry_encrypted(&id, &file, "hunter2").unwrap();
+        assert!(!fs::read(&file).unwrap().windows(6).any(|w| w == b"Shared"));
+        let error = db.import_encrypted_entry(&file, "wrong").unwrap_err();
+        assert_eq!(error.to_string(), "Incorrect password, or the file has been modified");
+        
+        // The vault already holds this entry, so importing it adds nothing.
+        assert_eq!(db.import_encrypted_entry(&file, "hunter2").unwrap().id, id);
+        assert_eq!(db.list_diaries().unwrap().len(), 1);
+        
+        db.delete_diary(&id).unwrap();
+        let imported = db.import_encrypted_entry(&file, "hunter2").unwrap().id;
+        assert_ne!(imported, id);
+        let copy = db.get_diary(&imported).unwrap();
+        assert_eq!(copy.title, "Shared");
+        assert_eq!(copy.content, "for a friend");
+        assert_eq!(copy.tags, vec!["letters".to_string()]);
+        assert_eq!(copy.created_at, original.created_at);
+        assert!(matches!(db.export_entry_encrypted(&id, &file, ""), Err(DiaryError::Validation(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_reimport_skips_duplicates() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let export = dir.join("Journal.json");
+        fs::write(&export, r#"[
+            {"text": "Walk\nCold", "creationDate": "2015-02-01T09:00:00Z"},
+            {"text": "Walk\nCold", "creationDate": "2015-02-01T09:00:00Z"},
+            {"text": "Run\nWarm", "creationDate": "2015-02-02T09:00:00Z", "tags": ["sport"]}
+        ]"#).unwrap();
+        
+        let first = db.import_day_one(&export, false, false, &mut Progress::silent()).unwrap();
+        assert_eq!((first.imported.len(), first.skipped, first.updated), (2, 1, 0));
+        
+        // A dry run spots entries whose hash isn't stored yet without storing it.
+        let walk = first.imported[0].id.clone().unwrap();
+        let stored_hash = |db: &DiaryDB| -> Option<String> {
+            let conn = db.pool.get().unwrap();
+            conn.query_row("SELECT content_hash FROM diary_entries WHERE id = ?1", params![walk], |row| row.get(0)).unwrap()
+        };
+        db.pool.get().unwrap().execute("UPDATE diary_entries SET content_hash = NULL WHERE id = ?1", params![walk]).unwrap();
+        let preview = db.import_day_one(&export, true, false, &mut Progress::silent()).unwrap();
+        assert_eq!((preview.imported.len(), preview.skipped), (0, 3));
+        assert_eq!(stored_hash(&db), None);
+        
+        let again = db.import_day_one(&export, false, false, &mut Progress::silent()).unwrap();
+        assert_eq!((again.imported.len(), again.skipped, again.updated), (0, 3, 0));
+        assert_eq!(db.list_diaries().unwrap().len(), 2);
+        
+        fs::write(&export, r#"[
+            {"text": "Run\nWarm", "creationDate": "2015-02-02T09:00:00Z", "tags": ["outdoors"]}
+        ]"#).unwrap();
+        let merged = db.import_day_one(&export, false, true, &mut Progress::silent()).unwrap();
+        assert_eq!((merged.imported.len(), merged.skipped, merged.updated), (0, 0, 1));
+        let run = first.imported[1].id.clone().unwrap();
+        let mut tags = db.get_diary(&run).unwrap().tags;
+        tags.sort();
+        assert_eq!(tags, vec!["outdoors".to_string(), "sport".to_string()]);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_find_duplicate_diaries() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let created_at = Some("2024-03-01T10:00:00Z".parse().unwrap());
+        let copy = |content: &str| NewDiary {
+            title: "Copied".to_string(),
+            content: content.to_string(),
+            tags: Vec::new(),
+            created_at,
+            updated_at: None,
+        };
+        let ids = db.save_diaries_batch(&[copy("same"), copy("same"), copy("different")]).unwrap();
+        {
+            // Entries saved before hashes existed are hashed on demand.
+            let conn = db.pool.get().unwrap();
+            conn.execute("UPDATE diary_entries SET content_hash = NULL WHERE id = ?1", params![ids[1]]).unwrap();
+        }
+        
+        let groups = db.find_duplicate_diaries().unwrap();
+        assert_eq!(groups.len(), 1);
+        let mut group: Vec<String> = groups[0].iter().map(|summary| summary.id.clone()).collect();
+        group.sort();
+        let mut expected = vec![ids[0].clone(), ids[1].clone()];
+        expected.sort();
+        assert_eq!(group, expected);
+        
+        // Editing one copy makes it distinct.
+        db.save_diary(Some(&ids[1]), "Copied", "edited", &[]).unwrap();
+        assert!(db.find_duplicate_diaries().unwrap().is_empty());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_reset_vault() {
+        let dir = temp_dir();
+        let mut db = open_temp_db(&dir);
+        let id = db.save_diary(None, "Secret", "gone soon", &["private".to_string()]).unwrap();
+        db.save_template(None, "Template", "body", &[]).unwrap();
+        db.set_reminder(&id, "2020-01-01T00:00:00Z", "never").unwrap();
+        let old_key = fs::read(dir.join("encryption.key")).unwrap();
+        
+        assert!(matches!(db.reset_vault("delete everything"), Err(DiaryError::Validation(_))));
+        assert_eq!(db.list_diaries().unwrap().len(), 1);
+        
+        db.set_passphrase(None, "open sesame").unwrap();
+        db.lock().unwrap();
+        assert!(matches!(db.reset_vault(RESET_VAULT_CONFIRMATION), Err(DiaryError::Locked)));
+        db.unlock("open sesame").unwrap();
+        assert!(matches!(db.reset_vault(RESET_VAULT_CONFIRMATION), Err(DiaryError::Validation(_))));
+        assert_eq!(db.list_diaries().unwrap().len(), 1);
+        db.verify_password("open sesame").unwrap();
+        db.reset_vault(RESET_VAULT_CONFIRMATION).unwrap();
+        assert!(!db.is_locked());
+        assert_ne!(fs::read(dir.join("encryption.key")).unwrap(), old_key);
+        assert!(db.list_diaries().unwrap().is_empty());
+        assert!(db.list_templates().unwrap().is_empty());
+        assert!(db.search_diaries_by_tag("private").unwrap().is_empty());
+        assert!(db.list_due_reminders().unwrap().is_empty());
+        
+        // The fresh vault works without reopening, and after reopening.
+        let new_id = db.save_diary(None, "Fresh", "start", &[]).unwrap();
+        db.set_diary_property(&new_id, "mood", &serde_json::json!("calm")).unwrap();
+        drop(db);
+        let reopened = open_temp_db(&dir);
+        assert_eq!(reopened.get_diary(&new_id).unwrap().title, "Fresh");
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_maintenance_reports_without_fixing() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let id = db.save_diary(None, "Entry", "text", &["tag".to_string()]).unwrap();
+        
+        let report = db.run_maintenance().unwrap();
+        assert!(report.passed);
+        assert!(report.integrity_errors.is_empty());
+        
+        {
+            let conn = db.pool.get().unwrap();
+            conn.execute_batch("PRAGMA foreign_keys = OFF;").unwrap();
+            conn.execute(
+                "INSERT INTO diary_tags (diary_id, tag_id) VALUES ('missing-entry', (SELECT id FROM tags))",
+                [],
+            ).unwrap();
+            conn.execute_batch("PRAGMA foreign_keys = ON;").unwrap();
+        }
+        let report = db.run_maintenance().unwrap();
+        assert!(!report.passed);
+        assert_eq!(report.foreign_key_violations.len(), 1);
+        assert_eq!(report.foreign_key_violations[0].table, "diary_tags");
+        assert_eq!(report.foreign_key_violations[0].parent, "diary_entries");
+        assert!(!db.run_maintenance().unwrap().passed);
+        
+        let info = db.get_database_info().unwrap();
+        assert!(info.path.ends_with("diary.db"));
+        assert!(info.file_size_bytes > 0);
+        assert_eq!((info.entry_count, info.tag_count, info.relationship_count), (1, 1, 0));
+        assert_eq!(info.schema_version, MIGRATIONS.len() as i64);
+        assert_eq!(db.get_diary(&id).unwrap().tags, vec!["tag".to_string()]);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_quick_capture_appends_to_inbox() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        
+        let inbox = db.quick_capture("  first thought ").unwrap();
+        assert_eq!(inbox.title, "Inbox");
+        assert_eq!(db.quick_capture("second\nline").unwrap().id, inbox.id);
+        assert!(matches!(db.quick_capture("   "), Err(DiaryError::Validation(_))));
+        
+        let entry = db.get_diary(&inbox.id).unwrap();
+        assert_eq!(entry.tags, vec!["inbox".to_string()]);
+        let lines: Vec<&str> = entry.content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("- [") && lines[0].ends_with("] first thought"));
+        assert!(lines[1].ends_with("] second"));
+        assert_eq!(lines[2], "  line");
+        
+        // Captures racing from several windows all land.
+        std::thread::scope(|scope| {
+            for worker in 0..4 {
+                let db = &db;
+                scope.spawn(move || {
+                    for n in 0..5 {
+                        db.quick_capture(&format!("worker {} note {}", worker, n)).unwrap();
+                    }
+                });
+            }
+        });
+        let content = db.get_diary(&inbox.id).unwrap().content;
+        assert_eq!(content.lines().filter(|line| line.starts_with("- [")).count(), 22);
+        assert_eq!(db.list_diaries().unwrap().len(), 1);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_export_csv_quotes_titles_and_skips_archived() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        
+        let tricky = db.save_diary(None, "Fish, \"chips\"\nand peas", "one two three", &["food".to_string(), "b".to_string()]).unwrap();
+        let other = db.save_diary(None, "Plain", "four", &[]).unwrap();
+        let archived = db.save_diary(None, "Old", "five", &[]).unwrap();
+        db.add_relationship(&tricky, &other, "depends_on", None, None).unwrap();
+        db.archive_diary(&archived).unwrap();
+        
+        let path = dir.join("index.csv");
+        assert_eq!(db.export_csv(&path, false, true, &mut Progress::silent()).unwrap(), 2);
+        let bytes = fs::read(&path).unwrap();
+        assert!(bytes.starts_with(csv_export::UTF8_BOM));
+        let csv = String::from_utf8(bytes[3..].to_vec()).unwrap();
+        assert!(csv.starts_with("id,title,created_at,updated_at,tags,word_count,relationship_count\r\n"));
+        assert!(csv.contains(&format!("{},\"Fish, \"\"chips\"\"\nand peas\",", tricky)));
+        assert!(csv.contains(",b;food,3,1\r\n"));
+        assert!(!csv.contains("five") && !csv.contains(&archived));
+        
+        assert_eq!(db.export_csv(&path, true, false, &mut Progress::silent()).unwrap(), 3);
+        assert!(fs::read_to_string(&path).unwrap().starts_with("id,"));
+        db.unarchive_diary(&archived).unwrap();
+        assert_eq!(db.export_csv(&path, false, false, &mut Progress::silent()).unwrap(), 3);
+        assert!(matches!(db.archive_diary("missing"), Err(DiaryError::NotFound(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_tag_cooccurrence_pairs_once() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let tags = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        db.save_diary(None, "One", "1", &tags(&["rust", "async", "work"])).unwrap();
+        db.save_diary(None, "Two", "2", &tags(&["work", "rust"])).unwrap();
+        db.save_diary(None, "Three", "3", &tags(&["async"])).unwrap();
+        
+        let pairs = db.get_tag_cooccurrence(1).unwrap();
+        let found: Vec<(&str, &str, i64)> = pairs.iter().map(|p| (p.tag_a.as_str(), p.tag_b.as_str(), p.count)).collect();
+        assert_eq!(found, vec![("rust", "work", 2), ("async", "rust", 1), ("async", "work", 1)]);
+        assert_eq!(db.get_tag_cooccurrence(2).unwrap().len(), 1);
+        assert_eq!(db.get_tag_cooccurrence(0).unwrap().len(), 3);
+        
+        let graph = db.get_graph_data(&GraphFilter { tag_cooccurrence: Some(2), ..GraphFilter::default() }).unwrap();
+        let cooccurrence: Vec<&GraphEdge> = graph.edges.iter().filter(|e| e.edge_type == GraphEdgeType::TagCooccurrence).collect();
+        assert_eq!(cooccurrence.len(), 1);
+        assert_eq!(cooccurrence[0].weight, 2.0);
+        let label = |id: &str| graph.nodes.iter().find(|n| n.id == id).unwrap().label.clone();
+        assert_eq!((label(&cooccurrence[0].source), label(&cooccurrence[0].target)), ("rust".to_string(), "work".to_string()));
+        
+        let without_tags = db.get_graph_data(&GraphFilter {
+            include_tags: false,
+            tag_cooccurrence: Some(1),
+            ..GraphFilter::default()
+        }).unwrap();
+        assert!(without_tags.edges.is_empty());
+        assert!(db.get_graph_data(&GraphFilter::default()).unwrap().edges.iter().all(|e| e.edge_type != GraphEdgeType::TagCooccurrence));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_on_this_day_and_stale_entries() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let noon = |y: i32, m: u32, d: u32| {
+            NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(12, 0, 0).unwrap()
+                .and_local_timezone(Local).unwrap().with_timezone(&Utc)
+        };
+        let entry = |title: &str, at: DateTime<Utc>| NewDiary {
+            title: title.to_string(),
+            content: String::new(),
+            tags: Vec::new(),
+            created_at: Some(at),
+            updated_at: Some(at),
+        };
+        let ids = db.save_diaries_batch(&[
+            entry("Leap day", noon(2020, 2, 29)),
+            entry("Last year", noon(2022, 2, 28)),
+            entry("This year", noon(2023, 2, 28)),
+            entry("Other day", noon(2021, 3, 1)),
+        ]).unwrap();
+        
+        let titles = |summaries: Vec<DiarySummary>| summaries.into_iter().map(|s| s.title).collect::<Vec<_>>();
+        let feb_28 = NaiveDate::from_ymd_opt(2023, 2, 28).unwrap();
+        assert_eq!(titles(db.on_this_day(feb_28).unwrap()), vec!["Last year", "Leap day"]);
+        let leap_feb_28 = NaiveDate::from_ymd_opt(2024, 2, 28).unwrap();
+        assert_eq!(titles(db.on_this_day(leap_feb_28).unwrap()), vec!["This year", "Last year"]);
+        let leap_feb_29 = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        assert_eq!(titles(db.on_this_day(leap_feb_29).unwrap()), vec!["Leap day"]);
+        
+        db.save_diary(None, "Fresh", "new", &[]).unwrap();
+        db.archive_diary(&ids[3]).unwrap();
+        assert_eq!(titles(db.list_stale_diaries(30, 10).unwrap()), vec!["Leap day", "Last year", "This year"]);
+        db.get_diary(&ids[0]).unwrap();
+        assert_eq!(titles(db.list_stale_diaries(30, 1).unwrap()), vec!["Last year"]);
+        assert_eq!(db.list_stale_diaries(30, 10).unwrap().len(), 2);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_versions_are_kept_and_diffed() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let id = db.save_diary(None, "Plan", "milk\neggs\nbread\n", &[]).unwrap();
+        db.save_diary(Some(&id), "Plan", "milk\neggs\nbread\n", &["unchanged".to_string()]).unwrap();
+        assert!(db.list_diary_versions(&id).unwrap().is_empty());
+        
+        db.save_diary(Some(&id), "Shopping", "milk\nflour\nbread\n", &[]).unwrap();
+        let versions = db.list_diary_versions(&id).unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!((versions[0].version, versions[0].title.as_str()), (1, "Plan"));
+        
+        let diff = db.diff_diary_versions(&id, "1", "current").unwrap();
+        assert_eq!((diff.from_title.as_str(), diff.to_title.as_str()), ("Plan", "Shopping"));
+        let changed: Vec<(DiffLineKind, &str)> = diff.diff.hunks[0].lines.iter()
+            .filter(|l| l.kind != DiffLineKind::Unchanged)
+            .map(|l| (l.kind, l.text.as_str()))
+            .collect();
+        assert_eq!(changed, vec![(DiffLineKind::Removed, "eggs"), (DiffLineKind::Added, "flour")]);
+        
+        db.save_draft(Some(&id), "Shopping", "milk\nflour\nbread\nbutter\n", &[]).unwrap();
+        let draft = db.diff_diary_versions(&id, "current", "draft").unwrap();
+        assert_eq!(draft.diff.hunks[0].lines.last().unwrap().text, "butter");
+        
+        assert!(matches!(db.diff_diary_versions(&id, "7", "current"), Err(DiaryError::NotFound(_))));
+        assert!(matches!(db.diff_diary_versions(&id, "latest", "current"), Err(DiaryError::Validation(_))));
+        
+        for n in 0..MAX_VERSIONS_PER_ENTRY + 5 {
+            db.save_diary(Some(&id), "Shopping", &format!("revision {}", n), &[]).unwrap();
+        }
+        let versions = db.list_diary_versions(&id).unwrap();
+        assert_eq!(versions.len() as i64, MAX_VERSIONS_PER_ENTRY);
+        assert_eq!(versions[0].version, MAX_VERSIONS_PER_ENTRY + 6);
+        
+        db.delete_diary(&id).unwrap();
+        let conn = db.pool.get().unwrap();
+        let left: i64 = conn.query_row("SELECT COUNT(*) FROM diary_versions", [], |row| row.get(0)).unwrap();
+        assert_eq!(left, 0);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_saving_over_undecryptable_entry_repairs_it() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let id = db.save_diary(None, "Entry", "content", &[]).unwrap();
+        
+        // A title written under some other key, as left for `verify_vault`.
+        let foreign = db.crypto().unwrap().generate_replacement();
+        let conn = db.pool.get().unwrap();
+        conn.execute(
+            "UPDATE diary_entries SET title = ?1 WHERE id = ?2",
+            params![foreign.encrypt("Entry"), id],
+        ).unwrap();
+        drop(conn);
+        
+        db.save_diary(Some(&id), "Entry", "content", &[]).unwrap();
+        assert_eq!(db.get_diary(&id).unwrap().title, "Entry");
+        let conn = db.pool.get().unwrap();
+        let versions: i64 = conn.query_row("SELECT COUNT(*) FROM diary_versions", [], |row| row.get(0)).unwrap();
+        assert_eq!(versions, 1);
+        // The damaged copy kept as a version is reported, not a panic.
+        assert!(matches!(db.list_diary_versions(&id), Err(DiaryError::Crypto(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_tags_and_titles_match_ignoring_case_and_accents() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let tags = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        
+        let cafe = db.save_diary(None, "Cafe\u{301} notes", "x", &tags(&["Cafe\u{301}"])).unwrap();
+        let again = db.save_diary(None, "More", "y", &tags(&["CAFÉ", "🦀 Rust"])).unwrap();
+        let turkish = db.save_diary(None, "İstanbul", "z", &tags(&["ılık"])).unwrap();
+        
+        // Both spellings share the first tag, stored in NFC.
+        let conn = db.pool.get().unwrap();
+        let names: Vec<String> = conn
+            .prepare("SELECT name FROM tags ORDER BY rowid").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<SqliteResult<_>>().unwrap();
+        drop(conn);
+        assert_eq!(names, vec!["Café", "🦀 Rust", "ılık"]);
+        
+        let ids = |entries: Vec<DiaryEntry>| entries.into_iter().map(|e| e.id).collect::<HashSet<_>>();
+        assert_eq!(ids(db.search_diaries_by_tag("cafe").unwrap()), HashSet::from([cafe.clone(), again.clone()]));
+        assert_eq!(ids(db.search_diaries_by_tag("🦀 rust").unwrap()), HashSet::from([again.clone()]));
+        assert!(db.search_diaries_by_tag("ilik").unwrap().is_empty());
+        assert!(db.search_diaries_by_tag("ILIK").unwrap().is_empty());
+        assert_eq!(ids(db.search_diaries_by_tag("ılık").unwrap()), HashSet::from([turkish.clone()]));
+        
+        let suggested: Vec<(String, i64)> = db.suggest_tags("ca", 10).unwrap().into_iter().map(|t| (t.name, t.count)).collect();
+        assert_eq!(suggested, vec![("Café".to_string(), 2)]);
+        assert_eq!(db.suggest_tags("🦀", 10).unwrap()[0].name, "🦀 Rust");
+        assert_eq!(db.suggest_tags("", 2).unwrap().len(), 2);
+        
+        assert_eq!(db.find_diary_by_title("CAFÉ NOTES").unwrap(), vec![cafe.clone()]);
+        assert_eq!(db.find_diary_by_title("istanbul").unwrap(), vec![turkish.clone()]);
+        assert_eq!(db.search_titles("cafe", 10).unwrap()[0].title, "Cafe\u{301} notes");
+        
+        db.bulk_remove_tag(std::slice::from_ref(&cafe), "cafe").unwrap();
+        assert!(db.get_diary(&cafe).unwrap().tags.is_empty());
+        
+        // Tags written before match keys existed are backfilled on open.
+        let conn = db.pool.get().unwrap();
+        conn.execute("INSERT INTO tags (id, name) VALUES ('legacy', 'Ünïcode')", []).unwrap();
+        drop(conn);
+        db.initialize_db().unwrap();
+        assert_eq!(db.suggest_tags("unicode", 10).unwrap()[0].name, "Ünïcode");
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_read_only_vault_is_never_written() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let id = db.save_diary(None, "Backup", "look but don't touch", &["old".to_string()]).unwrap();
+        drop(db);
+        
+        let path = dir.join("diary.db");
+        let before = fs::read(&path).unwrap();
+        let crypto = Crypto::with_key_path(dir.join("encryption.key"));
+        let db = DiaryDB::open_read_only_with(path.to_str().unwrap(), Some(crypto)).unwrap();
+        assert!(db.is_read_only());
+        
+        assert_eq!(db.get_diary(&id).unwrap().content, "look but don't touch");
+        assert_eq!(db.search_diaries_by_tag("old").unwrap().len(), 1);
+        assert_eq!(db.get_statistics().unwrap().total_entries, 1);
+        assert!(db.list_recent_diaries(10, "viewed").unwrap().is_empty());
+        let today = Utc::now().format(DAILY_NOTE_DATE_FORMAT).to_string();
+        assert_eq!(db.get_activity_heatmap(&today, &today, Some(0)).unwrap()[0].entry_count, 1);
+        assert!(db.find_duplicate_diaries().unwrap().is_empty());
+        
+        assert!(matches!(db.save_diary(Some(&id), "Backup", "edited", &[]), Err(DiaryError::ReadOnly)));
+        assert!(matches!(db.delete_diary(&id), Err(DiaryError::ReadOnly)));
+        assert!(matches!(db.quick_capture("note"), Err(DiaryError::ReadOnly)));
+        assert!(matches!(db.run_maintenance(), Err(DiaryError::ReadOnly)));
+        assert!(db.import_day_one(&dir.join("missing.json"), true, false, &mut Progress::silent()).is_err_and(|e| !matches!(e, DiaryError::ReadOnly)));
+        drop(db);
+        assert_eq!(fs::read(&path).unwrap(), before);
+        
+        let missing = dir.join("nope.db");
+        assert!(matches!(DiaryDB::open_read_only_with(missing.to_str().unwrap(), None), Err(DiaryError::NotFound(_))));
+        assert!(!missing.exists());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_read_only_opens_baseline_schema() {
+        let dir = temp_dir();
+        let path = dir.join("backup.db");
+        let crypto = Crypto::with_key_path(dir.join("encryption.key"));
+        // The schema and plaintext titles of a vault from before any migration.
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(BASELINE_SCHEMA).unwrap();
+        conn.execute_batch(
+            "INSERT INTO tags VALUES ('t1', 'Old'), ('t2', 'Café');
+             INSERT INTO diary_tags VALUES ('a', 't1'), ('b', 't2');
+             INSERT INTO relationships VALUES ('r1', 'a', 'b', 'related', '2020-01-02T00:00:00+00:00');",
+        )
+        .unwrap();
+        for (id, title, content) in [("a", "First", "first body"), ("b", "Second", "second body")] {
+            conn.execute(
+                "INSERT INTO diary_entries VALUES (?1, ?2, ?3, '2020-01-01T00:00:00+00:00', '2020-01-01T00:00:00+00:00')",
+                params![id, title, crypto.encrypt(content)],
+            )
+            .unwrap();
+        }
+        drop(conn);
+        let before = fs::read(&path).unwrap();
+        
+        let db = DiaryDB::open_read_only_with(path.to_str().unwrap(), Some(crypto)).unwrap();
+        let entry = db.get_diary("a").unwrap();
+        assert_eq!((entry.title.as_str(), entry.content.as_str(), entry.locked), ("First", "first body", false));
+        assert_eq!(entry.tags, vec!["Old".to_string()]);
+        assert_eq!(db.list_diaries().unwrap().len(), 2);
+        assert_eq!(db.search_diaries_by_tag("old").unwrap().len(), 1);
+        assert_eq!(db.search_diaries_by_tag("cafe").unwrap()[0].id, "b");
+        assert_eq!(db.search_diaries("second", 10).unwrap()[0].entry.id, "b");
+        assert_eq!(db.list_recent_diaries(10, "updated").unwrap().len(), 2);
+        assert!(db.list_most_viewed_diaries(10, None).unwrap().is_empty());
+        let relationships = db.get_relationships("a").unwrap();
+        assert_eq!(relationships.len(), 1);
+        assert_eq!(relationships[0].relationship.source, SOURCE_MANUAL);
+        assert_eq!(relationships[0].other_title, "Second");
+        drop(db);
+        assert_eq!(fs::read(&path).unwrap(), before);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_cancelled_import_keeps_finished_batches() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let export = dir.join("Journal.json");
+        let entries: Vec<String> = (0..250)
+            .map(|i| format!(r#"{{"text": "Entry {}", "creationDate": "2016-01-01T{:02}:{:02}:00Z"}}"#, i, i / 60, i % 60))
+            .collect();
+        fs::write(&export, format!(r#"{{"entries": [{}]}}"#, entries.join(","))).unwrap();
+        
+        let operations = Operations::default();
+        let flag = operations.start("import").unwrap();
+        let mut progress = Progress::new(flag, |phase: &str, done, _| {
+            if phase == "importing" && done > 0 {
+                operations.cancel("import");
+            }
+        });
+        assert!(matches!(db.import_day_one(&export, false, false, &mut progress), Err(DiaryError::Cancelled)));
+        assert_eq!(db.list_diaries().unwrap().len(), IMPORT_BATCH_SIZE);
+        
+        let rerun = db.import_day_one(&export, false, false, &mut Progress::silent()).unwrap();
+        assert_eq!(rerun.imported.len(), 250 - IMPORT_BATCH_SIZE);
+        assert_eq!(rerun.skipped, IMPORT_BATCH_SIZE);
+        assert_eq!(db.list_diaries().unwrap().len(), 250);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_cancelled_csv_export_removes_file() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let diaries: Vec<NewDiary> = (0..150)
+            .map(|i| NewDiary {
+                title: format!("Entry {}", i),
+                content: "text".to_string(),
+                tags: Vec::new(),
+                created_at: None,
+                updated_at: None,
+            })
+            .collect();
+        db.save_diaries_batch(&diaries).unwrap();
+        
+        let operations = Operations::default();
+        let flag = operations.start("export").unwrap();
+        let mut reports = Vec::new();
+        let mut progress = Progress::new(flag, |_: &str, done, total| {
+            reports.push((done, total));
+            if done > 0 {
+                operations.cancel("export");
+            }
+        });
+        let path = dir.join("index.csv");
+        assert!(matches!(db.export_csv(&path, false, false, &mut progress), Err(DiaryError::Cancelled)));
+        drop(progress);
+        assert_eq!(reports, vec![(0, 150), (EXPORT_PROGRESS_BATCH, 150)]);
+        assert!(!path.exists());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_most_viewed_counts_only_user_views() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let reference = db.save_diary(None, "Reference", "look me up", &["ref".to_string()]).unwrap();
+        let other = db.save_diary(None, "Other", "once", &[]).unwrap();
+        db.save_diary(None, "Unread", "never", &[]).unwrap();
+        
+        let before = Utc::now().to_rfc3339();
+        for _ in 0..3 {
+            db.get_diary(&reference).unwrap();
+        }
+        db.get_diary(&other).unwrap();
+        db.export_entry_encrypted(&other, &dir.join("shared.entry"), "pw").unwrap();
+        
+        let viewed = db.list_most_viewed_diaries(10, None).unwrap();
+        let counts: Vec<(&str, i64)> = viewed.iter().map(|v| (v.summary.title.as_str(), v.view_count)).collect();
+        assert_eq!(counts, vec![("Reference", 3), ("Other", 1)]);
+        assert_eq!(viewed[0].summary.tags, vec!["ref".to_string()]);
+        
+        let since = db.list_most_viewed_diaries(10, Some(&before)).unwrap();
+        assert_eq!(since.len(), 2);
+        assert!(db.list_most_viewed_diaries(10, Some("2999-01-01T00:00:00Z")).unwrap().is_empty());
+        assert!(matches!(db.list_most_viewed_diaries(10, Some("yesterday")), Err(DiaryError::Validation(_))));
+        
+        // A view that can't be written doesn't fail the read.
+        let blocker = Connection::open(dir.join("diary.db")).unwrap();
+        blocker.execute_batch("BEGIN IMMEDIATE;").unwrap();
+        assert_eq!(db.get_diary(&reference).unwrap().content, "look me up");
+        blocker.execute_batch("ROLLBACK;").unwrap();
+        assert_eq!(db.list_most_viewed_diaries(1, None).unwrap()[0].view_count, 3);
+        
+        db.delete_diary(&reference).unwrap();
+        assert_eq!(db.list_most_viewed_diaries(10, Some(&before)).unwrap().len(), 1);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_view_history_is_pruned_and_kept_with_counts() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let id = db.save_diary(None, "Entry", "body", &[]).unwrap();
+        db.get_diary(&id).unwrap();
+        let conn = db.pool.get().unwrap();
+        conn.execute(
+            "INSERT INTO diary_views (diary_id, viewed_at) VALUES (?1, '2000-01-01T00:00:00+00:00')",
+            params![id],
+        )
+        .unwrap();
+        
+        assert_eq!(db.prune_view_history().unwrap(), 0);
+        assert!(db.set_view_history_retention_days(Some(0)).is_err());
+        db.set_view_history_retention_days(Some(30)).unwrap();
+        assert_eq!(db.get_view_history_retention_days().unwrap(), Some(30));
+        assert_eq!(db.get_audit_retention_days().unwrap(), None);
+        assert_eq!(db.prune_view_history().unwrap(), 1);
+        let views: i64 = conn.query_row("SELECT COUNT(*) FROM diary_views", [], |row| row.get(0)).unwrap();
+        assert_eq!(views, 1);
+        assert_eq!(db.list_most_viewed_diaries(1, None).unwrap()[0].view_count, 1);
+        
+        // A view whose history can't be written isn't counted either.
+        conn.execute("DROP TABLE diary_views", []).unwrap();
+        drop(conn);
+        assert_eq!(db.get_diary(&id).unwrap().content, "body");
+        assert_eq!(db.list_most_viewed_diaries(1, None).unwrap()[0].view_count, 1);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_saved_search_runs_stored_query() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let standup = db.save_diary(None, "Standup", "sprint planning", &["work".to_string(), "meetings".to_string()]).unwrap();
+        let review = db.save_diary(None, "Review", "sprint review", &["Work".to_string()]).unwrap();
+        let hike = db.save_diary(None, "Hike", "sprint up the hill", &["outdoors".to_string()]).unwrap();
+        let old = db.save_diary(None, "Old work", "sprint", &["work".to_string()]).unwrap();
+        let conn = db.pool.get().unwrap();
+        conn.execute("UPDATE diary_entries SET created_at = '2001-01-01T00:00:00+00:00' WHERE id = ?1", params![old]).unwrap();
+        db.archive_diary(&review).unwrap();
+        
+        let recent_work = SearchQuery {
+            tags: vec!["WORK".to_string()],
+            text: "sprint".to_string(),
+            within_days: Some(30),
+            ..Default::default()
+        };
+        let saved = db.save_search("Recent work", &recent_work).unwrap();
+        let ids = |summaries: Vec<DiarySummary>| summaries.into_iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!(ids(db.run_saved_search(&saved.id).unwrap()), vec![standup.clone()]);
+        
+        let either = SearchQuery {
+            tags: vec!["meetings".to_string(), "outdoors".to_string()],
+            tag_match: TagMatch::Any,
+            include_archived: true,
+            ..Default::default()
+        };
+        let mut found = ids(db.search_entries(&either).unwrap());
+        found.sort();
+        let mut expected = vec![standup.clone(), hike.clone()];
+        expected.sort();
+        assert_eq!(found, expected);
+        let both = SearchQuery { tag_match: TagMatch::All, ..either };
+        assert_eq!(ids(db.search_entries(&both).unwrap()), Vec::<String>::new());
+        
+        // Missing fields take their defaults and unknown ones are ignored.
+        let legacy = SearchQuery::from_stored(r#"{"version": 1, "tags": ["work"], "future_field": true}"#).unwrap();
+        assert_eq!(legacy.tag_match, TagMatch::All);
+        assert!(matches!(SearchQuery::from_stored(r#"{"version": 99}"#), Err(DiaryError::Validation(_))));
+        
+        let gone = db.save_search("Gone", &SearchQuery { tags: vec!["temporary".to_string()], ..Default::default() }).unwrap();
+        assert!(db.run_saved_search(&gone.id).unwrap().is_empty());
+        
+        let names: Vec<String> = db.list_saved_searches().unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["Gone".to_string(), "Recent work".to_string()]);
+        assert_eq!(db.list_saved_searches().unwrap()[1].query, recent_work);
+        db.delete_saved_search(&gone.id).unwrap();
+        assert!(matches!(db.run_saved_search(&gone.id), Err(DiaryError::NotFound(_))));
+        assert!(matches!(db.save_search(" ", &recent_work), Err(DiaryError::Validation(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_list_saved_searches_skips_unreadable_rows() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        db.save_search("Readable", &SearchQuery { text: "plan".to_string(), ..Default::default() }).unwrap();
+        let newer = db.save_search("Newer", &SearchQuery::default()).unwrap();
+        let corrupt = db.save_search("Corrupt", &SearchQuery::default()).unwrap();
+        let unnamed = db.save_search("Unnamed", &SearchQuery::default()).unwrap();
+        let crypto = db.crypto().unwrap();
+        let conn = db.pool.get().unwrap();
+        conn.execute(
+            "UPDATE saved_searches SET query = ?1 WHERE id = ?2",
+            params![crypto.encrypt(r#"{"version": 99}"#), newer.id],
+        )
+        .unwrap();
+        conn.execute("UPDATE saved_searches SET query = 'not encrypted' WHERE id = ?1", params![corrupt.id]).unwrap();
+        conn.execute("UPDATE saved_searches SET name = 'not encrypted' WHERE id = ?1", params![unnamed.id]).unwrap();
+        drop(conn);
+        
+        let searches = db.list_saved_searches().unwrap();
+        assert_eq!(searches.len(), 1);
+        assert_eq!(searches[0].name, "Readable");
+        assert_eq!(searches[0].query.text, "plan");
+        assert!(matches!(db.run_saved_search(&newer.id), Err(DiaryError::Validation(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_locked_entry_needs_passphrase() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let secret = db.save_diary(None, "Therapy notes", "private words", &["health".to_string()]).unwrap();
+        db.save_diary(None, "Groceries", "milk", &[]).unwrap();
+        
+        assert!(matches!(db.set_diary_locked(&secret, true), Err(DiaryError::Validation(_))));
+        assert!(matches!(db.verify_password("anything"), Err(DiaryError::Validation(_))));
+        db.set_passphrase(None, "correct horse").unwrap();
+        db.set_diary_locked(&secret, true).unwrap();
+        assert!(matches!(db.set_passphrase(Some("wrong"), "other"), Err(DiaryError::Crypto(_))));
+        
+        // Setting the passphrase elevated this session; a new one starts locked.
+        assert_eq!(db.get_diary(&secret).unwrap().content, "private words");
+        drop(db);
+        let db = open_temp_db(&dir);
+        assert!(!db.is_elevated());
+        assert!(matches!(db.get_diary(&secret), Err(DiaryError::EntryLocked)));
+        assert!(matches!(db.export_entry_encrypted(&secret, &dir.join("out.entry"), "pw"), Err(DiaryError::EntryLocked)));
+        assert!(matches!(db.set_diary_locked(&secret, false), Err(DiaryError::EntryLocked)));
+        
+        let listed = db.list_diaries().unwrap();
+        let locked = listed.iter().find(|entry| entry.id == secret).unwrap();
+        assert!(locked.locked && locked.content.is_empty());
+        assert_eq!(locked.title, "Therapy notes");
+        assert!(db.search_diaries("private", 10).unwrap().is_empty());
+        assert_eq!(db.search_diaries("therapy", 10).unwrap()[0].snippet, "");
+        assert!(db.search_diaries_by_tag("health").unwrap()[0].content.is_empty());
+        
+        assert!(matches!(db.verify_password("wrong"), Err(DiaryError::Crypto(_))));
+        db.verify_password("correct horse").unwrap();
+        assert_eq!(db.search_diaries("private", 10).unwrap().len(), 1);
+        assert_eq!(db.get_diary(&secret).unwrap().content, "private words");
+        db.set_diary_locked(&secret, false).unwrap();
+        assert!(!db.get_diary(&secret).unwrap().locked);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_locked_entry_guards_drafts_attachments_and_writes() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let secret = db.save_diary(None, "Therapy notes", "private words", &[]).unwrap();
+        let file = dir.join("scan.txt");
+        fs::write(&file, "scanned letter").unwrap();
+        let attachment = db.add_attachment(&secret, &file).unwrap();
+        db.save_draft(Some(&secret), "Therapy notes", "unsaved private words", &[]).unwrap();
+        db.save_draft(None, "New entry", "open draft", &[]).unwrap();
+        db.set_passphrase(None, "correct horse").unwrap();
+        db.set_diary_locked(&secret, true).unwrap();
+        drop(db);
+        
+        let db = open_temp_db(&dir);
+        let drafts = db.list_unsaved_drafts().unwrap();
+        assert_eq!(drafts.len(), 1);
+        assert_eq!(drafts[0].content, "open draft");
+        assert!(matches!(db.get_draft(Some(&secret)), Err(DiaryError::EntryLocked)));
+        assert!(matches!(db.save_draft(Some(&secret), "t", "c", &[]), Err(DiaryError::EntryLocked)));
+        assert!(matches!(db.get_attachment(&attachment.id), Err(DiaryError::EntryLocked)));
+        assert!(matches!(db.add_attachment(&secret, &file), Err(DiaryError::EntryLocked)));
+        assert!(matches!(db.delete_attachment(&attachment.id), Err(DiaryError::EntryLocked)));
+        assert!(matches!(db.save_diary(Some(&secret), "Overwritten", "gone", &[]), Err(DiaryError::EntryLocked)));
+        assert!(matches!(db.rename_diary(&secret, "Renamed", true), Err(DiaryError::EntryLocked)));
+        assert!(matches!(
+            db.set_diary_property(&secret, "mood", &serde_json::json!("calm")),
+            Err(DiaryError::EntryLocked)
+        ));
+        
+        db.verify_password("correct horse").unwrap();
+        assert_eq!(db.list_unsaved_drafts().unwrap().len(), 2);
+        assert_eq!(db.get_attachment(&attachment.id).unwrap().attachment.diary_id, secret);
+        assert_eq!(db.get_diary(&secret).unwrap().content, "private words");
+        db.save_diary(Some(&secret), "Therapy notes", "edited", &[]).unwrap();
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_journal_round_trip_at_scale() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let count = 3000;
+        let diaries: Vec<NewDiary> = (0..count)
+            .map(|i| NewDiary {
+                title: format!("Entry {}", i),
+                content: format!("line one of {}\nsee [[Entry {}]]\n{}", i, (i + 1) % count, "transcript ".repeat(i % 50)),
+                tags: vec![format!("group-{}", i % 7)],
+                created_at: Some(Utc::now() - Duration::minutes(i as i64)),
+                updated_at: None,
+            })
+            .collect();
+        for chunk in diaries.chunks(500) {
+            db.save_diaries_batch(chunk).unwrap();
+        }
+        let first = db.search_titles("Entry 0", 1).unwrap()[0].id.clone();
+        db.set_diary_property(&first, "mood", &serde_json::json!({"level": 4})).unwrap();
+        
+        let path = dir.join("journal.jsonl");
+        let mut reports = 0;
+        let export = db.export_journal(&path, &mut Progress::new(Arc::new(Default::default()), |_: &str, _, _| reports += 1)).unwrap();
+        assert_eq!((export.exported, export.skipped_locked), (count, 0));
+        assert!(reports > count / EXPORT_PROGRESS_BATCH);
+        let file = fs::read_to_string(&path).unwrap();
+        assert_eq!(file.lines().count(), count + 1);
+        drop(file);
+        
+        let target_dir = temp_dir();
+        let target = open_temp_db(&target_dir);
+        let imported = target.import_journal(&path, &mut Progress::silent()).unwrap();
+        assert_eq!((imported.imported, imported.skipped, imported.errors.len()), (count, 0, 0));
+        assert_eq!(target.list_diaries().unwrap().len(), count);
+        
+        let copy_id = target.search_titles("Entry 0", 1).unwrap()[0].id.clone();
+        let (original, copy) = (db.get_diary(&first).unwrap(), target.get_diary(&copy_id).unwrap());
+        assert_eq!((copy.title, copy.content, copy.tags), (original.title, original.content, original.tags));
+        assert_eq!(copy.created_at, original.created_at);
+        assert_eq!(copy.properties["mood"]["level"], 4);
+        // A link into a later batch still resolves.
+        let last = target.search_titles(&format!("Entry {}", count - 1), 1).unwrap()[0].id.clone();
+        assert!(target.get_backlinks(&copy_id).unwrap().iter().any(|link| link.diary_id == last));
+        
+        let again = target.import_journal(&path, &mut Progress::silent()).unwrap();
+        assert_eq!((again.imported, again.skipped), (0, count));
+        
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(target_dir).unwrap();
+    }
+    
+    #[test]
+    fn test_journal_round_trip_keeps_entry_state() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        db.set_passphrase(None, "open sesame").unwrap();
+        let (daily, _) = db.get_or_create_daily_note(Some("2024-03-01")).unwrap();
+        let pinned = db.save_diary(None, "Pinned", "pinned body", &[]).unwrap();
+        let archived = db.save_diary(None, "Archived", "old body", &[]).unwrap();
+        let private = db.save_diary(None, "Private", "secret body", &[]).unwrap();
+        db.set_diary_property(&private, "mood", &serde_json::json!(2)).unwrap();
+        db.set_diary_locked(&private, true).unwrap();
+        db.pin_diary(&pinned).unwrap();
+        db.pin_diary(&daily.id).unwrap();
+        db.reorder_pinned_diaries(&[pinned.clone(), daily.id.clone()]).unwrap();
+        db.archive_diary(&archived).unwrap();
+        db.add_relationship(&pinned, &archived, "depends_on", Some("first"), Some(2.5)).unwrap();
+        db.add_relationship(&private, &pinned, "related_to", None, None).unwrap();
+        
+        let path = dir.join("journal.jsonl");
+        db.export_journal(&path, &mut Progress::silent()).unwrap();
+        let target_dir = temp_dir();
+        let target = open_temp_db(&target_dir);
+        let imported = target.import_journal(&path, &mut Progress::silent()).unwrap();
+        assert_eq!((imported.imported, imported.relationships), (4, 2));
+        
+        let state = |db: &DiaryDB, title: &str| {
+            let id = db.search_titles(title, 1).unwrap()[0].id.clone();
+            let conn = db.pool.get().unwrap();
+            let columns: (bool, Option<String>, Option<String>, Option<i64>, Option<String>) = conn
+                .query_row(
+                    "SELECT locked, archived_at, pinned_at, sort_order, daily_date FROM diary_entries WHERE id = ?1",
+                    params![id],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+                )
+                .unwrap();
+            let properties = db.get_properties_for_diary(&id).unwrap();
+            let mut relationships: Vec<(String, String, Option<String>, f64, String)> = db
+                .get_relationships(&id)
+                .unwrap()
+                .into_iter()
+                .map(|linked| {
+                    let relationship = linked.relationship;
+                    (linked.other_title, relationship.relationship_type, relationship.note, relationship.weight, relationship.source)
+                })
+                .collect();
+            relationships.sort_by(|a, b| a.0.cmp(&b.0));
+            (columns, properties, relationships)
+        };
+        for title in ["2024-03-01", "Pinned", "Archived", "Private"] {
+            assert_eq!(state(&target, title), state(&db, title), "{}", title);
+        }
+        let (columns, properties, _) = state(&target, "Private");
+        assert!(columns.0);
+        assert_eq!(properties["mood"], 2);
+        assert_eq!(state(&target, "Pinned").0 .3, Some(0));
+        assert_eq!(state(&target, "2024-03-01").0 .4.as_deref(), Some("2024-03-01"));
+        assert!(state(&target, "Archived").0 .1.is_some());
+        
+        // Importing again adds neither entries nor relationships.
+        let again = target.import_journal(&path, &mut Progress::silent()).unwrap();
+        assert_eq!((again.imported, again.skipped, again.relationships), (0, 4, 0));
+        
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(target_dir).unwrap();
+    }
+    
+    #[test]
+    fn test_cancelled_journal_import_commits_whole_batches() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let path = dir.join("journal.jsonl");
+        let mut lines = String::from("{\"format\": \"secondbrain-journal\", \"version\": 1}\n");
+        for i in 0..500 {
+            lines.push_str(&format!(
+                "{{\"id\": \"{}\", \"title\": \"Note {}\", \"content\": \"body\", \"created_at\": \"2020-01-01T00:00:00Z\", \"updated_at\": \"2020-01-01T00:00:00Z\"}}\n",
+                i, i
+            ));
+        }
+        lines.push_str("not json\n");
+        fs::write(&path, lines).unwrap();
+        
+        let operations = Operations::default();
+        let flag = operations.start("import").unwrap();
+        let mut progress = Progress::new(flag, |_: &str, done, _| {
+            if done > 0 {
+                operations.cancel("import");
+            }
+        });
+        assert!(matches!(db.import_journal(&path, &mut progress), Err(DiaryError::Cancelled)));
+        assert_eq!(db.list_diaries().unwrap().len(), JOURNAL_IMPORT_BATCH);
+        
+        let rest = db.import_journal(&path, &mut Progress::silent()).unwrap();
+        assert_eq!((rest.imported, rest.skipped), (500 - JOURNAL_IMPORT_BATCH, JOURNAL_IMPORT_BATCH));
+        assert_eq!(rest.errors.len(), 1);
+        assert_eq!(rest.errors[0].index, 500);
+        
+        fs::write(&path, "{\"entries\": []}\n").unwrap();
+        assert!(matches!(db.import_journal(&path, &mut Progress::silent()), Err(DiaryError::Validation(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_audit_log_records_changes_without_content() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let first = db.save_diary(None, "Secret title", "Secret body", &[]).unwrap();
+        let second = db.save_diary(None, "Other", "body", &[]).unwrap();
+        db.save_diary(Some(&first), "Secret title", "Changed body", &[]).unwrap();
+        db.add_relationship(&first, &second, "related", None, None).unwrap();
+        db.archive_diary(&second).unwrap();
+        db.delete_diary(&second).unwrap();
+        // A failed delete rolls its audit record back with it.
+        assert!(db.delete_diary(&second).is_err());
+        
+        let log = db.get_audit_log(100, 0, None).unwrap();
+        let operations: Vec<AuditOperation> = log.iter().map(|record| record.operation).collect();
+        assert_eq!(operations, vec![
+            AuditOperation::Delete,
+            AuditOperation::Archive,
+            AuditOperation::RelationshipAdd,
+            AuditOperation::Save,
+            AuditOperation::Save,
+            AuditOperation::Save,
+        ]);
+        assert_eq!(log[2].entry_ids, vec![first.clone(), second.clone()]);
+        assert_eq!(log[2].detail, "related");
+        assert!(log.iter().all(|record| !record.detail.contains("Secret")));
+        
+        let first_log = db.get_audit_log(100, 0, Some(&first)).unwrap();
+        assert_eq!(first_log.len(), 3);
+        assert_eq!(db.get_audit_log(2, 1, None).unwrap()[0].operation, AuditOperation::Archive);
+        
+        let conn = db.pool.get().unwrap();
+        assert!(conn.execute("UPDATE audit_log SET detail = 'edited'", []).is_err());
+        conn.execute(
+            "INSERT INTO audit_log (operation, detail, created_at) VALUES ('export', '', '2000-01-01T00:00:00.000000Z')",
+            [],
+        )
+        .unwrap();
+        drop(conn);
+        assert!(db.set_audit_retention_days(Some(0)).is_err());
+        db.set_audit_retention_days(Some(30)).unwrap();
+        assert_eq!(db.get_audit_retention_days().unwrap(), Some(30));
+        assert_eq!(db.prune_audit_log().unwrap(), 1);
+        assert_eq!(db.get_audit_log(100, 0, None).unwrap().len(), log.len());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_audit_log_records_every_kind_of_change() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        db.set_passphrase(None, "open sesame").unwrap();
+        let first = db.save_diary(None, "First", "body", &[]).unwrap();
+        let second = db.save_diary(None, "Second", "See [[First]]", &[]).unwrap();
+        let ids = [first.clone(), second.clone()];
+        let operations_since = |count: usize| -> Vec<AuditOperation> {
+            let log = db.get_audit_log(100, 0, None).unwrap();
+            log[..log.len() - count].iter().rev().map(|record| record.operation).collect()
+        };
+        let start = db.get_audit_log(100, 0, None).unwrap().len();
+        
+        db.bulk_add_tag(&ids, "work").unwrap();
+        // Nothing changes the second time, so nothing is recorded.
+        db.bulk_add_tag(&ids, "work").unwrap();
+        db.bulk_remove_tag(&ids, "work").unwrap();
+        db.set_diary_property(&first, "mood", &serde_json::json!(3)).unwrap();
+        db.delete_diary_property(&first, "mood").unwrap();
+        let file = dir.join("note.txt");
+        fs::write(&file, "attached").unwrap();
+        let attachment = db.add_attachment(&first, &file).unwrap();
+        db.delete_attachment(&attachment.id).unwrap();
+        db.set_diary_locked(&first, true).unwrap();
+        db.set_diary_locked(&first, false).unwrap();
+        let relationship = db.add_relationship(&first, &second, "related", None, None).unwrap();
+        db.update_relationship(&relationship, None, None, None, None, Some(2.0)).unwrap();
+        db.rename_relationship_type("related", "see_also").unwrap();
+        db.save_diary(Some(&second), "Second", "no links", &[]).unwrap();
+        
+        assert_eq!(operations_since(start), vec![
+            AuditOperation::TagAdd,
+            AuditOperation::TagRemove,
+            AuditOperation::PropertySet,
+            AuditOperation::PropertyDelete,
+            AuditOperation::AttachmentAdd,
+            AuditOperation::AttachmentDelete,
+            AuditOperation::Lock,
+            AuditOperation::Unlock,
+            AuditOperation::RelationshipAdd,
+            AuditOperation::RelationshipUpdate,
+            AuditOperation::RelationshipUpdate,
+            AuditOperation::RelationshipRemove,
+            AuditOperation::Save,
+        ]);
+        let log = db.get_audit_log(100, 0, None).unwrap();
+        assert_eq!(log[1].entry_ids, vec![second.clone(), first.clone()]);
+        assert_eq!(log[1].detail, SOURCE_WIKILINK);
+        assert_eq!(log[8].detail, attachment.id);
+        assert!(log.iter().all(|record| !record.detail.contains("note.txt")));
+        
+        // The wikilink saved with the second entry was recorded with it.
+        let earlier = db.get_audit_log(100, log.len() - start, None).unwrap();
+        assert_eq!(earlier[0].operation, AuditOperation::Save);
+        assert_eq!(earlier[1].operation, AuditOperation::RelationshipAdd);
+        assert_eq!(earlier[1].entry_ids, vec![second.clone(), first.clone()]);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_rename_diary_rewrites_links() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let target = db.save_diary(None, "Old Name", "target", &[]).unwrap();
+        let linking = db
+            .save_diary(None, "Linking", "See [[Old Name]] and [[old name|this]]. Old Name as text.", &[])
+            .unwrap();
+        let plain = db.save_diary(None, "Plain", "Old Name as text only", &[]).unwrap();
+        
+        let rewritten = db.rename_diary(&target, "New Name", true).unwrap();
+        assert_eq!(rewritten.iter().map(|entry| entry.id.as_str()).collect::<Vec<_>>(), vec![linking.as_str()]);
+        assert_eq!(db.get_diary(&target).unwrap().title, "New Name");
+        assert_eq!(
+            db.get_diary(&linking).unwrap().content,
+            "See [[New Name]] and [[New Name|this]]. Old Name as text."
+        );
+        assert_eq!(db.get_diary(&plain).unwrap().content, "Old Name as text only");
+        assert_eq!(db.list_diary_versions(&linking).unwrap().len(), 1);
+        assert_eq!(db.list_diary_versions(&target).unwrap().len(), 1);
+        assert!(db.list_diary_versions(&plain).unwrap().is_empty());
+        assert_eq!(db.get_backlinks(&target).unwrap().len(), 1);
+        assert_eq!(db.find_diary_by_title("old name").unwrap().len(), 0);
+        
+        assert!(db.rename_diary(&target, "Newer Name", false).unwrap().is_empty());
+        assert!(db.get_diary(&linking).unwrap().content.contains("[[New Name]]"));
+        assert!(matches!(db.rename_diary("missing", "x", true), Err(DiaryError::NotFound(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_rename_diary_rejects_titles_that_break_links() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let target = db.save_diary(None, "Target", "target", &[]).unwrap();
+        let linking = db.save_diary(None, "Linking", "See [[Target]]", &[]).unwrap();
+        
+        for bad in ["", "   ", "a]]b", "a|b", "two\nlines", "cr\rhere"] {
+            assert!(matches!(db.rename_diary(&target, bad, true), Err(DiaryError::Validation(_))), "{:?}", bad);
+        }
+        assert_eq!(db.get_diary(&target).unwrap().title, "Target");
+        assert_eq!(db.get_diary(&linking).unwrap().content, "See [[Target]]");
+        
+        db.rename_diary(&target, "  Renamed  ", true).unwrap();
+        assert_eq!(db.get_diary(&target).unwrap().title, "Renamed");
+        assert_eq!(db.get_diary(&linking).unwrap().content, "See [[Renamed]]");
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_generate_review_updates_the_same_period() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let long_ago = Utc::now() - Duration::days(400);
+        let ids = db.save_diaries_batch(&[
+            NewDiary {
+                title: "Old note".to_string(),
+                content: String::new(),
+                tags: vec!["work".to_string()],
+                created_at: Some(long_ago),
+                updated_at: Some(Utc::now()),
+            },
+            NewDiary {
+                title: "Untouched".to_string(),
+                content: String::new(),
+                tags: Vec::new(),
+                created_at: Some(long_ago),
+                updated_at: Some(long_ago),
+            },
+        ]).unwrap();
+        let trip = db.save_diary(None, "Trip plan", "packing", &["travel".to_string(), "work".to_string()]).unwrap();
+        db.add_relationship(&trip, &ids[0], "related", None, None).unwrap();
+        db.quick_capture("call the bank").unwrap();
+        
+        let today = Local::now().date_naive();
+        let (from, to) = ((today - Duration::days(6)).to_string(), today.to_string());
+        let review = db.generate_review(&from, &to).unwrap();
+        let entry = db.get_diary(&review.id).unwrap();
+        assert_eq!(entry.title, format!("Review {} to {}", from, to));
+        assert_eq!(entry.tags, vec![REVIEW_TAG]);
+        assert!(entry.content.contains("## Created\n\n- [[Trip plan]]\n- [[Inbox]]\n"));
+        assert!(entry.content.contains("## Modified\n\n- [[Old note]]\n\n"));
+        assert!(entry.content.contains("## New tags\n\n- #inbox\n- #travel\n\n"));
+        assert!(entry.content.contains("- [[Trip plan]] related [[Old note]]\n"));
+        assert!(entry.content.contains("] call the bank\n"));
+        assert!(!entry.content.contains("Untouched"));
+        
+        let again = db.generate_review(&from, &to).unwrap();
+        assert_eq!(again.id, review.id);
+        let content = db.get_diary(&review.id).unwrap().content;
+        assert!(!content.contains("[[Review"));
+        assert!(!content.contains("links_to"));
+        assert_eq!(db.find_diary_by_title(&review.title).unwrap().len(), 1);
+        assert!(matches!(db.generate_review(&to, &from), Err(DiaryError::Validation(_))));
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_verify_vault_reports_each_failure_without_changes() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let good = db.save_diary(None, "Good", "fine", &[]).unwrap();
+        let bad = db.save_diary(None, "Bad", "soon broken", &[]).unwrap();
+        db.save_search("All", &SearchQuery::default()).unwrap();
+        let crypto = db.crypto().unwrap();
+        let other_key = crypto.generate_replacement().encrypt("other key");
+        let not_utf8 = crypto.encrypt_bytes(&[0xff, 0xfe]);
+        {
+            let conn = db.pool.get().unwrap();
+            conn.execute("UPDATE diary_entries SET title = 'not json', content = ?1 WHERE id = ?2", params![other_key, bad])
+                .unwrap();
+            conn.execute("UPDATE saved_searches SET query = ?1", params![not_utf8]).unwrap();
+        }
+        
+        let report = db.verify_vault().unwrap();
+        assert!(!report.passed);
+        assert_eq!(report.checked, 3);
+        let failures: Vec<(&str, &str, DecryptFailureKind)> = report
+            .failures
+            .iter()
+            .map(|failure| (failure.table.as_str(), failure.column.as_str(), failure.kind))
+            .collect();
+        assert_eq!(failures, vec![
+            ("diary_entries", "title", DecryptFailureKind::InvalidEnvelope),
+            ("diary_entries", "content", DecryptFailureKind::Aead),
+            ("saved_searches", "query", DecryptFailureKind::InvalidUtf8),
+        ]);
+        assert!(report.failures[..2].iter().all(|failure| failure.id == bad));
+        assert_eq!(db.get_diary(&good).unwrap().content, "fine");
+        let content: String = db.pool.get().unwrap()
+            .query_row("SELECT content FROM diary_entries WHERE id = ?1", params![bad], |row| row.get(0))
+            .unwrap();
+        assert_eq!(content, other_key);
+        
+        assert!(!db.get_verify_on_startup().unwrap());
+        db.set_verify_on_startup(true).unwrap();
+        assert!(db.get_verify_on_startup().unwrap());
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    fn seed_filter_entries(db: &DiaryDB) -> Vec<String> {
+        let at = |month: u32, day: u32| Utc.with_ymd_and_hms(2024, month, day, 12, 0, 0).unwrap();
+        let entry = |title: &str, content: &str, tags: &[&str], created: DateTime<Utc>| NewDiary {
+            title: title.to_string(),
+            content: content.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            created_at: Some(created),
+            updated_at: Some(created),
+        };
+        let ids = db.save_diaries_batch(&[
+            entry("Standup", "sprint planning", &["work"], at(3, 4)),
+            entry("Retro", "sprint went well", &["work", "team"], at(3, 20)),
+            entry("Hike", "mountain trail", &["outdoors"], at(3, 25)),
+            entry("Budget", "quarterly numbers", &["work"], at(4, 2)),
+        ]).unwrap();
+        for (id, mood) in ids.iter().zip([2, 5, 4, 4]) {
+            db.set_diary_property(id, "mood", &serde_json::json!(mood)).unwrap();
+        }
+        db.set_diary_property(&ids[2], "place", &serde_json::json!("Mont Blanc")).unwrap();
+        db.set_diary_property(&ids[1], "people", &serde_json::json!(["Ana", "Bo"])).unwrap();
+        ids
+    }
+    
+    #[test]
+    fn test_query_diaries_filters_each_dimension() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        let ids = seed_filter_entries(&db);
+        let titles = |filter: DiaryFilter| -> Vec<String> {
+            db.query_diaries(&filter).unwrap().into_iter().map(|s| s.title).collect()
+        };
+        let mood = |op, value: i32| PropertyPredicate { key: "mood".to_string(), op, value: serde_json::json!(value) };
+        
+        assert_eq!(titles(DiaryFilter::default()), vec!["Budget", "Hike", "Retro", "Standup"]);
+        assert_eq!(titles(DiaryFilter { tags: vec!["WORK".to_string(), "team".to_string()], ..DiaryFilter::default() }), vec!["Retro"]);
+        assert_eq!(
+            titles(DiaryFilter {
+                tags: vec!["team".to_string(), "outdoors".to_string()],
+                tag_match: TagMatch::Any,
+                ..DiaryFilter::default()
+            }),
+            vec!["Hike", "Retro"]
+        );
+        assert_eq!(titles(DiaryFilter { text: "SPRINT well".to_string(), ..DiaryFilter::default() }), vec!["Retro"]);
+        assert_eq!(titles(DiaryFilter { properties: vec![mood(PropertyOp::Eq, 4)], ..DiaryFilter::default() }), vec!["Budget", "Hike"]);
+        assert_eq!(titles(DiaryFilter { properties: vec![mood(PropertyOp::Gt, 4)], ..DiaryFilter::default() }), vec!["Retro"]);
+        assert_eq!(titles(DiaryFilter { properties: vec![mood(PropertyOp::Lt, 3)], ..DiaryFilter::default() }), vec!["Standup"]);
+        let contains = |key: &str, value: serde_json::Value| DiaryFilter {
+            properties: vec![PropertyPredicate { key: key.to_string(), op: PropertyOp::Contains, value }],
+            ..DiaryFilter::default()
+        };
+        assert_eq!(titles(contains("place", serde_json::json!("blanc"))), vec!["Hike"]);
+        assert_eq!(titles(contains("people", serde_json::json!("Bo"))), vec!["Retro"]);
+        assert_eq!(
+            titles(DiaryFilter {
+                created_from: Some(Utc.with_ymd_and_hms(2024, 3, 10, 0, 0, 0).unwrap()),
+                created_to: Some(Utc.with_ymd_and_hms(2024, 3, 31, 23, 59, 59).unwrap()),
+                ..DiaryFilter::default()
+            }),
+            vec!["Hike", "Retro"]
+        );
+        
+        db.archive_diary(&ids[0]).unwrap();
+        db.pin_diary(&ids[2]).unwrap();
+        assert_eq!(titles(DiaryFilter::default()).len(), 3);
+        assert_eq!(titles(DiaryFilter { archived: Some(true), ..DiaryFilter::default() }), vec!["Standup"]);
+        assert_eq!(titles(DiaryFilter { archived: None, ..DiaryFilter::default() }).len(), 4);
+        assert_eq!(titles(DiaryFilter { pinned: Some(true), ..DiaryFilter::default() }), vec!["Hike"]);
+        assert_eq!(titles(DiaryFilter { pinned: Some(false), ..DiaryFilter::default() }), vec!["Budget", "Retro"]);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_query_diaries_combines_sorts_and_pages() {
+        let dir = temp_dir();
+        let db = open_temp_db(&dir);
+        seed_filter_entries(&db);
+        let titles = |filter: DiaryFilter| -> Vec<String> {
+            db.query_diaries(&filter).unwrap().into_iter().map(|s| s.title).collect()
+        };
+        
+        // tag:work AND mood >= 4 AND created in March
+        let march_work = DiaryFilter {
+            tags: vec!["work".to_string()],
+            properties: vec![PropertyPredicate { key: "mood".to_string(), op: PropertyOp::Gt, value: serde_json::json!(3) }],
+            created_from: Some(Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap()),
+            created_to: Some(Utc.with_ymd_and_hms(2024, 3, 31, 23, 59, 59).unwrap()),
+            ..DiaryFilter::default()
+        };
+        assert_eq!(titles(march_work.clone()), vec!["Retro"]);
+        assert!(titles(DiaryFilter { text: "numbers".to_string(), ..march_work }).is_empty());
+        assert_eq!(
+            titles(DiaryFilter { tags: vec!["work".to_string()], text: "sprint".to_string(), ..DiaryFilter::default() }),
+            vec!["Retro", "Standup"]
+        );
+        
+        assert_eq!(
+            titles(DiaryFilter { sort: DiarySort::CreatedAsc, offset: 1, limit: Some(2), ..DiaryFilter::default() }),
+            vec!["Retro", "Hike"]
+        );
+        assert_eq!(
+            titles(DiaryFilter { sort: DiarySort::Title, offset: 1, limit: Some(2), ..DiaryFilter::default() }),
+            vec!["Hike", "Retro"]
+        );
+        let work = db
+            .query_diaries(&DiaryFilter { tags: vec!["work".to_string()], limit: Some(1), ..DiaryFilter::default() })
+            .unwrap();
+        assert_eq!(work.len(), 1);
+        assert_eq!(work[0].tags, vec!["work"]);
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+use crate::database::NewDiary;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+/// Longest title taken from the first line of an entry, in characters.
+const MAX_TITLE_CHARS: usize = 120;
+
+/// Day One fields kept as custom properties when present.
+const PROPERTY_FIELDS: [&str; 2] = ["location", "weather"];
+
+/// A Day One entry mapped to this app's shape.
+#[derive(Debug)]
+pub struct DayOneEntry {
+    pub diary: NewDiary,
+    pub properties: Vec<(String, Value)>,
+}
+
+/// Parses a Day One JSON export, either `{"entries": [...]}` or a bare
+/// array of entries. The outer result fails only if the file isn't a
+/// journal at all; each entry is converted on its own so one bad entry
+/// doesn't hide the rest.
+pub fn parse_export(json: &str) -> Result<Vec<Result<DayOneEntry, String>>, String> {
+    let document: Value = serde_json::from_str(json).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let entries = match &document {
+        Value::Array(entries) => entries,
+        Value::Object(object) => match object.get("entries") {
+            Some(Value::Array(entries)) => entries,
+            _ => return Err("Expected an \"entries\" array".to_string()),
+        },
+        _ => return Err("Expected an \"entries\" array".to_string()),
+    };
+
+    Ok(entries.iter().map(parse_entry).collect())
+}
+
+fn parse_entry(entry: &Value) -> Result<DayOneEntry, String> {
+    let entry = entry.as_object().ok_or("entry is not an object")?;
+
+    let text = entry.get("text").and_then(Value::as_str).unwrap_or_default();
+    if text.trim().is_empty() {
+        return Err("entry has no text".to_string());
+    }
+    let created_at = match entry.get("creationDate") {
+        Some(Value::String(date)) => parse_date(date)?,
+        Some(_) => return Err("creationDate is not a string".to_string()),
+        None => return Err("entry has no creationDate".to_string()),
+    };
+    let updated_at = match entry.get("modifiedDate").and_then(Value::as_str) {
+        Some(date) => parse_date(date)?.max(created_at),
+        None => created_at,
+    };
+
+    let mut tags: Vec<String> = Vec::new();
+    for tag in entry.get("tags").and_then(Value::as_array).into_iter().flatten() {
+        if let Some(tag) = tag.as_str().map(str::trim).filter(|tag| !tag.is_empty()) {
+            if !tags.iter().any(|existing| existing == tag) {
+                tags.push(tag.to_string());
+            }
+        }
+    }
+
+    let properties = PROPERTY_FIELDS
+        .iter()
+        .filter_map(|&key| match entry.get(key) {
+            Some(Value::Null) | None => None,
+            Some(value) => Some((key.to_string(), value.clone())),
+        })
+        .collect();
+
+    let (title, content) = split_title(text);
+    Ok(DayOneEntry {
+        diary: NewDiary {
+            title,
+            content,
+            tags,
+            created_at: Some(created_at),
+            updated_at: Some(updated_at),
+        },
+        properties,
+    })
+}
+
+fn parse_date(date: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(date)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|_| format!("invalid date '{}'", date))
+}
+
+/// Day One has no titles; the first non-empty line (without Markdown
+/// heading marks) becomes the title and the rest the content.
+fn split_title(text: &str) -> (String, String) {
+    let text = text.trim_start();
+    let (first_line, rest) = text.split_once('\n').unwrap_or((text, ""));
+    let heading = first_line.trim().trim_start_matches('#').trim();
+
+    if heading.chars().count() > MAX_TITLE_CHARS {
+        let title: String = heading.chars().take(MAX_TITLE_CHARS).collect();
+        return (format!("{}…", title.trim_end()), text.trim_end().to_string());
+    }
+    let title = if heading.is_empty() { first_line.trim() } else { heading };
+    (title.to_string(), rest.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_export_tolerates_missing_fields() {
+        let json = r##"{
+            "metadata": { "version": "1.0" },
+            "entries": [
+                {
+                    "text": "# Lisbon\nTrams and pastries.",
+                    "creationDate": "2016-05-01T10:00:00Z",
+                    "modifiedDate": "2016-05-02T08:30:00Z",
+                    "tags": ["travel", " travel ", ""],
+                    "location": { "localityName": "Lisbon", "latitude": 38.7 },
+                    "weather": null
+                },
+                { "text": "Just one line", "creationDate": "2016-05-03T21:15:00+02:00" },
+                { "text": "no date" },
+                { "creationDate": "2016-05-04T10:00:00Z" },
+                { "text": "bad date", "creationDate": "yesterday" },
+                "not an entry"
+            ]
+        }"##;
+        let entries = parse_export(json).unwrap();
+        assert_eq!(entries.len(), 6);
+
+        let lisbon = entries[0].as_ref().unwrap();
+        assert_eq!(lisbon.diary.title, "Lisbon");
+        assert_eq!(lisbon.diary.content, "Trams and pastries.");
+        assert_eq!(lisbon.diary.tags, vec!["travel".to_string()]);
+        assert_eq!(lisbon.properties.len(), 1);
+        assert_eq!(lisbon.properties[0].0, "location");
+        assert!(lisbon.diary.updated_at > lisbon.diary.created_at);
+
+        let short = entries[1].as_ref().unwrap();
+        assert_eq!(short.diary.title, "Just one line");
+        assert_eq!(short.diary.content, "");
+        assert_eq!(short.diary.created_at.unwrap().to_rfc3339(), "2016-05-03T19:15:00+00:00");
+        assert!(short.properties.is_empty());
+
+        assert!(entries[2..].iter().all(|entry| entry.is_err()));
+        assert!(parse_export("{\"journal\": []}").is_err());
+        assert_eq!(parse_export("[]").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_long_first_line_is_truncated_and_kept_in_content() {
+        let text = "word ".repeat(60);
+        let (title, content) = split_title(&text);
+        assert!(title.ends_with('…'));
+        assert!(title.chars().count() <= MAX_TITLE_CHARS + 1);
+        assert_eq!(content, text.trim_end());
+    }
+}
@@ -0,0 +1,61 @@
+use crate::crypto::CryptoError;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum DiaryError {
+    Sqlite(rusqlite::Error),
+    Crypto(CryptoError),
+    Io(std::io::Error),
+    KeyUnavailable,
+    Locked,
+    ReadOnly,
+    Cancelled,
+    EntryLocked,
+    NotFound(String),
+    Validation(String),
+}
+
+pub type DiaryResult<T> = Result<T, DiaryError>;
+
+impl fmt::Display for DiaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiaryError::Sqlite(e) => write!(f, "Database error: {}", e),
+            DiaryError::Crypto(CryptoError::IncorrectPassword) => {
+                write!(f, "Incorrect password, or the file has been modified")
+            }
+            DiaryError::Crypto(e) => write!(f, "Encryption error: {}", e),
+            DiaryError::Io(e) => write!(f, "I/O error: {}", e),
+            DiaryError::KeyUnavailable => write!(
+                f,
+                "Encryption key is unavailable; allow keychain access and try again"
+            ),
+            DiaryError::Locked => write!(f, "Vault is locked"),
+            DiaryError::ReadOnly => write!(f, "Vault is open read-only"),
+            DiaryError::Cancelled => write!(f, "Operation was cancelled"),
+            DiaryError::EntryLocked => write!(f, "Entry is locked; enter your passphrase to open it"),
+            DiaryError::NotFound(what) => write!(f, "{} not found", what),
+            DiaryError::Validation(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for DiaryError {}
+
+impl From<rusqlite::Error> for DiaryError {
+    fn from(e: rusqlite::Error) -> Self {
+        DiaryError::Sqlite(e)
+    }
+}
+
+impl From<CryptoError> for DiaryError {
+    fn from(e: CryptoError) -> Self {
+        DiaryError::Crypto(e)
+    }
+}
+
+impl From<std::io::Error> for DiaryError {
+    fn from(e: std::io::Error) -> Self {
+        DiaryError::Io(e)
+    }
+}
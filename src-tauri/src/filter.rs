@@ -0,0 +1,251 @@
+use crate::database::TagMatch;
+use crate::normalize;
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::types::Value as SqlValue;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// Column of the first property value in `build_sql`'s rows; the values of
+/// `DiaryFilter::properties` follow in order.
+pub const PROPERTY_COLUMN: usize = 8;
+
+/// Filters, sort and page for `DiaryDB::query_diaries`. Every field is
+/// optional; an empty filter lists all unarchived entries.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiaryFilter {
+    /// Tags to filter on, ignoring case and accents.
+    pub tags: Vec<String>,
+    pub tag_match: TagMatch,
+    /// Whitespace-separated terms that must all appear in the title or
+    /// content, as in `search_diaries`.
+    pub text: String,
+    /// Predicates on entry properties, all of which must hold.
+    pub properties: Vec<PropertyPredicate>,
+    /// Inclusive bounds on `created_at`.
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+    /// Inclusive bounds on the date of daily notes; setting either leaves
+    /// out entries that aren't daily notes.
+    pub daily_from: Option<NaiveDate>,
+    pub daily_to: Option<NaiveDate>,
+    /// `Some(false)` leaves archived entries out, `Some(true)` lists only
+    /// those, `None` both.
+    pub archived: Option<bool>,
+    /// Same as `archived`, for pinned entries.
+    pub pinned: Option<bool>,
+    pub sort: DiarySort,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl Default for DiaryFilter {
+    fn default() -> Self {
+        Self {
+            tags: Vec::new(),
+            tag_match: TagMatch::All,
+            text: String::new(),
+            properties: Vec::new(),
+            created_from: None,
+            created_to: None,
+            daily_from: None,
+            daily_to: None,
+            archived: Some(false),
+            pinned: None,
+            sort: DiarySort::UpdatedDesc,
+            offset: 0,
+            limit: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiarySort {
+    #[default]
+    UpdatedDesc,
+    UpdatedAsc,
+    CreatedDesc,
+    CreatedAsc,
+    /// By title, ignoring case and accents. Titles are encrypted, so this
+    /// sorts every match in memory before paging.
+    Title,
+    /// The manual pinned order, then most recently updated.
+    Pinned,
+    /// Daily notes by date, oldest first, then other entries.
+    DailyDate,
+}
+
+/// A condition on the value of entry property `key`. Entries without the
+/// property never match.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropertyPredicate {
+    pub key: String,
+    pub op: PropertyOp,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PropertyOp {
+    /// Exactly equal JSON values.
+    Eq,
+    /// Numbers compare numerically and strings lexically, so ISO dates
+    /// work too; other values never match.
+    Gt,
+    Lt,
+    /// A string containing the value, ignoring case and accents, or an
+    /// array with an element equal to it.
+    Contains,
+}
+
+impl PropertyPredicate {
+    pub fn matches(&self, actual: &Value) -> bool {
+        match self.op {
+            PropertyOp::Eq => *actual == self.value,
+            PropertyOp::Gt => compare(actual, &self.value) == Some(Ordering::Greater),
+            PropertyOp::Lt => compare(actual, &self.value) == Some(Ordering::Less),
+            PropertyOp::Contains => match (actual, &self.value) {
+                (Value::String(text), Value::String(part)) => normalize::fold(text).contains(&normalize::fold(part)),
+                (Value::Array(items), wanted) => items.contains(wanted),
+                _ => false,
+            },
+        }
+    }
+}
+
+fn compare(actual: &Value, wanted: &Value) -> Option<Ordering> {
+    match (actual, wanted) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// SQL and parameters selecting the entries that pass the filters SQL can
+/// check: tags, archived and pinned state, and which properties are set.
+/// Text, dates and property values are encrypted or stored in mixed
+/// formats, so the caller checks those on each row. Every value from the
+/// filter is bound as a parameter, never written into the SQL.
+///
+/// Rows hold id, title, created_at, updated_at, last_viewed_at,
+/// word_count, content and locked, then one encrypted value per property
+/// predicate from `PROPERTY_COLUMN`.
+pub fn build_sql(filter: &DiaryFilter) -> (String, Vec<SqlValue>) {
+    let mut params = Vec::new();
+    let mut bind = |value: String| {
+        params.push(SqlValue::Text(value));
+        format!("?{}", params.len())
+    };
+
+    let mut columns = String::from(
+        "e.id, e.title, e.created_at, e.updated_at, e.last_viewed_at, e.word_count, e.content, e.locked",
+    );
+    let mut conditions = Vec::new();
+    for predicate in &filter.properties {
+        let property = format!(
+            "(SELECT p.value FROM diary_properties p WHERE p.diary_id = e.id AND p.key = {})",
+            bind(predicate.key.clone())
+        );
+        columns.push_str(", ");
+        columns.push_str(&property);
+        conditions.push(format!("{} IS NOT NULL", property));
+    }
+
+    if let Some(from) = filter.daily_from {
+        conditions.push(format!("e.daily_date >= {}", bind(from.format("%Y-%m-%d").to_string())));
+    }
+    if let Some(to) = filter.daily_to {
+        conditions.push(format!("e.daily_date <= {}", bind(to.format("%Y-%m-%d").to_string())));
+    }
+
+    match filter.archived {
+        Some(true) => conditions.push("e.archived_at IS NOT NULL".to_string()),
+        Some(false) => conditions.push("e.archived_at IS NULL".to_string()),
+        None => {}
+    }
+    match filter.pinned {
+        Some(true) => conditions.push("e.pinned_at IS NOT NULL".to_string()),
+        Some(false) => conditions.push("e.pinned_at IS NULL".to_string()),
+        None => {}
+    }
+
+    let mut tag_keys: Vec<String> = filter.tags.iter().map(|tag| normalize::fold(tag.trim())).collect();
+    tag_keys.sort_unstable();
+    tag_keys.dedup();
+    let tag_exists = "EXISTS (SELECT 1 FROM diary_tags dt JOIN tags t ON dt.tag_id = t.id
+                      WHERE dt.diary_id = e.id AND t.name_key";
+    match filter.tag_match {
+        TagMatch::All => {
+            for key in tag_keys {
+                conditions.push(format!("{} = {})", tag_exists, bind(key)));
+            }
+        }
+        TagMatch::Any if !tag_keys.is_empty() => {
+            let placeholders: Vec<String> = tag_keys.into_iter().map(&mut bind).collect();
+            conditions.push(format!("{} IN ({}))", tag_exists, placeholders.join(", ")));
+        }
+        TagMatch::Any => {}
+    }
+
+    let order = match filter.sort {
+        DiarySort::UpdatedDesc | DiarySort::Title => "e.updated_at DESC",
+        DiarySort::UpdatedAsc => "e.updated_at",
+        DiarySort::CreatedDesc => "e.created_at DESC",
+        DiarySort::CreatedAsc => "e.created_at",
+        DiarySort::Pinned => "e.sort_order IS NULL, e.sort_order, e.updated_at DESC",
+        DiarySort::DailyDate => "e.daily_date IS NULL, e.daily_date",
+    };
+
+    let mut sql = format!("SELECT {} FROM diary_entries e", columns);
+    if !conditions.is_empty() {
+        sql.push_str(" WHERE ");
+        sql.push_str(&conditions.join(" AND "));
+    }
+    sql.push_str(" ORDER BY ");
+    sql.push_str(order);
+    sql.push_str(", e.id");
+
+    (sql, params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_filter_values_are_bound_not_interpolated() {
+        let filter = DiaryFilter {
+            tags: vec!["work'; DROP TABLE tags; --".to_string(), "Café".to_string()],
+            tag_match: TagMatch::Any,
+            properties: vec![PropertyPredicate { key: "mood') OR 1=1".to_string(), op: PropertyOp::Eq, value: json!(1) }],
+            daily_to: NaiveDate::from_ymd_opt(2024, 3, 31),
+            archived: None,
+            pinned: Some(true),
+            ..DiaryFilter::default()
+        };
+        let (sql, params) = build_sql(&filter);
+        assert!(!sql.contains("DROP") && !sql.contains("mood") && !sql.contains("cafe"));
+        assert!(sql.contains("e.pinned_at IS NOT NULL") && !sql.contains("archived_at"));
+        assert!(!sql.contains("2024"));
+        assert_eq!(params.len(), 4);
+        assert_eq!(params[0], SqlValue::Text("mood') OR 1=1".to_string()));
+        assert!(params.contains(&SqlValue::Text("cafe".to_string())));
+    }
+
+    #[test]
+    fn test_property_ops() {
+        let predicate = |op, value| PropertyPredicate { key: "k".to_string(), op, value };
+        assert!(predicate(PropertyOp::Eq, json!(4)).matches(&json!(4)));
+        assert!(!predicate(PropertyOp::Eq, json!("4")).matches(&json!(4)));
+        assert!(predicate(PropertyOp::Gt, json!(3)).matches(&json!(4.5)));
+        assert!(!predicate(PropertyOp::Gt, json!(4)).matches(&json!(4)));
+        assert!(predicate(PropertyOp::Lt, json!("2024-04-01")).matches(&json!("2024-03-15")));
+        assert!(!predicate(PropertyOp::Lt, json!(5)).matches(&json!("3")));
+        assert!(predicate(PropertyOp::Contains, json!("CAFE")).matches(&json!("Le café")));
+        assert!(predicate(PropertyOp::Contains, json!("b")).matches(&json!(["a", "b"])));
+        assert!(!predicate(PropertyOp::Contains, json!(1)).matches(&json!(12)));
+    }
+}
@@ -0,0 +1,205 @@
+use crate::database::{GraphData, GraphEdgeType};
+use crate::error::{DiaryError, DiaryResult};
+use std::fmt::Write;
+
+/// File formats the knowledge graph can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    GraphMl,
+    Dot,
+}
+
+impl GraphFormat {
+    pub fn parse(format: &str) -> DiaryResult<Self> {
+        match format.trim().to_lowercase().as_str() {
+            "graphml" => Ok(GraphFormat::GraphMl),
+            "dot" | "gv" => Ok(GraphFormat::Dot),
+            other => Err(DiaryError::Validation(format!(
+                "Unsupported graph export format '{}'; expected 'graphml' or 'dot'",
+                other
+            ))),
+        }
+    }
+}
+
+pub fn render(graph: &GraphData, format: GraphFormat) -> String {
+    match format {
+        GraphFormat::GraphMl => to_graphml(graph),
+        GraphFormat::Dot => to_dot(graph),
+    }
+}
+
+fn edge_type_name(edge_type: GraphEdgeType) -> &'static str {
+    match edge_type {
+        GraphEdgeType::Relationship => "relationship",
+        GraphEdgeType::Tag => "tag",
+        GraphEdgeType::TagCooccurrence => "tag_cooccurrence",
+    }
+}
+
+fn created_at(properties: &serde_json::Value) -> Option<&str> {
+    properties.get("created_at").and_then(|value| value.as_str())
+}
+
+/// Escapes text for XML content and attribute values. Characters XML 1.0
+/// cannot represent at all are dropped.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 || c == '\u{FFFE}' || c == '\u{FFFF}' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Quotes text as a DOT string, so ids with hyphens or spaces stay valid.
+fn quote_dot(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => {}
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn to_graphml(graph: &GraphData) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    out.push_str("  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n");
+    out.push_str("  <key id=\"node_type\" for=\"node\" attr.name=\"node_type\" attr.type=\"string\"/>\n");
+    out.push_str("  <key id=\"created_at\" for=\"node\" attr.name=\"created_at\" attr.type=\"string\"/>\n");
+    out.push_str("  <key id=\"edge_label\" for=\"edge\" attr.name=\"label\" attr.type=\"string\"/>\n");
+    out.push_str("  <key id=\"edge_type\" for=\"edge\" attr.name=\"edge_type\" attr.type=\"string\"/>\n");
+    out.push_str("  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"double\"/>\n");
+    out.push_str("  <graph id=\"secondbrain\" edgedefault=\"directed\">\n");
+
+    for node in &graph.nodes {
+        let _ = writeln!(out, "    <node id=\"{}\">", escape_xml(&node.id));
+        let _ = writeln!(out, "      <data key=\"label\">{}</data>", escape_xml(&node.label));
+        let _ = writeln!(out, "      <data key=\"node_type\">{}</data>", escape_xml(&node.node_type));
+        if let Some(created_at) = created_at(&node.properties) {
+            let _ = writeln!(out, "      <data key=\"created_at\">{}</data>", escape_xml(created_at));
+        }
+        out.push_str("    </node>\n");
+    }
+
+    for edge in &graph.edges {
+        let _ = writeln!(
+            out,
+            "    <edge id=\"{}\" source=\"{}\" target=\"{}\">",
+            escape_xml(&edge.id),
+            escape_xml(&edge.source),
+            escape_xml(&edge.target)
+        );
+        let _ = writeln!(out, "      <data key=\"edge_label\">{}</data>", escape_xml(&edge.label));
+        let _ = writeln!(out, "      <data key=\"edge_type\">{}</data>", edge_type_name(edge.edge_type));
+        let _ = writeln!(out, "      <data key=\"weight\">{}</data>", edge.weight);
+        out.push_str("    </edge>\n");
+    }
+
+    out.push_str("  </graph>\n");
+    out.push_str("</graphml>\n");
+    out
+}
+
+fn to_dot(graph: &GraphData) -> String {
+    let mut out = String::from("digraph secondbrain {\n");
+
+    for node in &graph.nodes {
+        let _ = write!(
+            out,
+            "  {} [label={}, node_type={}",
+            quote_dot(&node.id),
+            quote_dot(&node.label),
+            quote_dot(&node.node_type)
+        );
+        if let Some(created_at) = created_at(&node.properties) {
+            let _ = write!(out, ", created_at={}", quote_dot(created_at));
+        }
+        out.push_str("];\n");
+    }
+
+    for edge in &graph.edges {
+        let _ = writeln!(
+            out,
+            "  {} -> {} [label={}, edge_type={}, weight={}];",
+            quote_dot(&edge.source),
+            quote_dot(&edge.target),
+            quote_dot(&edge.label),
+            quote_dot(edge_type_name(edge.edge_type)),
+            edge.weight
+        );
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{GraphEdge, GraphNode, GRAPH_SCHEMA_VERSION};
+
+    fn sample_graph() -> GraphData {
+        GraphData {
+            version: GRAPH_SCHEMA_VERSION,
+            nodes: vec![
+                GraphNode {
+                    id: "diary-1".to_string(),
+                    label: "Fish & <Chips> \"quoted\"".to_string(),
+                    node_type: "diary".to_string(),
+                    properties: serde_json::json!({ "created_at": "2024-01-01T00:00:00Z" }),
+                },
+                GraphNode {
+                    id: "tag-rust".to_string(),
+                    label: "rust".to_string(),
+                    node_type: "tag".to_string(),
+                    properties: serde_json::json!({ "name": "rust" }),
+                },
+            ],
+            edges: vec![GraphEdge {
+                id: "tag-diary-1-rust".to_string(),
+                source: "diary-1".to_string(),
+                target: "tag-rust".to_string(),
+                label: "tagged".to_string(),
+                weight: 1.0,
+                edge_type: GraphEdgeType::Tag,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_graphml_escapes_titles() {
+        let xml = render(&sample_graph(), GraphFormat::GraphMl);
+        assert!(xml.contains("<data key=\"label\">Fish &amp; &lt;Chips&gt; &quot;quoted&quot;</data>"));
+        assert!(xml.contains("<data key=\"created_at\">2024-01-01T00:00:00Z</data>"));
+        assert!(xml.contains("<edge id=\"tag-diary-1-rust\" source=\"diary-1\" target=\"tag-rust\">"));
+        assert_eq!(escape_xml("a\u{1}b"), "ab");
+    }
+
+    #[test]
+    fn test_dot_quotes_ids() {
+        let dot = render(&sample_graph(), GraphFormat::Dot);
+        assert!(dot.starts_with("digraph secondbrain {"));
+        assert!(dot.contains("\"diary-1\" [label=\"Fish & <Chips> \\\"quoted\\\"\", node_type=\"diary\", created_at=\"2024-01-01T00:00:00Z\"];"));
+        assert!(dot.contains("\"diary-1\" -> \"tag-rust\" [label=\"tagged\", edge_type=\"tag\", weight=1];"));
+        assert_eq!(GraphFormat::parse(" GraphML ").unwrap(), GraphFormat::GraphMl);
+        assert!(GraphFormat::parse("csv").is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::io::{self, Write};
+
+/// Identifies a journal export in its first line.
+const FORMAT: &str = "secondbrain-journal";
+/// Version 2 added entry state and manual relationships; version 1 files
+/// still read, with those fields left at their defaults.
+const VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+}
+
+/// One entry of a journal export. The file is JSON Lines: a header line,
+/// then one record per line, so both directions hold a single entry in
+/// memory at a time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalRecord {
+    pub id: String,
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub properties: Map<String, Value>,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub archived_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub pinned_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub sort_order: Option<i64>,
+    #[serde(default)]
+    pub daily_date: Option<NaiveDate>,
+    /// Manual relationships from this entry, by the journal ids of their
+    /// children. Wikilink relationships are rebuilt from the content.
+    #[serde(default)]
+    pub relationships: Vec<JournalRelationship>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalRelationship {
+    pub child_id: String,
+    pub relationship_type: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    pub weight: f64,
+    pub created_at: DateTime<Utc>,
+}
+
+pub fn write_header<W: Write>(out: &mut W) -> io::Result<()> {
+    let header = Header { format: FORMAT.to_string(), version: VERSION };
+    serde_json::to_writer(&mut *out, &header)?;
+    out.write_all(b"\n")
+}
+
+pub fn write_record<W: Write>(out: &mut W, record: &JournalRecord) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, record)?;
+    out.write_all(b"\n")
+}
+
+/// Checks the first line of a file is a journal header this version reads.
+pub fn check_header(line: &str) -> Result<(), String> {
+    let header: Header = serde_json::from_str(line).map_err(|_| "Not a journal export".to_string())?;
+    if header.format != FORMAT {
+        return Err("Not a journal export".to_string());
+    }
+    if header.version > VERSION {
+        return Err(format!("Journal export version {} is newer than this app supports", header.version));
+    }
+    Ok(())
+}
+
+pub fn parse_record(line: &str) -> Result<JournalRecord, String> {
+    serde_json::from_str(line).map_err(|e| format!("Invalid record: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_round_trips_on_one_line() {
+        let record = JournalRecord {
+            id: "1".to_string(),
+            title: "Multi\nline".to_string(),
+            content: "first\nsecond \"quoted\"".to_string(),
+            tags: vec!["a".to_string()],
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            properties: Map::from_iter([("mood".to_string(), Value::from(3))]),
+            locked: true,
+            archived_at: None,
+            pinned_at: Some(Utc::now()),
+            sort_order: Some(2),
+            daily_date: NaiveDate::from_ymd_opt(2024, 3, 1),
+            relationships: vec![JournalRelationship {
+                child_id: "2".to_string(),
+                relationship_type: "depends_on".to_string(),
+                note: Some("first".to_string()),
+                weight: 2.5,
+                created_at: Utc::now(),
+            }],
+        };
+        let mut out = Vec::new();
+        write_header(&mut out).unwrap();
+        write_record(&mut out, &record).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(check_header(lines[0]).is_ok());
+        assert_eq!(parse_record(lines[1]).unwrap(), record);
+    }
+
+    #[test]
+    fn test_header_is_checked() {
+        assert!(check_header(r#"{"entries": []}"#).is_err());
+        assert!(check_header(r#"{"format": "other", "version": 1}"#).is_err());
+        assert!(check_header(r#"{"format": "secondbrain-journal", "version": 3}"#).is_err());
+        assert!(check_header(r#"{"format": "secondbrain-journal", "version": 1}"#).is_ok());
+        assert!(parse_record(r#"{"id": "1"}"#).is_err());
+    }
+}
@@ -1,20 +1,205 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod auto_lock;
 mod crypto;
+mod csv_export;
 mod database;
+mod day_one;
+mod error;
+mod filter;
+mod graph_export;
+mod journal;
+mod normalize;
+mod operations;
+mod review;
+mod search;
+mod text_diff;
+mod wikilinks;
 
-use database::{DiaryDB, DiaryEntry, GraphData, Relationship};
+use auto_lock::AutoLock;
+use crypto::KeyStorageBackend;
+use database::{
+    Attachment, AttachmentData, AuditRecord, Backlink, BulkItemResult, BulkStatus, CsvExporter,
+    DatabaseInfo, DayActivity, DayOneImporter, DiaryDB, DiaryEntry, DiarySummary, DiaryVersion,
+    Draft, DueReminder, GraphData, GraphFilter, GraphPath, JournalExporter, JournalImporter,
+    LinkedRelationship, MaintenanceReport, NewDiary, RelatedEntry, Relationship,
+    RelationshipTypeCount, Reminder, SavedSearch, SearchQuery, SearchResult, Statistics, Subgraph,
+    TagCooccurrence, TagUsage, Template, TitleMatch, VaultReport, VersionDiff, ViewedDiary,
+    WORD_COUNT_BACKFILL_BATCH,
+};
+use error::{DiaryError, DiaryResult};
+use filter::DiaryFilter;
+use operations::{Batched, Operations, Progress};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
 use std::sync::Mutex;
-use tauri::State;
-use uuid::Uuid;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State, WebviewUrl, WebviewWindowBuilder};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
+
+/// Label of the quick-capture window and the shortcut that opens it.
+const CAPTURE_WINDOW: &str = "capture";
+const CAPTURE_SHORTCUT: &str = "CommandOrControl+Shift+Space";
 
 struct AppState {
     db: Mutex<DiaryDB>,
+    auto_lock: Mutex<AutoLock>,
+    operations: Operations,
+}
+
+#[derive(Clone, Serialize)]
+struct KeyRotationProgress {
+    done: usize,
+    total: usize,
+}
+
+/// Payload of `diary-saved`, emitted after an entry is created or updated.
+#[derive(Clone, Serialize)]
+struct DiarySaved {
+    id: String,
+    title: String,
+}
+
+/// Payload of `diary-deleted`.
+#[derive(Clone, Serialize)]
+struct DiaryDeleted {
+    id: String,
+}
+
+/// Payload of `relationship-added`.
+#[derive(Clone, Serialize)]
+struct RelationshipAdded {
+    id: String,
+    parent_id: String,
+    child_id: String,
+    relationship_type: String,
+}
+
+/// Payload of `relationship-deleted`.
+#[derive(Clone, Serialize)]
+struct RelationshipDeleted {
+    id: String,
+}
+
+/// Payload of `relationship-changed`: relationships were edited, retyped
+/// or re-synced from wikilinks. `diary_ids` are the entries involved, or
+/// empty when a relationship type was renamed across the vault.
+#[derive(Clone, Serialize)]
+struct RelationshipChanged {
+    diary_ids: Vec<String>,
+}
+
+/// Payload of `tag-changed`: the entries whose tags changed and the tags
+/// involved.
+#[derive(Clone, Serialize)]
+struct TagChanged {
+    diary_ids: Vec<String>,
+    tags: Vec<String>,
+}
+
+/// Payload of `operation-progress`, emitted between batches of a
+/// background operation.
+#[derive(Clone, Serialize)]
+struct OperationProgress {
+    operation_id: String,
+    phase: String,
+    done: usize,
+    total: usize,
+}
+
+/// Payload of `operation-complete`.
+#[derive(Clone, Serialize)]
+struct OperationComplete<T> {
+    operation_id: String,
+    result: T,
+}
+
+/// Payload of `operation-failed`.
+#[derive(Clone, Serialize)]
+struct OperationFailed {
+    operation_id: String,
+    error: String,
+    cancelled: bool,
+}
+
+/// Runs `work` on a background thread as operation `operation_id`, which
+/// `cancel_operation` can stop between batches. Progress and the outcome
+/// are reported through `operation-*` events instead of the return value.
+fn spawn_operation<T, F>(app: AppHandle, operation_id: String, work: F) -> Result<(), String>
+where
+    T: Serialize + Clone,
+    F: FnOnce(&AppHandle, &mut Progress) -> DiaryResult<T> + Send + 'static,
+{
+    let cancelled = app.state::<AppState>().operations.start(&operation_id).map_err(|e| e.to_string())?;
+    std::thread::spawn(move || {
+        let result = {
+            let mut progress = Progress::new(cancelled, |phase: &str, done, total| {
+                let _ = app.emit(
+                    "operation-progress",
+                    OperationProgress { operation_id: operation_id.clone(), phase: phase.to_string(), done, total },
+                );
+            });
+            work(&app, &mut progress)
+        };
+        app.state::<AppState>().operations.finish(&operation_id);
+        match result {
+            Ok(result) => {
+                let _ = app.emit("operation-complete", OperationComplete { operation_id, result });
+            }
+            Err(e) => {
+                println!("⚠️ [OPERATION] {} failed: {}", operation_id, e);
+                let cancelled = matches!(e, DiaryError::Cancelled);
+                let _ = app.emit("operation-failed", OperationFailed { operation_id, error: e.to_string(), cancelled });
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Runs `operation` a batch at a time, taking the database lock for each
+/// batch only, as the word count backfill does.
+fn run_batched<B: Batched>(app: &AppHandle, mut operation: B, progress: &mut Progress) -> DiaryResult<B::Output> {
+    loop {
+        let state = app.state::<AppState>();
+        let step = operation.step(&state.db.lock().unwrap(), progress)?;
+        if let Some(output) = step {
+            return Ok(output);
+        }
+    }
+}
+
+fn emit_diary_saved(app: &AppHandle, id: &str, title: &str) {
+    let _ = app.emit("diary-saved", DiarySaved { id: id.to_string(), title: title.to_string() });
+}
+
+fn emit_diary_deleted(app: &AppHandle, id: &str) {
+    let _ = app.emit("diary-deleted", DiaryDeleted { id: id.to_string() });
+}
+
+fn emit_relationship_changed(app: &AppHandle, diary_ids: Vec<String>) {
+    let _ = app.emit("relationship-changed", RelationshipChanged { diary_ids });
+}
+
+fn emit_tag_changed(app: &AppHandle, diary_ids: Vec<String>, tags: Vec<String>) {
+    if !diary_ids.is_empty() {
+        let _ = app.emit("tag-changed", TagChanged { diary_ids, tags });
+    }
+}
+
+/// Ids of the items a bulk operation actually changed.
+fn succeeded_ids(results: &[BulkItemResult]) -> Vec<String> {
+    results
+        .iter()
+        .filter(|result| result.status == BulkStatus::Succeeded)
+        .map(|result| result.id.clone())
+        .collect()
 }
 
 #[tauri::command]
 fn save_diary(
+    app: AppHandle,
     state: State<AppState>,
     id: Option<String>,
     title: String,
@@ -22,10 +207,191 @@ fn save_diary(
     tags: Vec<String>,
 ) -> Result<String, String> {
     let db = state.db.lock().unwrap();
-    db.save_diary(id.as_deref(), &title, &content, &tags)
+    let saved = db
+        .save_diary_from_editor(id.as_deref(), &title, &content, &tags)
+        .map_err(|e| e.to_string())?;
+    emit_diary_saved(&app, &saved.id, &title);
+    if saved.tags_changed {
+        emit_tag_changed(&app, vec![saved.id.clone()], tags);
+    }
+    if saved.links_changed {
+        emit_relationship_changed(&app, vec![saved.id.clone()]);
+    }
+    Ok(saved.id)
+}
+
+#[tauri::command]
+fn save_diaries_batch(app: AppHandle, state: State<AppState>, entries: Vec<NewDiary>) -> Result<Vec<String>, String> {
+    let db = state.db.lock().unwrap();
+    let ids = db.save_diaries_batch(&entries).map_err(|e| e.to_string())?;
+    let mut linked = Vec::new();
+    for (id, entry) in ids.iter().zip(&entries) {
+        emit_diary_saved(&app, id, &entry.title);
+        if !entry.tags.is_empty() {
+            emit_tag_changed(&app, vec![id.clone()], entry.tags.clone());
+        }
+        if !wikilinks::extract_wikilinks(&entry.content).is_empty() {
+            linked.push(id.clone());
+        }
+    }
+    if !linked.is_empty() {
+        emit_relationship_changed(&app, linked);
+    }
+    Ok(ids)
+}
+
+#[tauri::command]
+fn rename_diary(
+    app: AppHandle,
+    state: State<AppState>,
+    id: String,
+    new_title: String,
+    update_links: bool,
+) -> Result<Vec<TitleMatch>, String> {
+    let db = state.db.lock().unwrap();
+    let rewritten = db
+        .rename_diary(&id, &new_title, update_links)
+        .map_err(|e| e.to_string())?;
+    emit_diary_saved(&app, &id, &new_title);
+    for entry in &rewritten {
+        emit_diary_saved(&app, &entry.id, &entry.title);
+    }
+    Ok(rewritten)
+}
+
+/// Starts a Day One import as background operation `operation_id`; the
+/// `ImportReport` arrives with `operation-complete`.
+#[tauri::command]
+fn import_day_one(
+    app: AppHandle,
+    operation_id: String,
+    path: String,
+    dry_run: Option<bool>,
+    update_duplicates: Option<bool>,
+) -> Result<(), String> {
+    spawn_operation(app, operation_id, move |app, progress| {
+        let import = DayOneImporter::new(Path::new(&path), dry_run.unwrap_or(false), update_duplicates.unwrap_or(false))?;
+        let report = run_batched(app, import, progress)?;
+        for entry in &report.imported {
+            if let Some(id) = &entry.id {
+                emit_diary_saved(app, id, &entry.title);
+            }
+        }
+        Ok(report)
+    })
+}
+
+#[tauri::command]
+fn find_duplicate_diaries(state: State<AppState>) -> Result<Vec<Vec<DiarySummary>>, String> {
+    let db = state.db.lock().unwrap();
+    db.find_duplicate_diaries().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_entry_encrypted(state: State<AppState>, id: String, path: String, password: String) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.export_entry_encrypted(&id, Path::new(&path), &password)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_encrypted_entry(
+    app: AppHandle,
+    state: State<AppState>,
+    path: String,
+    password: String,
+) -> Result<String, String> {
+    let db = state.db.lock().unwrap();
+    let entry = db
+        .import_encrypted_entry(Path::new(&path), &password)
+        .map_err(|e| e.to_string())?;
+    emit_diary_saved(&app, &entry.id, &entry.title);
+    Ok(entry.id)
+}
+
+#[tauri::command]
+fn quick_capture(app: AppHandle, state: State<AppState>, text: String) -> Result<String, String> {
+    let db = state.db.lock().unwrap();
+    let inbox = db.quick_capture(&text).map_err(|e| e.to_string())?;
+    emit_diary_saved(&app, &inbox.id, &inbox.title);
+    Ok(inbox.id)
+}
+
+/// Shows the quick-capture window, creating it the first time.
+fn show_capture_window(app: &AppHandle) {
+    let window = match app.get_webview_window(CAPTURE_WINDOW) {
+        Some(window) => window,
+        None => {
+            let built = WebviewWindowBuilder::new(app, CAPTURE_WINDOW, WebviewUrl::App("capture".into()))
+                .title("Quick capture")
+                .inner_size(480.0, 140.0)
+                .resizable(false)
+                .always_on_top(true)
+                .skip_taskbar(true)
+                .center()
+                .build();
+            match built {
+                Ok(window) => window,
+                Err(e) => {
+                    println!("⚠️ [CAPTURE] Could not open capture window: {}", e);
+                    return;
+                }
+            }
+        }
+    };
+    let _ = window.show();
+    let _ = window.set_focus();
+}
+
+#[tauri::command]
+fn list_diary_versions(state: State<AppState>, id: String) -> Result<Vec<DiaryVersion>, String> {
+    let db = state.db.lock().unwrap();
+    db.list_diary_versions(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn diff_diary_versions(
+    state: State<AppState>,
+    id: String,
+    from_version: String,
+    to_version: String,
+) -> Result<VersionDiff, String> {
+    let db = state.db.lock().unwrap();
+    db.diff_diary_versions(&id, &from_version, &to_version)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn save_draft(
+    state: State<AppState>,
+    diary_id: Option<String>,
+    title: String,
+    content: String,
+    tags: Vec<String>,
+) -> Result<Draft, String> {
+    let db = state.db.lock().unwrap();
+    db.save_draft(diary_id.as_deref(), &title, &content, &tags)
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn get_draft(state: State<AppState>, diary_id: Option<String>) -> Result<Option<Draft>, String> {
+    let db = state.db.lock().unwrap();
+    db.get_draft(diary_id.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn discard_draft(state: State<AppState>, diary_id: Option<String>) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.discard_draft(diary_id.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_unsaved_drafts(state: State<AppState>) -> Result<Vec<Draft>, String> {
+    let db = state.db.lock().unwrap();
+    db.list_unsaved_drafts().map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn get_diary(state: State<AppState>, id: String) -> Result<DiaryEntry, String> {
     let db = state.db.lock().unwrap();
@@ -38,6 +404,136 @@ fn list_diaries(state: State<AppState>) -> Result<Vec<DiaryEntry>, String> {
     db.list_diaries().map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn list_recent_diaries(state: State<AppState>, limit: Option<usize>, by: String) -> Result<Vec<DiarySummary>, String> {
+    let db = state.db.lock().unwrap();
+    db.list_recent_diaries(limit.unwrap_or(10), &by).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_on_this_day(state: State<AppState>) -> Result<Vec<DiarySummary>, String> {
+    let db = state.db.lock().unwrap();
+    db.get_on_this_day().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_stale_diaries(state: State<AppState>, days: u32, limit: Option<usize>) -> Result<Vec<DiarySummary>, String> {
+    let db = state.db.lock().unwrap();
+    db.list_stale_diaries(days, limit.unwrap_or(10)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_or_create_daily_note(app: AppHandle, state: State<AppState>, date: Option<String>) -> Result<DiaryEntry, String> {
+    let db = state.db.lock().unwrap();
+    let (note, created) = db.get_or_create_daily_note(date.as_deref()).map_err(|e| e.to_string())?;
+    if created {
+        emit_diary_saved(&app, &note.id, &note.title);
+        emit_tag_changed(&app, vec![note.id.clone()], note.tags.clone());
+    }
+    Ok(note)
+}
+
+#[tauri::command]
+fn list_daily_notes(state: State<AppState>, from: String, to: String) -> Result<Vec<DiarySummary>, String> {
+    let db = state.db.lock().unwrap();
+    db.list_daily_notes(&from, &to).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn generate_review(app: AppHandle, state: State<AppState>, from: String, to: String) -> Result<String, String> {
+    let db = state.db.lock().unwrap();
+    let review = db.generate_review(&from, &to).map_err(|e| e.to_string())?;
+    emit_diary_saved(&app, &review.id, &review.title);
+    Ok(review.id)
+}
+
+#[tauri::command]
+fn find_diary_by_title(state: State<AppState>, title: String) -> Result<Vec<String>, String> {
+    let db = state.db.lock().unwrap();
+    db.find_diary_by_title(&title).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn search_titles(state: State<AppState>, prefix: String, limit: Option<usize>) -> Result<Vec<TitleMatch>, String> {
+    let db = state.db.lock().unwrap();
+    db.search_titles(&prefix, limit.unwrap_or(20)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn search_diaries(state: State<AppState>, query: String, limit: Option<usize>) -> Result<Vec<SearchResult>, String> {
+    let db = state.db.lock().unwrap();
+    db.search_diaries(&query, limit.unwrap_or(50)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_diary_property(
+    state: State<AppState>,
+    diary_id: String,
+    key: String,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.set_diary_property(&diary_id, &key, &value).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_diary_property(state: State<AppState>, diary_id: String, key: String) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.delete_diary_property(&diary_id, &key).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn query_diaries_by_property(
+    state: State<AppState>,
+    key: String,
+    value: serde_json::Value,
+) -> Result<Vec<DiaryEntry>, String> {
+    let db = state.db.lock().unwrap();
+    db.query_diaries_by_property(&key, &value).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn query_diaries(state: State<AppState>, filter: DiaryFilter) -> Result<Vec<DiarySummary>, String> {
+    let db = state.db.lock().unwrap();
+    db.query_diaries(&filter).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pin_diary(state: State<AppState>, id: String) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.pin_diary(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn unpin_diary(state: State<AppState>, id: String) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.unpin_diary(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn archive_diary(state: State<AppState>, id: String) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.archive_diary(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn unarchive_diary(state: State<AppState>, id: String) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.unarchive_diary(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_pinned_diaries(state: State<AppState>) -> Result<Vec<DiarySummary>, String> {
+    let db = state.db.lock().unwrap();
+    db.list_pinned_diaries().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn reorder_pinned_diaries(state: State<AppState>, ids_in_order: Vec<String>) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.reorder_pinned_diaries(&ids_in_order).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn search_diaries_by_tag(state: State<AppState>, tag: String) -> Result<Vec<DiaryEntry>, String> {
     let db = state.db.lock().unwrap();
@@ -45,13 +541,146 @@ fn search_diaries_by_tag(state: State<AppState>, tag: String) -> Result<Vec<Diar
 }
 
 #[tauri::command]
-fn get_graph_data(state: State<AppState>) -> Result<GraphData, String> {
+fn suggest_tags(state: State<AppState>, prefix: String, limit: Option<usize>) -> Result<Vec<TagUsage>, String> {
+    let db = state.db.lock().unwrap();
+    db.suggest_tags(&prefix, limit.unwrap_or(10)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_orphan_diaries(state: State<AppState>) -> Result<Vec<DiaryEntry>, String> {
+    let db = state.db.lock().unwrap();
+    db.list_orphan_diaries().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_statistics(state: State<AppState>) -> Result<Statistics, String> {
+    let db = state.db.lock().unwrap();
+    db.get_statistics().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_activity_heatmap(
+    state: State<AppState>,
+    from: String,
+    to: String,
+    utc_offset_minutes: Option<i32>,
+) -> Result<Vec<DayActivity>, String> {
+    let db = state.db.lock().unwrap();
+    db.get_activity_heatmap(&from, &to, utc_offset_minutes).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_graph_data(state: State<AppState>, filter: Option<GraphFilter>) -> Result<GraphData, String> {
+    let db = state.db.lock().unwrap();
+    db.get_graph_data(&filter.unwrap_or_default()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_graph(
+    state: State<AppState>,
+    path: String,
+    format: String,
+    filter: Option<GraphFilter>,
+) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.export_graph(Path::new(&path), &format, &filter.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+/// Starts a CSV export as background operation `operation_id`; the number
+/// of rows written arrives with `operation-complete`.
+#[tauri::command]
+fn export_csv(
+    app: AppHandle,
+    operation_id: String,
+    path: String,
+    include_archived: bool,
+    excel_bom: Option<bool>,
+) -> Result<(), String> {
+    spawn_operation(app, operation_id, move |app, progress| {
+        let export = CsvExporter::new(Path::new(&path), include_archived, excel_bom.unwrap_or(false))?;
+        run_batched(app, export, progress)
+    })
+}
+
+/// Starts a journal export as background operation `operation_id`; the
+/// `JournalExport` arrives with `operation-complete`.
+#[tauri::command]
+fn export_journal(app: AppHandle, operation_id: String, path: String) -> Result<(), String> {
+    spawn_operation(app, operation_id, move |app, progress| {
+        run_batched(app, JournalExporter::new(Path::new(&path))?, progress)
+    })
+}
+
+/// Starts a journal import as background operation `operation_id`; the
+/// `JournalImport` arrives with `operation-complete`.
+#[tauri::command]
+fn import_journal(app: AppHandle, operation_id: String, path: String) -> Result<(), String> {
+    spawn_operation(app, operation_id, move |app, progress| {
+        run_batched(app, JournalImporter::new(Path::new(&path))?, progress)
+    })
+}
+
+#[tauri::command]
+fn cancel_operation(state: State<AppState>, operation_id: String) -> bool {
+    state.operations.cancel(&operation_id)
+}
+
+#[tauri::command]
+fn list_most_viewed_diaries(
+    state: State<AppState>,
+    limit: Option<usize>,
+    since: Option<String>,
+) -> Result<Vec<ViewedDiary>, String> {
+    let db = state.db.lock().unwrap();
+    db.list_most_viewed_diaries(limit.unwrap_or(10), since.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn save_search(state: State<AppState>, name: String, query: SearchQuery) -> Result<SavedSearch, String> {
+    let db = state.db.lock().unwrap();
+    db.save_search(&name, &query).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_saved_searches(state: State<AppState>) -> Result<Vec<SavedSearch>, String> {
+    let db = state.db.lock().unwrap();
+    db.list_saved_searches().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_saved_search(state: State<AppState>, id: String) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.delete_saved_search(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn run_saved_search(state: State<AppState>, id: String) -> Result<Vec<DiarySummary>, String> {
+    let db = state.db.lock().unwrap();
+    db.run_saved_search(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_tag_cooccurrence(state: State<AppState>, min_count: u32) -> Result<Vec<TagCooccurrence>, String> {
+    let db = state.db.lock().unwrap();
+    db.get_tag_cooccurrence(min_count).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_subgraph(state: State<AppState>, center_id: String, depth: u32) -> Result<Subgraph, String> {
+    let db = state.db.lock().unwrap();
+    db.get_subgraph(&center_id, depth).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn find_path(state: State<AppState>, from_id: String, to_id: String) -> Result<GraphPath, String> {
     let db = state.db.lock().unwrap();
-    db.get_graph_data().map_err(|e| e.to_string())
+    db.find_path(&from_id, &to_id).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-fn delete_diary(state: State<AppState>, id: String) -> Result<(), String> {
+fn delete_diary(app: AppHandle, state: State<AppState>, id: String) -> Result<(), String> {
     println!("🚀 [TAURI] delete_diary command called with ID: {}", id);
     
     if id.is_empty() {
@@ -65,6 +694,7 @@ fn delete_diary(state: State<AppState>, id: String) -> Result<(), String> {
     match db.delete_diary(&id) {
         Ok(_) => {
             println!("✅ [TAURI] delete_diary succeeded for ID: {}", id);
+            emit_diary_deleted(&app, &id);
             Ok(())
         },
         Err(e) => {
@@ -74,82 +704,661 @@ fn delete_diary(state: State<AppState>, id: String) -> Result<(), String> {
     }
 }
 
+#[tauri::command]
+fn bulk_add_tag(
+    app: AppHandle,
+    state: State<AppState>,
+    ids: Vec<String>,
+    tag: String,
+) -> Result<Vec<BulkItemResult>, String> {
+    let db = state.db.lock().unwrap();
+    let results = db.bulk_add_tag(&ids, &tag).map_err(|e| e.to_string())?;
+    emit_tag_changed(&app, succeeded_ids(&results), vec![tag]);
+    Ok(results)
+}
+
+#[tauri::command]
+fn bulk_remove_tag(
+    app: AppHandle,
+    state: State<AppState>,
+    ids: Vec<String>,
+    tag: String,
+) -> Result<Vec<BulkItemResult>, String> {
+    let db = state.db.lock().unwrap();
+    let results = db.bulk_remove_tag(&ids, &tag).map_err(|e| e.to_string())?;
+    emit_tag_changed(&app, succeeded_ids(&results), vec![tag]);
+    Ok(results)
+}
+
+#[tauri::command]
+fn bulk_delete_diaries(app: AppHandle, state: State<AppState>, ids: Vec<String>) -> Result<Vec<BulkItemResult>, String> {
+    let db = state.db.lock().unwrap();
+    let results = db.bulk_delete_diaries(&ids).map_err(|e| e.to_string())?;
+    for id in succeeded_ids(&results) {
+        emit_diary_deleted(&app, &id);
+    }
+    Ok(results)
+}
+
 #[tauri::command]
 fn add_relationship(
+    app: AppHandle,
     state: State<AppState>,
-    id: Option<String>,
-    parent_id: Option<String>,
-    child_id: Option<String>,
+    parent_id: String,
+    child_id: String,
     relationship_type: Option<String>,
+    note: Option<String>,
+    weight: Option<f64>,
 ) -> Result<String, String> {
-    // Add debug logging
-    println!("Debug: add_relationship called with parameters:");
-    println!("  - id: '{:?}'", id);
-    println!("  - parent_id: '{:?}'", parent_id);
-    println!("  - child_id: '{:?}'", child_id);
-    println!("  - relationship_type: '{:?}'", relationship_type);
-    
-    // Check if all parameters are None, which suggests an accidental or unintended call
-    if parent_id.is_none() && child_id.is_none() && relationship_type.is_none() {
-        println!("Debug: Empty relationship call detected and rejected");
-        return Err("Empty relationship parameters - operation aborted".to_string());
-    }
-    
-    // Validate required parameters
-    let final_parent_id = parent_id.ok_or_else(|| "Parent ID is required".to_string())?;
-    let final_child_id = child_id.ok_or_else(|| "Child ID is required".to_string())?;
-    let final_relationship_type = relationship_type.unwrap_or_else(|| "depends_on".to_string());
-    let final_id = id.unwrap_or_else(|| Uuid::new_v4().to_string());
-    
-    // Validate parameters
-    if final_parent_id.is_empty() {
-        println!("Debug: parent_id is empty!");
-        return Err("Parent ID is required".to_string());
-    }
-    if final_child_id.is_empty() {
-        println!("Debug: child_id is empty!");
-        return Err("Child ID is required".to_string());
-    }
-    
+    let relationship_type = relationship_type.unwrap_or_else(|| "depends_on".to_string());
     let db = state.db.lock().unwrap();
-    db.add_relationship(&final_id, &final_parent_id, &final_child_id, &final_relationship_type)
-        .map_err(|e| {
-            println!("Debug: Error in add_relationship: {}", e);
-            e.to_string()
-        })
+    let id = db
+        .add_relationship(&parent_id, &child_id, &relationship_type, note.as_deref(), weight)
+        .map_err(|e| e.to_string())?;
+    let _ = app.emit(
+        "relationship-added",
+        RelationshipAdded { id: id.clone(), parent_id, child_id, relationship_type },
+    );
+    Ok(id)
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn update_relationship(
+    app: AppHandle,
+    state: State<AppState>,
+    id: String,
+    relationship_type: Option<String>,
+    parent_id: Option<String>,
+    child_id: Option<String>,
+    note: Option<String>,
+    weight: Option<f64>,
+) -> Result<Relationship, String> {
+    let db = state.db.lock().unwrap();
+    let relationship = db
+        .update_relationship(
+            &id,
+            relationship_type.as_deref(),
+            parent_id.as_deref(),
+            child_id.as_deref(),
+            note.as_deref(),
+            weight,
+        )
+        .map_err(|e| e.to_string())?;
+    emit_relationship_changed(&app, vec![relationship.parent_id.clone(), relationship.child_id.clone()]);
+    Ok(relationship)
 }
 
 #[tauri::command]
-fn delete_relationship(state: State<AppState>, id: String) -> Result<(), String> {
+fn delete_relationship(app: AppHandle, state: State<AppState>, id: String) -> Result<(), String> {
     let db = state.db.lock().unwrap();
-    db.delete_relationship(&id).map_err(|e| e.to_string())
+    db.delete_relationship(&id).map_err(|e| e.to_string())?;
+    let _ = app.emit("relationship-deleted", RelationshipDeleted { id });
+    Ok(())
 }
 
 #[tauri::command]
-fn get_relationships(state: State<AppState>, diary_id: String) -> Result<Vec<Relationship>, String> {
+fn get_relationships(state: State<AppState>, diary_id: String) -> Result<Vec<LinkedRelationship>, String> {
     let db = state.db.lock().unwrap();
     db.get_relationships(&diary_id).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn get_relationship_types(state: State<AppState>) -> Result<Vec<RelationshipTypeCount>, String> {
+    let db = state.db.lock().unwrap();
+    db.get_relationship_types().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn rename_relationship_type(app: AppHandle, state: State<AppState>, old: String, new: String) -> Result<usize, String> {
+    let db = state.db.lock().unwrap();
+    let renamed = db.rename_relationship_type(&old, &new).map_err(|e| e.to_string())?;
+    if renamed > 0 {
+        emit_relationship_changed(&app, Vec::new());
+    }
+    Ok(renamed)
+}
+
+#[tauri::command]
+fn get_backlinks(state: State<AppState>, diary_id: String) -> Result<Vec<Backlink>, String> {
+    let db = state.db.lock().unwrap();
+    db.get_backlinks(&diary_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_related_entries(
+    state: State<AppState>,
+    diary_id: String,
+    limit: Option<usize>,
+    exclude_linked: Option<bool>,
+) -> Result<Vec<RelatedEntry>, String> {
+    let db = state.db.lock().unwrap();
+    db.get_related_entries(&diary_id, limit.unwrap_or(10), exclude_linked.unwrap_or(false))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn sync_wikilinks(app: AppHandle, state: State<AppState>, diary_id: String) -> Result<Vec<String>, String> {
+    let db = state.db.lock().unwrap();
+    let unresolved = db.sync_wikilinks(&diary_id).map_err(|e| e.to_string())?;
+    emit_relationship_changed(&app, vec![diary_id]);
+    Ok(unresolved)
+}
+
+#[tauri::command]
+fn set_reminder(
+    state: State<AppState>,
+    diary_id: String,
+    remind_at: String,
+    message: String,
+) -> Result<Reminder, String> {
+    let db = state.db.lock().unwrap();
+    db.set_reminder(&diary_id, &remind_at, &message).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_due_reminders(state: State<AppState>) -> Result<Vec<DueReminder>, String> {
+    let db = state.db.lock().unwrap();
+    db.list_due_reminders().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn complete_reminder(state: State<AppState>, id: String) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.complete_reminder(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_reminders_for_diary(state: State<AppState>, diary_id: String) -> Result<Vec<Reminder>, String> {
+    let db = state.db.lock().unwrap();
+    db.list_reminders_for_diary(&diary_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn save_template(
+    state: State<AppState>,
+    id: Option<String>,
+    name: String,
+    content: String,
+    default_tags: Vec<String>,
+) -> Result<Template, String> {
+    let db = state.db.lock().unwrap();
+    db.save_template(id.as_deref(), &name, &content, &default_tags)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_templates(state: State<AppState>) -> Result<Vec<Template>, String> {
+    let db = state.db.lock().unwrap();
+    db.list_templates().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_template(state: State<AppState>, id: String) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.delete_template(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn create_diary_from_template(
+    app: AppHandle,
+    state: State<AppState>,
+    template_id: String,
+    title: String,
+) -> Result<DiaryEntry, String> {
+    let db = state.db.lock().unwrap();
+    let diary = db.create_diary_from_template(&template_id, &title).map_err(|e| e.to_string())?;
+    emit_diary_saved(&app, &diary.id, &diary.title);
+    emit_tag_changed(&app, vec![diary.id.clone()], diary.tags.clone());
+    Ok(diary)
+}
+
+#[tauri::command]
+fn add_attachment(state: State<AppState>, diary_id: String, path: String) -> Result<Attachment, String> {
+    let db = state.db.lock().unwrap();
+    db.add_attachment(&diary_id, Path::new(&path)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_attachment(state: State<AppState>, id: String) -> Result<AttachmentData, String> {
+    let db = state.db.lock().unwrap();
+    db.get_attachment(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_attachments(state: State<AppState>, diary_id: String) -> Result<Vec<Attachment>, String> {
+    let db = state.db.lock().unwrap();
+    db.list_attachments(&diary_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_attachment(state: State<AppState>, id: String) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.delete_attachment(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn rotate_encryption_key(app: AppHandle, state: State<AppState>) -> Result<usize, String> {
+    let mut db = state.db.lock().unwrap();
+    db.rotate_encryption_key(|done, total| {
+        let _ = app.emit("key-rotation-progress", KeyRotationProgress { done, total });
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn run_maintenance(state: State<AppState>) -> Result<MaintenanceReport, String> {
+    let db = state.db.lock().unwrap();
+    db.run_maintenance().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn verify_vault(state: State<AppState>) -> Result<VaultReport, String> {
+    let db = state.db.lock().unwrap();
+    db.verify_vault().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_verify_on_startup(state: State<AppState>) -> Result<bool, String> {
+    let db = state.db.lock().unwrap();
+    db.get_verify_on_startup().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_verify_on_startup(state: State<AppState>, enabled: bool) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.set_verify_on_startup(enabled).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_passphrase(state: State<AppState>, current: Option<String>, passphrase: String) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.set_passphrase(current.as_deref(), &passphrase).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn has_passphrase(state: State<AppState>) -> Result<bool, String> {
+    let db = state.db.lock().unwrap();
+    db.has_passphrase().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn verify_password(state: State<AppState>, password: String) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.verify_password(&password).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn is_elevated(state: State<AppState>) -> bool {
+    state.db.lock().unwrap().is_elevated()
+}
+
+#[tauri::command]
+fn set_diary_locked(state: State<AppState>, id: String, locked: bool) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.set_diary_locked(&id, locked).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_audit_log(
+    state: State<AppState>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    filter_by_entry: Option<String>,
+) -> Result<Vec<AuditRecord>, String> {
+    let db = state.db.lock().unwrap();
+    db.get_audit_log(limit.unwrap_or(100), offset.unwrap_or(0), filter_by_entry.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_audit_retention_days(state: State<AppState>) -> Result<Option<u32>, String> {
+    let db = state.db.lock().unwrap();
+    db.get_audit_retention_days().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_audit_retention_days(state: State<AppState>, days: Option<u32>) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.set_audit_retention_days(days).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_view_history_retention_days(state: State<AppState>) -> Result<Option<u32>, String> {
+    let db = state.db.lock().unwrap();
+    db.get_view_history_retention_days().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_view_history_retention_days(state: State<AppState>, days: Option<u32>) -> Result<(), String> {
+    let db = state.db.lock().unwrap();
+    db.set_view_history_retention_days(days).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn is_read_only(state: State<AppState>) -> bool {
+    state.db.lock().unwrap().is_read_only()
+}
+
+#[tauri::command]
+fn get_database_info(state: State<AppState>) -> Result<DatabaseInfo, String> {
+    let db = state.db.lock().unwrap();
+    db.get_database_info().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn reset_vault(app: AppHandle, state: State<AppState>, confirmation: String) -> Result<(), String> {
+    let mut db = state.db.lock().unwrap();
+    db.reset_vault(&confirmation).map_err(|e| e.to_string())?;
+    drop(db);
+    state.auto_lock.lock().unwrap().touch();
+    let _ = app.emit("vault-reset", ());
+    Ok(())
+}
+
+#[tauri::command]
+fn compact_storage(state: State<AppState>) -> Result<usize, String> {
+    let db = state.db.lock().unwrap();
+    db.compact_storage().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_key_storage_backend(state: State<AppState>) -> Result<KeyStorageBackend, String> {
+    let db = state.db.lock().unwrap();
+    db.key_storage_backend().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn retry_key_access(state: State<AppState>) -> Result<KeyStorageBackend, String> {
+    let mut db = state.db.lock().unwrap();
+    db.reload_key().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn configure_auto_lock(state: State<AppState>, minutes: u32) -> Result<(), String> {
+    // Without a passphrase the vault couldn't be unlocked again, so it never locks.
+    if minutes > 0 && !state.db.lock().unwrap().has_passphrase().map_err(|e| e.to_string())? {
+        return Err("Set a passphrase before turning on auto-lock".to_string());
+    }
+    state.auto_lock.lock().unwrap().configure(minutes);
+    Ok(())
+}
+
+#[tauri::command]
+fn touch_activity(state: State<AppState>) {
+    state.auto_lock.lock().unwrap().touch();
+}
+
+#[tauri::command]
+fn lock_vault(app: AppHandle, state: State<AppState>) -> Result<(), String> {
+    state.db.lock().unwrap().lock().map_err(|e| e.to_string())?;
+    let _ = app.emit("vault-locked", ());
+    Ok(())
+}
+
+#[tauri::command]
+fn unlock_vault(state: State<AppState>, passphrase: String) -> Result<(), String> {
+    let mut db = state.db.lock().unwrap();
+    db.unlock(&passphrase).map_err(|e| e.to_string())?;
+    state.auto_lock.lock().unwrap().touch();
+    Ok(())
+}
+
+#[tauri::command]
+fn is_vault_locked(state: State<AppState>) -> bool {
+    state.db.lock().unwrap().is_locked()
+}
+
+/// Locks the vault once the configured idle timeout has elapsed.
+async fn run_auto_lock(app: AppHandle) {
+    let mut interval = tokio::time::interval(Duration::from_secs(10));
+    loop {
+        interval.tick().await;
+        let state = app.state::<AppState>();
+        if !state.auto_lock.lock().unwrap().is_expired(Instant::now()) {
+            continue;
+        }
+        let mut db = state.db.lock().unwrap();
+        if db.is_locked() {
+            state.auto_lock.lock().unwrap().touch();
+            continue;
+        }
+        if let Err(e) = db.lock() {
+            // E.g. the passphrase was removed; stop retrying until reconfigured.
+            println!("⚠️ [AUTO_LOCK] Could not lock the vault, auto-lock disabled: {}", e);
+            state.auto_lock.lock().unwrap().configure(0);
+            continue;
+        }
+        drop(db);
+        println!("🔒 [AUTO_LOCK] Vault locked after inactivity");
+        let _ = app.emit("vault-locked", ());
+    }
+}
+
+/// Fills in word counts and content hashes for entries saved before they
+/// were tracked, one batch at a time so commands can take the database
+/// lock in between.
+async fn run_word_count_backfill(app: AppHandle) {
+    loop {
+        let state = app.state::<AppState>();
+        let result = state.db.lock().unwrap().backfill_word_counts(WORD_COUNT_BACKFILL_BATCH);
+        match result {
+            Ok(progress) => {
+                let _ = app.emit("word-count-backfill-progress", progress.clone());
+                if progress.remaining == 0 || progress.processed == 0 {
+                    break;
+                }
+            }
+            Err(e) => {
+                // Locked or no key yet; get_statistics finishes the job later.
+                println!("⚠️ [BACKFILL] Word count backfill stopped: {}", e);
+                break;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// Emits `reminder-due` once per due reminder, checking every minute.
+/// Reminders are skipped while the vault is locked and picked up again
+/// after unlocking.
+async fn run_reminders(app: AppHandle) {
+    let mut notified: HashSet<String> = HashSet::new();
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        let state = app.state::<AppState>();
+        let due = match state.db.lock().unwrap().list_due_reminders() {
+            Ok(due) => due,
+            Err(_) => continue,
+        };
+        notified.retain(|id| due.iter().any(|d| &d.reminder.id == id));
+        for reminder in due {
+            if notified.insert(reminder.reminder.id.clone()) {
+                println!("⏰ [REMINDER] Due: {}", reminder.reminder.id);
+                let _ = app.emit("reminder-due", reminder);
+            }
+        }
+    }
+}
+
+/// Runs `verify_vault` once at startup when started with `--verify` or
+/// with verification on startup turned on, and emits `vault-verified`
+/// with the report.
+async fn run_startup_verification(app: AppHandle) {
+    let state = app.state::<AppState>();
+    let report = {
+        let db = state.db.lock().unwrap();
+        let enabled = std::env::args().any(|arg| arg == "--verify") || db.get_verify_on_startup().unwrap_or(false);
+        if !enabled {
+            return;
+        }
+        db.verify_vault()
+    };
+    match report {
+        Ok(report) => {
+            let _ = app.emit("vault-verified", report);
+        }
+        // Locked or no key yet; the check can be run by hand after unlocking.
+        Err(e) => println!("⚠️ [VERIFY] Startup verification skipped: {}", e),
+    }
+}
+
+/// Vault file given as `--read-only <path>` on the command line, if any.
+fn read_only_path() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--read-only" {
+            return args.next();
+        }
+    }
+    None
+}
+
 fn main() {
-    let db = DiaryDB::new();
+    // `--read-only <path>` opens another vault file, such as a backup, for
+    // looking things up without writing to it; `--verify` checks that every
+    // record still decrypts.
+    let db = match read_only_path() {
+        Some(path) => DiaryDB::open_read_only(&path)
+            .unwrap_or_else(|e| panic!("Failed to open {} read-only: {}", path, e)),
+        None => DiaryDB::new(),
+    };
     let app_state = AppState {
         db: Mutex::new(db),
+        auto_lock: Mutex::new(AutoLock::new()),
+        operations: Operations::default(),
     };
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .manage(app_state)
+        .setup(|app| {
+            app.handle().plugin(
+                tauri_plugin_global_shortcut::Builder::new()
+                    .with_handler(|app, _shortcut, event| {
+                        if event.state() == ShortcutState::Pressed {
+                            show_capture_window(app);
+                        }
+                    })
+                    .build(),
+            )?;
+            // Another app may own the shortcut; quick capture is optional.
+            if let Err(e) = app.global_shortcut().register(CAPTURE_SHORTCUT) {
+                println!("⚠️ [CAPTURE] Could not register {}: {}", CAPTURE_SHORTCUT, e);
+            }
+            tauri::async_runtime::spawn(run_auto_lock(app.handle().clone()));
+            tauri::async_runtime::spawn(run_word_count_backfill(app.handle().clone()));
+            tauri::async_runtime::spawn(run_reminders(app.handle().clone()));
+            tauri::async_runtime::spawn(run_startup_verification(app.handle().clone()));
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             save_diary,
+            save_diaries_batch,
+            rename_diary,
+            import_day_one,
+            find_duplicate_diaries,
+            export_entry_encrypted,
+            import_encrypted_entry,
+            quick_capture,
+            list_diary_versions,
+            diff_diary_versions,
+            save_draft,
+            get_draft,
+            discard_draft,
+            list_unsaved_drafts,
             get_diary,
             list_diaries,
+            list_recent_diaries,
+            get_on_this_day,
+            list_stale_diaries,
+            get_or_create_daily_note,
+            list_daily_notes,
+            generate_review,
+            find_diary_by_title,
+            search_titles,
+            pin_diary,
+            unpin_diary,
+            archive_diary,
+            unarchive_diary,
+            list_pinned_diaries,
+            reorder_pinned_diaries,
+            search_diaries,
             search_diaries_by_tag,
+            suggest_tags,
+            set_diary_property,
+            delete_diary_property,
+            query_diaries_by_property,
+            query_diaries,
+            list_orphan_diaries,
+            get_statistics,
+            get_activity_heatmap,
             get_graph_data,
+            export_graph,
+            export_csv,
+            export_journal,
+            import_journal,
+            cancel_operation,
+            list_most_viewed_diaries,
+            save_search,
+            list_saved_searches,
+            delete_saved_search,
+            run_saved_search,
+            set_passphrase,
+            has_passphrase,
+            verify_password,
+            is_elevated,
+            set_diary_locked,
+            get_audit_log,
+            get_audit_retention_days,
+            set_audit_retention_days,
+            get_view_history_retention_days,
+            set_view_history_retention_days,
+            get_tag_cooccurrence,
+            get_subgraph,
+            find_path,
             delete_diary,
+            bulk_add_tag,
+            bulk_remove_tag,
+            bulk_delete_diaries,
             add_relationship,
+            update_relationship,
             delete_relationship,
-            get_relationships
+            get_relationships,
+            get_relationship_types,
+            rename_relationship_type,
+            get_backlinks,
+            get_related_entries,
+            sync_wikilinks,
+            save_template,
+            list_templates,
+            delete_template,
+            create_diary_from_template,
+            set_reminder,
+            list_due_reminders,
+            complete_reminder,
+            list_reminders_for_diary,
+            add_attachment,
+            get_attachment,
+            list_attachments,
+            delete_attachment,
+            rotate_encryption_key,
+            run_maintenance,
+            verify_vault,
+            get_verify_on_startup,
+            set_verify_on_startup,
+            get_database_info,
+            is_read_only,
+            reset_vault,
+            compact_storage,
+            get_key_storage_backend,
+            retry_key_access,
+            configure_auto_lock,
+            touch_activity,
+            lock_vault,
+            unlock_vault,
+            is_vault_locked
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,45 @@
+use caseless::default_case_fold_str;
+use unicode_normalization::{char::canonical_combining_class, UnicodeNormalization};
+
+/// Canonical (NFC) form of `text`, used for tag names as they are stored.
+pub fn nfc(text: &str) -> String {
+    text.nfc().collect()
+}
+
+/// Matching key for `text`: case-folded and stripped of combining marks,
+/// so "Café", "CAFE" and "cafe\u{301}" all compare equal.
+///
+/// Only marks with a non-zero combining class are dropped, which removes
+/// accents but keeps emoji variation selectors and joiners intact. Turkish
+/// dotted capital İ folds to plain i, while dotless ı stays distinct.
+pub fn fold(text: &str) -> String {
+    default_case_fold_str(text)
+        .nfd()
+        .filter(|&c| canonical_combining_class(c) == 0)
+        .nfc()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fold_ignores_case_and_accents() {
+        assert_eq!(fold("Café"), "cafe");
+        assert_eq!(fold("cafe\u{301}"), "cafe");
+        assert_eq!(fold("Straße"), fold("STRASSE"));
+        assert_eq!(fold("ΟΔΟΣ"), fold("οδος"));
+        assert_eq!(nfc("cafe\u{301}"), "café");
+    }
+
+    #[test]
+    fn test_fold_turkish_i_and_emoji() {
+        assert_eq!(fold("İstanbul"), "istanbul");
+        assert_eq!(fold("ISPARTA"), "isparta");
+        assert_ne!(fold("ılık"), fold("ilik"));
+        assert_eq!(fold("❤\u{fe0f} Notes"), "❤\u{fe0f} notes");
+        assert_eq!(fold("👩‍💻 Work"), "👩‍💻 work");
+        assert_eq!(fold("한국어"), "한국어");
+    }
+}
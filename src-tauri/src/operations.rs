@@ -0,0 +1,124 @@
+use crate::database::DiaryDB;
+use crate::error::{DiaryError, DiaryResult};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+type ProgressFn<'a> = Box<dyn FnMut(&str, usize, usize) + 'a>;
+
+/// Progress reporting and cancellation for one long-running operation.
+/// Work reports how far it got per phase and checks for cancellation
+/// between batches.
+pub struct Progress<'a> {
+    cancelled: Arc<AtomicBool>,
+    on_progress: ProgressFn<'a>,
+}
+
+impl<'a> Progress<'a> {
+    pub fn new(cancelled: Arc<AtomicBool>, on_progress: impl FnMut(&str, usize, usize) + 'a) -> Self {
+        Self { cancelled, on_progress: Box::new(on_progress) }
+    }
+
+    /// Progress that isn't reported anywhere and is never cancelled.
+    #[cfg(test)]
+    pub fn silent() -> Self {
+        Self::new(Arc::new(AtomicBool::new(false)), |_, _, _| {})
+    }
+
+    pub fn report(&mut self, phase: &str, done: usize, total: usize) {
+        (self.on_progress)(phase, done, total);
+    }
+
+    /// Fails with `DiaryError::Cancelled` once the operation was cancelled.
+    pub fn check_cancelled(&self) -> DiaryResult<()> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(DiaryError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+/// An operation split into batches. Each `step` borrows the database for
+/// one batch only, so a caller keeping it behind a mutex can release it in
+/// between and other commands aren't blocked for the whole run.
+pub trait Batched {
+    type Output;
+
+    /// Runs the next batch, returning the output once there is none left.
+    fn step(&mut self, db: &DiaryDB, progress: &mut Progress) -> DiaryResult<Option<Self::Output>>;
+}
+
+/// Runs `operation` to the end with `db` borrowed throughout.
+#[cfg(test)]
+pub fn run<B: Batched>(db: &DiaryDB, mut operation: B, progress: &mut Progress) -> DiaryResult<B::Output> {
+    loop {
+        if let Some(output) = operation.step(db, progress)? {
+            return Ok(output);
+        }
+    }
+}
+
+/// Cancellation flags of the operations currently running, by the id the
+/// frontend gave them.
+#[derive(Default)]
+pub struct Operations {
+    running: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl Operations {
+    /// Registers operation `id` and returns its cancellation flag.
+    pub fn start(&self, id: &str) -> DiaryResult<Arc<AtomicBool>> {
+        if id.trim().is_empty() {
+            return Err(DiaryError::Validation("Operation id must not be empty".to_string()));
+        }
+        let mut running = self.running.lock().unwrap();
+        if running.contains_key(id) {
+            return Err(DiaryError::Validation(format!("Operation {} is already running", id)));
+        }
+        let cancelled = Arc::new(AtomicBool::new(false));
+        running.insert(id.to_string(), cancelled.clone());
+        Ok(cancelled)
+    }
+
+    /// Asks operation `id` to stop at its next check. Returns false if no
+    /// such operation is running.
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.running.lock().unwrap().get(id) {
+            Some(cancelled) => {
+                cancelled.store(true, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn finish(&self, id: &str) {
+        self.running.lock().unwrap().remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_reaches_running_operation() {
+        let operations = Operations::default();
+        let flag = operations.start("export-1").unwrap();
+        assert!(operations.start("export-1").is_err());
+        assert!(operations.start(" ").is_err());
+
+        let mut reports = Vec::new();
+        let mut progress = Progress::new(flag, |phase: &str, done, total| reports.push((phase.to_string(), done, total)));
+        progress.report("exporting", 1, 2);
+        assert!(progress.check_cancelled().is_ok());
+        assert!(operations.cancel("export-1"));
+        assert!(matches!(progress.check_cancelled(), Err(DiaryError::Cancelled)));
+        drop(progress);
+        assert_eq!(reports, vec![("exporting".to_string(), 1, 2)]);
+
+        operations.finish("export-1");
+        assert!(!operations.cancel("export-1"));
+        assert!(operations.start("export-1").is_ok());
+    }
+}
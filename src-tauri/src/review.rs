@@ -0,0 +1,108 @@
+use chrono::NaiveDate;
+use std::fmt::Write;
+
+/// Tag carried by generated review entries.
+pub const REVIEW_TAG: &str = "review";
+
+/// A relationship created during the review period, by entry titles.
+pub struct ReviewRelationship {
+    pub parent: String,
+    pub child: String,
+    pub relationship_type: String,
+}
+
+/// What happened between `from` and `to`, inclusive.
+pub struct Review {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// Titles of entries created in the period.
+    pub created: Vec<String>,
+    /// Titles of older entries modified in the period.
+    pub modified: Vec<String>,
+    /// Tags first used in the period.
+    pub new_tags: Vec<String>,
+    pub relationships: Vec<ReviewRelationship>,
+    /// Items still waiting in the inbox.
+    pub inbox: Vec<String>,
+}
+
+/// Title of the review entry for a period; the same period always gets the
+/// same title, which is how an earlier review is found again.
+pub fn title(from: NaiveDate, to: NaiveDate) -> String {
+    format!("Review {} to {}", from.format("%Y-%m-%d"), to.format("%Y-%m-%d"))
+}
+
+pub fn render(review: &Review) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}", title(review.from, review.to));
+
+    section(&mut out, "Created", review.created.iter().map(|title| link(title)));
+    section(&mut out, "Modified", review.modified.iter().map(|title| link(title)));
+    section(&mut out, "New tags", review.new_tags.iter().map(|tag| format!("#{}", tag)));
+    section(
+        &mut out,
+        "New relationships",
+        review.relationships.iter().map(|relationship| {
+            format!(
+                "{} {} {}",
+                link(&relationship.parent),
+                relationship.relationship_type,
+                link(&relationship.child)
+            )
+        }),
+    );
+    section(&mut out, "Inbox", review.inbox.iter().cloned());
+
+    out
+}
+
+fn section(out: &mut String, heading: &str, items: impl Iterator<Item = String>) {
+    let _ = write!(out, "\n## {}\n\n", heading);
+    let mut empty = true;
+    for item in items {
+        let _ = writeln!(out, "- {}", item);
+        empty = false;
+    }
+    if empty {
+        out.push_str("_Nothing_\n");
+    }
+}
+
+/// `[[title]]`, or the plain title when it couldn't be parsed back as a
+/// wikilink.
+fn link(title: &str) -> String {
+    let title = title.trim();
+    if title.is_empty() || title.contains(['[', ']', '|', '\n']) {
+        return title.to_string();
+    }
+    format!("[[{}]]", title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_links_titles_and_marks_empty_sections() {
+        let review = Review {
+            from: NaiveDate::from_ymd_opt(2024, 3, 4).unwrap(),
+            to: NaiveDate::from_ymd_opt(2024, 3, 10).unwrap(),
+            created: vec!["Trip plan".to_string(), "odd [title]".to_string()],
+            modified: Vec::new(),
+            new_tags: vec!["travel".to_string()],
+            relationships: vec![ReviewRelationship {
+                parent: "Trip plan".to_string(),
+                child: "Packing".to_string(),
+                relationship_type: "links_to".to_string(),
+            }],
+            inbox: vec!["[2024-03-05 09:00] call the bank".to_string()],
+        };
+        let markdown = render(&review);
+        assert!(markdown.starts_with("# Review 2024-03-04 to 2024-03-10\n"));
+        assert!(markdown.contains("## Created\n\n- [[Trip plan]]\n- odd [title]\n"));
+        assert!(markdown.contains("## Modified\n\n_Nothing_\n"));
+        assert!(markdown.contains("- #travel\n"));
+        assert!(markdown.contains("- [[Trip plan]] links_to [[Packing]]\n"));
+        assert!(markdown.contains("- [2024-03-05 09:00] call the bank\n"));
+    }
+}
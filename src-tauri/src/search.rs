@@ -0,0 +1,137 @@
+use crate::normalize::fold;
+use serde::{Deserialize, Serialize};
+
+/// A highlighted range inside a snippet, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MatchRange {
+    pub start: usize,
+    pub len: usize,
+}
+
+const ELLIPSIS: &str = "…";
+
+/// Returns the byte ranges of every case- and accent-insensitive,
+/// non-overlapping occurrence of `needle` in `haystack`, in order, as
+/// compared by `normalize::fold`. Ranges always start and end on character
+/// boundaries, and take in any accents trailing the last character.
+pub fn find_matches(haystack: &str, needle: &str) -> Vec<(usize, usize)> {
+    let needle: Vec<char> = fold(needle).chars().collect();
+    if needle.is_empty() {
+        return Vec::new();
+    }
+
+    // Start, end and folded form of each character; combining accents
+    // fold to nothing.
+    let folded: Vec<(usize, usize, String)> = haystack
+        .char_indices()
+        .map(|(start, c)| (start, start + c.len_utf8(), fold(c.encode_utf8(&mut [0; 4]))))
+        .collect();
+
+    let mut matches = Vec::new();
+    let mut index = 0;
+    while index < folded.len() {
+        if folded[index].2.is_empty() {
+            index += 1;
+            continue;
+        }
+        let mut pending = needle.iter();
+        let mut last = None;
+        for (offset, (_, _, c)) in folded[index..].iter().enumerate() {
+            if !c.chars().all(|l| pending.next() == Some(&l)) {
+                break;
+            }
+            if pending.len() == 0 {
+                last = Some(index + offset);
+                break;
+            }
+        }
+        match last {
+            Some(mut last) => {
+                while folded.get(last + 1).is_some_and(|(_, _, c)| c.is_empty()) {
+                    last += 1;
+                }
+                matches.push((folded[index].0, folded[last].1));
+                index = last + 1;
+            }
+            None => index += 1,
+        }
+    }
+
+    matches
+}
+
+/// Cuts a snippet of about `max_chars` characters around the first match
+/// and returns it with the matches that fall inside it, relative to the
+/// snippet. Line breaks become spaces; cut ends are marked with "…".
+pub fn cut_snippet(text: &str, matches: &[(usize, usize)], max_chars: usize) -> (String, Vec<MatchRange>) {
+    let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).chain([text.len()]).collect();
+    let char_count = boundaries.len() - 1;
+    let char_at = |byte: usize| boundaries.partition_point(|&b| b < byte);
+
+    let (first_start, first_end) = matches.first().map(|&(s, e)| (char_at(s), char_at(e))).unwrap_or((0, 0));
+    let context = max_chars.saturating_sub(first_end - first_start) / 2;
+    let mut start_char = first_start.saturating_sub(context);
+    let end_char = (start_char + max_chars).min(char_count);
+    start_char = end_char.saturating_sub(max_chars).min(start_char);
+
+    let (start, end) = (boundaries[start_char], boundaries[end_char]);
+    let prefix = if start > 0 { ELLIPSIS } else { "" };
+    let body: String = text[start..end]
+        .chars()
+        .map(|c| if c == '\n' || c == '\r' { ' ' } else { c })
+        .collect();
+    let suffix = if end < text.len() { ELLIPSIS } else { "" };
+
+    let ranges = matches
+        .iter()
+        .filter(|&&(s, e)| s >= start && e <= end)
+        .map(|&(s, e)| MatchRange { start: prefix.len() + s - start, len: e - s })
+        .collect();
+
+    (format!("{}{}{}", prefix, body, suffix), ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_matches_is_case_insensitive_and_utf8_safe() {
+        let text = "Café CAFÉ café 😀 日本語 café";
+        let matches = find_matches(text, "café");
+        assert_eq!(matches.len(), 4);
+        for (start, end) in &matches {
+            assert_eq!(text[*start..*end].to_lowercase(), "café");
+        }
+        assert_eq!(find_matches("日本語の日本", "日本"), vec![(0, 6), (12, 18)]);
+        assert_eq!(find_matches("aaaa", "aa"), vec![(0, 2), (2, 4)]);
+        assert!(find_matches("abc", "").is_empty());
+    }
+
+    #[test]
+    fn test_find_matches_ignores_accents() {
+        let text = "Cafe\u{301} and CAFE and café";
+        let matches = find_matches(text, "cafe");
+        assert_eq!(matches.len(), 3);
+        assert_eq!(&text[matches[0].0..matches[0].1], "Cafe\u{301}");
+        assert_eq!(find_matches("İstanbul", "istanbul"), vec![(0, 9)]);
+        assert!(find_matches("ılık", "ilik").is_empty());
+        assert_eq!(find_matches("❤\u{fe0f} notes", "❤\u{fe0f}"), vec![(0, 6)]);
+    }
+
+    #[test]
+    fn test_snippet_cuts_on_char_boundaries() {
+        let text = format!("{}needle{}", "😀".repeat(100), "語".repeat(100));
+        let matches = find_matches(&text, "NEEDLE");
+        let (snippet, ranges) = cut_snippet(&text, &matches, 20);
+        assert!(snippet.starts_with(ELLIPSIS) && snippet.ends_with(ELLIPSIS));
+        assert_eq!(ranges.len(), 1);
+        let range = ranges[0];
+        assert_eq!(&snippet[range.start..range.start + range.len], "needle");
+        assert_eq!(snippet.chars().count(), 20 + 2);
+
+        let (short, ranges) = cut_snippet("one\ntwo", &find_matches("one\ntwo", "two"), 160);
+        assert_eq!(short, "one two");
+        assert_eq!(ranges, vec![MatchRange { start: 4, len: 3 }]);
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use std::time::Duration;
+
+/// Largest amount of text diffed from each side, in bytes. Longer inputs
+/// are cut at the last line break before the limit.
+const MAX_DIFF_INPUT_BYTES: usize = 256 * 1024;
+
+/// Past this the diff falls back to a quicker, less minimal result.
+const DIFF_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Unchanged lines kept around each change.
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffLineKind {
+    Unchanged,
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    /// The line without its line break.
+    pub text: String,
+}
+
+/// A run of changes with its context. Starts are 1-based line numbers, as
+/// in a unified diff header.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiffHunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LineDiff {
+    /// Empty when both sides are the same.
+    pub hunks: Vec<DiffHunk>,
+    /// Whether either side was cut to `MAX_DIFF_INPUT_BYTES` first.
+    pub truncated: bool,
+}
+
+/// Line-level diff of `old` against `new`, grouped into hunks.
+pub fn diff_lines(old: &str, new: &str) -> LineDiff {
+    let (old, old_truncated) = truncate(old);
+    let (new, new_truncated) = truncate(new);
+    let diff = TextDiff::configure().timeout(DIFF_TIMEOUT).diff_lines(old, new);
+
+    let hunks = diff
+        .grouped_ops(CONTEXT_LINES)
+        .into_iter()
+        .map(|group| {
+            let (first, last) = (&group[0], &group[group.len() - 1]);
+            let old_range = first.old_range().start..last.old_range().end;
+            let new_range = first.new_range().start..last.new_range().end;
+            let lines = group
+                .iter()
+                .flat_map(|op| diff.iter_changes(op))
+                .map(|change| DiffLine {
+                    kind: match change.tag() {
+                        ChangeTag::Equal => DiffLineKind::Unchanged,
+                        ChangeTag::Insert => DiffLineKind::Added,
+                        ChangeTag::Delete => DiffLineKind::Removed,
+                    },
+                    text: change.value().trim_end_matches('\n').trim_end_matches('\r').to_string(),
+                })
+                .collect();
+            DiffHunk {
+                old_start: old_range.start + 1,
+                old_lines: old_range.len(),
+                new_start: new_range.start + 1,
+                new_lines: new_range.len(),
+                lines,
+            }
+        })
+        .collect();
+
+    LineDiff { hunks, truncated: old_truncated || new_truncated }
+}
+
+fn truncate(text: &str) -> (&str, bool) {
+    if text.len() <= MAX_DIFF_INPUT_BYTES {
+        return (text, false);
+    }
+    let mut end = MAX_DIFF_INPUT_BYTES;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let end = text[..end].rfind('\n').map_or(end, |newline| newline + 1);
+    (&text[..end], true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_groups_changes_into_hunks() {
+        let old = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\n";
+        let new = "one\n2\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\neleven\n";
+        let diff = diff_lines(old, new);
+        assert!(!diff.truncated);
+        assert_eq!(diff.hunks.len(), 2);
+
+        let first = &diff.hunks[0];
+        assert_eq!((first.old_start, first.old_lines, first.new_start, first.new_lines), (1, 5, 1, 5));
+        let changed: Vec<(DiffLineKind, &str)> =
+            first.lines.iter().filter(|l| l.kind != DiffLineKind::Unchanged).map(|l| (l.kind, l.text.as_str())).collect();
+        assert_eq!(changed, vec![(DiffLineKind::Removed, "two"), (DiffLineKind::Added, "2")]);
+
+        let last = diff.hunks[1].lines.last().unwrap();
+        assert_eq!((last.kind, last.text.as_str()), (DiffLineKind::Added, "eleven"));
+        assert!(diff_lines("same\n", "same\n").hunks.is_empty());
+    }
+
+    #[test]
+    fn test_large_input_is_truncated_at_a_line_break() {
+        let line = "é".repeat(50) + "\n";
+        let big = line.repeat(MAX_DIFF_INPUT_BYTES / line.len() + 10);
+        let (cut, truncated) = truncate(&big);
+        assert!(truncated);
+        assert!(cut.len() <= MAX_DIFF_INPUT_BYTES && cut.ends_with('\n'));
+        assert!(diff_lines(&big, "").truncated);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::normalize;
+
+/// Extracts the targets of `[[wikilinks]]` in `content`.
+///
+/// `[[Target|label]]` links to `Target`. Targets are trimmed, empty or
+/// multi-line links are ignored, and duplicates (compared the way links
+/// resolve, ignoring case and accents) are returned once in order of first
+/// appearance.
+pub fn extract_wikilinks(content: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("[[") {
+        let after_open = &rest[start + 2..];
+        let end = match after_open.find("]]") {
+            Some(end) => end,
+            None => break,
+        };
+        let inner = &after_open[..end];
+        rest = &after_open[end + 2..];
+
+        // A nested "[[" means the earlier opener was unterminated text.
+        let inner = match inner.rfind("[[") {
+            Some(nested) => &inner[nested + 2..],
+            None => inner,
+        };
+        if inner.contains('\n') {
+            continue;
+        }
+        let target = inner.split('|').next().unwrap_or_default().trim();
+        if target.is_empty() {
+            continue;
+        }
+        let key = normalize::fold(target);
+        if !links.iter().any(|link| normalize::fold(link) == key) {
+            links.push(target.to_string());
+        }
+    }
+
+    links
+}
+
+/// Points the `[[old]]` links in `content` at `new`, keeping any `|label`.
+///
+/// Targets match the way links resolve, ignoring case and accents, and are
+/// found with the same rules as `extract_wikilinks`. `old` as plain text,
+/// outside brackets, is left alone. Returns `None` when no link changed.
+pub fn rename_wikilinks(content: &str, old: &str, new: &str) -> Option<String> {
+    let old = normalize::fold(old.trim());
+    let mut renamed = String::with_capacity(content.len());
+    let mut changed = false;
+    let mut rest = content;
+
+    while let Some(start) = rest.find("[[") {
+        let after_open = &rest[start + 2..];
+        let end = match after_open.find("]]") {
+            Some(end) => end,
+            None => break,
+        };
+        let inner = &after_open[..end];
+        let link_start = inner.rfind("[[").map_or(0, |nested| nested + 2);
+        let link = &inner[link_start..];
+        renamed.push_str(&rest[..start + 2 + link_start]);
+
+        let (target, label) = link.split_at(link.find('|').unwrap_or(link.len()));
+        if !link.contains('\n') && !target.trim().is_empty() && normalize::fold(target.trim()) == old {
+            renamed.push_str(new);
+            renamed.push_str(label);
+            changed = true;
+        } else {
+            renamed.push_str(link);
+        }
+        renamed.push_str("]]");
+        rest = &after_open[end + 2..];
+    }
+    renamed.push_str(rest);
+
+    changed.then_some(renamed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_wikilinks() {
+        let content = "See [[Project Atlas]] and [[project atlas]], also [[Reading List|books]].\n\
+                       Broken [[ ]] and [[unterminated\nline]] and [[a [[Nested]]";
+        assert_eq!(
+            extract_wikilinks(content),
+            vec!["Project Atlas", "Reading List", "Nested"]
+        );
+        assert_eq!(extract_wikilinks("[[Café]] then [[cafe]] and [[CAFE\u{301}]]"), vec!["Café"]);
+        assert!(extract_wikilinks("no links here").is_empty());
+    }
+
+    #[test]
+    fn test_rename_wikilinks_keeps_labels_and_plain_text() {
+        let content = "Old Name is great: [[Old Name]], [[old name|see here]], [[Old Names]].\n\
+                       [[a [[Old Name]] and [[Old\nName]]";
+        assert_eq!(
+            rename_wikilinks(content, "Old Name", "New Name").unwrap(),
+            "Old Name is great: [[New Name]], [[New Name|see here]], [[Old Names]].\n\
+             [[a [[New Name]] and [[Old\nName]]"
+        );
+        assert_eq!(rename_wikilinks("Old Name, unlinked", "Old Name", "New Name"), None);
+    }
+}